@@ -0,0 +1,62 @@
+//! Parsing and formatting of a large batch of records by a single thread and on all the cores,
+//! run with `cargo bench --bench batch --features rayon`, which prints the speedup on the cores of the machine.
+
+use fragstrings::{frag_format, frag_parse, par_format, par_parse};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const RECORDS: i64 = 1_000_000;
+const ITERATIONS: u32 = 10;
+
+fn bench(name: &str, mut f: impl FnMut() -> usize) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+    println!("{:<40} {:>12?}", name, elapsed);
+    elapsed
+}
+
+fn main() {
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    println!("{} records, {} cores", RECORDS, cores);
+
+    let rows = (0..RECORDS)
+        .map(|i| (format!("WAVES/USDN/{}", i), i, i * 7))
+        .collect::<Vec<_>>();
+    let records = rows
+        .iter()
+        .map(|(pair, a, b)| frag_format!("%s%d%d", pair, *a, *b))
+        .collect::<Vec<_>>();
+
+    let single = bench("parse, single thread", || {
+        let parsed = records
+            .iter()
+            .map(|record| frag_parse!("%s%d%d", record))
+            .collect::<Vec<_>>();
+        parsed.len()
+    });
+    let parallel = bench("parse, all the cores", || par_parse!("%s%d%d", &records).len());
+    println!(
+        "{:<40} {:>11.1}x",
+        "speedup",
+        single.as_secs_f64() / parallel.as_secs_f64()
+    );
+
+    let single = bench("format, single thread", || {
+        let formatted = rows
+            .iter()
+            .map(|(pair, a, b)| frag_format!("%s%d%d", pair, *a, *b))
+            .collect::<Vec<_>>();
+        formatted.len()
+    });
+    let parallel = bench("format, all the cores", || {
+        par_format!("%s%d%d", &rows, |(pair, a, b)| pair, *a, *b).len()
+    });
+    println!(
+        "{:<40} {:>11.1}x",
+        "speedup",
+        single.as_secs_f64() / parallel.as_secs_f64()
+    );
+}
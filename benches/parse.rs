@@ -0,0 +1,86 @@
+//! Parsing of adversarial values with lots of separators, run with `cargo bench`.
+//!
+//! Closed formats split the value only as far as needed to reject the extra fragments,
+//! and values with mismatching descriptors are rejected before any fragment is split,
+//! so both take the same time whatever the length of the rest of the value is.
+//!
+//! Splitting of realistic keys by `str::split` and by the helper used by the generated code
+//! is compared as well, the latter is faster with `cargo bench --features perf`.
+
+use fragstrings::__private::Fragments;
+use fragstrings::frag_parse;
+use std::hint::black_box;
+use std::time::Instant;
+
+const ITERATIONS: u32 = 1000;
+
+fn bench(name: &str, mut f: impl FnMut() -> bool) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+    println!("{:<40} {:>12?}", name, elapsed);
+}
+
+fn main() {
+    // About 1 MB of empty fragments after the expected ones
+    let extra = "__".repeat(512 * 1024);
+    let closed = format!("%s%d__foo__42{}", extra);
+    let mismatched = format!("%d%s__42__foo{}", extra);
+
+    bench("split all the fragments (baseline)", || {
+        black_box(&closed).split("__").count() > 3
+    });
+    bench("closed format, extra fragments", || {
+        frag_parse!("%s%d", black_box(&closed)).is_none()
+    });
+    bench("closed format with version", || {
+        frag_parse!(version = [0, 1], "%s%d", black_box(&closed)).is_none()
+    });
+    bench("open-ended format", || {
+        frag_parse!("%s%d*", black_box(&closed)).is_some()
+    });
+    bench("mismatching descriptor", || {
+        frag_parse!("%s%d", black_box(&mismatched)).is_none()
+    });
+
+    // Keys of the usual shape: addresses, asset ids and snake_case names
+    let keys = (0..1000)
+        .map(|i| {
+            format!(
+                "%s%s%d%s__3P{:0>33}__order_status__{}__DG2xFkPdDwKUoBkzGAhQtLpSGzfXLiCYPEzeKH2Ad24p",
+                i,
+                i * 7919
+            )
+        })
+        .collect::<Vec<_>>();
+    let long_keys = keys.iter().map(|key| key.repeat(20)).collect::<Vec<_>>();
+
+    bench("keys, str::split", || {
+        keys.iter().map(|key| black_box(key).split("__").count()).sum::<usize>() > 0
+    });
+    bench("keys, Fragments", || {
+        keys.iter()
+            .map(|key| Fragments::new(black_box(key), None).count())
+            .sum::<usize>()
+            > 0
+    });
+    bench("long keys, str::split", || {
+        long_keys
+            .iter()
+            .map(|key| black_box(key).split("__").count())
+            .sum::<usize>()
+            > 0
+    });
+    bench("long keys, Fragments", || {
+        long_keys
+            .iter()
+            .map(|key| Fragments::new(black_box(key), None).count())
+            .sum::<usize>()
+            > 0
+    });
+    bench("keys, frag_parse!", || {
+        keys.iter().all(|key| frag_parse!("%s%s%d%s", black_box(key)).is_some())
+    });
+}
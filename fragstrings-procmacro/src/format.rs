@@ -0,0 +1,701 @@
+//! Code generation of the formatting macros.
+
+use proc_macro2::{Ident, Literal, Span, TokenStream, TokenTree};
+use quote::format_ident;
+
+use std::convert::TryFrom;
+use std::iter;
+
+use crate::utils::{
+    errors::{CompileError, SpannedError},
+    fmt_strings::{
+        hex_width, is_non_zero_u64, is_system_time, parse_format_string, parse_format_string_ex, FormatEnding,
+        FormatErrorKind, FormatItem, FormatItemOpt, FormatItemType, FormatString, FormatStringError, Padding,
+    },
+    literals::{parse_int_literal, parse_string_literal, parse_string_literals},
+    options::{parse_ident_value, parse_int_value, parse_options, parse_str_value},
+    punct::{parse_punctuated_args, stream_span},
+    registry::pattern_registration,
+};
+
+/// Type of the formatted value.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Output {
+    String,
+    Small,
+}
+
+/// Generates the formatting code, or the error, in which case the `dummy` value is updated to match
+/// the type of the output.
+pub(crate) fn frag_format_impl(
+    args: TokenStream,
+    output: Output,
+    dummy: &mut TokenStream,
+) -> Result<TokenStream, SpannedError> {
+    let (output_type, output_new) = match output {
+        Output::String => (
+            quote_mixed! { ::fragstrings::__private::alloc::string::String },
+            quote_mixed! { ::fragstrings::__private::alloc::string::String::new() },
+        ),
+        Output::Small => (
+            quote_mixed! { ::fragstrings::small::SmallString },
+            quote_mixed! { ::fragstrings::small::SmallString::new() },
+        ),
+    };
+
+    let args = parse_punctuated_args(args)?;
+
+    let (options, args) = parse_options(args).map_err(|span| CompileError::BadOption.at(span))?;
+    let mut checked = false;
+    let mut version = None;
+    let mut html_encode = false;
+    let mut emoji = false;
+    let mut quote_spaces = false;
+    let mut path_safe = false;
+    let mut path_escape = false;
+    let mut cstring = false;
+    let mut rgba = false;
+    let mut roman = false;
+    let mut human_duration = false;
+    let mut packed_date = false;
+    let mut fixed_point = None;
+    let mut bitmask = false;
+    let mut percent = None;
+    let mut int_form = false;
+    let mut max_bytes = None;
+    let mut max_items = None;
+    let mut null_sentinel = None;
+    let mut null_sentinel_option = None;
+    for option in options {
+        let bad_option = CompileError::BadOption.at(option.span);
+        match (option.name.as_str(), option.value) {
+            ("checked", None) => checked = true,
+            ("html_encode", None) => html_encode = true,
+            ("emoji", None) if cfg!(feature = "emojis") => emoji = true,
+            ("quote_spaces", None) => quote_spaces = true,
+            // Characters which can not be in file names are either rejected or escaped
+            ("path_safe", None) if !path_escape => path_safe = true,
+            ("path_safe", Some(value)) if !path_safe => match parse_ident_value(&value).as_deref() {
+                Some("escape") => path_escape = true,
+                _ => return Err(bad_option),
+            },
+            // Plain strings are taken either as C strings or as colors
+            ("cstring", None) if !rgba => cstring = true,
+            ("rgba", None) if !cstring => rgba = true,
+            // Plain integers are written in one form at most
+            ("roman", None) if !int_form => {
+                roman = true;
+                int_form = true;
+            }
+            ("human_duration", None) if !int_form => {
+                human_duration = true;
+                int_form = true;
+            }
+            ("packed_date", None) if cfg!(feature = "chrono") && !int_form => {
+                packed_date = true;
+                int_form = true;
+            }
+            ("fixed_point", Some(value)) if !int_form => {
+                let value = parse_int_value(&value).filter(|v| (1..=18).contains(v));
+                fixed_point = Some(value.ok_or(bad_option)? as u32);
+                int_form = true;
+            }
+            ("bitmask", None) if !int_form => {
+                bitmask = true;
+                int_form = true;
+            }
+            ("percent", None) if !int_form => {
+                percent = Some(false);
+                int_form = true;
+            }
+            ("percent", Some(value)) if !int_form => {
+                if parse_ident_value(&value).as_deref() != Some("strict") {
+                    return Err(bad_option);
+                }
+                percent = Some(true);
+                int_form = true;
+            }
+            ("null_sentinel", Some(value)) => {
+                let value = parse_str_value(&value).filter(|s| !s.is_empty() && !s.contains("__"));
+                null_sentinel = Some(value.ok_or(bad_option)?);
+                null_sentinel_option = Some(option.span);
+            }
+            ("max_items", Some(value)) => max_items = Some(parse_int_value(&value).ok_or(bad_option)?),
+            ("max_bytes", Some(value)) => {
+                let value = parse_int_value(&value).filter(|&v| v > 0 && v <= usize::MAX as u64);
+                max_bytes = Some(value.ok_or(bad_option)? as usize);
+            }
+            ("version", Some(value)) => {
+                let value = parse_int_value(&value).filter(|&v| v > 0 && v <= u32::MAX as u64);
+                version = Some(value.ok_or(bad_option)?);
+            }
+            _ => return Err(bad_option),
+        }
+    }
+    // The sentinel is written as is, so it must be fine for file names too
+    if let (Some(sentinel), Some(span)) = (&null_sentinel, null_sentinel_option) {
+        if (path_safe || path_escape) && !is_path_safe(sentinel) {
+            return Err(CompileError::BadOption.at(span));
+        }
+    }
+    // Numbers which can not be written in the given form are errors, like too long values,
+    // and so are strings which can not be in file names
+    let fallible =
+        roman || human_duration || packed_date || fixed_point.is_some() || bitmask || percent.is_some() || path_safe;
+    if fallible || max_bytes.is_some() {
+        *dummy = quote_mixed! {
+            ::core::result::Result::<_, ::fragstrings::FormatError>::Ok(#output_new)
+        };
+    }
+
+    let mut args = args.into_iter();
+    let fmt_literals = parse_string_literals(args.next().unwrap_or_default())?;
+    let (fmt_string, fmt_span) = (&fmt_literals.value, fmt_literals.span);
+
+    // The call site is registered for auditing, if enabled, with the format string as written
+    let registration = if cfg!(feature = "registry") {
+        Some(pattern_registration(fmt_string, "Format"))
+    } else {
+        None
+    };
+
+    // Errors point at the offending character, if possible
+    let bad_format_string = |err: FormatStringError| {
+        let span = fmt_literals.span_at(err.pos);
+        CompileError::from(err).at(span)
+    };
+
+    // Optional items are allowed only if there is a sentinel to write instead of the absent value
+    let fmt_items = if null_sentinel.is_some() {
+        match parse_format_string_ex(fmt_string).map_err(bad_format_string)? {
+            FormatString(items, FormatEnding::Closed) => items,
+            FormatString(_, FormatEnding::Open) => {
+                let err = FormatStringError {
+                    kind: FormatErrorKind::AsteriskNotAllowed,
+                    pos: fmt_string.len() - 1,
+                };
+                return Err(bad_format_string(err));
+            }
+        }
+    } else {
+        parse_format_string(fmt_string).map_err(bad_format_string)?
+    };
+
+    // Paths are not guaranteed to be valid UTF-8, so they are supported for parsing only
+    if fmt_items.iter().any(|item| item.ty == FormatItemType::Path) {
+        return Err(CompileError::PathNotFormattable.at(fmt_span));
+    }
+
+    // Length limits, regexes, tries and asserted values are for validating parsed values, use `max_bytes` to limit the formatted one
+    if fmt_items.iter().any(|item| item.length_limit.is_some()) {
+        return Err(CompileError::LengthLimitNotFormattable.at(fmt_span));
+    }
+    if fmt_items.iter().any(|item| item.regex.is_some()) {
+        return Err(CompileError::RegexNotFormattable.at(fmt_span));
+    }
+    if fmt_items.iter().any(|item| item.trie.is_some()) {
+        return Err(CompileError::TrieNotFormattable.at(fmt_span));
+    }
+    if fmt_items.iter().any(|item| item.assert.is_some()) {
+        return Err(CompileError::AssertNotFormattable.at(fmt_span));
+    }
+    // A byte range takes a part of the parsed fragment, so there is nothing to take when formatting
+    if fmt_items.iter().any(|item| item.slice.is_some()) {
+        return Err(CompileError::SliceNotFormattable.at(fmt_span));
+    }
+
+    let args = args.collect::<Vec<_>>();
+
+    // Extra arguments are reported where they start, and missing ones at the whole macro call,
+    // because the closing parenthesis is not visible to the macro
+    if fmt_items.len() != args.len() {
+        let span = args.get(fmt_items.len()).map_or_else(Span::call_site, stream_span);
+        let error = CompileError::ArgCountMismatch {
+            items: fmt_items,
+            args: args.len(),
+        };
+        return Err(error.at(span));
+    }
+
+    let n = fmt_items.len();
+
+    // Version and checksum are fragments too
+    if let Some(max_items) = max_items {
+        if (n + version.is_some() as usize + checked as usize) as u64 > max_items {
+            return Err(CompileError::TooManyItems.at(fmt_span));
+        }
+    }
+
+    // Custom types are not described in the fragmented string
+    let fmt_string = fmt_items.iter().map(|item| item.ty.descriptor()).collect::<String>();
+
+    // Padded items are aligned by their placeholders, like `{:<20}`
+    let placeholders = fmt_items
+        .iter()
+        .map(|item| match item.padding {
+            Some(Padding { width, align }) => format!("{{:{}{}}}", align.as_char(), width),
+            None => "{}".to_owned(),
+        })
+        .collect::<Vec<_>>();
+
+    // Length of the arguments, if it is known at compile time, i.e. all of them are literals
+    let literal_args_len = if html_encode || emoji || quote_spaces || path_escape || cstring || rgba || fallible {
+        None
+    } else {
+        fmt_items
+            .iter()
+            .zip(&args)
+            .map(|(item, arg)| literal_arg_len(item, arg))
+            .sum::<Option<usize>>()
+    };
+
+    let vars = (0..n)
+        .map(|i| format_ident!("_{}", i, span = Span::mixed_site()))
+        .collect::<Vec<_>>();
+
+    // Single item, which is formatted into a string anyway, is just appended to the rest of the value
+    let single_str = match fmt_items.as_slice() {
+        [FormatItem {
+            ty: item_type,
+            custom_type: None,
+            padding: None,
+            ..
+        }] => matches!(
+            item_type,
+            FormatItemType::Str | FormatItemType::Base62 | FormatItemType::StrSet | FormatItemType::StrMap
+        ),
+        _ => false,
+    };
+
+    // String values are encoded in the reverse order of decoding them by `frag_parse!`
+    let str_encode = |var: &Ident, arg: &TokenStream| {
+        let emoji = if emoji {
+            Some(quote_mixed! {
+                let #var = ::fragstrings::__private::shortcodes_to_emoji(&#var, |code| {
+                    ::fragstrings::__private::emojis::get_by_shortcode(code).map(::fragstrings::__private::emojis::Emoji::as_str)
+                });
+            })
+        } else {
+            None
+        };
+        let html_encode = if html_encode {
+            Some(quote_mixed! { let #var = ::fragstrings::__private::html_encode(&#var); })
+        } else {
+            None
+        };
+        let quote_spaces = if quote_spaces {
+            Some(quote_mixed! { let #var = ::fragstrings::__private::quote_spaces(&#var); })
+        } else {
+            None
+        };
+        let path_safe = if path_safe {
+            Some(quote_mixed! {
+                if !::fragstrings::__private::is_path_safe(&#var) {
+                    break 'format ::core::result::Result::Err(::fragstrings::FormatError::NotPathSafe {
+                        arg: ::core::stringify!(#arg),
+                    });
+                }
+            })
+        } else if path_escape {
+            Some(quote_mixed! { let #var = ::fragstrings::__private::path_escape(&#var); })
+        } else {
+            None
+        };
+        quote_mixed! { #emoji #html_encode #quote_spaces #path_safe }
+    };
+    let encoded = html_encode || emoji || quote_spaces || path_safe || path_escape;
+
+    let var_decls = vars
+        .iter()
+        .zip(fmt_items)
+        .zip(args)
+        .map(|((var, FormatItem { ty: item_type, opt: item_opt, custom_type, padding, .. }), arg)| {
+            // Custom types may ignore the width in their `Display`, so they are padded as strings
+            let pad_as_string = padding.is_some() && custom_type.as_deref().is_some_and(|ty| hex_width(ty).is_none());
+            // Optional arguments are `Option`s, and the value inside is formatted as usual
+            let value = match item_opt {
+                FormatItemOpt::Mandatory => arg.clone(),
+                FormatItemOpt::Optional => quote_mixed! { *#var },
+            };
+            let decl = match (item_type, custom_type) {
+                // `%s:hex16{N}` is `[u8; N]`, which is written as hex digits, which have nothing to encode
+                (FormatItemType::Str, Some(custom_type)) if hex_width(&custom_type).is_some() => {
+                    let width = hex_width(&custom_type).unwrap_or_default();
+                    quote_mixed! {
+                        let #var: &[u8; #width] = &( #value );
+                        let #var = ::fragstrings::__private::hex_encode(#var);
+                    }
+                }
+                // Custom types are formatted using `Display`
+                (FormatItemType::Str, Some(custom_type)) if encoded => {
+                    let custom_type = custom_type.parse::<TokenStream>().expect("type path is checked");
+                    let encode = str_encode(var, &arg);
+                    quote_mixed! {
+                        let #var: &#custom_type = &( #value );
+                        let #var = ::fragstrings::__private::alloc::string::ToString::to_string(#var);
+                        #encode
+                    }
+                }
+                // `%d:SystemTime` is a Unix timestamp in seconds, negative if it is before the epoch
+                (FormatItemType::Int, Some(custom_type)) if is_system_time(&custom_type) => quote_mixed! {
+                    let #var: &::std::time::SystemTime = &( #value );
+                    let #var: i64 = match #var.duration_since(::std::time::UNIX_EPOCH) {
+                        ::core::result::Result::Ok(duration) => i64::saturating_add_unsigned(0, duration.as_secs()),
+                        ::core::result::Result::Err(err) => i64::saturating_sub_unsigned(0, err.duration().as_secs()),
+                    };
+                },
+                // `%d:NonZeroU64` is written as is, but it is known without being imported
+                (FormatItemType::Int, Some(custom_type)) if is_non_zero_u64(&custom_type) => quote_mixed! {
+                    let #var: &::core::num::NonZeroU64 = &( #value );
+                },
+                (_, Some(custom_type)) => {
+                    let custom_type = custom_type.parse::<TokenStream>().expect("type path is checked");
+                    quote_mixed! { let #var: &#custom_type = &( #value ); }
+                }
+                // C strings which are not UTF-8 are written as empty strings
+                (FormatItemType::Str, None) if cstring => {
+                    let encode = if encoded { Some(str_encode(var, &arg)) } else { None };
+                    quote_mixed! {
+                        let #var = &( #value );
+                        let #var: &::core::ffi::CStr = ::core::convert::AsRef::<::core::ffi::CStr>::as_ref(#var);
+                        let #var: &str = ::core::result::Result::unwrap_or(#var.to_str(), "");
+                        #encode
+                    }
+                }
+                // Colors have no characters to encode
+                (FormatItemType::Str, None) if rgba => quote_mixed! {
+                    let #var: &(u8, u8, u8, u8) = &( #value );
+                    let #var = ::fragstrings::__private::rgba_encode(*#var);
+                },
+                // Temporary values, like `String`s returned by functions, live as long as the reference
+                (FormatItemType::Str, None) if encoded => {
+                    let encode = str_encode(var, &arg);
+                    quote_mixed! {
+                        let #var = &( #value );
+                        let #var: &str = ::core::convert::AsRef::<str>::as_ref(#var);
+                        #encode
+                    }
+                }
+                (FormatItemType::Str, None) => {
+                    quote_mixed! {
+                        let #var = &( #value );
+                        let #var: &str = ::core::convert::AsRef::<str>::as_ref(#var);
+                    }
+                }
+                // Years out of the four digits can not be packed
+                (FormatItemType::Int, None) if packed_date => quote_mixed! {
+                    let #var: &::fragstrings::__private::chrono::NaiveDate = &( #value );
+                    let #var = match ::fragstrings::__private::packed_date_encode(
+                        ::fragstrings::__private::chrono::Datelike::year(#var),
+                        ::fragstrings::__private::chrono::Datelike::month(#var),
+                        ::fragstrings::__private::chrono::Datelike::day(#var),
+                    ) {
+                        ::core::option::Option::Some(#var) => #var,
+                        ::core::option::Option::None => {
+                            let value = i64::from(::fragstrings::__private::chrono::Datelike::year(#var));
+                            break 'format ::core::result::Result::Err(::fragstrings::FormatError::OutOfRange { value });
+                        }
+                    };
+                },
+                // Numbers which are too large to be scaled, and the ones which are not finite, can not be written
+                (FormatItemType::Int, None) if fixed_point.is_some() => {
+                    let scale = fixed_point.unwrap_or_default();
+                    quote_mixed! {
+                        let #var: f64 = { #value } as f64;
+                        let #var = match ::fragstrings::__private::fixed_point_encode(#var, #scale) {
+                            ::core::option::Option::Some(#var) => #var,
+                            ::core::option::Option::None => {
+                                break 'format ::core::result::Result::Err(::fragstrings::FormatError::FixedPointOutOfRange);
+                            }
+                        };
+                    }
+                }
+                // Shares which are not numbers, and out of range ones in the strict mode, can not be written
+                (FormatItemType::Int, None) if percent.is_some() => {
+                    let strict = percent.unwrap_or_default();
+                    quote_mixed! {
+                        let #var: f64 = { #value } as f64;
+                        let #var = match ::fragstrings::__private::percentage_encode(#var, #strict) {
+                            ::core::option::Option::Some(#var) => #var,
+                            ::core::option::Option::None => {
+                                break 'format ::core::result::Result::Err(::fragstrings::FormatError::FixedPointOutOfRange);
+                            }
+                        };
+                    }
+                }
+                // Sets of bit positions, like `HashSet<u8>`, are packed into integers, as long as the positions fit
+                (FormatItemType::Int, None) if bitmask => quote_mixed! {
+                    let #var = match ::fragstrings::__private::bitmask_encode(&( #value )) {
+                        ::core::result::Result::Ok(#var) => #var,
+                        ::core::result::Result::Err(pos) => {
+                            let value = i64::from(pos);
+                            break 'format ::core::result::Result::Err(::fragstrings::FormatError::OutOfRange { value });
+                        }
+                    };
+                },
+                (FormatItemType::Int, None) if roman || human_duration => {
+                    let encode = if roman {
+                        quote_mixed! { ::fragstrings::__private::roman_encode }
+                    } else {
+                        quote_mixed! { ::fragstrings::__private::duration_encode }
+                    };
+                    quote_mixed! {
+                        let #var: i64 = { #value } as i64;
+                        let #var = match #encode(#var) {
+                            ::core::option::Option::Some(#var) => #var,
+                            ::core::option::Option::None => {
+                                break 'format ::core::result::Result::Err(::fragstrings::FormatError::OutOfRange { value: #var });
+                            }
+                        };
+                    }
+                }
+                (FormatItemType::Int, None) => quote_mixed! { let #var: i64 = { #value } as i64; },
+                (FormatItemType::Base62, None) => quote_mixed! {
+                    let #var = ::fragstrings::__private::base62_encode(::core::convert::AsRef::<[u8]>::as_ref(&( #value )));
+                },
+                // Elements are joined in the sorted order, the set type guarantees it
+                (FormatItemType::StrSet, None) => {
+                    let encode = str_encode(&Ident::new("value", Span::mixed_site()), &arg);
+                    quote_mixed! {
+                        let #var: &::fragstrings::__private::alloc::collections::BTreeSet<_> = &( #value );
+                        let #var = {
+                            let mut joined = ::fragstrings::__private::alloc::string::String::new();
+                            for (i, value) in ::core::iter::Iterator::enumerate(#var.iter()) {
+                                if i > 0 {
+                                    joined.push(',');
+                                }
+                                let value: &str = ::core::convert::AsRef::<str>::as_ref(value);
+                                #encode
+                                joined.push_str(&value);
+                            }
+                            joined
+                        };
+                    }
+                }
+                // Entries are joined in the order of the keys, since the map has no order of its own
+                (FormatItemType::StrMap, None) => {
+                    let encode_key = str_encode(&Ident::new("key", Span::mixed_site()), &arg);
+                    let encode_value = str_encode(&Ident::new("value", Span::mixed_site()), &arg);
+                    let encode = quote_mixed! { #encode_key #encode_value };
+                    quote_mixed! {
+                        let #var: &::std::collections::HashMap<_, _, _> = &( #value );
+                        let #var = {
+                            let mut entries = ::core::iter::Iterator::collect::<::fragstrings::__private::alloc::vec::Vec<(&str, &str)>>(
+                                ::core::iter::Iterator::map(#var.iter(), |(key, value)| {
+                                    (::core::convert::AsRef::<str>::as_ref(key), ::core::convert::AsRef::<str>::as_ref(value))
+                                }),
+                            );
+                            entries.sort_unstable();
+                            let mut joined = ::fragstrings::__private::alloc::string::String::new();
+                            for (i, (key, value)) in ::core::iter::Iterator::enumerate(entries.into_iter()) {
+                                if i > 0 {
+                                    joined.push(',');
+                                }
+                                #encode
+                                joined.push_str(&key);
+                                joined.push('=');
+                                joined.push_str(&value);
+                            }
+                            joined
+                        };
+                    }
+                }
+                (FormatItemType::Path, None) => return Err(CompileError::PathNotFormattable.at(fmt_span)),
+            };
+            let decl = if pad_as_string {
+                quote_mixed! {
+                    #decl
+                    let #var = ::fragstrings::__private::alloc::string::ToString::to_string(&#var);
+                }
+            } else {
+                decl
+            };
+            Ok(match (item_opt, &null_sentinel) {
+                (FormatItemOpt::Optional, Some(sentinel)) => quote_mixed! {
+                    let #var = match &( #arg ) {
+                        ::core::option::Option::Some(#var) => {
+                            #decl
+                            ::fragstrings::__private::alloc::string::ToString::to_string(&#var)
+                        }
+                        ::core::option::Option::None => <::fragstrings::__private::alloc::string::String as ::core::convert::From<&str>>::from(#sentinel),
+                    };
+                },
+                _ => decl,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // In checked mode the checksum is described as one more integer item
+    let checksum_item = if checked { "%d" } else { "" };
+
+    // Version fragment goes first and is described as one more string item
+    let (version_item, version_tag) = match version {
+        Some(version) => ("%s", Some(format!("v{}", version))),
+        None => ("", None),
+    };
+
+    let descriptor = format!("{}{}{}", version_item, fmt_string, checksum_item);
+    let fmt_string = join_fragments(
+        iter::once(&*descriptor)
+            .chain(version_tag.as_deref())
+            .chain(placeholders.iter().map(String::as_str)),
+    );
+
+    if let (Some(max_bytes), Some(args_len)) = (max_bytes, literal_args_len) {
+        // Each "{}" placeholder is replaced by the argument, and the checksum is at least "__0"
+        let checksum_len = if checked { 3 } else { 0 };
+        if fmt_string.len() - 2 * n + args_len + checksum_len > max_bytes {
+            return Err(CompileError::TooLong.at(Span::call_site()));
+        }
+    }
+
+    let format = match (output, fmt_string.strip_suffix("{}")) {
+        (Output::String, Some(prefix)) if single_str => quote_mixed! { <[&str]>::concat(&[#prefix, &*#( #vars )*]) },
+        (Output::String, _) => quote_mixed! { ::fragstrings::__private::alloc::format!(#fmt_string, #( #vars ),*) },
+        (Output::Small, Some(prefix)) if single_str => quote_mixed! {
+            {
+                let mut value = <::fragstrings::small::SmallString as ::core::convert::From<&str>>::from(#prefix);
+                value.push_str(&*#( #vars )*);
+                value
+            }
+        },
+        (Output::Small, _) => quote_mixed! {
+            ::fragstrings::__private::format_small(::core::format_args!(#fmt_string, #( #vars ),*))
+        },
+    };
+
+    let res = if checked {
+        quote_mixed! {
+            {
+                #( #var_decls )*
+                let mut value = #format;
+                let checksum = ::fragstrings::__private::crc32(value.as_bytes());
+                value.push_str("__");
+                value.push_str(&::fragstrings::__private::alloc::string::ToString::to_string(&checksum));
+                value
+            }
+        }
+    } else {
+        quote_mixed! {
+            {
+                #( #var_decls )*
+                #format
+            }
+        }
+    };
+
+    let res = match max_bytes {
+        Some(max_bytes) => {
+            let max_bytes = Literal::usize_unsuffixed(max_bytes);
+            quote_mixed! {
+                {
+                    let value: #output_type = #res;
+                    if value.len() <= #max_bytes {
+                        ::core::result::Result::Ok(value)
+                    } else {
+                        ::core::result::Result::Err(::fragstrings::FormatError::TooLong {
+                            len: value.len(),
+                            max_bytes: #max_bytes,
+                        })
+                    }
+                }
+            }
+        }
+        None if fallible => {
+            quote_mixed! { ::core::result::Result::<#output_type, ::fragstrings::FormatError>::Ok(#res) }
+        }
+        None => res,
+    };
+    // Errors of the items break out of the whole formatting
+    let res = if fallible {
+        quote_mixed! { 'format: { #res } }
+    } else {
+        res
+    };
+
+    Ok(quote_mixed! { { #registration #res } })
+}
+
+/// Checks that the string can be a part of a file name both on Unix and on Windows,
+/// the same way as the `path_safe` option does at runtime.
+fn is_path_safe(s: &str) -> bool {
+    !s.bytes().any(|byte| byte < 0x20 || b"/\\<>:\"|?*".contains(&byte))
+}
+
+/// Joins the fragments with the `__` separators.
+fn join_fragments<'a>(fragments: impl Iterator<Item = &'a str>) -> String {
+    let mut res = String::new();
+    for (i, fragment) in fragments.enumerate() {
+        if i > 0 {
+            res.push_str("__");
+        }
+        res.push_str(fragment);
+    }
+    res
+}
+
+#[test]
+fn test_join_fragments() {
+    assert_eq!(join_fragments(iter::empty()), "");
+    assert_eq!(join_fragments(iter::once("%s%d")), "%s%d");
+    assert_eq!(join_fragments(["%s%d", "{}", "{}"].iter().copied()), "%s%d__{}__{}");
+    assert_eq!(join_fragments(["", ""].iter().copied()), "__");
+}
+
+/// Formatted length of the argument, if it is a literal of the item type.
+fn literal_arg_len(item: &FormatItem, arg: &TokenStream) -> Option<usize> {
+    let tokens = arg.clone().into_iter().collect::<Vec<_>>();
+    match (item, tokens.as_slice()) {
+        (
+            FormatItem {
+                ty: FormatItemType::Str,
+                custom_type: None,
+                padding: None,
+                ..
+            },
+            [TokenTree::Literal(lit)],
+        ) => parse_string_literal(&lit.to_string()).map(|value| value.len()),
+        (
+            FormatItem {
+                ty: FormatItemType::Int,
+                custom_type: None,
+                ..
+            },
+            [TokenTree::Literal(lit)],
+        ) => {
+            let value = i64::try_from(parse_int_literal(&lit.to_string())?).ok()?;
+            Some(value.to_string().len())
+        }
+        (
+            FormatItem {
+                ty: FormatItemType::Int,
+                custom_type: None,
+                ..
+            },
+            [TokenTree::Punct(minus), TokenTree::Literal(lit)],
+        ) if minus.as_char() == '-' => {
+            let value = i64::try_from(-i128::from(parse_int_literal(&lit.to_string())?)).ok()?;
+            Some(value.to_string().len())
+        }
+        _ => None,
+    }
+}
+
+#[test]
+fn test_frag_format_error_spans() {
+    /// Columns of the start and the end of the error, with its message.
+    fn error_at(args: &str) -> (usize, usize, String) {
+        let args = args.parse().expect("valid tokens");
+        let err = frag_format_impl(args, Output::String, &mut TokenStream::new()).expect_err("compile error");
+        (err.1.start().column, err.1.end().column, err.0.to_string())
+    }
+
+    // Bad format literal, at the unknown descriptor
+    let (start, end, msg) = error_at(r#"checked, "%s%x", a"#);
+    assert_eq!((start, end), (13, 14));
+    assert!(msg.starts_with("Bad format string: unknown descriptor 'x'"));
+    // Stray token
+    let (start, end, msg) = error_at(r#""%s%d", a b, 2"#);
+    assert_eq!((start, end), (10, 11));
+    assert_eq!(msg, "expected `,`");
+}
@@ -0,0 +1,817 @@
+//! DO NOT USE THIS CRATE DIRECTLY.
+//! It is an internal dependency of the main crate.
+//!
+//! Procedural macros for formatting and parsing fragmented strings.
+//!
+//! Can be used like this:
+//! ```
+//! # use fragstrings_procmacro::{frag_format, frag_parse};
+//! let value = frag_format!("%s%d", "foo", 42);
+//! assert_eq!(value, "%s%d__foo__42");
+//! let (foo, bar) = frag_parse!("%s%d", value).unwrap();
+//! assert_eq!(foo, "foo");
+//! assert_eq!(bar, 42);
+//! ```
+//!
+//! The macros are reexported in the main `fragstrings` crate:
+//! ```no_compile
+//! # // This doctest is disabled because the crate is not in scope.
+//! use fragstrings::{frag_format, frag_parse};
+//! ```
+//!
+//! The generated code refers to `core`, and to `alloc` through the main crate, which must be in scope,
+//! so that it compiles in `no_std` crates, except for the items and options which need `std`.
+
+#![forbid(unsafe_code)]
+
+#[macro_use]
+mod utils;
+mod format;
+mod parse;
+
+use proc_macro2::TokenStream;
+
+use format::{frag_format_impl, Output};
+use parse::{
+    frag_assert_disjoint_impl, frag_compat_assert_impl, frag_explain_impl, frag_parse_impl, frag_parse_os_impl,
+    Ownership,
+};
+
+/// Whether the parsing macros check descriptors in full, like `frag_parse_strict!`.
+const STRICT: bool = cfg!(feature = "strict");
+
+/// Procedural macro for formatting fragmented strings.
+///
+/// Can be used like this:
+/// ```
+/// # use fragstrings_procmacro::frag_format;
+/// let foo = "foo";
+/// let result = frag_format!("%s%s%d", foo, "bar", 42);
+/// assert_eq!(result, "%s%s%d__foo__bar__42");
+/// ```
+///
+/// The returned value is `String`.
+///
+/// A long format string can be split into adjacent string literals, normal or raw, which are concatenated,
+/// like `frag_format!("%s%s%d" "%s%s", ...)`. So it can in all the other macros.
+///
+/// Arguments of `%s62` items are bytes (anything `AsRef<[u8]>`), which are base62-encoded,
+/// so that the value contains only ASCII letters and digits.
+/// Requires the main `fragstrings` crate in scope.
+///
+/// Arguments of `%s{set}` items are `BTreeSet`s of strings, which are joined with commas in the sorted order.
+/// The elements must not contain commas themselves.
+/// Arguments of `%s{map}` items are `HashMap`s of strings, which are joined as `key=value` in the order of the keys.
+///
+/// A `%s` or `%d` item can be annotated with a custom type, like `%s:Color` or `%d:u8`,
+/// in which case the argument must be of this type and is formatted using `Display`.
+///
+/// The `%d:SystemTime` item is formatted as a Unix timestamp in seconds.
+///
+/// The `%d:NonZeroU64` item is formatted as is, but `NonZeroU64` does not need to be imported.
+///
+/// The `%s:hex16{N}` item, with `N` from 1 to 64, is `[u8; N]` written as `2 * N` lowercase hex digits.
+/// Requires the main `fragstrings` crate in scope.
+///
+/// A `%s` item can be padded with spaces to the width in chars, like `%s[pad:20]`, for fixed-column reports.
+/// The value is aligned to the left by default, or as given by `<`, `>` or `^` after the width, like `%s[pad:20^]`,
+/// same as by `format!("{:^20}", value)`, and longer values are written as is.
+///
+/// Options can be given before the format string:
+/// * `checked` - append a trailing `%d` fragment with a CRC32 of the preceding bytes,
+///   to be verified by the `frag_parse!` macro in the same mode.
+///   Requires the main `fragstrings` crate in scope.
+/// * `version = N` - prepend the version fragment `vN`, described as one more string item,
+///   to be checked by the `frag_parse!` macro with the `version` option.
+/// * `html_encode` - replace HTML special characters in string values with entities,
+///   so that the result can be embedded into HTML attributes.
+///   Requires the main `fragstrings` crate in scope.
+/// * `emoji` - replace shortcodes in string values, like `:smile:`, with emoji, before the other encoding.
+///   Unknown shortcodes are left as is. Requires the `emojis` feature of the main `fragstrings` crate.
+///   Requires the main `fragstrings` crate in scope.
+/// * `quote_spaces` - wrap string values with spaces into double quotes, like `"hello world"`, after the other
+///   encoding, escaping the quotes and backslashes in them with backslashes, e.g. for values passed through
+///   shells. So are the values which start with a quote. Requires the main `fragstrings` crate in scope.
+/// * `path_safe` - reject string values with characters which can not be in file names on Unix or on Windows,
+///   which are `/`, `\`, `<`, `>`, `:`, `"`, `|`, `?`, `*` and control characters, including NUL, after the other
+///   encoding. The returned value is `Result<String, fragstrings::FormatError>` then, which is
+///   `Err(NotPathSafe)` with the argument as written. Requires the main `fragstrings` crate in scope.
+/// * `path_safe = escape` - percent-encode such characters in string values instead, like `%2F`,
+///   and `%` itself, so that the value is always fine for file names. Requires the main `fragstrings` crate in scope.
+/// * `cstring` - take the arguments of `%s` items without custom types as C strings, anything `AsRef<CStr>`,
+///   like `CString` and `&CStr`, for values coming from C code. The ones which are not valid UTF-8
+///   are written as empty strings.
+/// * `rgba` - take the arguments of `%s` items without custom types as RGBA colors, which are `(u8, u8, u8, u8)`,
+///   and write them as `#rrggbbaa`, like `#ff880080`. Can not be used together with `cstring`.
+///   Requires the main `fragstrings` crate in scope.
+/// * `roman` - write plain `%d` items as Roman numerals, like `XIV`. The returned value is
+///   `Result<String, fragstrings::FormatError>` then, which is `Err(OutOfRange)` if the value is not
+///   in the range of 1 to 3999. Requires the main `fragstrings` crate in scope.
+/// * `human_duration` - write plain `%d` items, which are numbers of seconds, as human-readable durations,
+///   like `1h 30m`. The returned value is `Result<String, fragstrings::FormatError>` then, which is
+///   `Err(OutOfRange)` if the value is negative. Can not be used together with `roman`.
+///   Requires the main `fragstrings` crate in scope.
+/// * `packed_date` - write plain `%d` items, which are `chrono::NaiveDate`s, as eight digits of `YYYYMMDD`,
+///   like `20240115`. The returned value is `Result<String, fragstrings::FormatError>` then, which is
+///   `Err(OutOfRange)` with the year, if it is not in the range of 0 to 9999. Can not be used together
+///   with `roman` and `human_duration`. Requires the `chrono` feature of the main `fragstrings` crate.
+/// * `fixed_point = N` - write plain `%d` items, which are `f64`s, as fixed-point decimals with `N` digits
+///   after the point, from 1 to 18, like `314` for 3.14 with `fixed_point = 2`, rounding half away from zero.
+///   The returned value is `Result<String, fragstrings::FormatError>` then, which is `Err(FixedPointOutOfRange)`
+///   if the value is not finite, or too large. Can not be used together with `roman`, `human_duration`
+///   and `packed_date`. Requires the main `fragstrings` crate in scope.
+/// * `bitmask` - write plain `%d` items, which are collections of bit positions, like `HashSet<u8>`,
+///   as integers with these bits set, like `13` for 0, 2 and 3, where position 63 is the sign bit.
+///   The returned value is `Result<String, fragstrings::FormatError>` then, which is `Err(OutOfRange)`
+///   with the position, if it is not in the range of 0 to 63. Can not be used together with `roman`,
+///   `human_duration`, `packed_date` and `fixed_point`. Requires the main `fragstrings` crate in scope.
+/// * `percent` - write plain `%d` items, which are `f64` shares, as percentages with up to two decimals,
+///   like `75%` for 0.75 or `12.5%` for 0.125. Shares out of the range of 0 to 1 are clamped to it,
+///   or rejected with `percent = strict`. The returned value is `Result<String, fragstrings::FormatError>` then,
+///   which is `Err(FixedPointOutOfRange)` if the share is NaN, or rejected. Can not be used together with `roman`,
+///   `human_duration`, `packed_date`, `fixed_point` and `bitmask`. Requires the main `fragstrings` crate in scope.
+/// * `max_bytes = N` - limit the length of the value, e.g. to fit the Waves data entry limits
+///   (400 bytes for keys and 32767 bytes for string values). The returned value is
+///   `Result<String, fragstrings::FormatError>` then, and if all the arguments are literals,
+///   the length is also checked at compile time.
+///   Requires the main `fragstrings` crate in scope.
+/// * `null_sentinel = "NULL"` - allow optional items, like `%s?`, which take `Option` arguments,
+///   and write the given fragment instead of the absent value, like the legacy Scala writer does.
+/// * `max_items = N` - fail to compile if the value has more than `N` fragments (not counting
+///   the descriptor), which is useful for values consumed on-chain, because RIDE limits the size of lists.
+#[proc_macro]
+pub fn frag_format(args: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = args.into();
+    let mut dummy = quote_mixed! { ::fragstrings::__private::alloc::string::String::new() };
+    let output = match frag_format_impl(args, Output::String, &mut dummy) {
+        Ok(res) => res,
+        Err(err) => err.into_compile_error(dummy),
+    };
+    output.into()
+}
+
+/// Procedural macro for formatting fragmented strings into `fragstrings::small::SmallString`,
+/// which is stored inline, without allocation, if it is short.
+///
+/// Can be used like this:
+/// ```
+/// # use fragstrings_procmacro::frag_format_small;
+/// let result = frag_format_small!("%s%d", "foo", 42);
+/// assert_eq!(result, "%s%d__foo__42");
+/// assert!(result.is_inline());
+/// ```
+///
+/// Works the same way as the `frag_format!` macro, with the same options, except for the type of the value,
+/// which has the same contents. Requires the main `fragstrings` crate in scope, with the `small-strings` feature.
+#[proc_macro]
+pub fn frag_format_small(args: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = args.into();
+    let mut dummy = quote_mixed! { ::fragstrings::small::SmallString::new() };
+    let output = match frag_format_impl(args, Output::Small, &mut dummy) {
+        Ok(res) => res,
+        Err(err) => err.into_compile_error(dummy),
+    };
+    output.into()
+}
+
+/// Procedural macro for parsing fragmented strings.
+///
+/// Can be used like this:
+/// ```
+/// # use fragstrings_procmacro::frag_parse;
+/// let (foo, bar, baz) = frag_parse!("%s%s%d", "%s%s%d__foo__bar__42").unwrap();
+/// assert_eq!(foo, "foo");
+/// assert_eq!(bar, "bar");
+/// assert_eq!(baz, 42);
+/// ```
+///
+/// The returned value is `Option<(tuple)>`, where tuple has items which corresponds
+/// to the format descriptor.
+///
+/// Besides `%s` and `%d`, the format descriptor can contain `%P` items, which are strings
+/// in the fragmented string, but are returned as `PathBuf`.
+///
+/// Similarly, `%s62` items are base62-encoded bytes, which are decoded and returned as `Vec<u8>`.
+/// Requires the main `fragstrings` crate in scope.
+///
+/// And `%s{set}` items are comma-separated strings, which are returned as `BTreeSet<String>`.
+/// The empty fragment is the empty set.
+/// Likewise, `%s{map}` items are comma-separated `key=value` entries, which are returned as `HashMap<String, String>`,
+/// and an entry without `=` is not matched.
+///
+/// A `%s` or `%d` item can be annotated with a custom type, like `%s:Color` or `%d:u8`,
+/// in which case the value is parsed into this type using `FromStr`, and the whole value
+/// does not match if it fails.
+///
+/// The `%d:SystemTime` item is parsed from a Unix timestamp in seconds, which must not be negative.
+///
+/// The `%d:NonZeroU64` item is parsed into `core::num::NonZeroU64`, which does not need to be imported,
+/// and zero and negative integers do not match, e.g. for ids, counts and capacities.
+///
+/// The `%s:hex16{N}` item, with `N` from 1 to 64, is parsed from exactly `2 * N` hex digits, in any case,
+/// into `[u8; N]`, without allocation. Requires the main `fragstrings` crate in scope.
+///
+/// The `%s:str` item is special, it is returned as `&str` borrowed from the input,
+/// like in the `frag_parse_ref!` macro, so it can not be used together with `html_decode`, `unquote` and `decode_path`.
+/// So is the `%s:words` item, which is split on whitespace and returned as `Vec<&str>` of the words.
+///
+/// A `%s` item can be limited in length, like `%s[..64]` or `%s[1..64]`, in chars, or like `%s[bytes:..64]`
+/// in bytes, with inclusive bounds. The limit is checked for the fragment as it is stored,
+/// and the whole value does not match if it fails. It goes after the custom type, like `%s:str[..64]`.
+///
+/// A `%s` item can also be matched against a regex, like `%s[re=r'^[A-Z]{2,3}$']`, which goes after the length
+/// limit, if any. The regex must match the whole fragment as it is stored, otherwise the whole value does not match.
+/// It is parsed at compile time, and only a subset of the syntax is supported, see the README.
+///
+/// A `%s` item can be limited to one of the given values, like `%s{trie:buy,sell}`, which goes before
+/// the custom type, if any. The fragment as it is stored must be equal to one of them, otherwise the whole value
+/// does not match. Many values are matched by a trie of nested `match`es on the bytes, see `trie_threshold`.
+///
+/// A `%s` item can be asserted to be a constant, like `%s[assert=v1]`, in place of the length limit,
+/// which is the same as filtering the parsed values, but documents the constraint in the format string itself.
+/// The fragment as it is stored must be equal to the constant, otherwise the whole value does not match,
+/// and the item is still returned, so it is always the constant, or its custom type made of it.
+///
+/// A `%s` item can take only a part of the fragment, given by the byte range like `%s[slice:2..8]`,
+/// `%s[slice:2..]` or `%s[slice:..8]`, with the exclusive end, in place of the length limit. The range is taken
+/// from the fragment as it is stored, and the regex and the allowed values are checked for that part.
+/// If the range is out of bounds or not on char boundaries, the whole value does not match.
+///
+/// A `%s` item padded by `frag_format!`, like `%s[pad:20]`, has the padding spaces trimmed on the same side
+/// before anything else, so spaces on that side of the values themselves are trimmed too.
+///
+/// The value which is a string literal, or a nested `frag_format!` with a literal format string,
+/// fails to compile if its descriptor never matches the format string. A string literal is also parsed
+/// at compile time, unless there are options or items with custom types other than `%s:str`.
+///
+/// Options can be given before the format string:
+/// * `checked` - verify and strip the trailing CRC32 fragment appended by the `frag_format!` macro
+///   in the same mode. The returned value is `Result<(tuple), fragstrings::ParseError>` then.
+///   Requires the main `fragstrings` crate in scope.
+/// * `alloc = allocator` - place string values into the `allocator`, which must implement
+///   the `fragstrings::StringAllocator` trait, instead of allocating `String`s.
+///   String items are returned as `&str` borrowed from the allocator then.
+///   Requires the main `fragstrings` crate in scope.
+/// * `intern = &interner` - deduplicate string values through the `fragstrings::intern::FragInterner`,
+///   e.g. for ids which repeat across many values. String items are returned as `Arc<str>` shared
+///   with the interner then. Can not be used together with `alloc`. Requires the `intern` feature
+///   of the main `fragstrings` crate.
+/// * `version = N` - require the version fragment `vN` written by the `frag_format!` macro
+///   with the same option.
+/// * `version = [N, M, ...]` - accept any of the listed versions, where 0 stands for legacy
+///   records without the version fragment. The matched version is returned as the first item
+///   of the tuple, as `u32`.
+/// * `union_sep` - also accept legacy values with `-` separators, which are parsed if the value does not match
+///   with `__` ones. The result is `Option<(&'static str, T)>`, with the separator which matched before the values.
+///   Fragments of such values can not contain `-`, so negative numbers are not read from them.
+///   Not compatible with `checked`.
+/// * `html_decode` - decode HTML entities in string values, like `&amp;` or `&#39;`.
+///   Requires the main `fragstrings` crate in scope.
+/// * `unquote` - strip the double quotes around string values, and the backslashes escaping the characters in them,
+///   which are written by the `frag_format!` macro with the `quote_spaces` option, before the other decoding.
+///   The values which are not quoted are left as is. Requires the main `fragstrings` crate in scope.
+/// * `decode_path` - decode percent-encoded bytes in string values, like `%2F` or `%20`,
+///   for values used as URL path segments. Malformed escapes are left as is. With `html_decode`
+///   percent-encoded bytes are decoded first. Requires the main `fragstrings` crate in scope.
+/// * `path_safe` - reject string values with characters which can not be in file names, like the `frag_format!`
+///   macro with the same option does, after decoding. Requires the main `fragstrings` crate in scope.
+/// * `path_safe = escape` - decode the values written by the `frag_format!` macro with the same option,
+///   which is the same as `decode_path`.
+/// * `demoji` - replace emoji in string values with their shortcodes, like `:smile:`, after the other decoding.
+///   Emoji without shortcodes are left as is. Requires the `emojis` feature of the main `fragstrings` crate.
+///   Requires the main `fragstrings` crate in scope.
+/// * `hostname` - require string values to be valid DNS hostnames, like `node-1.example.com`.
+///   Requires the main `fragstrings` crate in scope.
+/// * `ident` - require string values to be valid Rust identifiers, which are not keywords, like `foo_bar`.
+///   Requires the main `fragstrings` crate in scope.
+/// * `valid_json` - require string values to be valid JSON of any type, like `{"a": [1, 2]}`,
+///   which are still returned as strings. Requires the main `fragstrings` crate in scope.
+/// * `redact` - return string values, including ones with custom types, wrapped into
+///   `fragstrings::redact::Redacted`, which hides them from `Debug`, e.g. for passwords and tokens.
+///   Requires the main `fragstrings` crate in scope.
+/// * `secret` - return `%s` items without custom types as `secrecy::SecretString`, which is zeroized on drop.
+///   Requires the `secrecy` feature of the main `fragstrings` crate. Can not be used together with `alloc`, `lazy`
+///   and `redact`, nor with `frag_parse_ref!`, `frag_parse_arc!` and `frag_parse_bytes!`.
+/// * `cstring` - return `%s` items without custom types as `CString`s, for values passed to C code.
+///   The values with nul bytes do not match, because they can not be C strings. Can not be used together
+///   with `alloc`, `lazy`, `redact` and `secret`, nor with `frag_parse_ref!`, `frag_parse_arc!` and `frag_parse_bytes!`.
+///   Requires the main `fragstrings` crate in scope.
+/// * `rgba` - return `%s` items without custom types as RGBA colors, which are `(u8, u8, u8, u8)`, parsed from
+///   `#rrggbb` or `#rrggbbaa` in any case, like `#FF8800`, where the alpha is 255 if it is missing.
+///   The values which are not colors do not match. Can not be used together with `alloc`, `lazy`, `redact`,
+///   `secret` and `cstring`, nor with `frag_parse_ref!`, `frag_parse_arc!` and `frag_parse_bytes!`.
+///   Requires the main `fragstrings` crate in scope.
+/// * `lazy` - return `%s` items without custom types as `fragstrings::lazy::LazyStr`, borrowed from the input,
+///   which is copied only when `get()` is called, so that nothing is allocated for the unused values.
+///   Can not be used together with `alloc`, `html_decode`, `unquote`, `decode_path` and `demoji`.
+///   Requires the main `fragstrings` crate in scope.
+/// * `roman` - parse plain `%d` items from Roman numerals in the canonical form, like `XIV`,
+///   written by the `frag_format!` macro with the same option. Requires the main `fragstrings` crate in scope.
+/// * `human_duration` - parse plain `%d` items from human-readable durations into numbers of seconds,
+///   like `1h30m` or `1h 30m`, with the units of `h`, `m` and `s` in this order.
+///   Can not be used together with `roman`. Requires the main `fragstrings` crate in scope.
+/// * `packed_date` - parse plain `%d` items of eight digits of `YYYYMMDD`, like `20240115`, into `chrono::NaiveDate`s,
+///   which must be valid dates. Can not be used together with `roman` and `human_duration`.
+///   Requires the `chrono` feature of the main `fragstrings` crate.
+/// * `radix = auto` - parse plain `%d` items with the `0x` or `0X` prefix as hexadecimal, and with the `0b` or `0B`
+///   prefix as binary, after the optional minus sign, like `0x1a2b` or `-0b101`, for values written by legacy tools.
+///   Items without prefixes are decimal as usual, and so are `%d` items with custom types.
+///   Can not be used together with `roman`, `human_duration`, `packed_date`, `fixed_point` and `percent`.
+///   Requires the main `fragstrings` crate in scope.
+/// * `fixed_point = N` - parse plain `%d` items as fixed-point decimals with `N` digits after the point, from 1 to 18,
+///   into `f64`s, like 3.14 from `314` with `fixed_point = 2`. Can not be used together with `roman`,
+///   `human_duration` and `packed_date`. Requires the main `fragstrings` crate in scope.
+/// * `bitmask` - parse plain `%d` items as bitmasks into `std::collections::HashSet<u8>`s of the positions
+///   of the set bits, from 0 to 63, like 0, 2 and 3 from `13`. Can be used together with `radix = auto`,
+///   but not with `roman`, `human_duration`, `packed_date` and `fixed_point`.
+///   Requires the main `fragstrings` crate in scope.
+/// * `percent` - parse plain `%d` items as percentages with the trailing `%` sign into `f64` shares,
+///   like 0.75 from `75%` or 0.125 from `12.5%`. Shares out of the range of 0 to 1 are clamped to it,
+///   or make the whole parse fail with `percent = strict`. Can not be used together with `roman`, `human_duration`,
+///   `packed_date`, `fixed_point` and `bitmask`. Requires the main `fragstrings` crate in scope.
+/// * `prime` - require integer values to be prime numbers, e.g. for key identifiers.
+///   Requires the main `fragstrings` crate in scope.
+/// * `count = &CALLS` - increment the given `AtomicU64` on every call, e.g. to monitor hot paths
+///   without locks.
+/// * `count_failures = &FAILURES` - increment the given `AtomicU64` on every call which does not return a value.
+/// * `verbose` - print the format string and the input to `stderr` on every call which does not return a value,
+///   for debugging. It is compiled out of release builds, i.e. without `debug_assertions`.
+/// * `audit = &AUDIT_LOG` - call `record()` of the given `fragstrings::AuditLogger` after every call
+///   with the format string as written, the input, and whether a value was returned, e.g. for compliance logging.
+///   Unlike `verbose`, it is not compiled out of release builds. Requires the main `fragstrings` crate in scope.
+/// * `on_fail = |fmt, input| ...` - call the given closure or function with the format string as written
+///   and the input on every call which does not return a value, e.g. to log the failure, update metrics, or panic.
+///   The value is still not returned then.
+/// * `trace` - emit a `tracing::debug!` event on every call which does not return a value, with the format string
+///   as written, the reason of the failure, and the input with the values hidden. It only works with the `tracing`
+///   feature of the main `fragstrings` crate, and is compiled out without it.
+/// * `null_sentinel = "NULL"` - treat the given fragment as the absent value, like the legacy Scala
+///   writer does: optional items are `None` then, and mandatory items do not match.
+/// * `trie_threshold = N` - match the values of `%s{trie:...}` items by the trie if there are at least `N` of them,
+///   and compare them one by one otherwise, which is faster for few values. The default is 16.
+/// * `max_fields = N` - fail to compile if the format string has more than `N` items, because such large tuples
+///   are likely mistakes, and slow down the compilation. The default is 16.
+/// * `max_items = N` - fail to compile if the value can have more than `N` fragments (not counting
+///   the descriptor), which is useful for values consumed on-chain, because RIDE limits the size of lists.
+///   Open-ended format strings are considered to have unbounded number of fragments.
+///
+/// The descriptor of the value is only checked to start with the mandatory items for format strings
+/// with optional items and open-ended ones, unless the `strict` feature of the main crate is enabled,
+/// in which case it is checked the same way as by the `frag_parse_strict!` macro.
+#[proc_macro]
+pub fn frag_parse(args: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = args.into();
+    let mut dummy = quote_mixed! { ::core::option::Option::None };
+    let output = match frag_parse_impl(args, Ownership::Owned, STRICT, false, &mut dummy) {
+        Ok(res) => res,
+        Err(err) => err.into_compile_error(dummy),
+    };
+    output.into()
+}
+
+/// Procedural macro for parsing fragmented strings with the full check of the descriptor.
+///
+/// Can be used like this:
+/// ```
+/// # use fragstrings_procmacro::frag_parse_strict;
+/// let parsed = frag_parse_strict!("%s%d?", "%s%d__foo__42");
+/// assert_eq!(parsed, Some(("foo".to_string(), Some(42))));
+/// // The descriptor has more items than the value
+/// assert_eq!(frag_parse_strict!("%s%d?", "%s%d__foo"), None);
+/// ```
+///
+/// Works the same way as the `frag_parse!` macro, with the same options, except that the descriptor of the value
+/// is checked item by item for format strings with optional items and open-ended ones: the items after
+/// the mandatory ones must be the optional ones of the same types, or any items of open-ended format strings,
+/// and the number of the items must be the number of the fragments. So the value either does not match,
+/// or it is parsed into the same result as by `frag_parse!`.
+#[proc_macro]
+pub fn frag_parse_strict(args: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = args.into();
+    let mut dummy = quote_mixed! { ::core::option::Option::None };
+    let output = match frag_parse_impl(args, Ownership::Owned, true, false, &mut dummy) {
+        Ok(res) => res,
+        Err(err) => err.into_compile_error(dummy),
+    };
+    output.into()
+}
+
+/// Procedural macro for parsing as much as possible of damaged fragmented strings, item by item.
+///
+/// Can be used like this:
+/// ```
+/// # use fragstrings_procmacro::frag_parse_partial;
+/// # use fragstrings::FieldError;
+/// let (foo, bar, baz) = frag_parse_partial!("%s%d%d", "%s%d%d__foo__4x2").unwrap();
+/// assert_eq!(foo, Ok("foo".to_string()));
+/// assert_eq!(bar, Err(FieldError::Malformed));
+/// assert_eq!(baz, Err(FieldError::Missing));
+/// ```
+///
+/// Works the same way as the `frag_parse!` macro, except that every item is returned as
+/// `Result<T, fragstrings::FieldError>`, which is `Err(Missing)` if there is no fragment for the item,
+/// and `Err(Malformed)` if the fragment can not be parsed, while optional items are `Ok(None)` if they are missing.
+/// The value still does not match, if its descriptor does not, but extra fragments are ignored,
+/// e.g. for tools which repair the data. Options `checked`, `version`, `count_failures`, `verbose`, `audit`,
+/// `on_fail` and `trace` are not supported, because there are no failures of the whole value to report.
+/// Requires the main `fragstrings` crate in scope.
+#[proc_macro]
+pub fn frag_parse_partial(args: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = args.into();
+    let mut dummy = quote_mixed! { ::core::option::Option::None };
+    let output = match frag_parse_impl(args, Ownership::Owned, false, true, &mut dummy) {
+        Ok(res) => res,
+        Err(err) => err.into_compile_error(dummy),
+    };
+    output.into()
+}
+
+/// Procedural macro for parsing fragmented strings without copying.
+///
+/// Can be used like this:
+/// ```
+/// # use fragstrings_procmacro::frag_parse_ref;
+/// # use std::path::Path;
+/// let input = "%s%s%d__foo__/tmp/bar__42".to_string();
+/// let (foo, bar, baz) = frag_parse_ref!("%s%P%d", input).unwrap();
+/// assert_eq!(foo, "foo");
+/// assert_eq!(bar, Path::new("/tmp/bar"));
+/// assert_eq!(baz, 42);
+/// ```
+///
+/// Works the same way as the `frag_parse!` macro, except that string items are returned as `&str`,
+/// and path items as `&Path`, borrowed from the input, so the input must outlive the returned values.
+/// Options `alloc`, `html_decode`, `unquote`, `decode_path` and `demoji` are not supported, because they need to copy values.
+#[proc_macro]
+pub fn frag_parse_ref(args: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = args.into();
+    let mut dummy = quote_mixed! { ::core::option::Option::None };
+    let output = match frag_parse_impl(args, Ownership::Borrowed, STRICT, false, &mut dummy) {
+        Ok(res) => res,
+        Err(err) => err.into_compile_error(dummy),
+    };
+    output.into()
+}
+
+/// Procedural macro for parsing fragmented strings into shared strings.
+///
+/// Can be used like this:
+/// ```
+/// # use fragstrings_procmacro::frag_parse_arc;
+/// # use std::sync::Arc;
+/// let (foo, bar) = frag_parse_arc!("%s%d", "%s%d__foo__42").unwrap();
+/// let cached = Arc::clone(&foo);
+/// assert_eq!(*foo, "foo");
+/// assert_eq!(bar, 42);
+/// ```
+///
+/// Works the same way as the `frag_parse!` macro, except that string items are returned as `Arc<String>`,
+/// and path items as `Arc<PathBuf>`, which are cheap to clone.
+/// Option `alloc` is not supported.
+#[proc_macro]
+pub fn frag_parse_arc(args: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = args.into();
+    let mut dummy = quote_mixed! { ::core::option::Option::None };
+    let output = match frag_parse_impl(args, Ownership::Shared, STRICT, false, &mut dummy) {
+        Ok(res) => res,
+        Err(err) => err.into_compile_error(dummy),
+    };
+    output.into()
+}
+
+/// Procedural macro for parsing fragmented strings stored in `bytes::Bytes` without copying.
+///
+/// Can be used like this:
+/// ```ignore
+/// # use fragstrings_procmacro::frag_parse_bytes;
+/// let input = bytes::Bytes::from("%s%d__foo__42");
+/// let (foo, bar) = frag_parse_bytes!("%s%d", input).unwrap();
+/// assert_eq!(foo, "foo"); // `foo` is `bytes::Bytes` sharing the buffer with the `input`
+/// assert_eq!(bar, 42);
+/// ```
+///
+/// Works the same way as the `frag_parse!` macro, except that the input is `bytes::Bytes`,
+/// which must be valid UTF-8, or the value does not match right away, without the callbacks, like `on_fail`,
+/// and string items are returned as `bytes::Bytes`
+/// made by `Bytes::slice()` of the input. The type is not named by the generated code, so any type
+/// with the same `slice()` method, which dereferences to `[u8]`, can be parsed the same way.
+/// Options `alloc`, `html_decode`, `unquote`, `decode_path`, `demoji` and `lazy` are not supported.
+#[proc_macro]
+pub fn frag_parse_bytes(args: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = args.into();
+    let mut dummy = quote_mixed! { ::core::option::Option::None };
+    let output = match frag_parse_impl(args, Ownership::Sliced, STRICT, false, &mut dummy) {
+        Ok(res) => res,
+        Err(err) => err.into_compile_error(dummy),
+    };
+    output.into()
+}
+
+/// Procedural macro for parsing fragmented strings stored in file names, or anything else `AsRef<OsStr>`.
+///
+/// Can be used like this:
+/// ```
+/// # use fragstrings_procmacro::frag_parse_os;
+/// # use std::path::Path;
+/// let path = Path::new("/var/cache/%s%d__foo__42.bin");
+/// let (foo, bar) = frag_parse_os!("%s%d", path.file_stem().unwrap()).unwrap();
+/// assert_eq!(foo, "foo");
+/// assert_eq!(bar, 42);
+/// ```
+///
+/// Works the same way as the `frag_parse!` macro, with the same options, except that the input is `OsStr`,
+/// `Path`, `OsString` or `PathBuf`, which must be valid UTF-8, or the value does not match, unlike
+/// `to_string_lossy()`, which would parse the replacement characters into the values.
+#[proc_macro]
+pub fn frag_parse_os(args: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = args.into();
+    let mut dummy = quote_mixed! { ::core::option::Option::None };
+    let output = match frag_parse_os_impl(args, false, STRICT, &mut dummy) {
+        Ok(res) => res,
+        Err(err) => err.into_compile_error(dummy),
+    };
+    output.into()
+}
+
+/// Procedural macro for parsing fragmented strings stored in file names of paths.
+///
+/// Can be used like this:
+/// ```
+/// # use fragstrings_procmacro::frag_parse_file_name;
+/// # use std::path::PathBuf;
+/// let path = PathBuf::from("/var/cache/%s%d__foo__42");
+/// let (foo, bar) = frag_parse_file_name!("%s%d", path).unwrap();
+/// assert_eq!(foo, "foo");
+/// assert_eq!(bar, 42);
+/// ```
+///
+/// Works the same way as the `frag_parse_os!` macro, except that the input is anything `AsRef<Path>`,
+/// and only its `file_name()` is parsed, so paths without file names, like `/` or `..`, do not match.
+#[proc_macro]
+pub fn frag_parse_file_name(args: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = args.into();
+    let mut dummy = quote_mixed! { ::core::option::Option::None };
+    let output = match frag_parse_os_impl(args, true, STRICT, &mut dummy) {
+        Ok(res) => res,
+        Err(err) => err.into_compile_error(dummy),
+    };
+    output.into()
+}
+
+/// Procedural macro for asserting at compile time that format strings never match the same value,
+/// e.g. when values of several formats are stored under the same keys and are told apart by parsing.
+///
+/// Can be used like this:
+/// ```
+/// # use fragstrings_procmacro::frag_assert_disjoint;
+/// frag_assert_disjoint!("%s%d", "%d%s", "%s%d%s%d?");
+/// ```
+///
+/// Fails to compile, if some value can be parsed by two of the format strings, like `%s%d` and `%s%d%s?`,
+/// which both match the `%s%d__foo__42` value, or `%s%d` and `%s*`. The values are compared by descriptors
+/// the same way as the `frag_parse!` macro does it, so that formats with optional items or open-ended ones
+/// match every value which starts with the descriptors of their mandatory items.
+/// Can be used both as an item and as a statement.
+#[proc_macro]
+pub fn frag_assert_disjoint(args: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let output = match frag_assert_disjoint_impl(args.into()) {
+        Ok(()) => TokenStream::new(),
+        Err(err) => {
+            let error = err.into_compile_error(quote_mixed! { () });
+            quote_mixed! { const _: () = #error; }
+        }
+    };
+    output.into()
+}
+
+/// Procedural macro for checking at compile time that a changed format string is compatible with the old one,
+/// so that the values and the readers of the old format string keep working after the change.
+///
+/// Can be used like this:
+/// ```
+/// # use fragstrings_procmacro::frag_compat_assert;
+/// frag_compat_assert!(old = "%s%d", new = "%s%d%s?");
+/// frag_compat_assert!(old = "%s%d*", new = "%s%d%s*");
+/// ```
+///
+/// Fails to compile, if the new format string is not a compatible extension of the old one, with the first
+/// incompatibility in the error message. The items of the old format string must be kept in the same order
+/// with the same types, like `%d` or `%d:u8`, and the same optionality, and the added items must be optional,
+/// unless the old format string is open-ended, so its readers skip them, in which case the new one must be
+/// open-ended too. Other annotations of the items, like length limits, are not compared.
+/// Can be used both as an item and as a statement.
+#[proc_macro]
+pub fn frag_compat_assert(args: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let output = match frag_compat_assert_impl(args.into()) {
+        Ok(()) => TokenStream::new(),
+        Err(err) => {
+            let error = err.into_compile_error(quote_mixed! { () });
+            quote_mixed! { const _: () = #error; }
+        }
+    };
+    output.into()
+}
+
+/// Procedural macro for describing the format string in plain words, e.g. for `--help` of tools
+/// and their error messages.
+///
+/// Can be used like this:
+/// ```
+/// # use fragstrings_procmacro::frag_explain;
+/// const HELP: &str = frag_explain!("%s%s%d?*");
+/// assert_eq!(HELP, "string, string, optional integer, followed by any extra fragments");
+/// ```
+///
+/// Expands to a `&'static str` literal, so it can be used in constants and with `concat!`.
+/// The format string is checked the same way as by the `frag_parse!` macro, and custom types,
+/// length limits, byte ranges, known values and regexes of the items are described as well.
+#[proc_macro]
+pub fn frag_explain(args: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let output = match frag_explain_impl(args.into()) {
+        Ok(res) => res,
+        Err(err) => err.into_compile_error(quote_mixed! { "" }),
+    };
+    output.into()
+}
+
+/// Expansions must not have panicking calls and indexing, which is checked by the tokens here
+/// for all the forms, and is proven by the linker for the ones, which do not allocate, in `no-panic-tests`.
+#[test]
+fn test_expansions_do_not_panic() {
+    use proc_macro2::{Delimiter, TokenTree};
+    use quote::quote;
+
+    fn check(tokens: TokenStream) {
+        let mut prev = None;
+        for token in tokens {
+            match &token {
+                TokenTree::Ident(ident) => {
+                    let name = ident.to_string();
+                    assert!(
+                        ![
+                            "panic",
+                            "unwrap",
+                            "expect",
+                            "unreachable",
+                            "assert",
+                            "assert_eq",
+                            "todo",
+                            "unimplemented",
+                            "unsafe"
+                        ]
+                        .contains(&name.as_str()),
+                        "{} is called",
+                        name
+                    );
+                }
+                TokenTree::Group(group) => {
+                    // Full range can not panic, it is used to make slices of byte string literals
+                    let indexed = matches!(
+                        prev,
+                        Some(TokenTree::Ident(_) | TokenTree::Literal(_) | TokenTree::Group(_))
+                    );
+                    let full_range = group.stream().to_string() == "..";
+                    assert!(
+                        group.delimiter() != Delimiter::Bracket || !indexed || full_range,
+                        "{} is indexed",
+                        group
+                    );
+                    check(group.stream());
+                }
+                _ => {}
+            }
+            prev = Some(token);
+        }
+    }
+
+    let formats = [
+        quote! { "%s%d%s62%s{set}%d:SystemTime%s:u8%s:hex16{8}", a, b, c, d, e, f, g },
+        quote! { checked, version = 2, html_encode, max_items = 10, "%s%d:roman", a, b },
+        quote! { max_bytes = 100, null_sentinel = "NULL", "%s%d:human_duration?", a, b },
+        quote! { html_encode, quote_spaces, "%s%s:Color%s{set}%s{map}", a, b, c, d },
+        #[cfg(feature = "emojis")]
+        quote! { emoji, html_encode, quote_spaces, "%s%s:Color%s{set}%s{map}", a, b, c, d },
+        quote! { fixed_point = 2, max_bytes = 100, "%d%s%d:u8", a, b, c },
+        quote! { percent = strict, "%d%s%d:u8", a, b, c },
+        quote! { cstring, html_encode, "%s%s:u8%s", a, b, c },
+        quote! { rgba, null_sentinel = "NULL", "%s%s:u8%s?", a, b, c },
+        quote! { bitmask, null_sentinel = "NULL", "%d%s%d?", a, b, c },
+        quote! { path_safe, html_encode, null_sentinel = "NULL", "%s%s:Color%s{set}%s{map}?", a, b, c, d },
+        quote! { path_safe = escape, "%s%s:Color%s{set}%s{map}", a, b, c, d },
+        quote! { null_sentinel = "NULL", "%s[pad:8]%s:Color[pad:4^]%s[pad:2>]?", a, b, c },
+    ];
+    for args in formats.iter() {
+        for &output in [Output::String, Output::Small].iter() {
+            check(frag_format_impl(args.clone(), output, &mut TokenStream::new()).expect("valid"));
+        }
+    }
+
+    // The other expansions fail: decoded items can not be borrowed, and partial parsing has no options for the whole value
+    const ALL: &[Ownership] = &[
+        Ownership::Owned,
+        Ownership::Borrowed,
+        Ownership::Shared,
+        Ownership::Sliced,
+    ];
+    const OWNED_SHARED: &[Ownership] = &[Ownership::Owned, Ownership::Shared];
+    const OWNED: &[Ownership] = &[Ownership::Owned];
+    let parses = [
+        (quote! { "%s%d%P%d:u8?*", input }, ALL, true),
+        (quote! { "%s", input }, ALL, true),
+        (
+            quote! { "%s62%s{set}%d:SystemTime%d:roman%d:human_duration%s:hex16{8}?", input },
+            ALL,
+            true,
+        ),
+        (quote! { "%d:NonZeroU64%d:NonZeroU64?", input }, ALL, true),
+        (
+            quote! { r"%s[re=r'^[A-Z]{2,3}$']%s[..8]%s{trie:foo,bar}", input },
+            ALL,
+            true,
+        ),
+        (
+            quote! { trie_threshold = 1, max_fields = 1, "%s{trie:foo,bar,baz}*", input },
+            ALL,
+            true,
+        ),
+        (
+            quote! { null_sentinel = "NULL", "%s[slice:2..8]%s[slice:1..]?", input },
+            ALL,
+            true,
+        ),
+        (
+            quote! { null_sentinel = "NULL", "%s[pad:8]%s:Color[pad:4^]%s[pad:2>]?", input },
+            ALL,
+            true,
+        ),
+        (
+            quote! { html_decode, hostname, ident, prime, "%s%d", input },
+            OWNED_SHARED,
+            true,
+        ),
+        (quote! { radix = auto, prime, "%d%d:u8?", input }, ALL, true),
+        (quote! { fixed_point = 18, prime, "%d%s%d:u8?", input }, ALL, true),
+        (quote! { percent, "%d%s%d:u8?", input }, ALL, true),
+        (quote! { bitmask, radix = auto, "%d%s%d:u8?", input }, ALL, true),
+        (quote! { valid_json, "%s", input }, ALL, true),
+        (quote! { cstring, html_decode, "%s%s:u8%s?", input }, OWNED, true),
+        (
+            quote! { rgba, html_decode, null_sentinel = "NULL", "%s%s:u8%s?*", input },
+            OWNED,
+            true,
+        ),
+        (
+            quote! { path_safe, path_safe = escape, "%s%s:Color%s{set}%s{map}", input },
+            OWNED_SHARED,
+            true,
+        ),
+        (
+            quote! { html_decode, decode_path, unquote, "%s%s:Color%s{set}%s{map}", input },
+            OWNED_SHARED,
+            true,
+        ),
+        #[cfg(feature = "emojis")]
+        (
+            quote! { demoji, html_decode, decode_path, unquote, "%s%s:Color%s{set}%s{map}", input },
+            OWNED_SHARED,
+            true,
+        ),
+        (
+            quote! { null_sentinel = "NULL", version = [0, 2], "%s%d?", input },
+            ALL,
+            false,
+        ),
+        (quote! { checked, version = 2, "%s", input }, ALL, false),
+        (quote! { union_sep, version = [0, 2], "%s%d?", input }, ALL, false),
+        (quote! { union_sep, trace, "%s", input }, ALL, false),
+        (
+            quote! { count = &calls, count_failures = &calls, audit = &log, trace, "%s%d*", input },
+            ALL,
+            false,
+        ),
+        (
+            quote! { on_fail = |fmt, input| fail(fmt, input), checked, "%s%d?", input },
+            ALL,
+            false,
+        ),
+        (quote! { verbose, redact, "%s%d%P%d:u8?*", input }, ALL, false),
+        (quote! { lazy, "%s%d%P%d:u8?*", input }, OWNED, true),
+        (quote! { alloc = allocator, "%s%d*", input }, OWNED, true),
+    ];
+    for (args, ownerships, partial_allowed) in parses.iter() {
+        for &ownership in ALL.iter() {
+            for &(strict, partial) in [(false, false), (true, false), (false, true)].iter() {
+                let res = frag_parse_impl(args.clone(), ownership, strict, partial, &mut TokenStream::new());
+                if ownerships.contains(&ownership) && (*partial_allowed || !partial) {
+                    check(res.unwrap_or_else(|_| panic!("failed to expand {}", args)));
+                } else {
+                    assert!(res.is_err(), "expanded {}", args);
+                }
+            }
+        }
+        for &file_name in [false, true].iter() {
+            let res = frag_parse_os_impl(args.clone(), file_name, false, &mut TokenStream::new());
+            check(res.unwrap_or_else(|_| panic!("failed to expand {}", args)));
+        }
+    }
+}
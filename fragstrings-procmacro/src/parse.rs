@@ -0,0 +1,1762 @@
+//! Code generation of the parsing macros.
+
+use proc_macro2::{Literal, Span, TokenStream, TokenTree};
+use quote::format_ident;
+
+use crate::utils::{
+    errors::{CompileError, SpannedError},
+    fmt_strings::{
+        hex_width, is_non_zero_u64, is_system_time, parse_format_string_ex, Align, ByteRange, FormatEnding, FormatItem,
+        FormatItemOpt, FormatItemType, FormatString, LengthLimit, LengthUnit, Padding,
+    },
+    literals::{literal_kind, parse_string_literal, parse_string_literals, LiteralKind},
+    options::{parse_ident_value, parse_int_value, parse_list_value, parse_options, parse_str_value},
+    punct::{parse_punctuated_args, stream_span},
+    regex::{compile, parse_regex, Inst},
+    registry::pattern_registration,
+};
+
+pub(crate) fn frag_assert_disjoint_impl(args: TokenStream) -> Result<(), SpannedError> {
+    let args = parse_punctuated_args(args)?;
+    if args.is_empty() {
+        return Err(CompileError::NoArgs.at(Span::call_site()));
+    }
+    let formats = args
+        .into_iter()
+        .map(|arg| parse_format_literal(Some(arg)))
+        .collect::<Result<Vec<_>, _>>()?;
+    for (i, (second, span, second_parsed)) in formats.iter().enumerate() {
+        for (first, _, first_parsed) in &formats[..i] {
+            if let Some(example) = common_descriptor(first_parsed, second_parsed) {
+                let error = CompileError::AmbiguousPatterns {
+                    first: first.clone(),
+                    second: second.clone(),
+                    example,
+                };
+                return Err(error.at(*span));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the new format string is a compatible extension of the old one, given as `old = "..."`
+/// and `new = "..."`, and reports the first incompatibility at the new one otherwise.
+pub(crate) fn frag_compat_assert_impl(args: TokenStream) -> Result<(), SpannedError> {
+    let args = parse_punctuated_args(args)?;
+    if args.is_empty() {
+        return Err(CompileError::NoArgs.at(Span::call_site()));
+    }
+    let (options, rest) = parse_options(args).map_err(|span| CompileError::BadOption.at(span))?;
+    if let Some(arg) = rest.first() {
+        return Err(CompileError::UnrecognizedToken.at(stream_span(arg)));
+    }
+    let (mut old, mut new) = (None, None);
+    for option in options {
+        let bad_option = CompileError::BadOption.at(option.span);
+        match (option.name.as_str(), option.value) {
+            ("old", Some(value)) if old.is_none() => old = Some(parse_format_literal(Some(value))?),
+            ("new", Some(value)) if new.is_none() => new = Some(parse_format_literal(Some(value))?),
+            _ => return Err(bad_option),
+        }
+    }
+    let ((old, _, old_parsed), (new, span, new_parsed)) = match (old, new) {
+        (Some(old), Some(new)) => (old, new),
+        _ => return Err(CompileError::BadOption.at(Span::call_site())),
+    };
+    match first_incompatibility(&old_parsed, &new_parsed) {
+        Some(reason) => Err(CompileError::IncompatiblePatterns { old, new, reason }.at(span)),
+        None => Ok(()),
+    }
+}
+
+/// Generates the description of the format string, which is a string literal.
+pub(crate) fn frag_explain_impl(args: TokenStream) -> Result<TokenStream, SpannedError> {
+    // There are no other arguments, so the comma is not expected either
+    let (_, _, fmt_parsed) = parse_format_literal(Some(args))?;
+    let explanation = fmt_parsed.explain();
+    Ok(quote_mixed! { #explanation })
+}
+
+/// Generates the parsing code for the input, which is `OsStr` or `Path`, or only its file name with `file_name`.
+/// Names which are not UTF-8 do not match, instead of being parsed with the replacement characters.
+pub(crate) fn frag_parse_os_impl(
+    args: TokenStream,
+    file_name: bool,
+    strict: bool,
+    dummy: &mut TokenStream,
+) -> Result<TokenStream, SpannedError> {
+    let mut args = parse_punctuated_args(args)?;
+    // Without the input the arguments are rejected the same way as by `frag_parse!`
+    let os_input = match args.len() {
+        0 | 1 => None,
+        _ => args.pop(),
+    };
+    let input = os_input.as_ref().map(|_| quote_mixed! { name });
+    let args = args.iter().chain(&input);
+    let parsed = frag_parse_impl(quote_mixed! { #(#args),* }, Ownership::Owned, strict, false, dummy)?;
+    // Names which are not UTF-8 do not match right away, without the callbacks, which take the input as `&str`
+    let mismatch = dummy.clone();
+    let os_str = if file_name {
+        quote_mixed! {
+            ::core::option::Option::and_then(
+                ::std::path::Path::file_name(::core::convert::AsRef::<::std::path::Path>::as_ref(os_input)),
+                ::std::ffi::OsStr::to_str,
+            )
+        }
+    } else {
+        quote_mixed! { ::std::ffi::OsStr::to_str(::core::convert::AsRef::<::std::ffi::OsStr>::as_ref(os_input)) }
+    };
+    Ok(quote_mixed! {
+        {
+            let os_input = &(#os_input);
+            match #os_str {
+                ::core::option::Option::Some(name) => #parsed,
+                ::core::option::Option::None => #mismatch,
+            }
+        }
+    })
+}
+
+/// How string and path items are returned.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Ownership {
+    /// Owned by the caller, or placed into the allocator with the `alloc` option.
+    Owned,
+    /// Borrowed from the input.
+    Borrowed,
+    /// Shared with `Arc`.
+    Shared,
+    /// Sliced from the input, which is `bytes::Bytes`.
+    Sliced,
+}
+
+/// Generates the parsing code, or the error, in which case the `dummy` value is updated to match
+/// the type of the output as far as it is known.
+pub(crate) fn frag_parse_impl(
+    args: TokenStream,
+    ownership: Ownership,
+    strict: bool,
+    partial: bool,
+    dummy: &mut TokenStream,
+) -> Result<TokenStream, SpannedError> {
+    let args = parse_punctuated_args(args)?;
+
+    let (options, args) = parse_options(args).map_err(|span| CompileError::BadOption.at(span))?;
+    let has_options = !options.is_empty();
+    let mut checked = false;
+    let mut alloc = None;
+    let mut intern = None;
+    let mut html_decode = false;
+    let mut unquote = false;
+    let mut demoji = false;
+    let mut decode_path = false;
+    let mut hostname = false;
+    let mut path_safe = false;
+    let mut ident = false;
+    let mut prime = false;
+    let mut roman = false;
+    let mut human_duration = false;
+    let mut packed_date = false;
+    let mut radix_auto = false;
+    let mut fixed_point = None;
+    let mut bitmask = false;
+    let mut percent = None;
+    let mut int_form = false;
+    let mut radix_option = None;
+    let mut valid_json = false;
+    let mut redact = false;
+    let mut secret = false;
+    let mut cstring = false;
+    let mut rgba = false;
+    let mut lazy = false;
+    let mut max_items = None;
+    let mut trie_threshold = TRIE_THRESHOLD;
+    let mut max_fields = MAX_FIELDS;
+    let mut count = None;
+    let mut count_failures = None;
+    let mut verbose = false;
+    let mut audit = None;
+    let mut on_fail = None;
+    let mut trace = false;
+    let mut null_sentinel = None;
+    let mut versions = None;
+    let mut union_sep = false;
+    // Locations of the last options which copy values, if any
+    let mut alloc_option = None;
+    let mut decode_option = None;
+    let mut lazy_option = None;
+    let mut secret_option = None;
+    let mut cstring_option = None;
+    let mut rgba_option = None;
+    let mut union_sep_option = None;
+    for option in options {
+        let bad_option = CompileError::BadOption.at(option.span);
+        match (option.name.as_str(), option.value) {
+            // Options which report failures of the whole value are not for partial parsing, which has none
+            ("checked", None) if !partial => {
+                checked = true;
+                *dummy = quote_mixed! { ::core::result::Result::Err(::fragstrings::ParseError::Mismatch) };
+            }
+            // Strings are either placed into the allocator or interned
+            ("alloc", Some(value)) if intern.is_none() => {
+                alloc = Some(value);
+                alloc_option = Some(option.span);
+            }
+            ("intern", Some(value)) if cfg!(feature = "intern") && alloc.is_none() => {
+                intern = Some(value);
+                alloc_option = Some(option.span);
+            }
+            ("html_decode", None) => {
+                html_decode = true;
+                decode_option = Some(option.span);
+            }
+            ("unquote", None) => {
+                unquote = true;
+                decode_option = Some(option.span);
+            }
+            ("decode_path", None) => {
+                decode_path = true;
+                decode_option = Some(option.span);
+            }
+            ("demoji", None) if cfg!(feature = "emojis") => {
+                demoji = true;
+                decode_option = Some(option.span);
+            }
+            ("hostname", None) => hostname = true,
+            // Values written with escaping are decoded, and the other ones are checked
+            ("path_safe", None) => path_safe = true,
+            ("path_safe", Some(value)) => match parse_ident_value(&value).as_deref() {
+                Some("escape") => {
+                    decode_path = true;
+                    decode_option = Some(option.span);
+                }
+                _ => return Err(bad_option),
+            },
+            ("ident", None) => ident = true,
+            ("prime", None) => prime = true,
+            // Plain integers are written in one form at most
+            ("roman", None) if !int_form => {
+                roman = true;
+                int_form = true;
+            }
+            ("human_duration", None) if !int_form => {
+                human_duration = true;
+                int_form = true;
+            }
+            ("packed_date", None) if cfg!(feature = "chrono") && !int_form => {
+                packed_date = true;
+                int_form = true;
+            }
+            ("fixed_point", Some(value)) if !int_form => {
+                let value = parse_int_value(&value).filter(|v| (1..=18).contains(v));
+                fixed_point = Some(value.ok_or(bad_option)? as u32);
+                int_form = true;
+            }
+            ("bitmask", None) if !int_form => {
+                bitmask = true;
+                int_form = true;
+            }
+            ("percent", None) if !int_form => {
+                percent = Some(false);
+                int_form = true;
+            }
+            ("percent", Some(value)) if !int_form => {
+                if parse_ident_value(&value).as_deref() != Some("strict") {
+                    return Err(bad_option);
+                }
+                percent = Some(true);
+                int_form = true;
+            }
+            ("radix", Some(value)) => match parse_ident_value(&value).as_deref() {
+                Some("auto") => {
+                    radix_auto = true;
+                    radix_option = Some(option.span);
+                }
+                _ => return Err(bad_option),
+            },
+            ("valid_json", None) => valid_json = true,
+            ("redact", None) => redact = true,
+            ("secret", None) if cfg!(feature = "secrecy") => {
+                secret = true;
+                secret_option = Some(option.span);
+            }
+            ("cstring", None) => {
+                cstring = true;
+                cstring_option = Some(option.span);
+            }
+            ("rgba", None) => {
+                rgba = true;
+                rgba_option = Some(option.span);
+            }
+            ("lazy", None) => {
+                lazy = true;
+                lazy_option = Some(option.span);
+            }
+            ("max_items", Some(value)) => max_items = Some(parse_int_value(&value).ok_or(bad_option)?),
+            ("trie_threshold", Some(value)) => trie_threshold = parse_int_value(&value).ok_or(bad_option)?,
+            ("max_fields", Some(value)) => max_fields = parse_int_value(&value).ok_or(bad_option)?,
+            ("count", Some(value)) => count = Some(value),
+            ("count_failures", Some(value)) if !partial => count_failures = Some(value),
+            ("verbose", None) if !partial => verbose = true,
+            ("audit", Some(value)) if !partial => audit = Some(value),
+            ("on_fail", Some(value)) if !partial => on_fail = Some(value),
+            ("trace", None) if !partial => trace = true,
+            ("version", Some(value)) if !partial => versions = Some(parse_versions(&value).ok_or(bad_option)?),
+            ("union_sep", None) => {
+                union_sep = true;
+                union_sep_option = Some(option.span);
+            }
+            ("null_sentinel", Some(value)) => {
+                let value = parse_str_value(&value).filter(|s| !s.is_empty() && !s.contains("__"));
+                null_sentinel = Some(value.ok_or(bad_option)?);
+            }
+            _ => return Err(bad_option),
+        }
+    }
+    // Values borrowed from the input can not be allocated or decoded, and shared values can not be allocated,
+    // while lazy values are borrowed from the input until they are copied
+    let conflicting_option = match ownership {
+        Ownership::Owned if lazy => alloc_option.or(decode_option),
+        Ownership::Owned => None,
+        Ownership::Borrowed => alloc_option.or(decode_option).or(lazy_option),
+        Ownership::Shared => alloc_option.or(lazy_option),
+        Ownership::Sliced => alloc_option.or(decode_option).or(lazy_option),
+    };
+    // Secret values are owned strings, which are wrapped instead of being redacted or copied lazily
+    let conflicting_option = conflicting_option.or(match secret_option {
+        Some(span) if ownership != Ownership::Owned || alloc_option.is_some() || lazy || redact => Some(span),
+        _ => None,
+    });
+    // So are C strings, which are copied from owned strings
+    let conflicting_option = conflicting_option.or(match cstring_option {
+        Some(span) if ownership != Ownership::Owned || alloc_option.is_some() || lazy || redact || secret => Some(span),
+        _ => None,
+    });
+    // And so are colors, which are not strings at all
+    let conflicting_option = conflicting_option.or(match rgba_option {
+        Some(span)
+            if ownership != Ownership::Owned || alloc_option.is_some() || lazy || redact || secret || cstring =>
+        {
+            Some(span)
+        }
+        _ => None,
+    });
+    // Checksums were never written with the legacy separator
+    let conflicting_option = conflicting_option.or(union_sep_option.filter(|_| checked));
+    // Numbers written in other forms have no radix
+    let conflicting_option = conflicting_option.or(match radix_option {
+        Some(span) if roman || human_duration || packed_date || fixed_point.is_some() || percent.is_some() => {
+            Some(span)
+        }
+        _ => None,
+    });
+    if let Some(span) = conflicting_option {
+        return Err(CompileError::BadOption.at(span));
+    }
+
+    let mut args = args.into_iter();
+    let (fmt_string, fmt_span, fmt_parsed) = parse_format_literal(args.next())?;
+    let FormatString(fmt_items, fmt_ending) = fmt_parsed;
+
+    let formatted_value_expr = match args.next() {
+        None => return Err(CompileError::StringExpressionExpected.at(Span::call_site())),
+        Some(stream) => stream,
+    };
+
+    if let Some(arg) = args.next() {
+        let span = stream_span(&arg);
+        return Err(CompileError::TooManyArguments(arg).at(span));
+    }
+
+    // The call site is registered for auditing, if enabled, with the format string as written
+    let registration = if cfg!(feature = "registry") {
+        Some(pattern_registration(&fmt_string, "Parse"))
+    } else {
+        None
+    };
+
+    let failed = if checked {
+        quote_mixed! { ::core::result::Result::is_err(&parsed) }
+    } else {
+        quote_mixed! { ::core::option::Option::is_none(&parsed) }
+    };
+    // Failures are logged with the format string as written, in debug builds of the calling crate only
+    let log_failure = if verbose {
+        Some(quote_mixed! {
+            #[cfg(debug_assertions)]
+            {
+                // Unlike `eprintln!`, writing does not panic if `stderr` is closed
+                if #failed {
+                    let _ = ::std::io::Write::write_fmt(
+                        &mut ::std::io::stderr(),
+                        ::core::format_args!("frag_parse failed: fmt={:?} input={:?}\n", #fmt_string, input),
+                    );
+                }
+            }
+        })
+    } else {
+        None
+    };
+    // All the attempts are recorded with the format string as written, in any build
+    let audit_record = audit.map(|audit| {
+        quote_mixed! {
+            {
+                use ::fragstrings::AuditLogger as _;
+                (#audit).record(#fmt_string, input, !#failed);
+            }
+        }
+    });
+    // So are the failures passed to the callback, which is called like a closure, e.g. a function
+    let fail_callback = on_fail.map(|on_fail| {
+        quote_mixed! {
+            if #failed {
+                (#on_fail)(#fmt_string, input);
+            }
+        }
+    });
+    // Failures are traced with the reason, without the values, if enabled by the feature,
+    // and the reason is found out after the fact, so that the success path is the same
+    let trace_failure = if trace && cfg!(feature = "tracing") {
+        let descriptor = rebuild_format_string(&fmt_items);
+        let mut expected = Vec::new();
+        if let Some((versions, _)) = &versions {
+            expected.push(format!("%s{}", descriptor));
+            if versions.contains(&0) {
+                expected.push(descriptor);
+            }
+        } else {
+            expected.push(descriptor);
+        }
+        let reason = quote_mixed! { ::fragstrings::__private::failure_reason(input, &[ #( #expected ),* ], #checked) };
+        let reason = if checked {
+            quote_mixed! {
+                match parsed {
+                    ::core::result::Result::Err(::fragstrings::ParseError::BadChecksum) => "bad_checksum",
+                    _ => #reason,
+                }
+            }
+        } else {
+            reason
+        };
+        Some(quote_mixed! {
+            if #failed {
+                ::fragstrings::__private::tracing::debug!(
+                    pattern = #fmt_string,
+                    reason = #reason,
+                    input = %::fragstrings::__private::redact_input(input),
+                    "frag_parse failed"
+                );
+            }
+        })
+    } else {
+        None
+    };
+
+    let fmt_string = rebuild_format_string(&fmt_items);
+    let optional_string = fmt_items
+        .iter()
+        .filter(|FormatItem { opt, .. }| *opt == FormatItemOpt::Optional)
+        .map(|FormatItem { ty, .. }| ty.descriptor())
+        .collect::<String>();
+    let has_borrowed_items = fmt_items
+        .iter()
+        .any(|item| matches!(item.custom_type.as_deref(), Some("str") | Some("words")));
+    let total_items = fmt_items.len();
+    let has_optionals = has_optional_items(&fmt_items);
+
+    let n = fmt_items.len();
+    if n as u64 > max_fields {
+        return Err(CompileError::TooManyFields(max_fields).at(fmt_span));
+    }
+
+    let vars = (0..n)
+        .map(|i| format_ident!("_{}", i, span = Span::mixed_site()))
+        .collect::<Vec<_>>();
+
+    // String fragments are either owned, or placed into the user-supplied allocator or interner,
+    // or borrowed from the input, or shared, and paths are made of them the same way,
+    // except for slices of the input bytes, which are `bytes::Bytes`, and the paths are owned then
+    let (str_type, str_value, path_type, path_value) = if ownership == Ownership::Sliced {
+        (
+            quote_mixed! { _ },
+            quote_mixed! {
+                {
+                    let start = value.as_ptr() as usize - input.as_ptr() as usize;
+                    bytes.slice(start..start + value.len())
+                }
+            },
+            quote_mixed! { ::std::path::PathBuf },
+            quote_mixed! { <::std::path::PathBuf as ::core::convert::From<&str>>::from(&*value) },
+        )
+    } else if ownership == Ownership::Borrowed {
+        (
+            quote_mixed! { &str },
+            quote_mixed! { value },
+            quote_mixed! { &::std::path::Path },
+            quote_mixed! { ::std::path::Path::new(value) },
+        )
+    } else if ownership == Ownership::Shared {
+        (
+            quote_mixed! { ::fragstrings::__private::alloc::sync::Arc<::fragstrings::__private::alloc::string::String> },
+            quote_mixed! { ::fragstrings::__private::alloc::sync::Arc::new(<::fragstrings::__private::alloc::string::String as ::core::convert::From<_>>::from(value)) },
+            quote_mixed! { ::fragstrings::__private::alloc::sync::Arc<::std::path::PathBuf> },
+            quote_mixed! { ::fragstrings::__private::alloc::sync::Arc::new(<::std::path::PathBuf as ::core::convert::From<&str>>::from(&*value)) },
+        )
+    } else if alloc.is_some() {
+        (
+            quote_mixed! { &str },
+            quote_mixed! { allocator.alloc_str(&value) },
+            quote_mixed! { &::std::path::Path },
+            quote_mixed! { ::std::path::Path::new(allocator.alloc_str(&value)) },
+        )
+    } else if intern.is_some() {
+        (
+            quote_mixed! { ::fragstrings::__private::alloc::sync::Arc<str> },
+            quote_mixed! { interner.get_or_insert(&value) },
+            quote_mixed! { ::std::path::PathBuf },
+            quote_mixed! { <::std::path::PathBuf as ::core::convert::From<&str>>::from(&*value) },
+        )
+    } else {
+        (
+            quote_mixed! { ::fragstrings::__private::alloc::string::String },
+            quote_mixed! { <::fragstrings::__private::alloc::string::String as ::core::convert::From<_>>::from(value) },
+            quote_mixed! { ::std::path::PathBuf },
+            quote_mixed! { <::std::path::PathBuf as ::core::convert::From<&str>>::from(&*value) },
+        )
+    };
+    // String fragments can be decoded before that, quotes go first, percent-encoded bytes next, and emoji go last
+    let str_decoders = [
+        (unquote, quote_mixed! { ::fragstrings::__private::unquote(&value) }),
+        (
+            decode_path,
+            quote_mixed! { ::fragstrings::__private::percent_decode(&value) },
+        ),
+        (
+            html_decode,
+            quote_mixed! { ::fragstrings::__private::html_decode(&value) },
+        ),
+        (
+            demoji,
+            quote_mixed! {
+                ::fragstrings::__private::emoji_to_shortcodes(&value, |emoji| {
+                    ::fragstrings::__private::emojis::get(emoji).and_then(::fragstrings::__private::emojis::Emoji::shortcode)
+                })
+            },
+        ),
+    ];
+    let mut str_decoders = str_decoders
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, decoder)| decoder)
+        .peekable();
+    let str_decode = if str_decoders.peek().is_some() {
+        Some(quote_mixed! { #( let value = #str_decoders; )* })
+    } else {
+        None
+    };
+    // And validated after decoding
+    let str_checks = [
+        (hostname, quote_mixed! { ::fragstrings::__private::is_hostname(&value) }),
+        (
+            path_safe,
+            quote_mixed! { ::fragstrings::__private::is_path_safe(&value) },
+        ),
+        (ident, quote_mixed! { ::fragstrings::__private::is_ident(&value) }),
+        (valid_json, quote_mixed! { ::fragstrings::__private::is_json(&value) }),
+    ];
+    let mut str_checks = str_checks
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, check)| check)
+        .peekable();
+    let str_valid = if str_checks.peek().is_some() {
+        quote_mixed! { #( #str_checks )&&* }
+    } else {
+        quote_mixed! { true }
+    };
+    // Integer fragments are validated before parsing, whatever the type is
+    let int_valid = if prime && radix_auto {
+        quote_mixed! {
+            ::core::matches!(
+                ::fragstrings::__private::parse_int_auto(&value).map(<u64 as ::core::convert::TryFrom<i64>>::try_from),
+                ::core::option::Option::Some(::core::result::Result::Ok(n)) if ::fragstrings::__private::is_prime(n)
+            )
+        }
+    } else if prime {
+        quote_mixed! { ::core::matches!(value.parse::<u64>(), ::core::result::Result::Ok(n) if ::fragstrings::__private::is_prime(n)) }
+    } else {
+        quote_mixed! { true }
+    };
+    let allocator_decl = match (alloc, intern) {
+        (Some(alloc), _) => Some(quote_mixed! {
+            use ::fragstrings::StringAllocator as _;
+            let allocator = &(#alloc);
+        }),
+        (None, Some(intern)) => Some(quote_mixed! {
+            let interner: &::fragstrings::intern::FragInterner = &(#intern);
+        }),
+        (None, None) => None,
+    };
+
+    // Descriptors of closed format strings without optional items are matched as a whole anyway,
+    // for the others the strict check is done at runtime only
+    let strict_descriptor = strict && (fmt_ending == FormatEnding::Open || has_optionals);
+
+    // Literal value is split right away, and without options it can be parsed right away too,
+    // unless some items have custom types or formats, which are parsed at runtime only
+    let literal_fragments = if ownership == Ownership::Sliced {
+        None
+    } else {
+        literal_fragments(&formatted_value_expr)
+    };
+    let folded_values = match &literal_fragments {
+        Some((fragments, _)) if !has_options && !strict_descriptor && !partial && fmt_items.iter().all(is_foldable) => {
+            let open_ending = fmt_ending == FormatEnding::Open;
+            Some(fold_fragments(
+                &fragments[1..],
+                &fmt_items,
+                open_ending,
+                &str_value,
+                &path_value,
+            ))
+        }
+        _ => None,
+    };
+
+    // Plain items, which are not affected by the options, are parsed by runtime helpers
+    let plain_values = !(html_decode
+        || unquote
+        || decode_path
+        || demoji
+        || hostname
+        || path_safe
+        || ident
+        || valid_json
+        || prime
+        || roman
+        || human_duration
+        || packed_date
+        || radix_auto
+        || fixed_point.is_some()
+        || bitmask
+        || percent.is_some()
+        || lazy
+        || redact
+        || secret
+        || cstring
+        || rgba
+        || allocator_decl.is_some()
+        || null_sentinel.is_some());
+
+    // Every item is parsed into an `Option`, which is `None` if the fragment is missing or malformed
+    let (var_decls, var_types): (Vec<_>, Vec<_>) = vars
+        .iter()
+        .zip(fmt_items)
+        .map(|(var, item)| {
+            let FormatItem {
+                ty: item_type,
+                opt: item_opt,
+                custom_type,
+                length_limit,
+                regex,
+                trie,
+                slice,
+                padding,
+                assert,
+            } = item;
+            let plain_item = plain_values
+                && custom_type.is_none()
+                && length_limit.is_none()
+                && regex.is_none()
+                && trie.is_none()
+                && slice.is_none()
+                && padding.is_none()
+                && assert.is_none()
+                && !partial
+                && item_opt == FormatItemOpt::Mandatory;
+            let plain_next = match (item_type, ownership) {
+                (FormatItemType::Str, Ownership::Owned) if plain_item => Some((
+                    quote_mixed! { ::fragstrings::__private::next_str(&mut fragments) },
+                    quote_mixed! { ::fragstrings::__private::alloc::string::String },
+                )),
+                (FormatItemType::Str, Ownership::Borrowed) if plain_item => Some((
+                    quote_mixed! { ::core::iter::Iterator::next(&mut fragments) },
+                    quote_mixed! { &str },
+                )),
+                (FormatItemType::Int, _) if plain_item => Some((
+                    quote_mixed! { ::fragstrings::__private::next_int(&mut fragments) },
+                    quote_mixed! { i64 },
+                )),
+                _ => None,
+            };
+            if let Some((next, value_type)) = plain_next {
+                return (
+                    quote_mixed! { let #var: ::core::option::Option<#value_type> = #next; },
+                    value_type,
+                );
+            }
+            let plain_str = item_type == FormatItemType::Str && custom_type.is_none();
+            // Type of the item and conversion of the fragment `value` into `Option` of it
+            let (value_type, value_conv) = match (item_type, custom_type) {
+                // `%s:str` is borrowed from the input, whatever the mode is
+                // `%s:words` is split on whitespace into slices borrowed from the input too
+                (FormatItemType::Str, Some(custom_type)) if custom_type == "words" => (
+                    quote_mixed! { ::fragstrings::__private::alloc::vec::Vec<&str> },
+                    quote_mixed! {
+                        if #str_valid {
+                            ::core::option::Option::Some(::core::iter::Iterator::collect(value.split_whitespace()))
+                        } else {
+                            ::core::option::Option::None
+                        }
+                    },
+                ),
+                (FormatItemType::Str, Some(custom_type)) if custom_type == "str" => (
+                    quote_mixed! { &str },
+                    quote_mixed! { { if #str_valid { ::core::option::Option::Some(value) } else { ::core::option::Option::None } } },
+                ),
+                // `%s:hex16{N}` is exactly `2 * N` hex digits, in any case, which are decoded without allocation
+                (FormatItemType::Str, Some(custom_type)) if hex_width(&custom_type).is_some() => {
+                    let width = hex_width(&custom_type).unwrap_or_default();
+                    (
+                        quote_mixed! { [u8; #width] },
+                        quote_mixed! {
+                            {
+                                #str_decode
+                                if #str_valid {
+                                    ::fragstrings::__private::hex_decode::<#width>(&value)
+                                } else {
+                                    ::core::option::Option::None
+                                }
+                            }
+                        },
+                    )
+                }
+                // `%d:SystemTime` is a Unix timestamp in seconds, which can not be negative
+                (FormatItemType::Int, Some(custom_type)) if is_system_time(&custom_type) => (
+                    quote_mixed! { ::std::time::SystemTime },
+                    quote_mixed! {
+                        value.parse::<u64>().ok().filter(|_| #int_valid).and_then(|secs| {
+                            ::std::time::UNIX_EPOCH.checked_add(::std::time::Duration::from_secs(secs))
+                        })
+                    },
+                ),
+                // `%d:NonZeroU64` is a positive integer, and zero and negative values do not match
+                (FormatItemType::Int, Some(custom_type)) if is_non_zero_u64(&custom_type) => (
+                    quote_mixed! { ::core::num::NonZeroU64 },
+                    quote_mixed! {
+                        value.parse::<u64>().ok().filter(|_| #int_valid).and_then(::core::num::NonZeroU64::new)
+                    },
+                ),
+                (_, Some(custom_type)) => {
+                    let custom_type = custom_type.parse::<TokenStream>().expect("type path is checked");
+                    let (str_decode, str_valid) = if item_type == FormatItemType::Str {
+                        (str_decode.clone(), str_valid.clone())
+                    } else {
+                        (None, int_valid.clone())
+                    };
+                    let value_conv = quote_mixed! {
+                        {
+                            #str_decode
+                            if #str_valid { ::core::str::FromStr::from_str(&value).ok() } else { ::core::option::Option::None }
+                        }
+                    };
+                    (custom_type, value_conv)
+                }
+                (FormatItemType::Str, None) if lazy => (
+                    quote_mixed! { ::fragstrings::lazy::LazyStr<'_> },
+                    quote_mixed! { { if #str_valid { ::core::option::Option::Some(::fragstrings::lazy::LazyStr::new(value)) } else { ::core::option::Option::None } } },
+                ),
+                (FormatItemType::Str, None) => (
+                    str_type.clone(),
+                    quote_mixed! { { #str_decode if #str_valid { ::core::option::Option::Some(#str_value) } else { ::core::option::Option::None } } },
+                ),
+                (FormatItemType::Path, None) => (path_type.clone(), quote_mixed! { { #str_decode ::core::option::Option::Some(#path_value) } }),
+                // Dates are checked by `chrono`, after the digits are split
+                (FormatItemType::Int, None) if packed_date => (
+                    quote_mixed! { ::fragstrings::__private::chrono::NaiveDate },
+                    quote_mixed! {
+                        ::fragstrings::__private::packed_date_decode(&value)
+                            .and_then(|(year, month, day)| ::fragstrings::__private::chrono::NaiveDate::from_ymd_opt(year, month, day))
+                    },
+                ),
+                (FormatItemType::Int, None) if roman || human_duration => {
+                    let decode = if roman {
+                        quote_mixed! { ::fragstrings::__private::roman_decode }
+                    } else {
+                        quote_mixed! { ::fragstrings::__private::duration_decode }
+                    };
+                    let prime_filter = if prime {
+                        Some(quote_mixed! { .filter(|&n| ::fragstrings::__private::is_prime(n as u64)) })
+                    } else {
+                        None
+                    };
+                    (quote_mixed! { i64 }, quote_mixed! { #decode(&value)#prime_filter })
+                }
+                // Fixed-point decimals are only read from plain items, and divided exactly by the power of ten
+                (FormatItemType::Int, None) if fixed_point.is_some() => {
+                    let scale = fixed_point.unwrap_or_default();
+                    (
+                        quote_mixed! { f64 },
+                        quote_mixed! {
+                            {
+                                if #int_valid {
+                                    value.parse().ok().map(|n| ::fragstrings::__private::fixed_point_decode(n, #scale))
+                                } else {
+                                    ::core::option::Option::None
+                                }
+                            }
+                        },
+                    )
+                }
+                // Percentages are read from plain items with the `%` sign, into shares of 0 to 1
+                (FormatItemType::Int, None) if percent.is_some() => {
+                    let strict = percent.unwrap_or_default();
+                    (
+                        quote_mixed! { f64 },
+                        quote_mixed! { ::fragstrings::__private::percentage_decode(&value, #strict) },
+                    )
+                }
+                // Bitmasks can be hexadecimal and binary too
+                (FormatItemType::Int, None) if bitmask => {
+                    let parse = if radix_auto {
+                        quote_mixed! { ::fragstrings::__private::parse_int_auto(&value) }
+                    } else {
+                        quote_mixed! { value.parse().ok() }
+                    };
+                    (
+                        quote_mixed! { ::std::collections::HashSet<u8> },
+                        quote_mixed! {
+                            {
+                                if #int_valid {
+                                    #parse.map(|mask| ::core::iter::Iterator::collect(::fragstrings::__private::bitmask_decode(mask)))
+                                } else {
+                                    ::core::option::Option::None
+                                }
+                            }
+                        },
+                    )
+                }
+                // Hexadecimal and binary numbers are only accepted by plain items, which are `i64` anyway
+                (FormatItemType::Int, None) if radix_auto => (
+                    quote_mixed! { i64 },
+                    quote_mixed! {
+                        {
+                            if #int_valid {
+                                ::fragstrings::__private::parse_int_auto(&value)
+                            } else {
+                                ::core::option::Option::None
+                            }
+                        }
+                    },
+                ),
+                (FormatItemType::Int, None) => (
+                    quote_mixed! { i64 },
+                    quote_mixed! { { if #int_valid { value.parse().ok() } else { ::core::option::Option::None } } },
+                ),
+                // Decoded bytes are always owned, there is nothing to borrow them from
+                (FormatItemType::Base62, None) => (
+                    quote_mixed! { ::fragstrings::__private::alloc::vec::Vec<u8> },
+                    quote_mixed! { ::fragstrings::__private::base62_decode(&value) },
+                ),
+                // Elements are strings, which are split before decoding, and the empty value is the empty set
+                (FormatItemType::StrSet, None) => (
+                    quote_mixed! { ::fragstrings::__private::alloc::collections::BTreeSet<#str_type> },
+                    quote_mixed! {
+                        if value.is_empty() {
+                            ::core::option::Option::Some(::fragstrings::__private::alloc::collections::BTreeSet::new())
+                        } else {
+                            let values = ::core::iter::Iterator::map(value.split(','), |value| {
+                                #str_decode
+                                if #str_valid { ::core::option::Option::Some(#str_value) } else { ::core::option::Option::None }
+                            });
+                            ::core::iter::Iterator::collect(values)
+                        }
+                    },
+                ),
+                // Entries are split on `,` and then on the first `=`, and an entry without `=` is malformed
+                (FormatItemType::StrMap, None) => (
+                    quote_mixed! { ::std::collections::HashMap<#str_type, #str_type> },
+                    quote_mixed! {
+                        if value.is_empty() {
+                            ::core::option::Option::Some(::std::collections::HashMap::new())
+                        } else {
+                            let entries = ::core::iter::Iterator::map(value.split(','), |entry| {
+                                let (key, value) = entry.split_once('=')?;
+                                let key = {
+                                    let value = key;
+                                    #str_decode
+                                    if #str_valid { #str_value } else { return ::core::option::Option::None }
+                                };
+                                #str_decode
+                                if #str_valid { ::core::option::Option::Some((key, #str_value)) } else { ::core::option::Option::None }
+                            });
+                            ::core::iter::Iterator::collect(entries)
+                        }
+                    },
+                ),
+            };
+            // String values are hidden from `Debug`
+            let (value_type, value_conv) = match item_type {
+                FormatItemType::Str | FormatItemType::StrSet | FormatItemType::StrMap if redact => (
+                    quote_mixed! { ::fragstrings::redact::Redacted<#value_type> },
+                    quote_mixed! { (#value_conv).map(::fragstrings::redact::Redacted) },
+                ),
+                // Strings are moved into the secret box, which zeroizes them on drop
+                FormatItemType::Str if secret && plain_str => (
+                    quote_mixed! { ::fragstrings::__private::secrecy::SecretString },
+                    quote_mixed! { (#value_conv).map(::fragstrings::__private::secrecy::SecretString::from) },
+                ),
+                // C strings can not contain nul bytes, so the values with them do not match
+                FormatItemType::Str if cstring && plain_str => (
+                    quote_mixed! { ::fragstrings::__private::alloc::ffi::CString },
+                    quote_mixed! {
+                        (#value_conv).and_then(|value| ::fragstrings::__private::alloc::ffi::CString::new(value).ok())
+                    },
+                ),
+                // Fragments which are not colors do not match
+                FormatItemType::Str if rgba && plain_str => (
+                    quote_mixed! { (u8, u8, u8, u8) },
+                    quote_mixed! { (#value_conv).and_then(|value| ::fragstrings::__private::rgba_decode(&value)) },
+                ),
+                _ => (value_type, value_conv),
+            };
+            // The sentinel fragment stands for the absent value, so it is never a value itself
+            let (mandatory_sentinel, optional_sentinel) = match &null_sentinel {
+                Some(sentinel) => (
+                    Some(quote_mixed! { ::core::option::Option::Some(#sentinel) => ::core::option::Option::None, }),
+                    Some(quote_mixed! { ::core::option::Option::Some(#sentinel) => ::core::option::Option::Some(::core::option::Option::None), }),
+                ),
+                None => (None, None),
+            };
+            // Length limit is checked for the fragment as it is stored, i.e. before decoding
+            let length_mismatch = length_limit.map(|LengthLimit { unit, min, max }| {
+                let len = match unit {
+                    LengthUnit::Chars => quote_mixed! { ::core::iter::Iterator::count(value.chars()) },
+                    LengthUnit::Bytes => quote_mixed! { value.len() },
+                };
+                let too_short = min.map(|min| quote_mixed! { #len < #min });
+                let too_long = max.map(|max| quote_mixed! { #len > #max });
+                let mismatch = too_short.into_iter().chain(too_long);
+                quote_mixed! { ::core::option::Option::Some(value) if #( #mismatch )||* => ::core::option::Option::None, }
+            });
+            // So is the regex, which is compiled at compile time into a static program
+            let regex_mismatch = regex.map(|pattern| {
+                let program = parse_regex(&pattern).ok().and_then(|regex| compile(&regex));
+                let regex = regex_expr(&program.expect("regex is checked"));
+                quote_mixed! {
+                    ::core::option::Option::Some(value) if !{
+                        #regex
+                        ::fragstrings::__private::regex_match(&REGEX, value)
+                    } => ::core::option::Option::None,
+                }
+            });
+            // So are the allowed values, which are matched by the trie, if there are many of them
+            let trie_mismatch = trie.map(|values| {
+                let matches = trie_match(&values, trie_threshold);
+                quote_mixed! { ::core::option::Option::Some(value) if !#matches => ::core::option::Option::None, }
+            });
+            // And so is the only allowed value
+            let assert_mismatch = assert.map(|value| {
+                quote_mixed! { ::core::option::Option::Some(value) if value != #value => ::core::option::Option::None, }
+            });
+            // The byte range is taken after the sentinel is compared with the whole fragment, and all the checks
+            // are for the part taken, which is `None` if the range is out of bounds or not on char boundaries
+            let checked_conv = |conv: TokenStream| {
+                let arms = quote_mixed! {
+                    #length_mismatch
+                    #regex_mismatch
+                    #trie_mismatch
+                    #assert_mismatch
+                    ::core::option::Option::Some(value) => #conv,
+                };
+                match slice {
+                    Some(ByteRange { start, end }) => {
+                        let end = end.map(|end| quote_mixed! { #end });
+                        quote_mixed! {
+                            ::core::option::Option::Some(value) => match value.get(#start..#end) {
+                                #arms
+                                ::core::option::Option::None => ::core::option::Option::None,
+                            },
+                        }
+                    }
+                    None => arms,
+                }
+            };
+            // The arms for the next fragment, when it is present, and the value when it is missing
+            let (fragment_arms, missing, value_type) = match item_opt {
+                FormatItemOpt::Mandatory => {
+                    let checked_conv = checked_conv(value_conv);
+                    (
+                        quote_mixed! { #mandatory_sentinel #checked_conv },
+                        quote_mixed! { ::core::option::Option::None },
+                        value_type,
+                    )
+                }
+                FormatItemOpt::Optional => {
+                    let checked_conv = checked_conv(quote_mixed! { (#value_conv).map(::core::option::Option::Some) });
+                    (
+                        quote_mixed! { #optional_sentinel #checked_conv },
+                        quote_mixed! { ::core::option::Option::Some(::core::option::Option::None) },
+                        quote_mixed! { ::core::option::Option<#value_type> },
+                    )
+                }
+            };
+            // Padding is trimmed before anything else, so that the sentinel can be padded too
+            let next_fragment = match padding {
+                Some(Padding { align, .. }) => {
+                    let trim = match align {
+                        Align::Left => quote_mixed! { <str>::trim_end_matches },
+                        Align::Right => quote_mixed! { <str>::trim_start_matches },
+                        Align::Center => quote_mixed! { <str>::trim_matches },
+                    };
+                    quote_mixed! {
+                        ::core::option::Option::map(::core::iter::Iterator::next(&mut fragments), |value| #trim(value, ' '))
+                    }
+                }
+                None => quote_mixed! { ::core::iter::Iterator::next(&mut fragments) },
+            };
+            // In partial mode missing fragments are told apart from malformed ones, and an absent optional item is fine
+            if partial {
+                let missing_partial = match item_opt {
+                    FormatItemOpt::Mandatory => {
+                        quote_mixed! { ::core::result::Result::Err(::fragstrings::FieldError::Missing) }
+                    }
+                    FormatItemOpt::Optional => {
+                        quote_mixed! { ::core::result::Result::Ok(::core::option::Option::None) }
+                    }
+                };
+                let value_type = quote_mixed! { ::core::result::Result<#value_type, ::fragstrings::FieldError> };
+                return (
+                    quote_mixed! {
+                        let #var: #value_type = match #next_fragment {
+                            ::core::option::Option::None => #missing_partial,
+                            fragment => {
+                                let value = match fragment {
+                                    #fragment_arms
+                                    ::core::option::Option::None => #missing,
+                                };
+                                ::core::option::Option::ok_or(value, ::fragstrings::FieldError::Malformed)
+                            }
+                        };
+                    },
+                    value_type,
+                );
+            }
+            (
+                quote_mixed! {
+                    let #var: ::core::option::Option<#value_type> = match #next_fragment {
+                        #fragment_arms
+                        ::core::option::Option::None => #missing,
+                    };
+                },
+                value_type,
+            )
+        })
+        .unzip();
+
+    let open_ending = fmt_ending == FormatEnding::Open;
+
+    // In checked mode the checksum is described as one more integer item, which is not returned
+    let next_pattern = if checked {
+        quote_mixed! { ::core::iter::Iterator::next(&mut fragments).and_then(|pattern| pattern.strip_suffix("%d")) }
+    } else {
+        quote_mixed! { ::core::iter::Iterator::next(&mut fragments) }
+    };
+
+    // When the set of versions is given, the matched one is returned as the first item of the tuple
+    let (version_var, version_type) = match versions {
+        Some((_, true)) => (Some(quote_mixed! { version, }), Some(quote_mixed! { u32, })),
+        _ => (None, None),
+    };
+
+    // Now the type of the output is known
+    *dummy = if checked {
+        quote_mixed! {
+            ::core::result::Result::<( #version_type #( #var_types ),* ), ::fragstrings::ParseError>::Err(
+                ::fragstrings::ParseError::Mismatch
+            )
+        }
+    } else if union_sep {
+        quote_mixed! { ::core::option::Option::<(&'static str, ( #version_type #( #var_types ),* ))>::None }
+    } else {
+        quote_mixed! { ::core::option::Option::<( #version_type #( #var_types ),* )>::None }
+    };
+
+    if let Some(max_items) = max_items {
+        if fmt_ending == FormatEnding::Open {
+            return Err(CompileError::UnboundedItems.at(fmt_span));
+        }
+        // Version and checksum are fragments too
+        let has_version = matches!(&versions, Some((versions, _)) if versions.iter().any(|&v| v != 0));
+        let total_items = total_items + has_version as usize + checked as usize;
+        if total_items as u64 > max_items {
+            return Err(CompileError::TooManyItems.at(fmt_span));
+        }
+    }
+
+    // Borrowed values can not be decoded
+    if has_borrowed_items && (html_decode || unquote || decode_path || demoji) {
+        return Err(CompileError::BadOption.at(fmt_span));
+    }
+
+    // Value which is a literal, or is formatted by a nested `frag_format!`, is known to fail,
+    // if its descriptor does not match, unless the literal has the legacy separator
+    let known_descriptor = match literal_fragments {
+        Some(_) if union_sep => None,
+        Some((fragments, span)) => Some((fragments[0].clone(), span)),
+        None if ownership == Ownership::Sliced => None,
+        None => nested_format_descriptor(&formatted_value_expr),
+    };
+    if let Some((descriptor, span)) = known_descriptor {
+        let starts_with = fmt_ending == FormatEnding::Open || has_optionals;
+        let versions = versions.as_ref().map(|(versions, _)| versions.as_slice());
+        if !descriptor_matches(&descriptor, &fmt_string, starts_with, checked, versions) {
+            return Err(CompileError::NeverMatches(descriptor).at(span));
+        }
+    }
+
+    if let Some(values) = folded_values {
+        let res = match values {
+            Some(values) => quote_mixed! { ::core::option::Option::<( #( #var_types ),* )>::Some(( #( #values ),* )) },
+            None => dummy.clone(),
+        };
+        let res = count_calls(res, count, count_failures, checked);
+        return Ok(quote_mixed! { { #registration #res } });
+    }
+
+    // The checks, which are the same for all the call sites, are runtime helpers, so that the expansion is short
+    let starts_with = open_ending || has_optionals;
+    let all_good = if open_ending {
+        quote_mixed! { true }
+    } else {
+        quote_mixed! { ::fragstrings::__private::finish_closed(&mut fragments) }
+    };
+    // The strict check also needs the number of the fragments after the descriptor, before they are parsed
+    let descriptor_matches = if strict_descriptor {
+        quote_mixed! {
+            ::fragstrings::__private::match_descriptor_strict(pattern, #fmt_string, #optional_string, #open_ending)
+                == ::core::option::Option::Some(::core::iter::Iterator::count(::core::clone::Clone::clone(&fragments)))
+        }
+    } else {
+        quote_mixed! { ::fragstrings::__private::match_descriptor(pattern, #fmt_string, #starts_with) }
+    };
+    let parse_fragments = if partial {
+        // Fragments are salvaged one by one, as long as the descriptor matches, and extra ones are ignored
+        quote_mixed! {
+            if #descriptor_matches {
+                #( #var_decls )*
+                ::core::option::Option::Some( ( #( #vars ),* ) )
+            } else {
+                ::core::option::Option::None
+            }
+        }
+    } else {
+        quote_mixed! {
+        if #descriptor_matches {
+            #( #var_decls )*
+            let all_good = #all_good;
+            match ( #( #vars, )* ) {
+                ( #( ::core::option::Option::Some(#vars), )* ) if all_good => ::core::option::Option::Some( ( #version_var #( #vars ),* ) ),
+                _ => ::core::option::Option::None,
+            }
+        } else {
+            ::core::option::Option::None
+        }
+        }
+    };
+
+    // Closed format needs the descriptor, the items and one more fragment to reject extra ones,
+    // so the rest of the input, if any, is not split at all
+    let split_fragments = |version_items: usize| {
+        let limit = if open_ending {
+            quote_mixed! { ::core::option::Option::None }
+        } else {
+            let limit = 1 + version_items + n + 1;
+            quote_mixed! { ::core::option::Option::Some(#limit) }
+        };
+        if union_sep {
+            quote_mixed! { ::fragstrings::__private::Fragments::with_separator(input, #limit, separator) }
+        } else {
+            quote_mixed! { ::fragstrings::__private::Fragments::new(input, #limit) }
+        }
+    };
+    let (unversioned_split, versioned_split) = (split_fragments(0), split_fragments(1));
+
+    // Version fragment goes first and is described as one more string item, which is not returned
+    let parse_fragments = match versions {
+        // Single item of a closed format is the rest of the value after the descriptor,
+        // which is matched right away, and has no more separators
+        None if n == 1 && !open_ending && !partial && !union_sep => {
+            let descriptor = if checked {
+                format!("{}%d", fmt_string)
+            } else {
+                fmt_string.clone()
+            };
+            quote_mixed! {
+                match ::fragstrings::__private::split_single(input) {
+                    ::core::option::Option::Some((#descriptor, value)) => {
+                        let mut fragments = ::fragstrings::__private::Fragments::new(value, ::core::option::Option::Some(1));
+                        #( #var_decls )*
+                        #( #vars )*
+                    }
+                    _ => ::core::option::Option::None,
+                }
+            }
+        }
+        None => quote_mixed! {
+            let mut fragments = #unversioned_split;
+            let pattern = #next_pattern;
+            #parse_fragments
+        },
+        Some((versions, report)) => {
+            let version_arms = versions.iter().filter(|&&version| version != 0).map(|&version| {
+                let tag = format!("v{}", version);
+                let version = version as u32;
+                quote_mixed! { ::core::option::Option::Some(#tag) => ::core::option::Option::Some(#version), }
+            });
+            let version_check = if report {
+                quote_mixed! { if let ::core::option::Option::Some(version) = version }
+            } else {
+                quote_mixed! { if version.is_some() }
+            };
+            let versioned = quote_mixed! {
+                let mut fragments = #versioned_split;
+                let pattern = #next_pattern.and_then(|pattern| pattern.strip_prefix("%s"));
+                let version: ::core::option::Option<u32> = match ::core::iter::Iterator::next(&mut fragments) {
+                    #( #version_arms )*
+                    _ => ::core::option::Option::None,
+                };
+                #version_check {
+                    #parse_fragments
+                } else {
+                    ::core::option::Option::None
+                }
+            };
+            // Version 0 means legacy records without the version fragment
+            if versions.contains(&0) {
+                quote_mixed! {
+                    let versioned = { #versioned };
+                    versioned.or_else(|| {
+                        let version = 0_u32;
+                        let mut fragments = #unversioned_split;
+                        let pattern = #next_pattern;
+                        #parse_fragments
+                    })
+                }
+            } else {
+                versioned
+            }
+        }
+    };
+
+    // Input bytes are checked to be UTF-8 beforehand
+    let input_decl = if ownership == Ownership::Sliced {
+        None
+    } else {
+        Some(quote_mixed! { let input: &str = &(#formatted_value_expr); })
+    };
+
+    let parsed = if checked {
+        quote_mixed! {
+            match ::fragstrings::__private::split_checksum(input) {
+                ::core::option::Option::Some((input, checksum))
+                    if checksum.parse::<u32>().ok() == ::core::option::Option::Some(::fragstrings::__private::crc32(input.as_bytes())) =>
+                {
+                    let parsed = { #parse_fragments };
+                    parsed.ok_or(::fragstrings::ParseError::Mismatch)
+                }
+                _ => ::core::result::Result::Err(::fragstrings::ParseError::BadChecksum),
+            }
+        }
+    } else {
+        parse_fragments
+    };
+    // Values are parsed with the current separator first, and then with the legacy one,
+    // which is returned along with the values
+    let parsed = if union_sep {
+        quote_mixed! {
+            let current = {
+                let separator: &'static str = "__";
+                #parsed
+            };
+            match current {
+                ::core::option::Option::Some(values) => ::core::option::Option::Some(("__", values)),
+                ::core::option::Option::None => {
+                    let separator: &'static str = "-";
+                    let legacy = { #parsed };
+                    ::core::option::Option::map(legacy, |values| ("-", values))
+                }
+            }
+        }
+    } else {
+        parsed
+    };
+    let res = if log_failure.is_some() || audit_record.is_some() || fail_callback.is_some() || trace_failure.is_some() {
+        quote_mixed! {
+            {
+                #allocator_decl
+                #input_decl
+                let parsed = { #parsed };
+                #log_failure
+                #audit_record
+                #fail_callback
+                #trace_failure
+                parsed
+            }
+        }
+    } else {
+        quote_mixed! {
+            {
+                #allocator_decl
+                #input_decl
+                #parsed
+            }
+        }
+    };
+
+    let res = count_calls(res, count, count_failures, checked);
+    // Input bytes which are not UTF-8 do not match right away, without the callbacks, which take the input as `&str`
+    let res = if ownership == Ownership::Sliced {
+        let mismatch = dummy.clone();
+        quote_mixed! {
+            {
+                let bytes = &(#formatted_value_expr);
+                match ::core::str::from_utf8(&bytes[..]) {
+                    ::core::result::Result::Ok(input) => #res,
+                    ::core::result::Result::Err(_) => #mismatch,
+                }
+            }
+        }
+    } else {
+        res
+    };
+    Ok(quote_mixed! { { #registration #res } })
+}
+
+/// Wraps the parsing code into the counting of calls and failures with the given atomic counters, if any.
+fn count_calls(
+    res: TokenStream,
+    count: Option<TokenStream>,
+    count_failures: Option<TokenStream>,
+    checked: bool,
+) -> TokenStream {
+    if count.is_none() && count_failures.is_none() {
+        return res;
+    }
+    let count_call = count.map(|count| {
+        quote_mixed! { ::core::sync::atomic::AtomicU64::fetch_add(#count, 1, ::core::sync::atomic::Ordering::Relaxed); }
+    });
+    let count_failure = count_failures.map(|count_failures| {
+        let failed = if checked {
+            quote_mixed! { ::core::result::Result::is_err(&parsed) }
+        } else {
+            quote_mixed! { ::core::option::Option::is_none(&parsed) }
+        };
+        quote_mixed! {
+            if #failed {
+                ::core::sync::atomic::AtomicU64::fetch_add(#count_failures, 1, ::core::sync::atomic::Ordering::Relaxed);
+            }
+        }
+    });
+    quote_mixed! {
+        {
+            #count_call
+            let parsed = #res;
+            #count_failure
+            parsed
+        }
+    }
+}
+
+/// Parses the format string argument, which must be one or more adjacent string literals,
+/// and returns the format string, its location and the parsed items.
+fn parse_format_literal(arg: Option<TokenStream>) -> Result<(String, Span, FormatString), SpannedError> {
+    let fmt_literals = parse_string_literals(arg.unwrap_or_default())?;
+
+    // Errors point at the offending character, if possible
+    let fmt_parsed = parse_format_string_ex(&fmt_literals.value).map_err(|err| {
+        let span = fmt_literals.span_at(err.pos);
+        CompileError::from(err).at(span)
+    })?;
+    Ok((fmt_literals.value, fmt_literals.span, fmt_parsed))
+}
+
+/// Descriptor of the values which match both format strings, if any, like `%s%d__…__…`.
+/// Such values start with the descriptors of the mandatory items of either format string,
+/// and have no more fragments than a closed format string has items, like in the generated code.
+fn common_descriptor(a: &FormatString, b: &FormatString) -> Option<String> {
+    let shape = |FormatString(items, ending): &FormatString| {
+        let max_len = match ending {
+            FormatEnding::Closed => Some(items.len()),
+            FormatEnding::Open => None,
+        };
+        (rebuild_format_string(items), max_len)
+    };
+    let ((a_prefix, a_max_len), (b_prefix, b_max_len)) = (shape(a), shape(b));
+    // All the descriptors are of the same length, so a prefix of the string is a prefix of the items
+    let (descriptor, other_max_len) = if a_prefix.starts_with(&b_prefix) {
+        (a_prefix, b_max_len)
+    } else if b_prefix.starts_with(&a_prefix) {
+        (b_prefix, a_max_len)
+    } else {
+        return None;
+    };
+    let len = descriptor.len() / 2;
+    if other_max_len.is_some_and(|max_len| len > max_len) {
+        return None;
+    }
+    Some(format!("{}{}", descriptor, "__…".repeat(len)))
+}
+
+/// First change in the new format string, which breaks either the readers or the values of the old one,
+/// described in plain words, if any. The items must keep their types and whether they are optional,
+/// and the added items must be optional, unless the old format string is open-ended, so its readers skip them.
+fn first_incompatibility(old: &FormatString, new: &FormatString) -> Option<String> {
+    let (FormatString(old_items, old_ending), FormatString(new_items, new_ending)) = (old, new);
+    for (i, old_item) in old_items.iter().enumerate() {
+        let pos = i + 1;
+        let new_item = match (new_items.get(i), old_item.opt) {
+            (Some(new_item), _) => new_item,
+            (None, FormatItemOpt::Mandatory) => {
+                return Some(format!("mandatory item {} `{}` is removed", pos, old_item))
+            }
+            (None, FormatItemOpt::Optional) => return Some(format!("optional item {} `{}` is removed", pos, old_item)),
+        };
+        if (new_item.ty, &new_item.custom_type) != (old_item.ty, &old_item.custom_type) {
+            return Some(format!(
+                "type of item {} is changed from `{}` to `{}`",
+                pos, old_item, new_item
+            ));
+        }
+        match (old_item.opt, new_item.opt) {
+            (FormatItemOpt::Optional, FormatItemOpt::Mandatory) => {
+                return Some(format!("optional item {} `{}` is made mandatory", pos, old_item));
+            }
+            (FormatItemOpt::Mandatory, FormatItemOpt::Optional) => {
+                return Some(format!("mandatory item {} `{}` is made optional", pos, old_item));
+            }
+            _ => {}
+        }
+    }
+    let added = new_items.iter().enumerate().skip(old_items.len());
+    if let Some((i, new_item)) = added
+        .filter(|_| *old_ending == FormatEnding::Closed)
+        .find(|(_, item)| item.opt == FormatItemOpt::Mandatory)
+    {
+        return Some(format!(
+            "mandatory item {} `{}` is added, while new items must be optional or follow `*`",
+            i + 1,
+            new_item
+        ));
+    }
+    if (*old_ending, *new_ending) == (FormatEnding::Open, FormatEnding::Closed) {
+        return Some("the `*` ending is removed, so the old values with extra fragments do not match".to_owned());
+    }
+    None
+}
+
+/// Parses the value of the `version` option, which is either a single version, like `version = 2`,
+/// or a set of versions, like `version = [0, 1, 2]`, where 0 stands for legacy unversioned records.
+/// Returns the versions and whether the matched one has to be returned along with the parsed values.
+fn parse_versions(value: &TokenStream) -> Option<(Vec<u64>, bool)> {
+    if let Some(version) = parse_int_value(value) {
+        return if version > 0 && version <= u32::MAX as u64 {
+            Some((vec![version], false))
+        } else {
+            None
+        };
+    }
+    let mut versions = Vec::new();
+    for item in parse_list_value(value)? {
+        let version = parse_int_value(&item)?;
+        if version > u32::MAX as u64 || versions.contains(&version) {
+            return None;
+        }
+        versions.push(version);
+    }
+    if versions.is_empty() {
+        return None;
+    }
+    Some((versions, true))
+}
+
+/// Descriptor of the value, if it is formatted by a nested `frag_format!` with a literal format string,
+/// like in `frag_parse!("%s%d", frag_format!("%s%d", foo, 42))`, and the location of the format string.
+fn nested_format_descriptor(expr: &TokenStream) -> Option<(String, Span)> {
+    let tokens = expr.clone().into_iter().collect::<Vec<_>>();
+    let (path, args) = match tokens.as_slice() {
+        [path @ .., TokenTree::Punct(bang), TokenTree::Group(args)] if bang.as_char() == '!' => (path, args.stream()),
+        _ => return None,
+    };
+    // Either `frag_format` or a path to it, like `fragstrings::frag_format`
+    match path.last() {
+        Some(TokenTree::Ident(ident)) if ident == "frag_format" => {}
+        _ => return None,
+    }
+    let is_path = path.iter().all(|token| match token {
+        TokenTree::Ident(_) => true,
+        TokenTree::Punct(punct) => punct.as_char() == ':',
+        _ => false,
+    });
+    if !is_path {
+        return None;
+    }
+
+    let (options, args) = parse_options(parse_punctuated_args(args).ok()?).ok()?;
+    let (mut version_item, mut checksum_item) = ("", "");
+    for option in options {
+        match option.name.as_str() {
+            "version" => version_item = "%s",
+            "checked" => checksum_item = "%d",
+            // The value is a `Result` then, which can not be parsed anyway
+            "max_bytes" => return None,
+            _ => {}
+        }
+    }
+    let literals = parse_string_literals(args.first()?.clone()).ok()?;
+    let FormatString(items, _) = parse_format_string_ex(&literals.value).ok()?;
+    let items = items.iter().map(|item| item.ty.descriptor()).collect::<String>();
+    Some((format!("{}{}{}", version_item, items, checksum_item), literals.span))
+}
+
+/// Fragments of the value, if it is a string literal, like in `frag_parse!("%s%d", "%s%d__foo__42")`,
+/// and the location of the literal.
+fn literal_fragments(expr: &TokenStream) -> Option<(Vec<String>, Span)> {
+    let literal = match expr.clone().into_iter().collect::<Vec<_>>().as_slice() {
+        [TokenTree::Literal(literal)] => literal.clone(),
+        _ => return None,
+    };
+    let literal_string = literal.to_string();
+    if literal_kind(&literal_string) != LiteralKind::Str {
+        return None;
+    }
+    let value = parse_string_literal(&literal_string)?;
+    let fragments = value.split("__").map(str::to_owned).collect();
+    Some((fragments, literal.span()))
+}
+
+/// Whether the item can be parsed at compile time, which is the case for strings, paths and `i64` integers.
+fn is_foldable(item: &FormatItem) -> bool {
+    item.regex.is_none()
+        && item.slice.is_none()
+        && item.padding.is_none()
+        && matches!(
+            (item.ty, item.custom_type.as_deref()),
+            (FormatItemType::Str, None)
+                | (FormatItemType::Str, Some("str"))
+                | (FormatItemType::Path, None)
+                | (FormatItemType::Int, None)
+        )
+}
+
+/// Default number of the items of a format string, above which it is considered a mistake,
+/// because so large tuples are hardly useful, and slow down the compilation.
+const MAX_FIELDS: u64 = 16;
+
+/// Default number of the allowed values of a `%s{trie:...}` item, starting from which they are matched
+/// by the trie, while fewer values are compared one by one, which is faster for them.
+const TRIE_THRESHOLD: u64 = 16;
+
+/// Expression which checks whether the fragment `value` is one of the allowed values. If there are
+/// at least `threshold` of them, it is the trie of nested matches on the bytes of the value,
+/// which takes the time proportional to the length of the value rather than the number of the values.
+fn trie_match(values: &[String], threshold: u64) -> TokenStream {
+    if (values.len() as u64) < threshold {
+        return quote_mixed! { ::core::matches!(value, #( #values )|*) };
+    }
+    let mut values = values.iter().map(String::as_bytes).collect::<Vec<_>>();
+    values.sort_unstable();
+    values.dedup();
+    let node = trie_node(&values, 0);
+    quote_mixed! {
+        {
+            let bytes = value.as_bytes();
+            #node
+        }
+    }
+}
+
+/// Node of the trie for the sorted values, which have the same first `depth` bytes.
+fn trie_node(values: &[&[u8]], depth: usize) -> TokenStream {
+    let index = Literal::usize_unsuffixed(depth);
+    if let [value] = values {
+        let rest = Literal::byte_string(&value[depth..]);
+        return quote_mixed! { bytes.get(#index..) == ::core::option::Option::Some(&#rest[..]) };
+    }
+    let is_end = values.iter().any(|value| value.len() == depth);
+    let mut arms = Vec::new();
+    let mut rest = values.iter().skip_while(|value| value.len() == depth).peekable();
+    while let Some(first) = rest.next() {
+        let byte = first[depth];
+        let mut group = vec![*first];
+        while let Some(value) = rest.next_if(|value| value[depth] == byte) {
+            group.push(*value);
+        }
+        let node = trie_node(&group, depth + 1);
+        let byte = Literal::u8_suffixed(byte);
+        arms.push(quote_mixed! { ::core::option::Option::Some(#byte) => #node, });
+    }
+    quote_mixed! {
+        match bytes.get(#index) {
+            ::core::option::Option::None => #is_end,
+            #( #arms )*
+            _ => false,
+        }
+    }
+}
+
+/// Expression of the regex program for the runtime matcher, so that it is not parsed at runtime.
+fn regex_expr(program: &[Inst]) -> TokenStream {
+    let len = program.len();
+    let insts = program.iter().map(|inst| match inst {
+        Inst::Char(ch) => quote_mixed! { ::fragstrings::__private::RegexInst::Char(#ch) },
+        Inst::Any => quote_mixed! { ::fragstrings::__private::RegexInst::Any },
+        Inst::Class { negated, ranges } => {
+            let (lo, hi): (Vec<_>, Vec<_>) = ranges.iter().copied().unzip();
+            quote_mixed! { ::fragstrings::__private::RegexInst::Class(#negated, &[ #( (#lo, #hi) ),* ]) }
+        }
+        Inst::Split(first, second) => quote_mixed! { ::fragstrings::__private::RegexInst::Split(#first, #second) },
+        Inst::Jump(target) => quote_mixed! { ::fragstrings::__private::RegexInst::Jump(#target) },
+        Inst::Match => quote_mixed! { ::fragstrings::__private::RegexInst::Match },
+    });
+    quote_mixed! {
+        static REGEX: [::fragstrings::__private::RegexInst; #len] = [ #( #insts ),* ];
+    }
+}
+
+/// Parses the fragments of a literal value following the descriptor at compile time, the same way
+/// as the generated code does it, into expressions of the items, or `None` if the value does not match.
+/// String and path items are made of the fragment `value` by the given expressions.
+fn fold_fragments(
+    fragments: &[String],
+    items: &[FormatItem],
+    open_ending: bool,
+    str_value: &TokenStream,
+    path_value: &TokenStream,
+) -> Option<Vec<TokenStream>> {
+    let mut fragments = fragments.iter();
+    let mut values = Vec::with_capacity(items.len());
+    for FormatItem {
+        ty: item_type,
+        opt: item_opt,
+        custom_type,
+        length_limit,
+        trie,
+        assert,
+        ..
+    } in items
+    {
+        let value = match (fragments.next(), item_opt) {
+            (Some(value), _) => value,
+            (None, FormatItemOpt::Optional) => {
+                values.push(quote_mixed! { ::core::option::Option::None });
+                continue;
+            }
+            (None, FormatItemOpt::Mandatory) => return None,
+        };
+        if let Some(LengthLimit { unit, min, max }) = length_limit {
+            let len = match unit {
+                LengthUnit::Chars => value.chars().count(),
+                LengthUnit::Bytes => value.len(),
+            };
+            if min.is_some_and(|min| len < min) || max.is_some_and(|max| len > max) {
+                return None;
+            }
+        }
+        if trie.as_ref().is_some_and(|values| !values.contains(value)) {
+            return None;
+        }
+        if assert.as_ref().is_some_and(|assert| assert != value) {
+            return None;
+        }
+        let literal = Literal::string(value);
+        let value = match (item_type, custom_type) {
+            (FormatItemType::Str, Some(_)) => quote_mixed! { #literal },
+            (FormatItemType::Str, None) => quote_mixed! { { let value: &str = #literal; #str_value } },
+            (FormatItemType::Path, _) => quote_mixed! { { let value: &str = #literal; #path_value } },
+            _ => {
+                let value = Literal::i64_suffixed(value.parse().ok()?);
+                quote_mixed! { #value }
+            }
+        };
+        values.push(match item_opt {
+            FormatItemOpt::Mandatory => value,
+            FormatItemOpt::Optional => quote_mixed! { ::core::option::Option::Some(#value) },
+        });
+    }
+    if !open_ending && fragments.next().is_some() {
+        return None;
+    }
+    Some(values)
+}
+
+/// Checks whether the value with the descriptor can match the format string,
+/// the same way as the generated code does it.
+fn descriptor_matches(
+    descriptor: &str,
+    fmt_string: &str,
+    starts_with: bool,
+    checked: bool,
+    versions: Option<&[u64]>,
+) -> bool {
+    let descriptor = if checked {
+        match descriptor.strip_suffix("%d") {
+            Some(descriptor) => descriptor,
+            None => return false,
+        }
+    } else {
+        descriptor
+    };
+    // Versioned values have one more string item, and version 0 stands for unversioned ones
+    let patterns = match versions {
+        None => vec![Some(descriptor)],
+        Some(versions) => vec![
+            Some(descriptor.strip_prefix("%s"))
+                .filter(|_| versions.iter().any(|&v| v != 0))
+                .flatten(),
+            Some(descriptor).filter(|_| versions.contains(&0)),
+        ],
+    };
+    patterns.into_iter().flatten().any(|pattern| {
+        if starts_with {
+            pattern.starts_with(fmt_string)
+        } else {
+            pattern == fmt_string
+        }
+    })
+}
+
+fn has_optional_items(items: &[FormatItem]) -> bool {
+    items
+        .iter()
+        .any(|FormatItem { opt, .. }| *opt == FormatItemOpt::Optional)
+}
+
+fn rebuild_format_string(items: &[FormatItem]) -> String {
+    items
+        .iter()
+        .filter(|FormatItem { opt, .. }| *opt == FormatItemOpt::Mandatory)
+        .map(|FormatItem { ty, .. }| ty.descriptor())
+        .collect()
+}
+
+#[test]
+fn test_frag_parse_error_spans() {
+    /// Columns of the start and the end of the error, with its message.
+    fn error_at(args: &str) -> (usize, usize, String) {
+        let args = args.parse().expect("valid tokens");
+        let err =
+            frag_parse_impl(args, Ownership::Owned, false, false, &mut TokenStream::new()).expect_err("compile error");
+        (err.1.start().column, err.1.end().column, err.0.to_string())
+    }
+
+    // Bad format literal, at the unknown descriptor
+    let (start, end, msg) = error_at(r#"checked, "%s%x", value"#);
+    assert_eq!((start, end), (13, 14));
+    assert!(msg.starts_with("Bad format string: unknown descriptor 'x'"));
+    // Stray token
+    let (start, end, msg) = error_at(r#""%s%d", value value"#);
+    assert_eq!((start, end), (14, 19));
+    assert_eq!(msg, "expected `,`");
+}
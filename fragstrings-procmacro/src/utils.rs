@@ -0,0 +1,2620 @@
+//! Utility functions for parsing fragstrings macros arguments.
+
+/// Same as `quote!`, but the tokens are spanned with `Span::mixed_site()`, so that local variables
+/// of the generated code are hygienic and never collide with the user ones, like in `macro_rules!`,
+/// while paths are resolved at the call site as usual. Interpolated tokens keep their spans.
+macro_rules! quote_mixed {
+    ($($tt:tt)*) => {
+        ::quote::quote_spanned! { ::proc_macro2::Span::mixed_site()=> $($tt)* }
+    };
+}
+
+pub mod punct {
+    use proc_macro2::{Span, TokenStream};
+    use quote::ToTokens;
+    use syn::parse::{Parse, ParseStream, Parser};
+    use syn::punctuated::Punctuated;
+    use syn::{Expr, ExprLit, Lit, LitStr, Token};
+
+    use crate::utils::errors::{CompileError, SpannedError};
+
+    /// Location of the stream for error reporting, which is the location of its first token.
+    pub fn stream_span(stream: &TokenStream) -> Span {
+        match stream.clone().into_iter().next() {
+            Some(token) => token.span(),
+            None => Span::call_site(),
+        }
+    }
+
+    /// Argument of a macro: an expression, or a format string split into adjacent string literals,
+    /// like `"%s%d" "%s"` or `new = "%s%d" "%s"`, which are concatenated.
+    struct Arg(TokenStream);
+
+    impl Parse for Arg {
+        fn parse(input: ParseStream) -> syn::Result<Self> {
+            if input.peek(Token![,]) {
+                return Err(input.error(CompileError::UnexpectedComma));
+            }
+            let expr = input.parse::<Expr>()?;
+            let value = match &expr {
+                Expr::Assign(assign) => &*assign.right,
+                expr => expr,
+            };
+            let format_string = matches!(value, Expr::Lit(ExprLit { lit: Lit::Str(_), .. }));
+            let mut arg = expr.into_token_stream();
+            if format_string {
+                while input.peek(LitStr) {
+                    input.parse::<LitStr>()?.to_tokens(&mut arg);
+                }
+            }
+            Ok(Arg(arg))
+        }
+    }
+
+    /// Splits the arguments, which are expressions, by commas, a single trailing comma is allowed,
+    /// like in `foo, bar,`. Returns the error at the unexpected token if some argument is empty or malformed.
+    pub fn parse_punctuated_args(args: TokenStream) -> Result<Vec<TokenStream>, SpannedError> {
+        let args = Punctuated::<Arg, Token![,]>::parse_terminated
+            .parse2(args)
+            .map_err(SpannedError::from)?;
+        Ok(args.into_iter().map(|Arg(arg)| arg).collect())
+    }
+
+    #[test]
+    fn test_parse_punctuated_args() {
+        use quote::quote;
+
+        fn test(input: TokenStream, expected: Option<Vec<&str>>) {
+            let parsed = parse_punctuated_args(input).ok();
+            let result = parsed.map(|args| args.into_iter().map(|stream| stream.to_string()).collect::<Vec<_>>());
+            let expected = expected.map(|args| args.into_iter().map(str::to_string).collect::<Vec<_>>());
+            assert_eq!(result, expected);
+        }
+
+        test(quote! { foo, bar, baz }, Some(vec!["foo", "bar", "baz"]));
+        test(quote! { one, 2+2, two }, Some(vec!["one", "2 + 2", "two"]));
+        test(quote! { x, (1+2)*3, y }, Some(vec!["x", "(1 + 2) * 3", "y"]));
+        test(quote! { x, (1, 2, 3), y }, Some(vec!["x", "(1 , 2 , 3)", "y"]));
+        test(quote! { foo }, Some(vec!["foo"]));
+        test(quote! {}, Some(vec![]));
+
+        // Commas which are not in groups
+        test(quote! { f::<A, B>(x), y }, Some(vec!["f :: < A , B > (x)", "y"]));
+        test(
+            quote! { Vec::<Vec<u8>>::new(), y },
+            Some(vec!["Vec :: < Vec < u8 > > :: new ()", "y"]),
+        );
+        test(
+            quote! { f::<Box<dyn Fn(A) -> B>, C>(), y },
+            Some(vec!["f :: < Box < dyn Fn (A) -> B > , C > ()", "y"]),
+        );
+        test(
+            quote! { <T as Tr<A, B>>::f(), y },
+            Some(vec!["< T as Tr < A , B > > :: f ()", "y"]),
+        );
+        test(
+            quote! { &w as &W<i64, i64>, 2 },
+            Some(vec!["& w as & W < i64 , i64 >", "2"]),
+        );
+        test(quote! { |a, b| a + b, y }, Some(vec!["| a , b | a + b", "y"]));
+        test(quote! { move |a, b| a, y }, Some(vec!["move | a , b | a", "y"]));
+        test(
+            quote! { &|a: Vec<u8>, b| a, y },
+            Some(vec!["& | a : Vec < u8 > , b | a", "y"]),
+        );
+        test(quote! { || 1, y }, Some(vec!["| | 1", "y"]));
+        test(quote! { 0..n, x..=y, .. }, Some(vec!["0 .. n", "x ..= y", ".."]));
+        test(
+            quote! { if a { 1 } else { 2 }, y },
+            Some(vec!["if a { 1 } else { 2 }", "y"]),
+        );
+
+        // Operators are not mistaken for them
+        test(quote! { a < b, c > d }, Some(vec!["a < b", "c > d"]));
+        test(quote! { a | b, c | d }, Some(vec!["a | b", "c | d"]));
+        test(quote! { a || b, c }, Some(vec!["a || b", "c"]));
+        test(quote! { a? | b, c }, Some(vec!["a ? | b", "c"]));
+        test(quote! { a as u8 > b, c }, Some(vec!["a as u8 > b", "c"]));
+
+        // Single trailing comma is allowed
+        test(quote! { foo, bar, }, Some(vec!["foo", "bar"]));
+        test(quote! { foo, }, Some(vec!["foo"]));
+
+        // Empty arguments are not
+        test(quote! { foo,, bar }, None);
+        test(quote! { foo, bar,, }, None);
+        test(quote! { , foo }, None);
+        test(quote! { , }, None);
+
+        // Nor are the ones which are not expressions
+        test(quote! { a, x -> y, b }, None);
+        test(quote! { a b, c }, None);
+
+        // Format strings can be split into adjacent literals, which are kept in one argument
+        test(
+            quote! { x = 1, "%s" r"%d" "%s", y },
+            Some(vec!["x = 1", r#""%s" r"%d" "%s""#, "y"]),
+        );
+        test(
+            quote! { new = "%s" "%d", old = "%s" },
+            Some(vec![r#"new = "%s" "%d""#, r#"old = "%s""#]),
+        );
+        test(quote! { x, "%s" y }, None);
+    }
+}
+
+pub mod options {
+    use proc_macro2::{Delimiter, Span, TokenStream, TokenTree};
+
+    use crate::utils::{
+        literals::{parse_int_literal, parse_string_literal},
+        punct::parse_punctuated_args,
+    };
+
+    /// Macro option, which precedes the format string: either a bare flag `name`,
+    /// or a `name = value` pair, where value is an arbitrary token stream.
+    pub struct MacroOption {
+        pub name: String,
+        pub value: Option<TokenStream>,
+        /// Location of the option name, for error reporting.
+        pub span: Span,
+    }
+
+    /// Splits leading options off the macro arguments.
+    /// Options are all the arguments which start with an identifier, the rest are returned as is.
+    /// Returns location of the error if some option is malformed.
+    pub fn parse_options(args: Vec<TokenStream>) -> Result<(Vec<MacroOption>, Vec<TokenStream>), Span> {
+        let mut options = Vec::new();
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.peek() {
+            let mut iter = arg.clone().into_iter();
+            let (name, span) = match iter.next() {
+                Some(TokenTree::Ident(ident)) => (ident.to_string(), ident.span()),
+                _ => break,
+            };
+            let value = match iter.next() {
+                None => None,
+                Some(TokenTree::Punct(punct)) if punct.as_char() == '=' => {
+                    let value = iter.collect::<TokenStream>();
+                    if value.is_empty() {
+                        return Err(punct.span());
+                    }
+                    Some(value)
+                }
+                Some(token) => return Err(token.span()),
+            };
+            options.push(MacroOption { name, value, span });
+            let _ = args.next(); // Consume it
+        }
+        Ok((options, args.collect()))
+    }
+
+    /// Parses option value which is an unsigned integer literal, like in `version = 2`.
+    pub fn parse_int_value(value: &TokenStream) -> Option<u64> {
+        let mut iter = value.clone().into_iter();
+        match (iter.next(), iter.next()) {
+            (Some(TokenTree::Literal(lit)), None) => parse_int_literal(&lit.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Parses option value which is a string literal, like in `null_sentinel = "NULL"`.
+    pub fn parse_str_value(value: &TokenStream) -> Option<String> {
+        let mut iter = value.clone().into_iter();
+        match (iter.next(), iter.next()) {
+            (Some(TokenTree::Literal(lit)), None) => parse_string_literal(&lit.to_string()).map(|s| s.into_owned()),
+            _ => None,
+        }
+    }
+
+    /// Parses option value which is an identifier, like in `radix = auto`.
+    pub fn parse_ident_value(value: &TokenStream) -> Option<String> {
+        let mut iter = value.clone().into_iter();
+        match (iter.next(), iter.next()) {
+            (Some(TokenTree::Ident(ident)), None) => Some(ident.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Parses option value which is a list in square brackets, like in `version = [1, 2]`.
+    pub fn parse_list_value(value: &TokenStream) -> Option<Vec<TokenStream>> {
+        let mut iter = value.clone().into_iter();
+        match (iter.next(), iter.next()) {
+            (Some(TokenTree::Group(group)), None) if group.delimiter() == Delimiter::Bracket => {
+                parse_punctuated_args(group.stream()).ok()
+            }
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_parse_options() {
+        use quote::quote;
+
+        fn test(input: Vec<TokenStream>, expected: Option<(Vec<&str>, usize)>) {
+            let result = parse_options(input).ok().map(|(options, rest)| {
+                let options = options
+                    .into_iter()
+                    .map(|opt| match opt.value {
+                        Some(value) => format!("{} = {}", opt.name, value),
+                        None => opt.name,
+                    })
+                    .collect::<Vec<_>>();
+                (options, rest.len())
+            });
+            let expected = expected.map(|(options, rest)| (options.into_iter().map(str::to_string).collect(), rest));
+            assert_eq!(result, expected);
+        }
+
+        test(vec![quote! { "%s" }, quote! { x }], Some((vec![], 2)));
+        test(
+            vec![quote! { foo }, quote! { "%s" }, quote! { x }],
+            Some((vec!["foo"], 2)),
+        );
+        test(vec![quote! { foo = 2 }, quote! { "%s" }], Some((vec!["foo = 2"], 1)));
+        test(
+            vec![quote! { foo = &bar }, quote! { baz }],
+            Some((vec!["foo = & bar", "baz"], 0)),
+        );
+        test(vec![quote! { foo = }, quote! { "%s" }], None);
+        test(vec![quote! { foo bar }, quote! { "%s" }], None);
+        test(vec![quote! { foo.bar }, quote! { "%s" }], None);
+    }
+
+    #[test]
+    fn test_parse_option_values() {
+        use quote::quote;
+
+        assert_eq!(parse_int_value(&quote! { 2 }), Some(2));
+        assert_eq!(parse_int_value(&quote! { 1_000 }), Some(1000));
+        assert_eq!(parse_int_value(&quote! { -2 }), None);
+        assert_eq!(parse_int_value(&quote! { 2 3 }), None);
+        assert_eq!(parse_int_value(&quote! { "2" }), None);
+        assert_eq!(parse_int_value(&quote! { x }), None);
+
+        assert_eq!(parse_str_value(&quote! { "NULL" }), Some("NULL".to_string()));
+        assert_eq!(parse_str_value(&quote! { r"a\b" }), Some("a\\b".to_string()));
+        assert_eq!(parse_str_value(&quote! { b"NULL" }), None);
+        assert_eq!(parse_str_value(&quote! { 2 }), None);
+        assert_eq!(parse_str_value(&quote! { "a" "b" }), None);
+
+        assert_eq!(parse_ident_value(&quote! { auto }), Some("auto".to_string()));
+        assert_eq!(parse_ident_value(&quote! { "auto" }), None);
+        assert_eq!(parse_ident_value(&quote! { auto auto }), None);
+
+        let list =
+            |value| parse_list_value(&value).map(|items| items.iter().map(|it| it.to_string()).collect::<Vec<_>>());
+        assert_eq!(list(quote! { [] }), Some(vec![]));
+        assert_eq!(list(quote! { [1] }), Some(vec!["1".to_string()]));
+        assert_eq!(list(quote! { [1, x] }), Some(vec!["1".to_string(), "x".to_string()]));
+        assert_eq!(list(quote! { (1, 2) }), None);
+        assert_eq!(list(quote! { [1] [2] }), None);
+        assert_eq!(list(quote! { 1 }), None);
+    }
+}
+
+pub mod literals {
+    use proc_macro2::{Literal, Span, TokenStream, TokenTree};
+    use std::borrow::Cow;
+
+    use crate::utils::errors::{CompileError, SpannedError};
+
+    /// Location of the character at `pos` of the parsed string literal `value`, if possible,
+    /// which is if the literal has no escape sequences, and the compiler supports sub-spans.
+    pub fn literal_subspan(lit: &Literal, value: &str, pos: usize) -> Option<Span> {
+        let source = lit.to_string();
+        let content_start = source.find('"')? + 1;
+        if !source[content_start..].starts_with(value) {
+            return None;
+        }
+        let len = value.get(pos..)?.chars().next().map_or(0, char::len_utf8);
+        let start = content_start + pos;
+        lit.subspan(start..start + len)
+    }
+
+    /// String written as adjacent string literals, like `"%s%d" "%s"` or `"%s" r"%d"`, which are concatenated,
+    /// so that long format strings can be split into lines.
+    pub struct StringLiterals {
+        pub value: String,
+        /// Location of the first literal
+        pub span: Span,
+        /// Literals with the offsets of their values in the concatenated one
+        parts: Vec<(Literal, usize)>,
+    }
+
+    impl StringLiterals {
+        /// Location of the character at `pos` of the concatenated value, if possible, see `literal_subspan`,
+        /// or else of the literal which it is in.
+        pub fn span_at(&self, pos: usize) -> Span {
+            let part = self
+                .parts
+                .iter()
+                .enumerate()
+                .take_while(|(_, (_, offset))| *offset <= pos)
+                .last();
+            let (i, (lit, offset)) = match part {
+                Some(part) => part,
+                None => return self.span,
+            };
+            let end = self.parts.get(i + 1).map_or(self.value.len(), |(_, end)| *end);
+            self.value
+                .get(*offset..end)
+                .and_then(|value| literal_subspan(lit, value, pos - offset))
+                .unwrap_or_else(|| lit.span())
+        }
+    }
+
+    /// Parses the argument which is one or more adjacent string literals, normal or raw, and concatenates them.
+    pub fn parse_string_literals(arg: TokenStream) -> Result<StringLiterals, SpannedError> {
+        let mut value = String::new();
+        let mut parts = Vec::new();
+        for (i, token) in arg.into_iter().enumerate() {
+            let lit = match token {
+                TokenTree::Literal(lit) => lit,
+                token if i == 0 => return Err(CompileError::StringLiteralExpected.at(token.span())),
+                token => return Err(CompileError::UnrecognizedToken.at(token.span())),
+            };
+            let source = lit.to_string();
+            match literal_kind(&source) {
+                LiteralKind::Str => {}
+                kind => return Err(CompileError::NotStrLiteral(kind).at(lit.span())),
+            }
+            let part = parse_string_literal(&source).ok_or_else(|| CompileError::BadStringLiteral.at(lit.span()))?;
+            parts.push((lit, value.len()));
+            value.push_str(&part);
+        }
+        let span = match parts.first() {
+            Some((lit, _)) => lit.span(),
+            None => return Err(CompileError::NoArgs.at(Span::call_site())),
+        };
+        Ok(StringLiterals { value, span, parts })
+    }
+
+    /// Kind of a literal token.
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub enum LiteralKind {
+        /// `"..."` or `r"..."`
+        Str,
+        /// `b"..."` or `br"..."`
+        ByteStr,
+        /// `c"..."` or `cr"..."`
+        CStr,
+        /// `'x'`
+        Char,
+        /// `b'x'`
+        Byte,
+        /// Integer or float literal
+        Number,
+    }
+
+    /// Determines kind of a literal, as printed by the `proc_macro2::Literal`, by its prefix.
+    pub fn literal_kind(lit: &str) -> LiteralKind {
+        let mut chars = lit.chars();
+        match (chars.next(), chars.next()) {
+            (Some('b'), Some('\'')) => LiteralKind::Byte,
+            (Some('b'), _) => LiteralKind::ByteStr,
+            (Some('c'), _) => LiteralKind::CStr,
+            (Some('\''), _) => LiteralKind::Char,
+            (Some('"'), _) | (Some('r'), _) => LiteralKind::Str,
+            _ => LiteralKind::Number,
+        }
+    }
+
+    /// Parses string literal, as printed by the `proc_macro2::Literal`, into its value.
+    /// Supports normal strings (with escape sequences) and raw strings.
+    /// Other kinds of literals, like byte strings, are not strings and are not parsed.
+    pub fn parse_string_literal(lit: &str) -> Option<Cow<'_, str>> {
+        if let Some(raw) = lit.strip_prefix('r') {
+            return parse_raw_string(raw).map(Cow::Borrowed);
+        }
+        let content = lit.strip_prefix('"')?.strip_suffix('"')?;
+        unescape(content)
+    }
+
+    // Raw string after the 'r' prefix: the content is enclosed in quotes and equal number of hashes.
+    fn parse_raw_string(s: &str) -> Option<&str> {
+        let hashes = s.chars().take_while(|&ch| ch == '#').count();
+        let closing = "#".repeat(hashes);
+        let s = s[hashes..].strip_suffix(closing.as_str())?;
+        s.strip_prefix('"')?.strip_suffix('"')
+    }
+
+    fn unescape(s: &str) -> Option<Cow<'_, str>> {
+        if !s.contains(['\\', '"']) {
+            return Some(Cow::Borrowed(s));
+        }
+        let mut res = String::with_capacity(s.len());
+        let mut chars = s.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '"' => return None,
+                '\\' => match chars.next()? {
+                    'n' => res.push('\n'),
+                    'r' => res.push('\r'),
+                    't' => res.push('\t'),
+                    '0' => res.push('\0'),
+                    '\\' => res.push('\\'),
+                    '\'' => res.push('\''),
+                    '"' => res.push('"'),
+                    'x' => {
+                        let hi = chars.next()?.to_digit(16)?;
+                        let lo = chars.next()?.to_digit(16)?;
+                        res.push(char::from_u32(hi * 16 + lo).filter(char::is_ascii)?);
+                    }
+                    'u' => {
+                        if chars.next()? != '{' {
+                            return None;
+                        }
+                        let mut code: u32 = 0;
+                        let mut digits = 0;
+                        loop {
+                            match chars.next()? {
+                                '}' => break,
+                                '_' => continue,
+                                ch => {
+                                    code = code.checked_mul(16)?.checked_add(ch.to_digit(16)?)?;
+                                    digits += 1;
+                                }
+                            }
+                        }
+                        if digits == 0 || digits > 6 {
+                            return None;
+                        }
+                        res.push(char::from_u32(code)?);
+                    }
+                    // Line continuation: the newline and the leading whitespace of the next line are skipped
+                    '\n' => while chars.next_if(|ch| ch.is_whitespace()).is_some() {},
+                    _ => return None,
+                },
+                _ => res.push(ch),
+            }
+        }
+        Some(Cow::Owned(res))
+    }
+
+    /// Parses unsuffixed decimal integer literal, possibly with underscores.
+    pub fn parse_int_literal(lit: &str) -> Option<u64> {
+        if !lit.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        let mut value: u64 = 0;
+        for ch in lit.chars() {
+            match ch {
+                '_' => continue,
+                '0'..='9' => value = value.checked_mul(10)?.checked_add(ch as u64 - '0' as u64)?,
+                _ => return None,
+            }
+        }
+        Some(value)
+    }
+
+    #[test]
+    fn test_parse_int_literal() {
+        assert_eq!(parse_int_literal("0"), Some(0));
+        assert_eq!(parse_int_literal("42"), Some(42));
+        assert_eq!(parse_int_literal("1_000"), Some(1000));
+        assert_eq!(parse_int_literal("18446744073709551615"), Some(u64::MAX));
+
+        assert_eq!(parse_int_literal(""), None);
+        assert_eq!(parse_int_literal("_1"), None);
+        assert_eq!(parse_int_literal("-1"), None);
+        assert_eq!(parse_int_literal("1.0"), None);
+        assert_eq!(parse_int_literal("0x10"), None);
+        assert_eq!(parse_int_literal("1u32"), None);
+        assert_eq!(parse_int_literal("18446744073709551616"), None);
+    }
+
+    #[test]
+    fn test_literal_kind() {
+        use LiteralKind::*;
+        assert_eq!(literal_kind(r#####""foo""#####), Str);
+        assert_eq!(literal_kind(r#####"r"foo""#####), Str);
+        assert_eq!(literal_kind(r#####"r#"foo"#"#####), Str);
+        assert_eq!(literal_kind(r#####"b"foo""#####), ByteStr);
+        assert_eq!(literal_kind(r#####"br"foo""#####), ByteStr);
+        assert_eq!(literal_kind(r#####"br#"foo"#"#####), ByteStr);
+        assert_eq!(literal_kind(r#####"c"foo""#####), CStr);
+        assert_eq!(literal_kind(r#####"cr"foo""#####), CStr);
+        assert_eq!(literal_kind(r#####"'x'"#####), Char);
+        assert_eq!(literal_kind(r#####"'\''"#####), Char);
+        assert_eq!(literal_kind(r#####"b'x'"#####), Byte);
+        assert_eq!(literal_kind(r#####"42"#####), Number);
+        assert_eq!(literal_kind(r#####"-1i64"#####), Number);
+        assert_eq!(literal_kind(r#####"1.5e3"#####), Number);
+    }
+
+    #[test]
+    fn test_parse_string_literal() {
+        assert_eq!(parse_string_literal(r#####""""#####).as_deref(), Some(""));
+        assert_eq!(parse_string_literal(r#####""foo""#####).as_deref(), Some("foo"));
+        assert_eq!(parse_string_literal(r#####"r"foo""#####).as_deref(), Some("foo"));
+        assert_eq!(parse_string_literal(r#####"r#"foo"#"#####).as_deref(), Some("foo"));
+        assert_eq!(parse_string_literal(r#####"r##"foo"##"#####).as_deref(), Some("foo"));
+        assert_eq!(parse_string_literal(r#####"r###"foo"###"#####).as_deref(), Some("foo"));
+
+        assert_eq!(parse_string_literal(r#####""#####).as_deref(), None);
+        assert_eq!(parse_string_literal(r#####"""#####).as_deref(), None);
+        assert_eq!(parse_string_literal(r#####"'foo'"#####).as_deref(), None);
+        assert_eq!(parse_string_literal(r#####"'foo"#####).as_deref(), None);
+        assert_eq!(parse_string_literal(r#####"foo'"#####).as_deref(), None);
+        assert_eq!(parse_string_literal(r#####""foo"#####).as_deref(), None);
+        assert_eq!(parse_string_literal(r#####"foo""#####).as_deref(), None);
+        assert_eq!(parse_string_literal(r#####"r#"foo""#####).as_deref(), None);
+        assert_eq!(parse_string_literal(r#####"b"foo""#####).as_deref(), None);
+        assert_eq!(parse_string_literal(r#####"br"foo""#####).as_deref(), None);
+        assert_eq!(parse_string_literal(r#####"c"foo""#####).as_deref(), None);
+        assert_eq!(parse_string_literal(r#####"r"foo"#"#####).as_deref(), None);
+
+        // Escape sequences
+        assert_eq!(parse_string_literal(r#####""%s\\%d""#####).as_deref(), Some("%s\\%d"));
+        assert_eq!(parse_string_literal(r#####""\"foo\"""#####).as_deref(), Some("\"foo\""));
+        assert_eq!(
+            parse_string_literal(r#####""\n\r\t\0\'""#####).as_deref(),
+            Some("\n\r\t\0'")
+        );
+        assert_eq!(
+            parse_string_literal(r#####""\x25s\u{25}d""#####).as_deref(),
+            Some("%s%d")
+        );
+        assert_eq!(
+            parse_string_literal(r#####""\u{1F600}\u{1_F600}""#####).as_deref(),
+            Some("😀😀")
+        );
+        assert_eq!(parse_string_literal("\"%s\\\n    %d\"").as_deref(), Some("%s%d"));
+        assert_eq!(parse_string_literal(r#####""\q""#####), None);
+        assert_eq!(parse_string_literal(r#####""\x80""#####), None);
+        assert_eq!(parse_string_literal(r#####""\x2""#####), None);
+        assert_eq!(parse_string_literal(r#####""\u25""#####), None);
+        assert_eq!(parse_string_literal(r#####""\u{}""#####), None);
+        assert_eq!(parse_string_literal(r#####""\u{D800}""#####), None);
+        assert_eq!(parse_string_literal(r#####""\u{1000000}""#####), None);
+        assert_eq!(parse_string_literal(r#####""foo\""#####), None);
+        assert_eq!(parse_string_literal(r#####""foo"bar""#####), None);
+
+        // Raw strings, the number of hashes must match exactly, and quotes are allowed inside
+        assert_eq!(
+            parse_string_literal(r#####"r#""foo""#"#####).as_deref(),
+            Some("\"foo\"")
+        );
+        assert_eq!(parse_string_literal(r#####"r##"a"#"##"#####).as_deref(), Some("a\"#"));
+        assert_eq!(parse_string_literal(r#####"r"\n""#####).as_deref(), Some("\\n"));
+        assert_eq!(parse_string_literal(r#####"r##"foo"#"#####), None);
+        assert_eq!(parse_string_literal(r#####"r#"foo"##"#####), None);
+        assert_eq!(parse_string_literal(r#####"r#foo#"#####), None);
+
+        // Non-ASCII content: Cyrillic, combining characters, emoji
+        assert_eq!(parse_string_literal(r#####""привет""#####).as_deref(), Some("привет"));
+        assert_eq!(
+            parse_string_literal(r#####""e\u{301}""#####).as_deref(),
+            Some("e\u{301}")
+        );
+        assert_eq!(parse_string_literal("\"e\u{301}\"").as_deref(), Some("e\u{301}"));
+        assert_eq!(parse_string_literal(r#####""😀%s👍🏽""#####).as_deref(), Some("😀%s👍🏽"));
+        assert_eq!(
+            parse_string_literal(r#####"r#"😀"%s"👍🏽"#"#####).as_deref(),
+            Some("😀\"%s\"👍🏽")
+        );
+        assert_eq!(parse_string_literal(r#####"r"я""#####).as_deref(), Some("я"));
+        assert_eq!(parse_string_literal(r#####""я"#####), None);
+        assert_eq!(parse_string_literal(r#####"я""#####), None);
+        assert_eq!(parse_string_literal(r#####"'я'"#####), None);
+
+        // Other kinds of literals
+        assert_eq!(parse_string_literal(r#####"c"foo""#####), None);
+        assert_eq!(parse_string_literal(r#####"42"#####), None);
+        assert_eq!(parse_string_literal(r#####"'%'"#####), None);
+    }
+
+    #[test]
+    fn test_parse_string_literals() {
+        use quote::quote;
+
+        let value = |arg: TokenStream| parse_string_literals(arg).ok().map(|literals| literals.value);
+        assert_eq!(value(quote! { "%s%d" }).as_deref(), Some("%s%d"));
+        assert_eq!(value(quote! { "%s%d" "%s" }).as_deref(), Some("%s%d%s"));
+        assert_eq!(value(quote! { "%s" r"%d" "" r#"%s"# }).as_deref(), Some("%s%d%s"));
+        assert_eq!(value(quote! { "\x25s" "%d" }).as_deref(), Some("%s%d"));
+
+        assert_eq!(value(quote! {}), None);
+        assert_eq!(value(quote! { x "%s" }), None);
+        assert_eq!(value(quote! { "%s" x "%d" }), None);
+        assert_eq!(value(quote! { "%s" , "%d" }), None);
+        assert_eq!(value(quote! { "%s" b"%d" }), None);
+        assert_eq!(value(quote! { "%s" 1 }), None);
+    }
+}
+
+pub mod regex {
+    /// Regular expression of the supported subset: literal characters, `.`, classes like `[A-Z_]` or `[^,]`,
+    /// escapes `\d`, `\w`, `\s` and their negations, groups, alternation and quantifiers `*`, `+`, `?`, `{n,m}`.
+    /// It always matches the whole fragment, so `^` and `$` are allowed only at the ends of the pattern.
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub enum Regex {
+        Char(char),
+        Any,
+        Class {
+            negated: bool,
+            ranges: Vec<(char, char)>,
+        },
+        Concat(Vec<Regex>),
+        Alt(Vec<Regex>),
+        /// Repetition with the maximum count, if any.
+        Repeat {
+            inner: Box<Regex>,
+            min: u32,
+            max: Option<u32>,
+        },
+    }
+
+    /// Parses the pattern, or returns the byte offset of the offending character.
+    pub fn parse_regex(pattern: &str) -> Result<Regex, usize> {
+        let body = pattern.strip_prefix('^').unwrap_or(pattern);
+        let offset = pattern.len() - body.len();
+        let body = match body.strip_suffix('$') {
+            // Escaped dollar is a literal one
+            Some(stripped) if stripped.chars().rev().take_while(|&ch| ch == '\\').count() % 2 == 0 => stripped,
+            _ => body,
+        };
+        let chars = body
+            .char_indices()
+            .map(|(pos, ch)| (pos + offset, ch))
+            .collect::<Vec<_>>();
+        let mut parser = Parser { chars, pos: 0 };
+        let regex = parser.alt()?;
+        match parser.chars.get(parser.pos) {
+            Some(&(pos, _)) => Err(pos),
+            None => Ok(regex),
+        }
+    }
+
+    struct Parser {
+        chars: Vec<(usize, char)>,
+        pos: usize,
+    }
+
+    impl Parser {
+        fn peek(&self) -> Option<char> {
+            self.chars.get(self.pos).map(|&(_, ch)| ch)
+        }
+
+        fn next(&mut self) -> Option<char> {
+            let ch = self.peek();
+            self.pos += 1;
+            ch
+        }
+
+        /// Offset of the current character in the pattern, or of its end.
+        fn offset(&self) -> usize {
+            match self.chars.get(self.pos) {
+                Some(&(pos, _)) => pos,
+                None => self.chars.last().map_or(0, |&(pos, ch)| pos + ch.len_utf8()),
+            }
+        }
+
+        fn alt(&mut self) -> Result<Regex, usize> {
+            let mut alts = vec![self.concat()?];
+            while self.peek() == Some('|') {
+                self.pos += 1;
+                alts.push(self.concat()?);
+            }
+            Ok(if alts.len() == 1 {
+                alts.remove(0)
+            } else {
+                Regex::Alt(alts)
+            })
+        }
+
+        fn concat(&mut self) -> Result<Regex, usize> {
+            let mut items = Vec::new();
+            while let Some(ch) = self.peek() {
+                if ch == '|' || ch == ')' {
+                    break;
+                }
+                let atom = self.atom()?;
+                items.push(self.quantified(atom)?);
+            }
+            Ok(if items.len() == 1 {
+                items.remove(0)
+            } else {
+                Regex::Concat(items)
+            })
+        }
+
+        fn atom(&mut self) -> Result<Regex, usize> {
+            let pos = self.offset();
+            match self.next() {
+                Some('(') => {
+                    // Non-capturing group is the same, nothing is captured anyway
+                    if self.peek() == Some('?') {
+                        self.pos += 1;
+                        if self.next() != Some(':') {
+                            return Err(pos);
+                        }
+                    }
+                    let inner = self.alt()?;
+                    match self.next() {
+                        Some(')') => Ok(inner),
+                        _ => Err(pos),
+                    }
+                }
+                Some('[') => self.class(pos),
+                Some('.') => Ok(Regex::Any),
+                Some('\\') => self.escape(pos),
+                Some('*' | '+' | '?' | '{' | '}' | ']' | '^' | '$') | None => Err(pos),
+                Some(ch) => Ok(Regex::Char(ch)),
+            }
+        }
+
+        fn escape(&mut self, pos: usize) -> Result<Regex, usize> {
+            let class = |negated, ranges: &[(char, char)]| Regex::Class {
+                negated,
+                ranges: ranges.to_vec(),
+            };
+            Ok(match self.next() {
+                Some('d') => class(false, DIGITS),
+                Some('D') => class(true, DIGITS),
+                Some('w') => class(false, WORD),
+                Some('W') => class(true, WORD),
+                Some('s') => class(false, SPACE),
+                Some('S') => class(true, SPACE),
+                Some(ch) => Regex::Char(self.escaped_char(ch).ok_or(pos)?),
+                None => return Err(pos),
+            })
+        }
+
+        fn escaped_char(&self, ch: char) -> Option<char> {
+            match ch {
+                't' => Some('\t'),
+                'n' => Some('\n'),
+                'r' => Some('\r'),
+                ch if ch.is_ascii_punctuation() => Some(ch),
+                _ => None,
+            }
+        }
+
+        fn class(&mut self, pos: usize) -> Result<Regex, usize> {
+            let negated = self.peek() == Some('^');
+            if negated {
+                self.pos += 1;
+            }
+            let mut ranges = Vec::new();
+            let mut first = true;
+            loop {
+                let lo = match self.next() {
+                    Some(']') if !first => break,
+                    Some('\\') => match self.next() {
+                        Some('d') => {
+                            ranges.extend_from_slice(DIGITS);
+                            continue;
+                        }
+                        Some('w') => {
+                            ranges.extend_from_slice(WORD);
+                            continue;
+                        }
+                        Some('s') => {
+                            ranges.extend_from_slice(SPACE);
+                            continue;
+                        }
+                        Some(ch) => self.escaped_char(ch).ok_or(pos)?,
+                        None => return Err(pos),
+                    },
+                    Some(ch) => ch,
+                    None => return Err(pos),
+                };
+                first = false;
+                // Dash before the closing bracket is a literal one
+                let is_range = self.peek() == Some('-') && self.chars.get(self.pos + 1).map(|&(_, ch)| ch) != Some(']');
+                if !is_range {
+                    ranges.push((lo, lo));
+                    continue;
+                }
+                self.pos += 1;
+                let hi = match self.next() {
+                    Some('\\') => match self.next() {
+                        Some(ch) => self.escaped_char(ch).ok_or(pos)?,
+                        None => return Err(pos),
+                    },
+                    Some(ch) => ch,
+                    None => return Err(pos),
+                };
+                if hi < lo {
+                    return Err(pos);
+                }
+                ranges.push((lo, hi));
+            }
+            Ok(Regex::Class { negated, ranges })
+        }
+
+        fn quantified(&mut self, atom: Regex) -> Result<Regex, usize> {
+            let pos = self.offset();
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => {
+                    let start = self.pos + 1;
+                    let end = (start..self.chars.len()).find(|&i| self.chars[i].1 == '}').ok_or(pos)?;
+                    let counts = self.chars[start..end].iter().map(|&(_, ch)| ch).collect::<String>();
+                    let count = |s: &str| s.parse::<u32>().map_err(|_| pos);
+                    let (min, max) = match counts.split_once(',') {
+                        None => (count(&counts)?, Some(count(&counts)?)),
+                        Some((min, "")) => (count(min)?, None),
+                        Some((min, max)) => (count(min)?, Some(count(max)?)),
+                    };
+                    if max.is_some_and(|max| max < min) {
+                        return Err(pos);
+                    }
+                    self.pos = end;
+                    (min, max)
+                }
+                _ => return Ok(atom),
+            };
+            self.pos += 1;
+            // Lazy quantifier matches the same whole fragments as the greedy one
+            if self.peek() == Some('?') {
+                self.pos += 1;
+            }
+            // Repetition of a repetition is not supported
+            if matches!(self.peek(), Some('*' | '+' | '?' | '{')) {
+                return Err(self.offset());
+            }
+            Ok(Regex::Repeat {
+                inner: Box::new(atom),
+                min,
+                max,
+            })
+        }
+    }
+
+    /// Instruction of the program, which the runtime matcher runs as an NFA, so the time is linear in the length
+    /// of the fragment on any pattern.
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub enum Inst {
+        Char(char),
+        Any,
+        Class {
+            negated: bool,
+            ranges: Vec<(char, char)>,
+        },
+        /// Continues with both instructions.
+        Split(usize, usize),
+        Jump(usize),
+        Match,
+    }
+
+    /// Longest program, since the runtime matcher keeps sets of its instructions on the stack.
+    pub const MAX_PROGRAM_LEN: usize = 1000;
+
+    /// Compiles the regex into the program, with the counted repetitions unrolled,
+    /// or returns `None` if it is longer than `MAX_PROGRAM_LEN`.
+    pub fn compile(regex: &Regex) -> Option<Vec<Inst>> {
+        let mut program = Vec::new();
+        emit(regex, &mut program)?;
+        program.push(Inst::Match);
+        Some(program).filter(|program| program.len() <= MAX_PROGRAM_LEN)
+    }
+
+    fn emit(regex: &Regex, program: &mut Vec<Inst>) -> Option<()> {
+        match regex {
+            Regex::Char(ch) => program.push(Inst::Char(*ch)),
+            Regex::Any => program.push(Inst::Any),
+            Regex::Class { negated, ranges } => program.push(Inst::Class {
+                negated: *negated,
+                ranges: ranges.clone(),
+            }),
+            Regex::Concat(items) => {
+                for item in items {
+                    emit(item, program)?;
+                }
+            }
+            Regex::Alt(alts) => {
+                // Every alternative but the last one is split from the rest, and jumps to the end
+                let mut jumps = Vec::new();
+                for (i, alt) in alts.iter().enumerate() {
+                    if i + 1 == alts.len() {
+                        emit(alt, program)?;
+                        break;
+                    }
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    emit(alt, program)?;
+                    jumps.push(program.len());
+                    program.push(Inst::Jump(0));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                let end = program.len();
+                for jump in jumps {
+                    program[jump] = Inst::Jump(end);
+                }
+            }
+            Regex::Repeat { inner, min, max } => {
+                // Empty repetitions are the same however many there are
+                if emits_nothing(inner) {
+                    return Some(());
+                }
+                for _ in 0..*min {
+                    emit(inner, program)?;
+                    if program.len() > MAX_PROGRAM_LEN {
+                        return None;
+                    }
+                }
+                match *max {
+                    None => {
+                        let split = program.len();
+                        program.push(Inst::Split(split + 1, 0));
+                        emit(inner, program)?;
+                        program.push(Inst::Jump(split));
+                        program[split] = Inst::Split(split + 1, program.len());
+                    }
+                    Some(max) => {
+                        // Every optional repetition skips all the rest
+                        let mut splits = Vec::new();
+                        for _ in *min..max {
+                            splits.push(program.len());
+                            program.push(Inst::Split(program.len() + 1, 0));
+                            emit(inner, program)?;
+                            if program.len() > MAX_PROGRAM_LEN {
+                                return None;
+                            }
+                        }
+                        let end = program.len();
+                        for split in splits {
+                            program[split] = Inst::Split(split + 1, end);
+                        }
+                    }
+                }
+            }
+        }
+        Some(()).filter(|_| program.len() <= MAX_PROGRAM_LEN)
+    }
+
+    /// Whether the regex is compiled into no instructions, and matches the empty string only.
+    fn emits_nothing(regex: &Regex) -> bool {
+        match regex {
+            Regex::Concat(items) => items.iter().all(emits_nothing),
+            Regex::Repeat { inner, max, .. } => *max == Some(0) || emits_nothing(inner),
+            _ => false,
+        }
+    }
+
+    const DIGITS: &[(char, char)] = &[('0', '9')];
+    const WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+    const SPACE: &[(char, char)] = &[('\t', '\r'), (' ', ' ')];
+
+    #[test]
+    fn test_parse_regex() {
+        use Regex::*;
+
+        let class = |negated, ranges: &[(char, char)]| Class {
+            negated,
+            ranges: ranges.to_vec(),
+        };
+        let repeat = |inner, min, max| Repeat {
+            inner: Box::new(inner),
+            min,
+            max,
+        };
+
+        assert_eq!(parse_regex("a"), Ok(Char('a')));
+        assert_eq!(parse_regex(""), Ok(Concat(vec![])));
+        assert_eq!(
+            parse_regex("^[A-Z]{2,3}$"),
+            Ok(repeat(class(false, &[('A', 'Z')]), 2, Some(3)))
+        );
+        assert_eq!(parse_regex(r"\d+"), Ok(repeat(class(false, DIGITS), 1, None)));
+        assert_eq!(
+            parse_regex("a|b."),
+            Ok(Alt(vec![Char('a'), Concat(vec![Char('b'), Any])]))
+        );
+        assert_eq!(
+            parse_regex("(?:ab)*?"),
+            Ok(repeat(Concat(vec![Char('a'), Char('b')]), 0, None))
+        );
+        assert_eq!(parse_regex("[^-a]"), Ok(class(true, &[('-', '-'), ('a', 'a')])));
+        assert_eq!(
+            parse_regex("[]a-]"),
+            Ok(class(false, &[(']', ']'), ('a', 'a'), ('-', '-')]))
+        );
+        assert_eq!(
+            parse_regex(r"x{3}\$"),
+            Ok(Concat(vec![repeat(Char('x'), 3, Some(3)), Char('$')]))
+        );
+        assert_eq!(parse_regex(r"\\$"), Ok(Char('\\')));
+
+        assert_eq!(parse_regex("(a"), Err(0));
+        assert_eq!(parse_regex("a)"), Err(1));
+        assert_eq!(parse_regex("[a"), Err(0));
+        assert_eq!(parse_regex("[z-a]"), Err(0));
+        assert_eq!(parse_regex("a{3,2}"), Err(1));
+        assert_eq!(parse_regex("a{x}"), Err(1));
+        assert_eq!(parse_regex("a**"), Err(2));
+        assert_eq!(parse_regex("*"), Err(0));
+        assert_eq!(parse_regex("a^b"), Err(1));
+        assert_eq!(parse_regex(r"\b"), Err(0));
+        assert_eq!(parse_regex("(?=a)"), Err(0));
+    }
+
+    #[test]
+    fn test_compile() {
+        use Inst::*;
+
+        let compile = |pattern: &str| compile(&parse_regex(pattern).expect("good regex"));
+        assert_eq!(compile("ab"), Some(vec![Char('a'), Char('b'), Match]));
+        assert_eq!(
+            compile("a|b|."),
+            Some(vec![
+                Split(1, 3),
+                Char('a'),
+                Jump(7),
+                Split(4, 6),
+                Char('b'),
+                Jump(7),
+                Any,
+                Match
+            ])
+        );
+        assert_eq!(compile("a*"), Some(vec![Split(1, 3), Char('a'), Jump(0), Match]));
+        assert_eq!(
+            compile("a{1,3}"),
+            Some(vec![Char('a'), Split(2, 5), Char('a'), Split(4, 5), Char('a'), Match])
+        );
+        assert_eq!(compile("(){1000000000}"), Some(vec![Match]));
+        assert_eq!(compile("(){0,1000000000}"), Some(vec![Match]));
+        assert_eq!(
+            compile(&"a".repeat(MAX_PROGRAM_LEN - 1)).map(|program| program.len()),
+            Some(MAX_PROGRAM_LEN)
+        );
+        assert_eq!(compile(&"a".repeat(MAX_PROGRAM_LEN)), None);
+        assert_eq!(compile("a{1000000000}"), None);
+        assert_eq!(compile("(a{0,100}){0,100}"), None);
+    }
+}
+
+pub mod fmt_strings {
+
+    use std::fmt;
+
+    use self::FormatEnding::{Closed, Open};
+    use self::FormatItemOpt::{Mandatory, Optional};
+    use self::FormatItemType::{Base62, Int, Path, Str, StrMap, StrSet};
+
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub struct FormatString(pub Vec<FormatItem>, pub FormatEnding);
+
+    /// Format item, like `%s` or `%d?`, with its annotations.
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub struct FormatItem {
+        pub ty: FormatItemType,
+        pub opt: FormatItemOpt,
+        /// Custom type, like `%s:Color`.
+        pub custom_type: Option<String>,
+        /// Length limit, like `%s[..64]`.
+        pub length_limit: Option<LengthLimit>,
+        /// Regex, like `%s[re=r'[A-Z]+']`.
+        pub regex: Option<String>,
+        /// Set of allowed values, like `%s{trie:foo,bar}`.
+        pub trie: Option<Vec<String>>,
+        /// Byte range of the fragment which is taken, like `%s[slice:2..8]`.
+        pub slice: Option<ByteRange>,
+        /// Padding, like `%s[pad:20]`.
+        pub padding: Option<Padding>,
+        /// The only allowed value, like `%s[assert=v1]`.
+        pub assert: Option<String>,
+    }
+
+    /// Length limit of a string item, like `[..64]` in chars or `[bytes:..64]` in bytes.
+    /// Both bounds are inclusive.
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub struct LengthLimit {
+        pub unit: LengthUnit,
+        pub min: Option<usize>,
+        pub max: Option<usize>,
+    }
+
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub enum LengthUnit {
+        Chars,
+        Bytes,
+    }
+
+    impl fmt::Display for LengthLimit {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("[")?;
+            if self.unit == LengthUnit::Bytes {
+                f.write_str("bytes:")?;
+            }
+            if let Some(min) = self.min {
+                write!(f, "{}", min)?;
+            }
+            f.write_str("..")?;
+            if let Some(max) = self.max {
+                write!(f, "{}", max)?;
+            }
+            f.write_str("]")
+        }
+    }
+
+    /// Parses the length limit without brackets, like `..64` or `bytes:1..64`.
+    fn parse_length_limit(s: &str) -> Option<LengthLimit> {
+        let (unit, s) = match s.strip_prefix("bytes:") {
+            Some(s) => (LengthUnit::Bytes, s),
+            None => (LengthUnit::Chars, s),
+        };
+        let (min, max) = s.split_once("..")?;
+        let bound = |s: &str| match s {
+            "" => Some(None),
+            s if s.bytes().all(|b| b.is_ascii_digit()) => s.parse().ok().map(Some),
+            _ => None,
+        };
+        let (min, max) = (bound(min)?, bound(max)?);
+        match (min, max) {
+            (None, None) => None,
+            (Some(min), Some(max)) if min > max => None,
+            _ => Some(LengthLimit { unit, min, max }),
+        }
+    }
+
+    /// Byte range of a string item, like `[slice:2..8]`, which takes only that part of the fragment.
+    /// The start is inclusive and the end is exclusive, as in Rust ranges.
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub struct ByteRange {
+        pub start: usize,
+        pub end: Option<usize>,
+    }
+
+    impl fmt::Display for ByteRange {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "[slice:{}..", self.start)?;
+            if let Some(end) = self.end {
+                write!(f, "{}", end)?;
+            }
+            f.write_str("]")
+        }
+    }
+
+    /// Parses the byte range without brackets and the `slice:` prefix, like `2..8` or `2..`.
+    fn parse_byte_range(s: &str) -> Option<ByteRange> {
+        let (start, end) = s.split_once("..")?;
+        let bound = |s: &str| match s {
+            "" => Some(None),
+            s if s.bytes().all(|b| b.is_ascii_digit()) => s.parse().ok().map(Some),
+            _ => None,
+        };
+        match (bound(start)?, bound(end)?) {
+            (None, None) => None,
+            (Some(start), Some(end)) if start >= end => None,
+            (start, end) => Some(ByteRange {
+                start: start.unwrap_or(0),
+                end,
+            }),
+        }
+    }
+
+    /// Minimum width of a string item in chars, like `[pad:20]`, which is filled with spaces
+    /// on the side given by the alignment, like `[pad:20>]`.
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub struct Padding {
+        pub width: usize,
+        pub align: Align,
+    }
+
+    /// Alignment of the padded value, which is `<`, `>` or `^`, like in `format!`.
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub enum Align {
+        Left,
+        Right,
+        Center,
+    }
+
+    impl Align {
+        pub fn as_char(self) -> char {
+            match self {
+                Align::Left => '<',
+                Align::Right => '>',
+                Align::Center => '^',
+            }
+        }
+    }
+
+    impl fmt::Display for Padding {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "[pad:{}", self.width)?;
+            if self.align != Align::Left {
+                write!(f, "{}", self.align.as_char())?;
+            }
+            f.write_str("]")
+        }
+    }
+
+    /// Parses the padding without brackets and the `pad:` prefix, like `20` or `20^`.
+    fn parse_padding(s: &str) -> Option<Padding> {
+        let (width, align) = match s.as_bytes().last()? {
+            b'<' => (&s[..s.len() - 1], Align::Left),
+            b'>' => (&s[..s.len() - 1], Align::Right),
+            b'^' => (&s[..s.len() - 1], Align::Center),
+            _ => (s, Align::Left),
+        };
+        if width.is_empty() || !width.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        match width.parse() {
+            Ok(width) if width > 0 => Some(Padding { width, align }),
+            _ => None,
+        }
+    }
+
+    /// Checks whether the custom type is `SystemTime`, which is supported by `%d` items
+    /// as a Unix timestamp in seconds, although it does not implement `FromStr` and `Display`.
+    pub fn is_system_time(custom_type: &str) -> bool {
+        matches!(
+            custom_type,
+            "SystemTime" | "time::SystemTime" | "std::time::SystemTime" | "::std::time::SystemTime"
+        )
+    }
+
+    /// Checks whether the custom type is `NonZeroU64`, which is supported by `%d` items as a positive integer
+    /// without importing it, for ids, counts and capacities.
+    pub fn is_non_zero_u64(custom_type: &str) -> bool {
+        let custom_type = custom_type.strip_prefix("::").unwrap_or(custom_type);
+        matches!(
+            custom_type,
+            "NonZeroU64" | "num::NonZeroU64" | "std::num::NonZeroU64" | "core::num::NonZeroU64"
+        )
+    }
+
+    /// Largest number of bytes of a `%s:hex16{N}` item.
+    pub const MAX_HEX_WIDTH: usize = 64;
+
+    /// Number of bytes of the custom type `hex16{N}`, which is supported by `%s` items as `[u8; N]`
+    /// written as `2 * N` hex digits.
+    pub fn hex_width(custom_type: &str) -> Option<usize> {
+        custom_type.strip_prefix("hex16{")?.strip_suffix('}')?.parse().ok()
+    }
+
+    /// Prints the item as it is written in the format string, for error messages.
+    impl fmt::Display for FormatItem {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let FormatItem {
+                ty: item_type,
+                opt: item_opt,
+                custom_type,
+                length_limit,
+                regex,
+                trie,
+                slice,
+                padding,
+                assert,
+            } = self;
+            f.write_str(match item_type {
+                Str => "%s",
+                Int => "%d",
+                Path => "%P",
+                Base62 => "%s62",
+                StrSet => "%s{set}",
+                StrMap => "%s{map}",
+            })?;
+            if let Some(trie) = trie {
+                write!(f, "{{trie:{}}}", trie.join(","))?;
+            }
+            if let Some(custom_type) = custom_type {
+                write!(f, ":{}", custom_type)?;
+            }
+            if let Some(length_limit) = length_limit {
+                write!(f, "{}", length_limit)?;
+            }
+            if let Some(slice) = slice {
+                write!(f, "{}", slice)?;
+            }
+            if let Some(padding) = padding {
+                write!(f, "{}", padding)?;
+            }
+            if let Some(assert) = assert {
+                write!(f, "[assert={}]", assert)?;
+            }
+            if let Some(regex) = regex {
+                write!(f, "[re=r'{}']", regex)?;
+            }
+            if *item_opt == Optional {
+                f.write_str("?")?;
+            }
+            Ok(())
+        }
+    }
+
+    impl FormatItem {
+        /// Describes the item in plain words, like `optional string of up to 64 chars`.
+        pub fn explain(&self) -> String {
+            let FormatItem {
+                ty: item_type,
+                opt: item_opt,
+                custom_type,
+                length_limit,
+                regex,
+                trie,
+                slice,
+                padding,
+                assert,
+            } = self;
+            let mut res = String::new();
+            if *item_opt == Optional {
+                res.push_str("optional ");
+            }
+            let width = custom_type.as_deref().and_then(hex_width);
+            match (item_type, custom_type.as_deref()) {
+                (Str, Some("str")) | (Str, None) => res.push_str("string"),
+                (Str, Some("words")) => res.push_str("words"),
+                (Str, Some(_)) if width.is_some() => {
+                    let width = width.unwrap_or_default();
+                    res.push_str(&format!(
+                        "{} {} in hex",
+                        width,
+                        if width == 1 { "byte" } else { "bytes" }
+                    ));
+                }
+                (Int, Some(custom_type)) if is_system_time(custom_type) => res.push_str("Unix timestamp"),
+                (Int, Some(custom_type)) if is_non_zero_u64(custom_type) => res.push_str("positive integer"),
+                (Str, Some(custom_type)) => res.push_str(&format!("string as {}", custom_type)),
+                (Int, Some(custom_type)) => res.push_str(&format!("integer as {}", custom_type)),
+                (Int, None) => res.push_str("integer"),
+                (Path, _) => res.push_str("path"),
+                (Base62, _) => res.push_str("base62 bytes"),
+                (StrSet, _) => res.push_str("set of strings"),
+                (StrMap, _) => res.push_str("map of strings"),
+            }
+            if let Some(ByteRange { start, end }) = slice {
+                match end {
+                    Some(end) => res.push_str(&format!(" from bytes {} to {} of the fragment", start, end)),
+                    None => res.push_str(&format!(" from byte {} of the fragment", start)),
+                }
+            }
+            if let Some(LengthLimit { unit, min, max }) = length_limit {
+                let unit = match unit {
+                    LengthUnit::Chars => "chars",
+                    LengthUnit::Bytes => "bytes",
+                };
+                match (min, max) {
+                    (Some(min), Some(max)) if min == max => res.push_str(&format!(" of {} {}", min, unit)),
+                    (Some(min), Some(max)) => res.push_str(&format!(" of {} to {} {}", min, max, unit)),
+                    (Some(min), None) => res.push_str(&format!(" of at least {} {}", min, unit)),
+                    (None, Some(max)) => res.push_str(&format!(" of up to {} {}", max, unit)),
+                    (None, None) => {}
+                }
+            }
+            if let Some(trie) = trie {
+                res.push_str(&format!(" which is one of {}", trie.join(", ")));
+            }
+            if let Some(assert) = assert {
+                res.push_str(&format!(" which is always {}", assert));
+            }
+            if let Some(regex) = regex {
+                res.push_str(&format!(" matching {}", regex));
+            }
+            if let Some(Padding { width, align }) = padding {
+                let side = match align {
+                    Align::Left => "on the right",
+                    Align::Right => "on the left",
+                    Align::Center => "on both sides",
+                };
+                res.push_str(&format!(" padded to {} chars with spaces {}", width, side));
+            }
+            res
+        }
+    }
+
+    impl FormatString {
+        /// Describes the items in plain words, like `string, optional integer, followed by any extra fragments`.
+        pub fn explain(&self) -> String {
+            let FormatString(items, ending) = self;
+            let mut res = items.iter().map(FormatItem::explain).collect::<Vec<_>>().join(", ");
+            if *ending == Open {
+                res.push_str(", followed by any extra fragments");
+            }
+            res
+        }
+    }
+
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub enum FormatItemType {
+        Str,
+        Int,
+        Path,
+        Base62,
+        /// Set of strings, which are sorted and joined with commas, like `a,b,c`.
+        StrSet,
+        /// Map of strings, which are joined with `=` into pairs, which are sorted by key and joined with commas,
+        /// like `a=1,b=2`.
+        StrMap,
+    }
+
+    impl FormatItemType {
+        /// Descriptor of the item in the fragmented string, `%P` and custom types are just strings there.
+        pub fn descriptor(self) -> &'static str {
+            match self {
+                Str | Path | Base62 | StrSet | StrMap => "%s",
+                Int => "%d",
+            }
+        }
+    }
+
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub enum FormatItemOpt {
+        Mandatory,
+        Optional,
+    }
+
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub enum FormatEnding {
+        Closed,
+        Open,
+    }
+
+    /// Reason why the format string is bad.
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub enum FormatErrorKind {
+        Empty,
+        InvalidChar(char),
+        DanglingPercent,
+        UnknownDescriptor(char),
+        BadCustomType,
+        CustomTypeNotAllowed,
+        BadLengthLimit,
+        LengthLimitNotAllowed,
+        BadRegex,
+        RegexNotAllowed,
+        RegexTooLong,
+        BadCollection,
+        BadTrie,
+        BadSlice,
+        SliceNotAllowed,
+        BadPadding,
+        PaddingNotAllowed,
+        BadAssert,
+        AssertNotAllowed,
+        BadHexWidth,
+        MisplacedOptional,
+        MandatoryAfterOptional,
+        AllOptional,
+        MisplacedAsterisk,
+        OnlyAsterisk,
+        OptionalNotAllowed,
+        AsteriskNotAllowed,
+    }
+
+    /// Error of parsing the format string, with the byte offset of the offending character.
+    /// All the characters before it are ASCII, so the offset is also the position of the character.
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub struct FormatStringError {
+        pub kind: FormatErrorKind,
+        pub pos: usize,
+    }
+
+    impl FormatStringError {
+        fn new(kind: FormatErrorKind, pos: usize) -> Self {
+            FormatStringError { kind, pos }
+        }
+    }
+
+    impl fmt::Display for FormatStringError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            use FormatErrorKind::*;
+            let pos = self.pos;
+            match self.kind {
+                Empty => write!(f, "format string is empty"),
+                InvalidChar(ch) => write!(
+                    f,
+                    "invalid character {:?} at position {} of format string; descriptors must be contiguous",
+                    ch, pos
+                ),
+                DanglingPercent => write!(f, "dangling '%' at position {} of format string", pos),
+                UnknownDescriptor(ch) => write!(
+                    f,
+                    "unknown descriptor {:?} at position {} of format string; expected %s, %d, %P or %s62",
+                    ch, pos
+                ),
+                BadCustomType => write!(
+                    f,
+                    "bad custom type at position {} of format string; expected a type path like `Color` or `std::net::IpAddr`",
+                    pos
+                ),
+                CustomTypeNotAllowed => write!(
+                    f,
+                    "custom type at position {} of format string is allowed for %s and %d only",
+                    pos
+                ),
+                BadLengthLimit => write!(
+                    f,
+                    "bad length limit at position {} of format string; expected a range like `[..64]`, `[1..]` or `[bytes:1..64]`",
+                    pos
+                ),
+                LengthLimitNotAllowed => write!(
+                    f,
+                    "length limit at position {} of format string is allowed for %s only",
+                    pos
+                ),
+                BadRegex => write!(
+                    f,
+                    "bad regex at position {} of format string; expected a pattern like `[re=r'^[A-Z]{{2,3}}$']`",
+                    pos
+                ),
+                RegexNotAllowed => write!(f, "regex at position {} of format string is allowed for %s only", pos),
+                RegexTooLong => write!(
+                    f,
+                    "regex at position {} of format string is too long, with the repetitions counted; \
+                     it is compiled into {} instructions at most",
+                    pos,
+                    crate::utils::regex::MAX_PROGRAM_LEN
+                ),
+                BadCollection => write!(
+                    f,
+                    "bad collection annotation at position {} of format string; expected `{{set}}`, `{{map}}` or `{{trie:...}}`",
+                    pos
+                ),
+                BadTrie => write!(
+                    f,
+                    "bad trie annotation at position {} of format string; expected non-empty values without `__`, like `{{trie:foo,bar}}`",
+                    pos
+                ),
+                BadHexWidth => write!(
+                    f,
+                    "bad width of hex bytes at position {} of format string; expected a number of bytes from 1 to {} of %s, like `%s:hex16{{8}}`",
+                    pos, MAX_HEX_WIDTH
+                ),
+                BadSlice => write!(
+                    f,
+                    "bad byte range at position {} of format string; expected a range like `[slice:2..8]` or `[slice:2..]`",
+                    pos
+                ),
+                SliceNotAllowed => write!(f, "byte range at position {} of format string is allowed for %s only", pos),
+                BadPadding => write!(
+                    f,
+                    "bad padding at position {} of format string; expected a width with optional alignment like `[pad:20]` or `[pad:20>]`",
+                    pos
+                ),
+                PaddingNotAllowed => write!(f, "padding at position {} of format string is allowed for %s only", pos),
+                BadAssert => write!(
+                    f,
+                    "bad asserted value at position {} of format string; expected a non-empty value without `__`, like `[assert=v1]`",
+                    pos
+                ),
+                AssertNotAllowed => write!(
+                    f,
+                    "asserted value at position {} of format string is allowed for %s only",
+                    pos
+                ),
+                MisplacedOptional => write!(
+                    f,
+                    "misplaced '?' at position {} of format string; it must directly follow a descriptor",
+                    pos
+                ),
+                MandatoryAfterOptional => write!(
+                    f,
+                    "mandatory item at position {} of format string follows an optional one; optional items must be at the end",
+                    pos
+                ),
+                AllOptional => write!(f, "all items of format string are optional; the first one must be mandatory"),
+                MisplacedAsterisk => write!(
+                    f,
+                    "misplaced '*' at position {} of format string; it must be the last character",
+                    pos
+                ),
+                OnlyAsterisk => write!(f, "format string has no items before '*'"),
+                OptionalNotAllowed => write!(f, "optional item at position {} of format string is not allowed here", pos),
+                AsteriskNotAllowed => write!(f, "'*' at position {} of format string is not allowed here", pos),
+            }
+        }
+    }
+
+    /// Parses format string which has neither optional items nor asterisk.
+    pub fn parse_format_string(fmt: &str) -> Result<Vec<FormatItem>, FormatStringError> {
+        let FormatString(items, ending) = parse_format_string_ex(fmt)?;
+        // Extra stuff is not allowed
+        if let Some(pos) = fmt.find('?') {
+            return Err(FormatStringError::new(FormatErrorKind::OptionalNotAllowed, pos));
+        }
+        if ending != Closed {
+            return Err(FormatStringError::new(
+                FormatErrorKind::AsteriskNotAllowed,
+                fmt.len() - 1,
+            ));
+        }
+        Ok(items)
+    }
+
+    pub fn parse_format_string_ex(fmt: &str) -> Result<FormatString, FormatStringError> {
+        use FormatErrorKind::*;
+        let error = |kind, pos| Err(FormatStringError::new(kind, pos));
+
+        if fmt.is_empty() {
+            return error(Empty, 0);
+        }
+
+        let approx_capacity = fmt.len() / 2;
+        let mut items = Vec::with_capacity(approx_capacity);
+        let mut ending = Closed;
+        let mut iter = fmt.char_indices().peekable();
+        loop {
+            match iter.next() {
+                None => break,
+                Some((pos, ch)) => {
+                    if ch == '*' {
+                        // Asterisk, if present, must be the last item in the format string
+                        if iter.next().is_some() {
+                            return error(MisplacedAsterisk, pos);
+                        }
+
+                        // Asterisk, if present, must be not the only item in the format string
+                        if items.is_empty() {
+                            return error(OnlyAsterisk, pos);
+                        }
+
+                        // Otherwise mark format string as open-ended and finish parsing
+                        ending = Open;
+                        break;
+                    }
+
+                    // All format descriptors must start with an '%'
+                    match ch {
+                        '%' => {}
+                        '?' => return error(MisplacedOptional, pos),
+                        _ => return error(InvalidChar(ch), pos),
+                    }
+
+                    // Next character is mandatory, otherwise abort parsing
+                    let (pos, ch) = match iter.next() {
+                        Some(next) => next,
+                        None => return error(DanglingPercent, pos),
+                    };
+                    let mut item_trie = None;
+                    let item_type = match ch {
+                        // Base62-encoded bytes are `%s62`
+                        's' if iter.peek().map(|&(_, ch)| ch) == Some('6') => {
+                            let _ = iter.next(); // Consume it
+                            match iter.next() {
+                                Some((_, '2')) => Base62,
+                                Some((pos, ch)) => return error(InvalidChar(ch), pos),
+                                None => return error(InvalidChar('6'), pos + 1),
+                            }
+                        }
+                        // Set of strings is `%s{set}`, map of strings is `%s{map}`,
+                        // and a string which is one of the given values is `%s{trie:foo,bar}`
+                        's' if iter.peek().map(|&(_, ch)| ch) == Some('{') => {
+                            let (pos, _) = iter.next().expect("peeked"); // Consume it
+                            let mut annotation = String::new();
+                            loop {
+                                match iter.next() {
+                                    Some((_, '}')) => break,
+                                    Some((_, ch)) => annotation.push(ch),
+                                    None => return error(BadCollection, pos),
+                                }
+                            }
+                            match annotation.as_str() {
+                                "set" => StrSet,
+                                "map" => StrMap,
+                                _ => match annotation.strip_prefix("trie:") {
+                                    Some(values) => {
+                                        let values = values.split(',').map(str::to_owned).collect::<Vec<_>>();
+                                        if values.iter().any(|value| value.is_empty() || value.contains("__")) {
+                                            return error(BadTrie, pos);
+                                        }
+                                        item_trie = Some(values);
+                                        Str
+                                    }
+                                    None => return error(BadCollection, pos),
+                                },
+                            }
+                        }
+                        's' => Str,
+                        'd' => Int,
+                        'P' => Path,
+                        _ => return error(UnknownDescriptor(ch), pos),
+                    };
+
+                    // Optional custom type annotation, like ':Color' or ':std::net::IpAddr'
+                    let item_custom_type = match iter.peek() {
+                        Some(&(pos, ':')) => {
+                            let _ = iter.next(); // Consume it
+                            let mut type_name = String::new();
+                            while let Some((_, ch)) =
+                                iter.next_if(|&(_, ch)| ch.is_ascii_alphanumeric() || ch == '_' || ch == ':')
+                            {
+                                type_name.push(ch);
+                            }
+                            if matches!(item_type, Path | Base62 | StrSet | StrMap) {
+                                return error(CustomTypeNotAllowed, pos);
+                            }
+                            if !is_type_path(&type_name) {
+                                return error(BadCustomType, pos + 1);
+                            }
+                            // Fixed-width hex bytes are `%s:hex16{8}`, with the number of bytes in braces
+                            if type_name == "hex16" && iter.peek().map(|&(_, ch)| ch) == Some('{') {
+                                let (pos, _) = iter.next().expect("peeked"); // Consume it
+                                let mut width = String::new();
+                                loop {
+                                    match iter.next() {
+                                        Some((_, '}')) => break,
+                                        Some((_, ch)) => width.push(ch),
+                                        None => return error(BadHexWidth, pos),
+                                    }
+                                }
+                                let valid =
+                                    item_type == Str && !width.is_empty() && width.bytes().all(|b| b.is_ascii_digit());
+                                match width.parse::<usize>() {
+                                    Ok(width) if valid && (1..=MAX_HEX_WIDTH).contains(&width) => {
+                                        type_name = format!("hex16{{{}}}", width);
+                                    }
+                                    _ => return error(BadHexWidth, pos),
+                                }
+                            }
+                            Some(type_name)
+                        }
+                        _ => None,
+                    };
+
+                    // Optional length limit, like '[..64]' or '[bytes:..64]',
+                    // or byte range of the fragment, like '[slice:2..8]', or padding, like '[pad:20]',
+                    // or the only allowed value, like '[assert=v1]'
+                    let (item_length_limit, item_slice, item_padding, item_assert) = match iter.peek() {
+                        Some(&(pos, '[')) if !fmt[pos..].starts_with(REGEX_START) => {
+                            let _ = iter.next(); // Consume it
+                            let mut limit = String::new();
+                            loop {
+                                match iter.next() {
+                                    Some((_, ']')) => break,
+                                    Some((_, ch)) => limit.push(ch),
+                                    None => return error(BadLengthLimit, pos),
+                                }
+                            }
+                            if let Some(value) = limit.strip_prefix("assert=") {
+                                if item_type != Str {
+                                    return error(AssertNotAllowed, pos);
+                                }
+                                if value.is_empty() || value.contains("__") {
+                                    return error(BadAssert, pos);
+                                }
+                                (None, None, None, Some(value.to_owned()))
+                            } else if let Some(padding) = limit.strip_prefix("pad:") {
+                                if item_type != Str {
+                                    return error(PaddingNotAllowed, pos);
+                                }
+                                match parse_padding(padding) {
+                                    Some(padding) => (None, None, Some(padding), None),
+                                    None => return error(BadPadding, pos),
+                                }
+                            } else {
+                                match limit.strip_prefix("slice:") {
+                                    Some(_) if item_type != Str => return error(SliceNotAllowed, pos),
+                                    Some(range) => match parse_byte_range(range) {
+                                        Some(range) => (None, Some(range), None, None),
+                                        None => return error(BadSlice, pos),
+                                    },
+                                    None if item_type != Str => return error(LengthLimitNotAllowed, pos),
+                                    None => match parse_length_limit(&limit) {
+                                        Some(limit) => (Some(limit), None, None, None),
+                                        None => return error(BadLengthLimit, pos),
+                                    },
+                                }
+                            }
+                        }
+                        _ => (None, None, None, None),
+                    };
+
+                    // Optional regex, like '[re=r'^[A-Z]{2,3}$']', which may contain brackets,
+                    // so it ends with the quote and the bracket, and can not contain them
+                    let item_regex = match iter.peek() {
+                        Some(&(pos, '[')) if fmt[pos..].starts_with(REGEX_START) => {
+                            let start = pos + REGEX_START.len();
+                            let end = match fmt[start..].find("']") {
+                                Some(len) => start + len,
+                                None => return error(BadRegex, pos),
+                            };
+                            if item_type != Str {
+                                return error(RegexNotAllowed, pos);
+                            }
+                            let pattern = &fmt[start..end];
+                            match crate::utils::regex::parse_regex(pattern) {
+                                Ok(regex) if crate::utils::regex::compile(&regex).is_none() => {
+                                    return error(RegexTooLong, pos);
+                                }
+                                Ok(_) => {}
+                                Err(offset) => return error(BadRegex, start + offset),
+                            }
+                            while iter.next_if(|&(pos, _)| pos <= end + 1).is_some() {}
+                            Some(pattern.to_owned())
+                        }
+                        _ => None,
+                    };
+
+                    // Optional '?' character
+                    let item_opt = if iter.next_if(|&(_, ch)| ch == '?').is_some() {
+                        Optional
+                    } else {
+                        Mandatory
+                    };
+
+                    // Optional items, if present, must all be in the end of the format string
+                    if item_opt == Mandatory {
+                        if let Some(FormatItem { opt: last_opt, .. }) = items.last() {
+                            if *last_opt == Optional {
+                                return error(MandatoryAfterOptional, pos - 1);
+                            }
+                        }
+                    }
+
+                    // Store the item
+                    items.push(FormatItem {
+                        ty: item_type,
+                        opt: item_opt,
+                        custom_type: item_custom_type,
+                        length_limit: item_length_limit,
+                        regex: item_regex,
+                        trie: item_trie,
+                        slice: item_slice,
+                        padding: item_padding,
+                        assert: item_assert,
+                    });
+                }
+            }
+        }
+
+        // All items can not be optional, there must be at least one mandatory item
+        match items.first() {
+            Some(first) if first.opt == Optional => return error(AllOptional, 0),
+            Some(_) => {}
+            // No items at all, which is not possible actually
+            None => return error(Empty, 0),
+        }
+
+        Ok(FormatString(items, ending))
+    }
+
+    const REGEX_START: &str = "[re=r'";
+
+    // Type path, like `Color` or `std::net::IpAddr`, without generics
+    fn is_type_path(s: &str) -> bool {
+        let s = s.strip_prefix("::").unwrap_or(s);
+        s.split("::").all(|segment| {
+            segment.starts_with(|ch: char| ch.is_ascii_alphabetic() || ch == '_')
+                && segment != "_"
+                && segment.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        })
+    }
+
+    #[test]
+    fn test_parse_format_string() {
+        // Parse and drop custom types
+        let parse_format_string = |s: &str| {
+            parse_format_string(s).ok().map(|items| {
+                assert!(items.iter().all(|item| item.custom_type.is_none()));
+                items.into_iter().map(|item| item.ty).collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(parse_format_string(""), None);
+
+        assert_eq!(parse_format_string("%s"), Some(vec![Str]));
+        assert_eq!(parse_format_string("%d"), Some(vec![Int]));
+        assert_eq!(parse_format_string("%s%d"), Some(vec![Str, Int]));
+        assert_eq!(parse_format_string("%d%s"), Some(vec![Int, Str]));
+        assert_eq!(parse_format_string("%s%s"), Some(vec![Str, Str]));
+        assert_eq!(parse_format_string("%d%d"), Some(vec![Int, Int]));
+        assert_eq!(parse_format_string("%P"), Some(vec![Path]));
+        assert_eq!(parse_format_string("%s%P%d"), Some(vec![Str, Path, Int]));
+        assert_eq!(parse_format_string("%s62"), Some(vec![Base62]));
+        assert_eq!(parse_format_string("%s%s62%d"), Some(vec![Str, Base62, Int]));
+        assert_eq!(parse_format_string("%s{set}"), Some(vec![StrSet]));
+        assert_eq!(parse_format_string("%s{set}%s%d"), Some(vec![StrSet, Str, Int]));
+        assert_eq!(parse_format_string("%d%s{map}"), Some(vec![Int, StrMap]));
+
+        assert_eq!(parse_format_string("%"), None);
+        assert_eq!(parse_format_string("%%"), None);
+        assert_eq!(parse_format_string("%f"), None);
+        assert_eq!(parse_format_string("%b"), None);
+        assert_eq!(parse_format_string("%x"), None);
+        assert_eq!(parse_format_string("%p"), None);
+        assert_eq!(parse_format_string("%s%x"), None);
+        assert_eq!(parse_format_string("%sx"), None);
+        assert_eq!(parse_format_string("%sxx"), None);
+        assert_eq!(parse_format_string("%s6"), None);
+        assert_eq!(parse_format_string("%s63"), None);
+        assert_eq!(parse_format_string("%s622"), None);
+        assert_eq!(parse_format_string("%d62"), None);
+        assert_eq!(parse_format_string("%s{}"), None);
+        assert_eq!(parse_format_string("%s{list}"), None);
+        assert_eq!(parse_format_string("%s{set"), None);
+        assert_eq!(parse_format_string("%d{set}"), None);
+        assert_eq!(parse_format_string("%s{set}:Color"), None);
+        assert_eq!(parse_format_string("%s{set}[..64]"), None);
+        assert_eq!(parse_format_string("%s foo"), None);
+        assert_eq!(parse_format_string("%s "), None);
+        assert_eq!(parse_format_string(" %s"), None);
+    }
+
+    #[test]
+    fn test_format_string_errors() {
+        use FormatErrorKind::*;
+        let err = |s: &str| parse_format_string_ex(s).err().map(|err| (err.kind, err.pos));
+        let err_strict = |s: &str| parse_format_string(s).err().map(|err| (err.kind, err.pos));
+
+        assert_eq!(err(""), Some((Empty, 0)));
+        assert_eq!(err("%s %d"), Some((InvalidChar(' '), 2)));
+        assert_eq!(err("s%d"), Some((InvalidChar('s'), 0)));
+        assert_eq!(err("%sя"), Some((InvalidChar('я'), 2)));
+        assert_eq!(err("%s%"), Some((DanglingPercent, 2)));
+        assert_eq!(err("%s%x"), Some((UnknownDescriptor('x'), 3)));
+        assert_eq!(err("%%"), Some((UnknownDescriptor('%'), 1)));
+        assert_eq!(err("%s63"), Some((InvalidChar('3'), 3)));
+        assert_eq!(err("%s6"), Some((InvalidChar('6'), 2)));
+        assert_eq!(err("%s:1x"), Some((BadCustomType, 3)));
+        assert_eq!(err("%P:Color"), Some((CustomTypeNotAllowed, 2)));
+        assert_eq!(err("%s%s[64]"), Some((BadLengthLimit, 4)));
+        assert_eq!(err("%s[..64"), Some((BadLengthLimit, 2)));
+        assert_eq!(err("%d[..64]"), Some((LengthLimitNotAllowed, 2)));
+        assert_eq!(err("%s%s[slice:8..2]"), Some((BadSlice, 4)));
+        assert_eq!(err("%s[slice:..]"), Some((BadSlice, 2)));
+        assert_eq!(err("%d[slice:2..8]"), Some((SliceNotAllowed, 2)));
+        assert_eq!(err("%s%s[pad:0]"), Some((BadPadding, 4)));
+        assert_eq!(err("%s[pad:20<>]"), Some((BadPadding, 2)));
+        assert_eq!(err("%d[pad:20]"), Some((PaddingNotAllowed, 2)));
+        assert_eq!(err("%s%s[assert=]"), Some((BadAssert, 4)));
+        assert_eq!(err("%s[assert=v__1]"), Some((BadAssert, 2)));
+        assert_eq!(err("%d[assert=1]"), Some((AssertNotAllowed, 2)));
+        assert_eq!(err("%s%s:hex16{0}"), Some((BadHexWidth, 10)));
+        assert_eq!(err("%s:hex16{65}"), Some((BadHexWidth, 8)));
+        assert_eq!(err("%s:hex16{+8}"), Some((BadHexWidth, 8)));
+        assert_eq!(err("%s:hex16{8"), Some((BadHexWidth, 8)));
+        assert_eq!(err("%d:hex16{8}"), Some((BadHexWidth, 8)));
+        assert_eq!(err("%s%s{Set}"), Some((BadCollection, 4)));
+        assert_eq!(err("%s{set"), Some((BadCollection, 2)));
+        assert_eq!(err("%s{map}:Color"), Some((CustomTypeNotAllowed, 7)));
+        assert_eq!(err("%s%s{trie:a,,b}"), Some((BadTrie, 4)));
+        assert_eq!(err("%s{trie:a__b}"), Some((BadTrie, 2)));
+        assert_eq!(err("%s{trie:}"), Some((BadTrie, 2)));
+        assert_eq!(err("%s{trie"), Some((BadCollection, 2)));
+        assert_eq!(err("?%s"), Some((MisplacedOptional, 0)));
+        assert_eq!(err("%s??"), Some((MisplacedOptional, 3)));
+        assert_eq!(err("%s%s?%d"), Some((MandatoryAfterOptional, 5)));
+        assert_eq!(err("%s?"), Some((AllOptional, 0)));
+        assert_eq!(err("%s*%d"), Some((MisplacedAsterisk, 2)));
+        assert_eq!(err("*"), Some((OnlyAsterisk, 0)));
+        assert_eq!(err("%s"), None);
+
+        assert_eq!(err_strict("%s%d?"), Some((OptionalNotAllowed, 4)));
+        assert_eq!(err_strict("%s%d*"), Some((AsteriskNotAllowed, 4)));
+        assert_eq!(err_strict("%s %d"), Some((InvalidChar(' '), 2)));
+
+        assert_eq!(
+            parse_format_string("%s %d").unwrap_err().to_string(),
+            "invalid character ' ' at position 2 of format string; descriptors must be contiguous"
+        );
+    }
+
+    #[test]
+    fn test_is_system_time() {
+        assert!(is_system_time("SystemTime"));
+        assert!(is_system_time("std::time::SystemTime"));
+        assert!(is_system_time("::std::time::SystemTime"));
+        assert!(!is_system_time("Instant"));
+        assert!(!is_system_time("my::SystemTime"));
+    }
+
+    #[test]
+    fn test_is_non_zero_u64() {
+        assert!(is_non_zero_u64("NonZeroU64"));
+        assert!(is_non_zero_u64("std::num::NonZeroU64"));
+        assert!(is_non_zero_u64("::core::num::NonZeroU64"));
+        assert!(!is_non_zero_u64("NonZeroU32"));
+        assert!(!is_non_zero_u64("my::NonZeroU64"));
+    }
+
+    #[test]
+    fn test_hex_width() {
+        assert_eq!(hex_width("hex16{8}"), Some(8));
+        assert_eq!(hex_width("hex16{64}"), Some(64));
+        assert_eq!(hex_width("hex16"), None);
+        assert_eq!(hex_width("Color"), None);
+    }
+
+    #[test]
+    fn test_format_string_explain() {
+        let explain = |s: &str| parse_format_string_ex(s).ok().map(|fmt| fmt.explain());
+
+        assert_eq!(
+            explain("%s%s%d?*").as_deref(),
+            Some("string, string, optional integer, followed by any extra fragments")
+        );
+        assert_eq!(
+            explain("%s:Color%d:u8%d:SystemTime%d:NonZeroU64%s:str%s:words").as_deref(),
+            Some("string as Color, integer as u8, Unix timestamp, positive integer, string, words")
+        );
+        assert_eq!(
+            explain("%P%s62%s{set}%s{map}%s:hex16{8}%s:hex16{1}").as_deref(),
+            Some("path, base62 bytes, set of strings, map of strings, 8 bytes in hex, 1 byte in hex")
+        );
+        assert_eq!(
+            explain("%s[..64]%s[bytes:1..8]%s[2..]%s[3..3]").as_deref(),
+            Some("string of up to 64 chars, string of 1 to 8 bytes, string of at least 2 chars, string of 3 chars")
+        );
+        assert_eq!(
+            explain("%s[slice:2..8]%s[slice:1..]?").as_deref(),
+            Some("string from bytes 2 to 8 of the fragment, optional string from byte 1 of the fragment")
+        );
+        assert_eq!(
+            explain("%s{trie:buy,sell}%s[re=r'^[A-Z]{3}$']").as_deref(),
+            Some("string which is one of buy, sell, string matching ^[A-Z]{3}$")
+        );
+        assert_eq!(
+            explain("%s[pad:20]%s:Color[pad:8^]").as_deref(),
+            Some("string padded to 20 chars with spaces on the right, string as Color padded to 8 chars with spaces on both sides")
+        );
+        assert_eq!(
+            explain("%s[assert=v1]%d").as_deref(),
+            Some("string which is always v1, integer")
+        );
+    }
+
+    #[test]
+    fn test_format_item_display() {
+        let display = |s: &str| {
+            parse_format_string_ex(s)
+                .ok()
+                .map(|FormatString(items, _)| items.iter().map(ToString::to_string).collect::<String>())
+        };
+        assert_eq!(display("%s%d%P%s62%s{set}").as_deref(), Some("%s%d%P%s62%s{set}"));
+        assert_eq!(
+            display("%s:Color%d:u8%s:::std::net::IpAddr").as_deref(),
+            Some("%s:Color%d:u8%s:::std::net::IpAddr")
+        );
+        assert_eq!(display("%s%s:str?%d?*").as_deref(), Some("%s%s:str?%d?"));
+        assert_eq!(display("%s:hex16{8}[..16]").as_deref(), Some("%s:hex16{8}[..16]"));
+        assert_eq!(
+            display("%s[..64]%s:str[bytes:1..]?").as_deref(),
+            Some("%s[..64]%s:str[bytes:1..]?")
+        );
+    }
+
+    #[test]
+    fn test_parse_format_string_custom_types() {
+        let custom = |s: &str| {
+            parse_format_string_ex(s).ok().map(|FormatString(items, _)| {
+                items
+                    .into_iter()
+                    .map(|item| (item.ty, item.custom_type))
+                    .collect::<Vec<_>>()
+            })
+        };
+        let t = |s: &str| Some(s.to_string());
+
+        assert_eq!(custom("%s:Color"), Some(vec![(Str, t("Color"))]));
+        assert_eq!(custom("%d:u8%s"), Some(vec![(Int, t("u8")), (Str, None)]));
+        assert_eq!(custom("%s%s:_Foo1?"), Some(vec![(Str, None), (Str, t("_Foo1"))]));
+        assert_eq!(custom("%s:std::net::IpAddr*"), Some(vec![(Str, t("std::net::IpAddr"))]));
+        assert_eq!(
+            custom("%s:::std::net::IpAddr"),
+            Some(vec![(Str, t("::std::net::IpAddr"))])
+        );
+        assert_eq!(custom("%d:u8%d:i16"), Some(vec![(Int, t("u8")), (Int, t("i16"))]));
+        assert_eq!(custom("%s:hex16{8}%d"), Some(vec![(Str, t("hex16{8}")), (Int, None)]));
+        assert_eq!(custom("%s:hex16{064}*"), Some(vec![(Str, t("hex16{64}"))]));
+        assert_eq!(custom("%s:hex16"), Some(vec![(Str, t("hex16"))]));
+
+        assert_eq!(custom("%s:"), None);
+        assert_eq!(custom("%s:?"), None);
+        assert_eq!(custom("%s:1x"), None);
+        assert_eq!(custom("%s:_"), None);
+        assert_eq!(custom("%s:a::"), None);
+        assert_eq!(custom("%s:a:b"), None);
+        assert_eq!(custom("%s:a:::b"), None);
+        assert_eq!(custom("%s:Vec<u8>"), None);
+        assert_eq!(custom("%s: Color"), None);
+        assert_eq!(custom("%s?:Color"), None);
+        assert_eq!(custom("%P:Color"), None);
+        assert_eq!(custom("%s62:Color"), None);
+    }
+
+    #[test]
+    fn test_parse_format_string_length_limits() {
+        let limits = |s: &str| {
+            parse_format_string_ex(s)
+                .ok()
+                .map(|FormatString(items, _)| items.into_iter().map(|item| item.length_limit).collect::<Vec<_>>())
+        };
+        let l = |unit, min, max| Some(LengthLimit { unit, min, max });
+        use LengthUnit::{Bytes, Chars};
+
+        assert_eq!(limits("%s[..64]"), Some(vec![l(Chars, None, Some(64))]));
+        assert_eq!(limits("%s[bytes:..64]%d"), Some(vec![l(Bytes, None, Some(64)), None]));
+        assert_eq!(
+            limits("%s[1..]%s[2..2]"),
+            Some(vec![l(Chars, Some(1), None), l(Chars, Some(2), Some(2))])
+        );
+        assert_eq!(
+            limits("%s%s:str[bytes:1..64]?*"),
+            Some(vec![None, l(Bytes, Some(1), Some(64))])
+        );
+
+        assert_eq!(limits("%s[]"), None);
+        assert_eq!(limits("%s[..]"), None);
+        assert_eq!(limits("%s[64]"), None);
+        assert_eq!(limits("%s[2..1]"), None);
+        assert_eq!(limits("%s[..=64]"), None);
+        assert_eq!(limits("%s[-1..]"), None);
+        assert_eq!(limits("%s[chars:..64]"), None);
+        assert_eq!(limits("%s[ ..64]"), None);
+        assert_eq!(limits("%s?[..64]"), None);
+        assert_eq!(limits("%s[..64]:str"), None);
+        assert_eq!(limits("%d[..64]"), None);
+        assert_eq!(limits("%P[..64]"), None);
+        assert_eq!(limits("%s62[..64]"), None);
+    }
+
+    #[test]
+    fn test_parse_format_string_regexes() {
+        let regexes = |s: &str| {
+            parse_format_string_ex(s)
+                .map(|FormatString(items, _)| items.into_iter().map(|item| item.regex).collect::<Vec<_>>())
+                .map_err(|err| (err.kind, err.pos))
+        };
+        let r = |s: &str| Some(s.to_owned());
+        use FormatErrorKind::{BadRegex, RegexNotAllowed, RegexTooLong};
+
+        assert_eq!(regexes("%s[re=r'^[A-Z]{2,3}$']%d"), Ok(vec![r("^[A-Z]{2,3}$"), None]));
+        assert_eq!(regexes("%d%s[..3][re=r'a|b']?"), Ok(vec![None, r("a|b")]));
+        assert_eq!(regexes("%s:str[re=r'[]a]+']*"), Ok(vec![r("[]a]+")]));
+        let items = parse_format_string_ex("%d%s[..3][re=r'a|b']?")
+            .expect("good format string")
+            .0;
+        assert_eq!(items[1].to_string(), "%s[..3][re=r'a|b']?");
+
+        assert_eq!(regexes("%s[re=r'a+"), Err((BadRegex, 2)));
+        assert_eq!(regexes("%s[re=r'a)']"), Err((BadRegex, 9)));
+        assert_eq!(regexes("%s[re='a']"), Err((FormatErrorKind::BadLengthLimit, 2)));
+        assert_eq!(regexes("%d[re=r'1']"), Err((RegexNotAllowed, 2)));
+        assert_eq!(regexes("%d%s[re=r'[0-9]{1,2000}']"), Err((RegexTooLong, 4)));
+        assert_eq!(
+            regexes("%s[re=r'a'][..3]"),
+            Err((FormatErrorKind::InvalidChar('['), 11))
+        );
+    }
+
+    #[test]
+    fn test_parse_format_string_tries() {
+        let tries = |s: &str| {
+            parse_format_string_ex(s)
+                .ok()
+                .map(|FormatString(items, _)| items.into_iter().map(|item| (item.ty, item.trie)).collect::<Vec<_>>())
+        };
+        let t = |values: &[&str]| Some(values.iter().map(|&value| value.to_owned()).collect::<Vec<_>>());
+
+        assert_eq!(
+            tries("%s{trie:foo,bar}%d"),
+            Some(vec![(Str, t(&["foo", "bar"])), (Int, None)])
+        );
+        assert_eq!(tries("%d%s{trie:a_b}?*"), Some(vec![(Int, None), (Str, t(&["a_b"]))]));
+        let items = parse_format_string_ex("%d%s{trie:foo,bar}:Color[..3]?")
+            .expect("good format string")
+            .0;
+        assert_eq!(items[1].to_string(), "%s{trie:foo,bar}:Color[..3]?");
+
+        assert_eq!(tries("%s{trie:foo,}"), None);
+        assert_eq!(tries("%s{Trie:foo}"), None);
+        assert_eq!(tries("%d{trie:1}"), None);
+        assert_eq!(tries("%s62{trie:a}"), None);
+    }
+
+    #[test]
+    fn test_parse_format_string_slices() {
+        let slices = |s: &str| {
+            parse_format_string_ex(s)
+                .ok()
+                .map(|FormatString(items, _)| items.into_iter().map(|item| (item.ty, item.slice)).collect::<Vec<_>>())
+        };
+        let r = |start: usize, end: Option<usize>| Some(ByteRange { start, end });
+
+        assert_eq!(
+            slices("%s[slice:2..8]%d"),
+            Some(vec![(Str, r(2, Some(8))), (Int, None)])
+        );
+        assert_eq!(slices("%s[slice:..8]"), Some(vec![(Str, r(0, Some(8)))]));
+        assert_eq!(slices("%d%s[slice:2..]?*"), Some(vec![(Int, None), (Str, r(2, None))]));
+        let items = parse_format_string_ex("%d%s{trie:ab,cd}:Color[slice:1..3][re=r'^[a-z]+$']?")
+            .expect("good format string")
+            .0;
+        assert_eq!(
+            items[1].to_string(),
+            "%s{trie:ab,cd}:Color[slice:1..3][re=r'^[a-z]+$']?"
+        );
+        assert_eq!(items[1].length_limit, None);
+
+        assert_eq!(slices("%s[slice:2..2]"), None);
+        assert_eq!(slices("%s[slice:2..=8]"), None);
+        assert_eq!(slices("%s[Slice:2..8]"), None);
+        assert_eq!(slices("%s[slice:2..8][..3]"), None);
+        assert_eq!(slices("%P[slice:2..8]"), None);
+    }
+
+    #[test]
+    fn test_parse_format_string_padding() {
+        let paddings = |s: &str| {
+            parse_format_string_ex(s)
+                .ok()
+                .map(|FormatString(items, _)| items.into_iter().map(|item| item.padding).collect::<Vec<_>>())
+        };
+        let p = |width: usize, align: Align| Some(Padding { width, align });
+
+        assert_eq!(
+            paddings("%s[pad:20]%d%s[pad:1<]%s[pad:8>]%s:Color[pad:3^]?"),
+            Some(vec![
+                p(20, Align::Left),
+                None,
+                p(1, Align::Left),
+                p(8, Align::Right),
+                p(3, Align::Center)
+            ])
+        );
+        let items = parse_format_string_ex("%s[pad:20<]%s[pad:8^][re=r'^[a-z]+$']?")
+            .expect("good format string")
+            .0;
+        assert_eq!(items[0].to_string(), "%s[pad:20]");
+        assert_eq!(items[1].to_string(), "%s[pad:8^][re=r'^[a-z]+$']?");
+
+        assert_eq!(paddings("%s[pad:]"), None);
+        assert_eq!(paddings("%s[pad:^]"), None);
+        assert_eq!(paddings("%s[pad:-1]"), None);
+        assert_eq!(paddings("%s[pad:20][..3]"), None);
+        assert_eq!(paddings("%s[Pad:20]"), None);
+        assert_eq!(paddings("%s62[pad:20]"), None);
+    }
+
+    #[test]
+    fn test_parse_format_string_asserts() {
+        let asserts = |s: &str| {
+            parse_format_string_ex(s)
+                .ok()
+                .map(|FormatString(items, _)| items.into_iter().map(|item| item.assert).collect::<Vec<_>>())
+        };
+        let a = |value: &str| Some(value.to_owned());
+
+        assert_eq!(
+            asserts("%s[assert=v1]%d%s:Color[assert=red]?"),
+            Some(vec![a("v1"), None, a("red")])
+        );
+        assert_eq!(asserts("%s[assert=a-b.c=d]"), Some(vec![a("a-b.c=d")]));
+        let items = parse_format_string_ex("%s[assert=v1]%s:Color[assert=red][re=r'^[a-z]+$']?")
+            .expect("good format string")
+            .0;
+        assert_eq!(items[0].to_string(), "%s[assert=v1]");
+        assert_eq!(items[1].to_string(), "%s:Color[assert=red][re=r'^[a-z]+$']?");
+
+        assert_eq!(asserts("%s[assert=v1][..3]"), None);
+        assert_eq!(asserts("%s[Assert=v1]"), None);
+        assert_eq!(asserts("%s{set}[assert=a]"), None);
+        assert_eq!(asserts("%P[assert=/tmp]"), None);
+    }
+
+    #[rustfmt::skip] // FIXME review settings of the rustfmt
+    #[test]
+    fn test_parse_format_string_ex() {
+        // Parse so that all items are mandatory
+        let pm = |s: &str| {
+            parse_format_string_ex(s).ok().map(|FormatString(items, ending)| {
+                let ok = items.iter().all(|item| item.opt == Mandatory);
+                assert!(ok, "All items in this format string supposed to be parsed as mandatory: {}", s);
+                let items = items.into_iter().map(|item| item.ty).collect::<Vec<_>>();
+                (items, ending)
+            })
+        };
+
+        // Parse with possible optional items
+        let po = |s: &str| {
+            parse_format_string_ex(s).ok().map(|FormatString(items, ending)| {
+                let items = items.into_iter().map(|item| (item.ty, item.opt)).collect::<Vec<_>>();
+                (items, ending)
+            })
+        };
+
+        assert_eq!(pm(""), None);
+
+        assert_eq!(pm("%s"), Some((vec![Str], Closed)));
+        assert_eq!(pm("%d"), Some((vec![Int], Closed)));
+        assert_eq!(pm("%s%d"), Some((vec![Str, Int], Closed)));
+        assert_eq!(pm("%d%s"), Some((vec![Int, Str], Closed)));
+        assert_eq!(pm("%s%s"), Some((vec![Str, Str], Closed)));
+        assert_eq!(pm("%d%d"), Some((vec![Int, Int], Closed)));
+
+        assert_eq!(pm("*"), None);
+        assert_eq!(pm("*%s"), None);
+        assert_eq!(pm("*%d"), None);
+        assert_eq!(pm("%s*"), Some((vec![Str], Open)));
+        assert_eq!(pm("%d*"), Some((vec![Int], Open)));
+        assert_eq!(pm("%s%d*"), Some((vec![Str, Int], Open)));
+
+        assert_eq!(po("?"), None);
+        assert_eq!(po("*?"), None);
+        assert_eq!(po("?*"), None);
+        assert_eq!(po("%?"), None);
+        assert_eq!(po("?%s"), None);
+        assert_eq!(po("%s?"), None);
+        assert_eq!(po("%d?"), None);
+        assert_eq!(po("%s?*"), None);
+        assert_eq!(po("%d?*"), None);
+        assert_eq!(po("%s?%s?"), None);
+        assert_eq!(po("%d?%d?"), None);
+        assert_eq!(po("%s?%s?*"), None);
+        assert_eq!(po("%d?%d?*"), None);
+        assert_eq!(po("%s%d?"), Some((vec![(Str, Mandatory), (Int, Optional)], Closed)));
+        assert_eq!(po("%d%s?"), Some((vec![(Int, Mandatory), (Str, Optional)], Closed)));
+        assert_eq!(po("%s%d?*"), Some((vec![(Str, Mandatory), (Int, Optional)], Open)));
+        assert_eq!(po("%d%s?*"), Some((vec![(Int, Mandatory), (Str, Optional)], Open)));
+        assert_eq!(po("%s%s%d?"), Some((vec![(Str, Mandatory), (Str, Mandatory), (Int, Optional)], Closed)));
+        assert_eq!(po("%s%s?%d?"), Some((vec![(Str, Mandatory), (Str, Optional), (Int, Optional)], Closed)));
+        assert_eq!(po("%s%s%d?*"), Some((vec![(Str, Mandatory), (Str, Mandatory), (Int, Optional)], Open)));
+        assert_eq!(po("%s%s?%d?*"), Some((vec![(Str, Mandatory), (Str, Optional), (Int, Optional)], Open)));
+        assert_eq!(po("%P%P?*"), Some((vec![(Path, Mandatory), (Path, Optional)], Open)));
+        assert_eq!(po("%s62%s62?*"), Some((vec![(Base62, Mandatory), (Base62, Optional)], Open)));
+        assert_eq!(po("%s?%s"), None);
+        assert_eq!(po("%s?%s*"), None);
+    }
+}
+
+pub mod registry {
+    use proc_macro2::{Ident, Span, TokenStream};
+
+    /// Generates a static constructor, which registers the format string of the call site
+    /// in `fragstrings::registry` at startup, `direction` is either `Format` or `Parse`.
+    pub fn pattern_registration(pattern: &str, direction: &str) -> TokenStream {
+        let direction = Ident::new(direction, Span::mixed_site());
+        quote_mixed! {
+            #[used]
+            #[cfg_attr(any(target_os = "linux", target_os = "android", target_os = "freebsd"), link_section = ".init_array")]
+            #[cfg_attr(any(target_os = "macos", target_os = "ios"), link_section = "__DATA,__mod_init_func")]
+            #[cfg_attr(windows, link_section = ".CRT$XCU")]
+            static REGISTER: extern "C" fn() = {
+                extern "C" fn register() {
+                    static REGISTRATION: ::fragstrings::registry::Registration =
+                        ::fragstrings::registry::Registration::new(::fragstrings::registry::PatternUse {
+                            file: ::core::file!(),
+                            line: ::core::line!(),
+                            pattern: #pattern,
+                            direction: ::fragstrings::registry::Direction::#direction,
+                        });
+                    REGISTRATION.register();
+                }
+                register
+            };
+        }
+    }
+}
+
+pub mod errors {
+    use proc_macro2::{Span, TokenStream};
+    use quote::quote_spanned;
+
+    use std::fmt;
+
+    use crate::utils::fmt_strings::{FormatItem, FormatStringError};
+    use crate::utils::literals::LiteralKind;
+
+    /// Compile error of either macro, so that the shared cases are worded the same way.
+    #[derive(Debug)]
+    pub enum CompileError {
+        NoArgs,
+        UnrecognizedToken,
+        UnexpectedComma,
+        /// Arguments which are not expressions, with the message of the parser.
+        Syntax(String),
+        StringLiteralExpected,
+        NotStrLiteral(LiteralKind),
+        BadStringLiteral,
+        BadFormatString(FormatStringError),
+        BadOption,
+        TooManyItems,
+        // Formatting only
+        PathNotFormattable,
+        LengthLimitNotFormattable,
+        RegexNotFormattable,
+        TrieNotFormattable,
+        AssertNotFormattable,
+        SliceNotFormattable,
+        ArgCountMismatch {
+            items: Vec<FormatItem>,
+            args: usize,
+        },
+        TooLong,
+        // Parsing only
+        StringExpressionExpected,
+        TooManyArguments(TokenStream),
+        UnboundedItems,
+        TooManyFields(u64),
+        NeverMatches(String),
+        AmbiguousPatterns {
+            first: String,
+            second: String,
+            example: String,
+        },
+        IncompatiblePatterns {
+            old: String,
+            new: String,
+            reason: String,
+        },
+    }
+
+    impl CompileError {
+        pub fn at(self, span: Span) -> SpannedError {
+            SpannedError(self, span)
+        }
+    }
+
+    impl From<syn::Error> for SpannedError {
+        fn from(err: syn::Error) -> Self {
+            CompileError::Syntax(err.to_string()).at(err.span())
+        }
+    }
+
+    impl From<FormatStringError> for CompileError {
+        fn from(err: FormatStringError) -> Self {
+            CompileError::BadFormatString(err)
+        }
+    }
+
+    impl fmt::Display for CompileError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            use CompileError::*;
+            match self {
+                NoArgs => write!(f, "Empty arguments"),
+                UnrecognizedToken => write!(f, "Unrecognized token"),
+                UnexpectedComma => write!(f, "Unexpected comma, arguments must not be empty"),
+                Syntax(msg) => write!(f, "{}", msg),
+                StringLiteralExpected => write!(f, "String literal expected"),
+                NotStrLiteral(kind) => match kind {
+                    LiteralKind::ByteStr => write!(f, "Format string must be a str literal, not a byte string"),
+                    LiteralKind::CStr => write!(f, "Format string must be a str literal, not a C string"),
+                    LiteralKind::Char => write!(f, "Format string must be a str literal, not a char"),
+                    LiteralKind::Byte => write!(f, "Format string must be a str literal, not a byte"),
+                    LiteralKind::Str | LiteralKind::Number => write!(f, "Format string must be a str literal"),
+                },
+                BadStringLiteral => write!(f, "Bad string literal"),
+                BadFormatString(err) => write!(f, "Bad format string: {}", err),
+                BadOption => write!(f, "Unknown or malformed option"),
+                TooManyItems => write!(
+                    f,
+                    "Number of fragments exceeds max_items, such value can not be split into a list on-chain"
+                ),
+                PathNotFormattable => write!(f, "Bad format string: %P items are supported by frag_parse! only"),
+                LengthLimitNotFormattable => {
+                    write!(f, "Bad format string: length limits are supported by frag_parse! only")
+                }
+                RegexNotFormattable => write!(f, "Bad format string: regexes are supported by frag_parse! only"),
+                TrieNotFormattable => write!(f, "Bad format string: tries are supported by frag_parse! only"),
+                AssertNotFormattable => write!(
+                    f,
+                    "Bad format string: asserted values are supported by frag_parse! only"
+                ),
+                SliceNotFormattable => write!(f, "Bad format string: byte ranges are supported by frag_parse! only"),
+                ArgCountMismatch { items, args } => {
+                    write!(
+                        f,
+                        "Format has {} {} but {} {} supplied",
+                        items.len(),
+                        if items.len() == 1 { "item" } else { "items" },
+                        args,
+                        if *args == 1 { "argument was" } else { "arguments were" },
+                    )?;
+                    for (i, item) in items.iter().enumerate().skip(*args) {
+                        write!(f, "{}{}", if i == *args { ", missing: " } else { ", " }, item)?;
+                    }
+                    Ok(())
+                }
+                TooLong => write!(f, "Formatted value is longer than max_bytes"),
+                StringExpressionExpected => write!(f, "String expression expected"),
+                TooManyArguments(arg) => write!(
+                    f,
+                    "Too many arguments, expected the format string and the value, but got also `{}`",
+                    arg
+                ),
+                UnboundedItems => write!(
+                    f,
+                    "Open-ended format string has unbounded number of fragments, so max_items can not be guaranteed"
+                ),
+                TooManyFields(max_fields) => write!(
+                    f,
+                    "Format string has more than {} items, which is likely a mistake; set max_fields if it is not",
+                    max_fields
+                ),
+                NeverMatches(descriptor) => write!(
+                    f,
+                    "Value formatted with the descriptor `{}` never matches the format string of frag_parse!",
+                    descriptor
+                ),
+                AmbiguousPatterns { first, second, example } => write!(
+                    f,
+                    "Format strings `{}` and `{}` are ambiguous, both match values like `{}`",
+                    first, second, example
+                ),
+                IncompatiblePatterns { old, new, reason } => write!(
+                    f,
+                    "Format string `{}` is not compatible with `{}`: {}",
+                    new, old, reason
+                ),
+            }
+        }
+    }
+
+    /// Compile error, reported at the location of the offending tokens.
+    #[derive(Debug)]
+    pub struct SpannedError(pub CompileError, pub Span);
+
+    impl SpannedError {
+        /// Expands into the error along with the `dummy` value, which has the type of the expected output,
+        /// so that the error is not followed by type errors at the call site.
+        pub fn into_compile_error(self, dummy: TokenStream) -> TokenStream {
+            let SpannedError(error, span) = self;
+            let error = syn::Error::new(span, error).to_compile_error();
+            // Extra curly braces are required here,
+            // because output is required to be an assignable expression.
+            quote_spanned! { span=> { #error #dummy } }
+        }
+    }
+
+    #[test]
+    fn test_compile_error_messages() {
+        use crate::utils::fmt_strings::parse_format_string_ex;
+
+        // Errors shared by both macros are worded the same way
+        assert_eq!(CompileError::NoArgs.to_string(), "Empty arguments");
+        assert_eq!(
+            CompileError::UnexpectedComma.to_string(),
+            "Unexpected comma, arguments must not be empty"
+        );
+        assert_eq!(
+            CompileError::NotStrLiteral(LiteralKind::ByteStr).to_string(),
+            "Format string must be a str literal, not a byte string"
+        );
+        assert_eq!(CompileError::BadOption.to_string(), "Unknown or malformed option");
+        let err = parse_format_string_ex("%x").expect_err("bad format string");
+        assert_eq!(
+            CompileError::from(err).to_string(),
+            "Bad format string: unknown descriptor 'x' at position 1 of format string; expected %s, %d, %P or %s62"
+        );
+
+        let items = parse_format_string_ex("%s%d%s").expect("good format string").0;
+        let err = CompileError::ArgCountMismatch {
+            items: items.clone(),
+            args: 1,
+        };
+        assert_eq!(
+            err.to_string(),
+            "Format has 3 items but 1 argument was supplied, missing: %d, %s"
+        );
+        let err = CompileError::ArgCountMismatch { items, args: 4 };
+        assert_eq!(err.to_string(), "Format has 3 items but 4 arguments were supplied");
+        let err = CompileError::TooManyArguments("foo".parse().expect("valid tokens"));
+        assert_eq!(
+            err.to_string(),
+            "Too many arguments, expected the format string and the value, but got also `foo`"
+        );
+    }
+
+    #[test]
+    fn test_into_compile_error() {
+        let dummy = "::std::string::String::new()".parse().expect("valid tokens");
+        let output = CompileError::BadOption.at(Span::call_site()).into_compile_error(dummy);
+        assert_eq!(
+            output.to_string(),
+            r#"{ :: core :: compile_error ! { "Unknown or malformed option" } :: std :: string :: String :: new () }"#
+        );
+
+        // The error is reported at the given span
+        let option = "checked,\n  bogus".parse::<TokenStream>().expect("valid tokens");
+        let span = option.into_iter().last().expect("option").span();
+        let output = CompileError::BadOption.at(span).into_compile_error(TokenStream::new());
+        let tokens = output.into_iter().collect::<Vec<_>>();
+        let error = match tokens.as_slice() {
+            [proc_macro2::TokenTree::Group(block)] => block.stream().into_iter().collect::<Vec<_>>(),
+            _ => panic!("block expected"),
+        };
+        for token in error {
+            assert_eq!((token.span().start(), token.span().end()), (span.start(), span.end()));
+        }
+        assert_eq!((span.start().line, span.start().column), (2, 2));
+    }
+}
@@ -0,0 +1,115 @@
+//! Checks that the code generated by the macros can not panic, which is proven by the linker:
+//! every function below fails to link, if the optimizer does not remove all the panic paths from it.
+//! Only the expansions which do not allocate are checked, because the allocation can panic itself.
+//! The check is done by `cargo build --profile no-panic -p no-panic-tests`, other builds only run the functions.
+
+use fragstrings::{frag_parse, frag_parse_ref, frag_parse_strict, AuditLogger};
+use std::sync::atomic::AtomicU64;
+use std::time::SystemTime;
+
+/// Calls the undefined function when dropped, i.e. if the function panics, in optimized builds only.
+struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        #[cfg(not(debug_assertions))]
+        {
+            extern "C" {
+                #[link_name = "\n\nERROR: the expansion of the macro can panic\n\n"]
+                fn panic_detected() -> !;
+            }
+            unsafe { panic_detected() }
+        }
+    }
+}
+
+macro_rules! no_panic {
+    ($(fn $name:ident($input:ident: &str) -> $output:ty $body:block)*) => {
+        $(
+            #[inline(never)]
+            fn $name($input: &str) -> $output {
+                let guard = Guard;
+                let res = $body;
+                std::mem::forget(guard);
+                res
+            }
+        )*
+    };
+}
+
+static CALLS: AtomicU64 = AtomicU64::new(0);
+
+struct Silent;
+
+impl AuditLogger for Silent {
+    fn record(&self, _fmt: &str, _input: &str, _success: bool) {}
+}
+
+no_panic! {
+    fn borrowed(input: &str) -> Option<(&str, i64)> {
+        frag_parse_ref!("%s%d", input)
+    }
+    fn open_ended(input: &str) -> Option<(&str, Option<i64>)> {
+        frag_parse_ref!("%s%d?*", input)
+    }
+    fn single(input: &str) -> Option<&str> {
+        frag_parse_ref!("%s", input)
+    }
+    fn custom_types(input: &str) -> Option<(&str, u8, SystemTime)> {
+        frag_parse!("%s:str%d:u8%d:SystemTime", input)
+    }
+    fn integers(input: &str) -> Option<(i64, Option<i64>)> {
+        frag_parse!(prime, null_sentinel = "NULL", "%d%d?", input)
+    }
+    fn strict(input: &str) -> Option<(i64, Option<i64>)> {
+        frag_parse_strict!("%d%d?*", input)
+    }
+    fn checked(input: &str) -> Result<(u32, &str), fragstrings::ParseError> {
+        frag_parse_ref!(checked, version = [0, 2], "%s", input)
+    }
+    fn durations(input: &str) -> Option<i64> {
+        frag_parse!(human_duration, "%d", input)
+    }
+    fn validated(input: &str) -> Option<(&str, &str, &str)> {
+        frag_parse_ref!(hostname, "%s%s[..8]%s{trie:buy,sell}", input)
+    }
+    fn tries(input: &str) -> Option<&str> {
+        frag_parse_ref!(trie_threshold = 1, "%s{trie:buy,sell,hold}", input)
+    }
+    fn identifiers(input: &str) -> Option<&str> {
+        frag_parse_ref!(ident, valid_json, "%s", input)
+    }
+    fn regexes(input: &str) -> Option<(&str, &str)> {
+        frag_parse_ref!(r"%s[re=r'^[A-Z]{2,3}$']%s[re=r'(a|aa)*b|\w+']", input)
+    }
+    fn counted(input: &str) -> Option<&str> {
+        frag_parse_ref!(count = &CALLS, count_failures = &CALLS, audit = &Silent, verbose, "%s", input)
+    }
+}
+
+#[cfg(feature = "tracing")]
+no_panic! {
+    fn traced(input: &str) -> Option<&str> {
+        frag_parse_ref!(trace, "%s", input)
+    }
+}
+
+fn main() {
+    for input in std::env::args().skip(1) {
+        println!("{:?}", borrowed(&input));
+        println!("{:?}", open_ended(&input));
+        println!("{:?}", single(&input));
+        println!("{:?}", custom_types(&input));
+        println!("{:?}", integers(&input));
+        println!("{:?}", strict(&input));
+        println!("{:?}", checked(&input));
+        println!("{:?}", durations(&input));
+        println!("{:?}", validated(&input));
+        println!("{:?}", tries(&input));
+        println!("{:?}", identifiers(&input));
+        println!("{:?}", regexes(&input));
+        println!("{:?}", counted(&input));
+        #[cfg(feature = "tracing")]
+        println!("{:?}", traced(&input));
+    }
+}
@@ -0,0 +1,87 @@
+//! Expansions of the macros in a `no_std` crate, which only has `core` and `alloc`.
+//!
+//! The functions below are compiled without `std`, which checks that the generated code does not refer to it,
+//! and the tests check that they work the same way as in `std` crates.
+
+#![no_std]
+
+extern crate alloc;
+#[cfg(test)]
+extern crate std;
+
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use fragstrings::small::SmallString;
+use fragstrings::{
+    frag_format, frag_format_small, frag_parse, frag_parse_arc, frag_parse_ref, FormatError, ParseError,
+};
+
+pub fn format_plain(name: &str, id: i64) -> String {
+    frag_format!("%s%d", name, id)
+}
+
+pub fn format_small(name: &str, id: i64) -> SmallString {
+    frag_format_small!("%s%d", name, id)
+}
+
+pub fn format_with_options(name: &str, tags: &BTreeSet<&str>, key: &[u8]) -> String {
+    frag_format!(checked, version = 2, html_encode, "%s%s{set}%s62", name, tags, key)
+}
+
+pub fn format_optional(name: Option<&str>) -> Result<String, FormatError> {
+    frag_format!(max_bytes = 32, null_sentinel = "NULL", "%d%s?", 42, name)
+}
+
+pub fn parse_plain(input: &str) -> Option<(String, i64)> {
+    frag_parse!("%s%d", input)
+}
+
+pub fn parse_with_options(input: &str) -> Result<(u32, String, BTreeSet<String>, Vec<u8>), ParseError> {
+    frag_parse!(checked, version = [0, 2], html_decode, "%s%s{set}%s62", input)
+}
+
+pub fn parse_borrowed(input: &str) -> Option<(&str, Vec<&str>, Option<i64>)> {
+    frag_parse_ref!(r"%s[re=r'^[a-z]+$']%s:words%d?", input)
+}
+
+pub fn parse_shared(input: &str) -> Option<(Arc<String>, u8)> {
+    frag_parse_arc!(ident, "%s%d:u8", input)
+}
+
+#[test]
+fn test_no_std_format() {
+    assert_eq!(format_plain("foo", 42), "%s%d__foo__42");
+    assert_eq!(format_small("foo", 42), "%s%d__foo__42");
+    let tags = ["b", "a"].iter().copied().collect();
+    assert_eq!(
+        format_with_options("<foo>", &tags, b"Hello"),
+        fragstrings::frag_format!(checked, version = 2, "%s%s%s62", "&lt;foo&gt;", "a,b", b"Hello")
+    );
+    assert_eq!(format_optional(None).as_deref(), Ok("%d%s__42__NULL"));
+    assert!(format_optional(Some("a very long name, which does not fit")).is_err());
+}
+
+#[test]
+fn test_no_std_parse() {
+    assert_eq!(parse_plain("%s%d__foo__42"), Some((String::from("foo"), 42)));
+    assert_eq!(parse_plain("%s%d__foo__bar"), None);
+
+    let tags = ["b", "a"].iter().copied().collect();
+    let value = format_with_options("<foo>", &tags, b"Hello");
+    let (version, name, tags, key) = parse_with_options(&value).expect("failed to parse");
+    assert_eq!((version, name.as_str(), key.as_slice()), (2, "<foo>", &b"Hello"[..]));
+    assert_eq!(tags.iter().map(String::as_str).collect::<Vec<_>>(), ["a", "b"]);
+
+    assert_eq!(
+        parse_borrowed("%s%s%d__foo__a b__42"),
+        Some(("foo", ["a", "b"].to_vec(), Some(42)))
+    );
+    assert_eq!(parse_borrowed("%s%s__Foo__a"), None);
+    assert_eq!(
+        parse_shared("%s%d__foo__7").map(|(name, n)| (String::clone(&name), n)),
+        Some((String::from("foo"), 7))
+    );
+}
@@ -0,0 +1,1846 @@
+//! Runtime support for the code generated by the macros, not a public API.
+
+use alloc::borrow::{Cow, ToOwned};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+/// The `alloc` crate, which is not in scope of the generated code, unlike `core`.
+pub extern crate alloc;
+
+/// The `tracing` crate, which the calling crate does not have to depend on.
+#[cfg(feature = "tracing")]
+pub use tracing;
+
+/// The `secrecy` crate, for the values returned with the `secret` option.
+#[cfg(feature = "secrecy")]
+pub use secrecy;
+
+/// The `emojis` crate, which looks up the shortcodes for the `emoji` and `demoji` options.
+#[cfg(feature = "emojis")]
+pub use emojis;
+
+/// The `chrono` crate, for the dates of the `packed_date` option.
+#[cfg(feature = "chrono")]
+pub use chrono;
+
+/// CRC32 (IEEE 802.3), bitwise implementation - the values are short, so no lookup table.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[test]
+fn test_crc32() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(crc32(b"The quick brown fox jumps over the lazy dog"), 0x414F_A339);
+}
+
+/// Replaces HTML special characters with entities.
+pub fn html_encode(s: &str) -> Cow<'_, str> {
+    if !s.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(s);
+    }
+    let mut res = String::with_capacity(s.len() + 16);
+    for ch in s.chars() {
+        match ch {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&#39;"),
+            _ => res.push(ch),
+        }
+    }
+    Cow::Owned(res)
+}
+
+/// Longest entity between `&` and `;`, like `#1114111`, with a couple of leading zeros.
+const MAX_HTML_ENTITY_LEN: usize = 10;
+
+/// Replaces HTML entities with characters they stand for.
+/// Supports numeric entities and the named ones for HTML special characters,
+/// unknown or malformed entities are left as is.
+pub fn html_decode(s: &str) -> Cow<'_, str> {
+    if !s.contains('&') {
+        return Cow::Borrowed(s);
+    }
+    let mut res = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(pos) = rest.find('&') {
+        res.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        // The entity is short, so the search for its end stops early, and takes linear time in total
+        let decoded = rest
+            .bytes()
+            .take(MAX_HTML_ENTITY_LEN + 2)
+            .position(|byte| byte == b';')
+            .and_then(|end| decode_html_entity(&rest[1..end]).map(|ch| (ch, end)));
+        match decoded {
+            Some((ch, end)) => {
+                res.push(ch);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                res.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    res.push_str(rest);
+    Cow::Owned(res)
+}
+
+fn decode_html_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => {
+            let code = entity.strip_prefix('#')?;
+            let code = match code.strip_prefix(|ch| ch == 'x' || ch == 'X') {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+#[test]
+fn test_html_encode() {
+    assert_eq!(html_encode(""), "");
+    assert_eq!(html_encode("foo"), "foo");
+    assert!(matches!(html_encode("foo"), Cow::Borrowed(_)));
+    assert_eq!(
+        html_encode("a < b && c > \"d\""),
+        "a &lt; b &amp;&amp; c &gt; &quot;d&quot;"
+    );
+    assert_eq!(html_encode("it's"), "it&#39;s");
+    assert_eq!(html_encode("привет & <мир>"), "привет &amp; &lt;мир&gt;");
+}
+
+#[test]
+fn test_html_decode() {
+    assert_eq!(html_decode(""), "");
+    assert_eq!(html_decode("foo"), "foo");
+    assert!(matches!(html_decode("foo"), Cow::Borrowed(_)));
+    assert_eq!(
+        html_decode("a &lt; b &amp;&amp; c &gt; &quot;d&quot;"),
+        "a < b && c > \"d\""
+    );
+    assert_eq!(html_decode("it&#39;s &apos;&#x41;&#X42;&#67;&apos;"), "it's 'ABC'");
+    assert_eq!(html_decode("&amp;lt;"), "&lt;");
+    assert_eq!(html_decode("&nbsp;"), "\u{a0}");
+
+    // Unknown and malformed entities
+    assert_eq!(html_decode("&"), "&");
+    assert_eq!(html_decode("&amp"), "&amp");
+    assert_eq!(html_decode("a & b; c"), "a & b; c");
+    assert_eq!(html_decode("&foo;"), "&foo;");
+    assert_eq!(html_decode("&#;&#x;&#xZZ;&#1114112;"), "&#;&#x;&#xZZ;&#1114112;");
+    assert_eq!(html_decode("&&amp;"), "&&");
+    assert_eq!(html_decode("привет &amp; мир"), "привет & мир");
+
+    // Entities are short
+    assert_eq!(html_decode("&#0000000065;&#000000065;"), "&#0000000065;A");
+    let ampersands = "&".repeat(100_000);
+    assert_eq!(html_decode(&format!("{};", ampersands)), format!("{};", ampersands));
+}
+
+/// Replaces percent-encoded bytes, like `%2F` or `%20`, with the characters they stand for,
+/// like in URL path segments, where `+` is not a space. Malformed escapes are left as is,
+/// and so is the whole string, if the decoded bytes are not valid UTF-8.
+pub fn percent_decode(s: &str) -> Cow<'_, str> {
+    if !s.contains('%') {
+        return Cow::Borrowed(s);
+    }
+    let hex = |byte: u8| (byte as char).to_digit(16);
+    let bytes = s.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes.get(i..i + 3) {
+            Some(&[b'%', hi, lo]) => hex(hi).zip(hex(lo)).map(|(hi, lo)| (hi * 16 + lo) as u8),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                res.push(byte);
+                i += 3;
+            }
+            None => {
+                res.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    match String::from_utf8(res) {
+        Ok(res) => Cow::Owned(res),
+        Err(_) => Cow::Borrowed(s),
+    }
+}
+
+#[test]
+fn test_percent_decode() {
+    assert_eq!(percent_decode(""), "");
+    assert_eq!(percent_decode("foo"), "foo");
+    assert!(matches!(percent_decode("foo+bar"), Cow::Borrowed(_)));
+    assert_eq!(percent_decode("foo%2Fbar%20baz+qux"), "foo/bar baz+qux");
+    assert_eq!(percent_decode("%2f%2F"), "//");
+    assert_eq!(percent_decode("%D0%BF%D1%80%D0%B8%D0%B2%D0%B5%D1%82"), "привет");
+    assert_eq!(percent_decode("%252F"), "%2F");
+
+    // Malformed escapes and invalid UTF-8
+    assert_eq!(percent_decode("%"), "%");
+    assert_eq!(percent_decode("100%"), "100%");
+    assert_eq!(percent_decode("%2"), "%2");
+    assert_eq!(percent_decode("%ZZ%2G"), "%ZZ%2G");
+    assert_eq!(percent_decode("%%2F"), "%/");
+    assert_eq!(percent_decode("%FF%2F"), "%FF%2F");
+    assert_eq!(percent_decode("привет%21"), "привет!");
+}
+
+/// Characters which can not be in file names, `/` on Unix and the others on Windows,
+/// where control characters can not be either, and NUL is not allowed anywhere.
+const PATH_UNSAFE: &[u8] = b"/\\<>:\"|?*";
+
+fn is_path_unsafe(byte: u8) -> bool {
+    byte < 0x20 || PATH_UNSAFE.contains(&byte)
+}
+
+/// Checks that the string can be a part of a file name both on Unix and on Windows.
+pub fn is_path_safe(s: &str) -> bool {
+    !s.bytes().any(is_path_unsafe)
+}
+
+/// Percent-encodes the characters which can not be in file names, and `%` itself,
+/// so that `percent_decode` restores the string.
+pub fn path_escape(s: &str) -> Cow<'_, str> {
+    if is_path_safe(s) && !s.contains('%') {
+        return Cow::Borrowed(s);
+    }
+    let mut res = String::with_capacity(s.len() + 8);
+    for ch in s.chars() {
+        match u8::try_from(ch) {
+            Ok(byte) if byte == b'%' || is_path_unsafe(byte) => {
+                res.push('%');
+                res.push_str(&alloc::format!("{:02X}", byte));
+            }
+            _ => res.push(ch),
+        }
+    }
+    Cow::Owned(res)
+}
+
+#[test]
+fn test_path_safe() {
+    assert!(is_path_safe(""));
+    assert!(is_path_safe("foo bar.txt"));
+    assert!(is_path_safe("привет-100%_#~"));
+    // Unix
+    assert!(!is_path_safe("a/b"));
+    assert!(!is_path_safe("a\0b"));
+    // Windows
+    for unsafe_str in [
+        "a\\b", "a<b", "a>b", "C:", "\"a\"", "a|b", "a?", "*", "\t", "\n", "\x1f",
+    ]
+    .iter()
+    {
+        assert!(!is_path_safe(unsafe_str), "{:?}", unsafe_str);
+    }
+    assert!(is_path_safe("\x7f"));
+
+    assert!(matches!(path_escape("foo bar.txt"), Cow::Borrowed(_)));
+    assert_eq!(path_escape("a/b\\c"), "a%2Fb%5Cc");
+    assert_eq!(path_escape("100%"), "100%25");
+    assert_eq!(path_escape("\0<>:\"|?*\n"), "%00%3C%3E%3A%22%7C%3F%2A%0A");
+    assert_eq!(path_escape("привет/мир"), "привет%2Fмир");
+    for s in ["", "a/b", "100%", "%2F", "C:\\Temp\\x?", "привет/мир\0"].iter() {
+        assert!(is_path_safe(&path_escape(s)));
+        assert_eq!(percent_decode(&path_escape(s)), *s);
+    }
+}
+
+/// Longest emoji in characters, like a family of four with the joiners between them.
+const MAX_EMOJI_CHARS: usize = 10;
+
+/// Replaces emoji with their shortcodes, like `:smile:`, which are looked up by `shortcode`,
+/// the longest emoji first. Emoji without shortcodes are left as is.
+pub fn emoji_to_shortcodes(s: &str, shortcode: impl Fn(&str) -> Option<&'static str>) -> Cow<'_, str> {
+    if s.is_ascii() {
+        return Cow::Borrowed(s);
+    }
+    let mut res = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(ch) = rest.chars().next() {
+        // Emoji start with a non-ASCII character, or are followed by one, like keycaps
+        let ascii = ch.is_ascii() && rest.as_bytes().get(1).map_or(true, u8::is_ascii);
+        let limit = rest.char_indices().nth(MAX_EMOJI_CHARS).map_or(rest.len(), |(i, _)| i);
+        let found = rest.get(..limit).filter(|_| !ascii).and_then(|candidates| {
+            candidates.char_indices().rev().find_map(|(i, ch)| {
+                let end = i + ch.len_utf8();
+                candidates.get(..end).and_then(&shortcode).map(|code| (code, end))
+            })
+        });
+        let end = match found {
+            Some((code, end)) => {
+                res.push(':');
+                res.push_str(code);
+                res.push(':');
+                end
+            }
+            None => {
+                res.push(ch);
+                ch.len_utf8()
+            }
+        };
+        rest = rest.get(end..).unwrap_or_default();
+    }
+    Cow::Owned(res)
+}
+
+/// Replaces shortcodes, like `:smile:`, with emoji, which are looked up by `emoji`.
+/// Unknown shortcodes are left as is.
+pub fn shortcodes_to_emoji(s: &str, emoji: impl Fn(&str) -> Option<&'static str>) -> Cow<'_, str> {
+    if !s.contains(':') {
+        return Cow::Borrowed(s);
+    }
+    let mut res = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some((before, after)) = rest.split_once(':') {
+        res.push_str(before);
+        let found = after
+            .split_once(':')
+            .and_then(|(code, after)| emoji(code).map(|emoji| (emoji, after)));
+        match found {
+            Some((emoji, after)) => {
+                res.push_str(emoji);
+                rest = after;
+            }
+            None => {
+                res.push(':');
+                rest = after;
+            }
+        }
+    }
+    res.push_str(rest);
+    Cow::Owned(res)
+}
+
+#[test]
+fn test_emoji_shortcodes() {
+    const EMOJI: [(&str, &str); 4] = [
+        ("\u{1f604}", "smile"),
+        ("\u{1f44d}", "+1"),
+        ("\u{1f44d}\u{1f3fd}", "+1_tone3"),
+        ("#\u{fe0f}\u{20e3}", "hash"),
+    ];
+    let shortcode = |s: &str| EMOJI.iter().find(|(emoji, _)| *emoji == s).map(|(_, code)| *code);
+    let emoji = |s: &str| EMOJI.iter().find(|(_, code)| *code == s).map(|(emoji, _)| *emoji);
+
+    assert!(matches!(emoji_to_shortcodes("foo", shortcode), Cow::Borrowed("foo")));
+    assert_eq!(emoji_to_shortcodes("hi \u{1f604}!", shortcode), "hi :smile:!");
+    assert_eq!(
+        emoji_to_shortcodes("\u{1f44d}\u{1f3fd}\u{1f44d}", shortcode),
+        ":+1_tone3::+1:"
+    );
+    assert_eq!(emoji_to_shortcodes("#\u{fe0f}\u{20e3}#", shortcode), ":hash:#");
+    assert_eq!(emoji_to_shortcodes("привет \u{1f600}", shortcode), "привет \u{1f600}");
+
+    assert!(matches!(shortcodes_to_emoji("foo", emoji), Cow::Borrowed("foo")));
+    assert_eq!(shortcodes_to_emoji("hi :smile:!", emoji), "hi \u{1f604}!");
+    assert_eq!(
+        shortcodes_to_emoji(":+1_tone3::+1:", emoji),
+        "\u{1f44d}\u{1f3fd}\u{1f44d}"
+    );
+    assert_eq!(shortcodes_to_emoji("a:b:smile:", emoji), "a:b\u{1f604}");
+    assert_eq!(shortcodes_to_emoji(":frown: :smile", emoji), ":frown: :smile");
+    assert_eq!(shortcodes_to_emoji("::smile::", emoji), ":\u{1f604}:");
+}
+
+/// Checks that the string is a valid DNS hostname: dot-separated labels of ASCII letters, digits
+/// and hyphens, not starting or ending with a hyphen, up to 63 bytes each and up to 253 bytes in total.
+pub fn is_hostname(s: &str) -> bool {
+    let is_label = |label: &[u8]| {
+        (1..=63).contains(&label.len())
+            && label.iter().all(|ch| ch.is_ascii_alphanumeric() || *ch == b'-')
+            && label.first() != Some(&b'-')
+            && label.last() != Some(&b'-')
+    };
+    (1..=253).contains(&s.len()) && s.as_bytes().split(|&ch| ch == b'.').all(is_label)
+}
+
+#[test]
+fn test_is_hostname() {
+    assert!(is_hostname("localhost"));
+    assert!(is_hostname("example.com"));
+    assert!(is_hostname("node-1.Waves.EXCHANGE"));
+    assert!(is_hostname("1.2.3.4"));
+    assert!(is_hostname("a"));
+    assert!(is_hostname(&"a".repeat(63)));
+    assert!(is_hostname(
+        &["a".repeat(63), "b".repeat(63), "c".repeat(63), "d".repeat(61)].join(".")
+    ));
+
+    assert!(!is_hostname(""));
+    assert!(!is_hostname("."));
+    assert!(!is_hostname(".example.com"));
+    assert!(!is_hostname("example.com."));
+    assert!(!is_hostname("example..com"));
+    assert!(!is_hostname("-example.com"));
+    assert!(!is_hostname("example-.com"));
+    assert!(!is_hostname("example.-com"));
+    assert!(!is_hostname("exa_mple.com"));
+    assert!(!is_hostname("example.com:80"));
+    assert!(!is_hostname("привет.рф"));
+    assert!(!is_hostname(&"a".repeat(64)));
+    assert!(!is_hostname(
+        &["a".repeat(63), "b".repeat(63), "c".repeat(63), "d".repeat(62)].join(".")
+    ));
+}
+
+/// Rust keywords, including the reserved ones, of all editions.
+const KEYWORDS: [&str; 52] = [
+    "Self", "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "macro",
+    "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "self", "static", "struct", "super",
+    "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Checks that the string is an ASCII Rust identifier, which is not a keyword.
+/// A sole underscore is not an identifier either.
+pub fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    let starts_ok = matches!(chars.next(), Some(ch) if ch.is_ascii_alphabetic() || ch == '_');
+    starts_ok && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_') && s != "_" && !KEYWORDS.contains(&s)
+}
+
+#[test]
+fn test_is_ident() {
+    assert!(is_ident("foo"));
+    assert!(is_ident("Foo_Bar1"));
+    assert!(is_ident("_foo"));
+    assert!(is_ident("__"));
+    assert!(is_ident("Type"));
+    assert!(is_ident("union"));
+
+    assert!(!is_ident(""));
+    assert!(!is_ident("_"));
+    assert!(!is_ident("1foo"));
+    assert!(!is_ident("foo-bar"));
+    assert!(!is_ident("foo bar"));
+    assert!(!is_ident("привет"));
+    assert!(!is_ident("type"));
+    assert!(!is_ident("self"));
+    assert!(!is_ident("Self"));
+    assert!(!is_ident("async"));
+    assert!(!is_ident("yield"));
+    assert!(!is_ident("r#type"));
+}
+
+/// Primes, which are enough as Miller-Rabin witnesses for all the 64-bit numbers.
+const SMALL_PRIMES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+/// Checks that the number is prime, using deterministic Miller-Rabin test,
+/// because values can be as large as 64 bits, which is too much for a sieve.
+pub fn is_prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    if let Some(&p) = SMALL_PRIMES.iter().find(|&&p| n % p == 0) {
+        return n == p;
+    }
+    let mul_mod = |a: u64, b: u64| (a as u128 * b as u128 % n as u128) as u64;
+    let pow_mod = |mut base: u64, mut exp: u64| {
+        let mut result = 1;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = mul_mod(result, base);
+            }
+            base = mul_mod(base, base);
+            exp >>= 1;
+        }
+        result
+    };
+    // n - 1 = d * 2^s, where d is odd
+    let s = (n - 1).trailing_zeros();
+    let d = (n - 1) >> s;
+    SMALL_PRIMES.iter().all(|&a| {
+        let mut x = pow_mod(a, d);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        (1..s).any(|_| {
+            x = mul_mod(x, x);
+            x == n - 1
+        })
+    })
+}
+
+#[test]
+fn test_is_prime() {
+    let sieved = {
+        let mut sieve = vec![true; 1000];
+        sieve[0] = false;
+        sieve[1] = false;
+        for i in 2..sieve.len() {
+            if sieve[i] {
+                (i * i..sieve.len()).step_by(i).for_each(|j| sieve[j] = false);
+            }
+        }
+        sieve
+    };
+    for (n, &prime) in sieved.iter().enumerate() {
+        assert_eq!(is_prime(n as u64), prime, "{}", n);
+    }
+
+    assert!(is_prime(2_147_483_647));
+    assert!(is_prime(1_000_000_007));
+    assert!(is_prime(18_446_744_073_709_551_557)); // The largest 64-bit prime
+    assert!(is_prime(9_223_372_036_854_775_783)); // The largest 63-bit prime
+
+    assert!(!is_prime(3_215_031_751)); // Strong pseudoprime to bases 2, 3, 5 and 7
+    assert!(!is_prime(3_825_123_056_546_413_051)); // Strong pseudoprime to bases up to 23
+    assert!(!is_prime(u64::MAX));
+    assert!(!is_prime(1_000_000_007 * 1_000_000_009));
+}
+
+/// Nesting limit of JSON arrays and objects, so that deeply nested values do not overflow the stack.
+const JSON_MAX_DEPTH: usize = 128;
+
+/// Checks that the string is a syntactically valid JSON value (RFC 8259) of any type,
+/// surrounded by optional whitespace. Nesting deeper than `JSON_MAX_DEPTH` is considered invalid.
+pub fn is_json(s: &str) -> bool {
+    let mut parser = JsonParser {
+        bytes: s.as_bytes(),
+        pos: 0,
+    };
+    parser.value(0) && {
+        parser.skip_whitespace();
+        parser.pos == parser.bytes.len()
+    }
+}
+
+/// Recursive descent validator of JSON, which does not build the value.
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let eaten = self.peek() == Some(byte);
+        self.pos += eaten as usize;
+        eaten
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn value(&mut self, depth: usize) -> bool {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') if depth < JSON_MAX_DEPTH => {
+                self.pos += 1;
+                self.skip_whitespace();
+                if self.eat(b'}') {
+                    return true;
+                }
+                loop {
+                    self.skip_whitespace();
+                    if !(self.string()
+                        && {
+                            self.skip_whitespace();
+                            self.eat(b':')
+                        }
+                        && self.value(depth + 1))
+                    {
+                        return false;
+                    }
+                    self.skip_whitespace();
+                    if !self.eat(b',') {
+                        return self.eat(b'}');
+                    }
+                }
+            }
+            Some(b'[') if depth < JSON_MAX_DEPTH => {
+                self.pos += 1;
+                self.skip_whitespace();
+                if self.eat(b']') {
+                    return true;
+                }
+                loop {
+                    if !self.value(depth + 1) {
+                        return false;
+                    }
+                    self.skip_whitespace();
+                    if !self.eat(b',') {
+                        return self.eat(b']');
+                    }
+                }
+            }
+            Some(b'"') => self.string(),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(b't') => self.literal(b"true"),
+            Some(b'f') => self.literal(b"false"),
+            Some(b'n') => self.literal(b"null"),
+            _ => false,
+        }
+    }
+
+    fn literal(&mut self, literal: &[u8]) -> bool {
+        let matched = self.bytes[self.pos..].starts_with(literal);
+        if matched {
+            self.pos += literal.len();
+        }
+        matched
+    }
+
+    fn number(&mut self) -> bool {
+        self.eat(b'-');
+        // No leading zeros
+        if !self.eat(b'0') && self.digits() == 0 {
+            return false;
+        }
+        if self.eat(b'.') && self.digits() == 0 {
+            return false;
+        }
+        if self.eat(b'e') || self.eat(b'E') {
+            let _ = self.eat(b'+') || self.eat(b'-');
+            if self.digits() == 0 {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn string(&mut self) -> bool {
+        if !self.eat(b'"') {
+            return false;
+        }
+        loop {
+            match self.peek() {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return true;
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't') => self.pos += 1,
+                        Some(b'u') => {
+                            self.pos += 1;
+                            let hex = self.bytes.get(self.pos..self.pos + 4);
+                            if !hex.is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit)) {
+                                return false;
+                            }
+                            self.pos += 4;
+                        }
+                        _ => return false,
+                    }
+                }
+                // Control characters must be escaped
+                Some(0x00..=0x1f) | None => return false,
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+}
+
+#[test]
+fn test_is_json() {
+    assert!(is_json("null"));
+    assert!(is_json("true"));
+    assert!(is_json("false"));
+    assert!(is_json("0"));
+    assert!(is_json("-12.5e+3"));
+    assert!(is_json("1E-7"));
+    assert!(is_json(r#""foo""#));
+    assert!(is_json(r#""привет \" \\ \/ \n \u00e9""#));
+    assert!(is_json("[]"));
+    assert!(is_json("{}"));
+    assert!(is_json(r#" { "a" : [1, 2.0, {"b": null}], "c": "d" } "#));
+    assert!(is_json("\n[\ttrue,\r\nfalse ]\n"));
+    assert!(is_json(&("[".repeat(128) + &"]".repeat(128))));
+
+    assert!(!is_json(""));
+    assert!(!is_json(" "));
+    assert!(!is_json("nul"));
+    assert!(!is_json("True"));
+    assert!(!is_json("foo"));
+    assert!(!is_json("01"));
+    assert!(!is_json("-"));
+    assert!(!is_json("1."));
+    assert!(!is_json(".5"));
+    assert!(!is_json("+1"));
+    assert!(!is_json("1e"));
+    assert!(!is_json("0x10"));
+    assert!(!is_json("'foo'"));
+    assert!(!is_json(r#""foo"#));
+    assert!(!is_json(r#""\x""#));
+    assert!(!is_json(r#""\u00e""#));
+    assert!(!is_json("\"a\tb\""));
+    assert!(!is_json("[1,]"));
+    assert!(!is_json("[1 2]"));
+    assert!(!is_json("{\"a\"}"));
+    assert!(!is_json("{\"a\": 1,}"));
+    assert!(!is_json("{a: 1}"));
+    assert!(!is_json("{1: 1}"));
+    assert!(!is_json("[] []"));
+    assert!(!is_json("[1"));
+    assert!(!is_json(&("[".repeat(129) + &"]".repeat(129))));
+}
+
+const BASE62_ALPHABET: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Encodes bytes as a big-endian base62 number, each leading zero byte is encoded as a separate `0`,
+/// so that the encoding is reversible.
+pub fn base62_encode(bytes: &[u8]) -> String {
+    let zeros = bytes.iter().take_while(|&&b| b == 0).count();
+    // Little-endian base62 digits of the number
+    let mut digits = Vec::with_capacity(bytes.len() * 138 / 100 + 1);
+    for &byte in &bytes[zeros..] {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 62) as u8;
+            carry /= 62;
+        }
+        while carry > 0 {
+            digits.push((carry % 62) as u8);
+            carry /= 62;
+        }
+    }
+    let mut res = String::with_capacity(zeros + digits.len());
+    res.extend(core::iter::repeat('0').take(zeros));
+    res.extend(
+        digits
+            .iter()
+            .rev()
+            .map(|&digit| BASE62_ALPHABET[digit as usize] as char),
+    );
+    res
+}
+
+/// Decodes bytes encoded with `base62_encode`, returns `None` on characters outside of the alphabet.
+pub fn base62_decode(s: &str) -> Option<Vec<u8>> {
+    let zeros = s.bytes().take_while(|&ch| ch == b'0').count();
+    // Little-endian bytes of the number
+    let mut bytes = Vec::with_capacity(s.len() * 3 / 4 + 1);
+    for ch in s[zeros..].bytes() {
+        let mut carry = match ch {
+            b'0'..=b'9' => ch - b'0',
+            b'A'..=b'Z' => ch - b'A' + 10,
+            b'a'..=b'z' => ch - b'a' + 36,
+            _ => return None,
+        } as u32;
+        for byte in bytes.iter_mut() {
+            carry += *byte as u32 * 62;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    bytes.resize(bytes.len() + zeros, 0);
+    bytes.reverse();
+    Some(bytes)
+}
+
+#[test]
+fn test_base62() {
+    assert_eq!(base62_encode(b""), "");
+    assert_eq!(base62_encode(&[0]), "0");
+    assert_eq!(base62_encode(&[0, 0, 1]), "001");
+    assert_eq!(base62_encode(&[61]), "z");
+    assert_eq!(base62_encode(&[62]), "10");
+    assert_eq!(base62_encode(&[255]), "47");
+    assert_eq!(base62_encode(&[1, 0]), "48");
+    assert_eq!(base62_encode(b"Hello"), "5TP3P3v");
+
+    assert_eq!(base62_decode("").as_deref(), Some(&b""[..]));
+    assert_eq!(base62_decode("0").as_deref(), Some(&[0][..]));
+    assert_eq!(base62_decode("001").as_deref(), Some(&[0, 0, 1][..]));
+    assert_eq!(base62_decode("48").as_deref(), Some(&[1, 0][..]));
+    assert_eq!(base62_decode("5TP3P3v").as_deref(), Some(&b"Hello"[..]));
+    assert_eq!(base62_decode("a_b"), None);
+    assert_eq!(base62_decode("ab="), None);
+    assert_eq!(base62_decode("абв"), None);
+
+    let data = (0..=255).rev().chain(0..=255).map(|b| b as u8).collect::<Vec<_>>();
+    for len in 0..data.len() {
+        let encoded = base62_encode(&data[..len]);
+        assert!(encoded.bytes().all(|ch| ch.is_ascii_alphanumeric()));
+        assert_eq!(base62_decode(&encoded).as_deref(), Some(&data[..len]));
+    }
+    let zeros = [0, 0, 0, 7, 0, 0];
+    assert_eq!(base62_decode(&base62_encode(&zeros)).as_deref(), Some(&zeros[..]));
+}
+
+/// Roman numerals with their values, including the subtractive pairs, from the largest one.
+const ROMAN_NUMERALS: [(&str, i64); 13] = [
+    ("M", 1000),
+    ("CM", 900),
+    ("D", 500),
+    ("CD", 400),
+    ("C", 100),
+    ("XC", 90),
+    ("L", 50),
+    ("XL", 40),
+    ("X", 10),
+    ("IX", 9),
+    ("V", 5),
+    ("IV", 4),
+    ("I", 1),
+];
+
+/// Formats the number as a Roman numeral, like `XIV`, or returns `None`
+/// if the number is not in the range of 1 to 3999, which can be written.
+pub fn roman_encode(n: i64) -> Option<String> {
+    if !(1..=3999).contains(&n) {
+        return None;
+    }
+    let mut res = String::new();
+    let mut rest = n;
+    for &(numeral, value) in &ROMAN_NUMERALS {
+        while rest >= value {
+            res.push_str(numeral);
+            rest -= value;
+        }
+    }
+    Some(res)
+}
+
+/// Parses a Roman numeral in the canonical form, like `XIV`, but not `XIIII` or `xiv`.
+pub fn roman_decode(s: &str) -> Option<i64> {
+    let mut n = 0_i64;
+    let mut rest = s;
+    for &(numeral, value) in &ROMAN_NUMERALS {
+        while let Some(tail) = rest.strip_prefix(numeral) {
+            n = n.saturating_add(value);
+            rest = tail;
+        }
+    }
+    // Every value has the only canonical form, which is the one written by `roman_encode`
+    if rest.is_empty() && roman_encode(n).as_deref() == Some(s) {
+        Some(n)
+    } else {
+        None
+    }
+}
+
+#[test]
+fn test_roman() {
+    assert_eq!(roman_encode(1).as_deref(), Some("I"));
+    assert_eq!(roman_encode(14).as_deref(), Some("XIV"));
+    assert_eq!(roman_encode(1994).as_deref(), Some("MCMXCIV"));
+    assert_eq!(roman_encode(3999).as_deref(), Some("MMMCMXCIX"));
+    for n in 1..=3999 {
+        assert_eq!(roman_encode(n).and_then(|s| roman_decode(&s)), Some(n));
+    }
+    assert_eq!(roman_encode(0), None);
+    assert_eq!(roman_encode(4000), None);
+    assert_eq!(roman_encode(-14), None);
+
+    assert_eq!(roman_decode(""), None);
+    assert_eq!(roman_decode("xiv"), None);
+    assert_eq!(roman_decode("XIIII"), None);
+    assert_eq!(roman_decode("IIX"), None);
+    assert_eq!(roman_decode("IC"), None);
+    assert_eq!(roman_decode("MMMM"), None);
+    assert_eq!(roman_decode("XIV "), None);
+    assert_eq!(roman_decode("14"), None);
+}
+
+/// Units of human-readable durations, in the order they are written, with their lengths in seconds.
+const DURATION_UNITS: [(char, i64); 3] = [('h', 3600), ('m', 60), ('s', 1)];
+
+/// Formats the number of seconds as a human-readable duration, like `1h 30m` or `45s`,
+/// omitting zero units, or returns `None` if the number is negative.
+pub fn duration_encode(secs: i64) -> Option<String> {
+    if secs < 0 {
+        return None;
+    }
+    if secs == 0 {
+        return Some("0s".to_owned());
+    }
+    let mut res = String::new();
+    let mut rest = secs;
+    for &(unit, len) in &DURATION_UNITS {
+        if rest >= len {
+            if !res.is_empty() {
+                res.push(' ');
+            }
+            res.push_str(&(rest / len).to_string());
+            res.push(unit);
+            rest %= len;
+        }
+    }
+    Some(res)
+}
+
+/// Parses a human-readable duration into the number of seconds, like `1h30m`, `90m` or `1h 30m 15s`.
+/// Units must go in the order of hours, minutes and seconds, each of them at most once,
+/// and can be separated by single spaces.
+pub fn duration_decode(s: &str) -> Option<i64> {
+    let mut secs = 0_i64;
+    let mut rest = s;
+    let mut units = DURATION_UNITS.iter();
+    loop {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let n = rest.get(..digits)?.parse::<i64>().ok()?;
+        let unit = rest.get(digits..)?.chars().next()?;
+        let &(_, len) = units.find(|&&(u, _)| u == unit)?;
+        secs = secs.checked_add(n.checked_mul(len)?)?;
+        rest = rest.get(digits + unit.len_utf8()..)?;
+        if rest.is_empty() {
+            return Some(secs);
+        }
+        rest = rest.strip_prefix(' ').unwrap_or(rest);
+    }
+}
+
+#[test]
+fn test_duration() {
+    assert_eq!(duration_encode(0).as_deref(), Some("0s"));
+    assert_eq!(duration_encode(45).as_deref(), Some("45s"));
+    assert_eq!(duration_encode(120).as_deref(), Some("2m"));
+    assert_eq!(duration_encode(5400).as_deref(), Some("1h 30m"));
+    assert_eq!(duration_encode(90061).as_deref(), Some("25h 1m 1s"));
+    assert_eq!(duration_encode(-1), None);
+    for secs in (0..100_000).step_by(7) {
+        assert_eq!(duration_encode(secs).and_then(|s| duration_decode(&s)), Some(secs));
+    }
+
+    assert_eq!(duration_decode("1h30m"), Some(5400));
+    assert_eq!(duration_decode("2m"), Some(120));
+    assert_eq!(duration_decode("90m"), Some(5400));
+    assert_eq!(duration_decode("1h 0m 5s"), Some(3605));
+    assert_eq!(duration_decode("0s"), Some(0));
+
+    assert_eq!(duration_decode(""), None);
+    assert_eq!(duration_decode("30m1h"), None);
+    assert_eq!(duration_decode("1h1h"), None);
+    assert_eq!(duration_decode("1h  30m"), None);
+    assert_eq!(duration_decode(" 1h"), None);
+    assert_eq!(duration_decode("1h "), None);
+    assert_eq!(duration_decode("h"), None);
+    assert_eq!(duration_decode("1d"), None);
+    assert_eq!(duration_decode("-1h"), None);
+    assert_eq!(duration_decode("5400"), None);
+    assert_eq!(duration_decode("9999999999999999h"), None);
+}
+
+/// Packs the date into eight digits of `YYYYMMDD`, like `20240115`, or returns `None`
+/// if the year is not in the range of 0 to 9999, which has four digits.
+pub fn packed_date_encode(year: i32, month: u32, day: u32) -> Option<String> {
+    if !(0..=9999).contains(&year) {
+        return None;
+    }
+    Some(alloc::format!("{:04}{:02}{:02}", year, month, day))
+}
+
+/// Unpacks eight digits of `YYYYMMDD` into the year, the month and the day,
+/// which are not checked to make a valid date.
+pub fn packed_date_decode(s: &str) -> Option<(i32, u32, u32)> {
+    if s.len() != 8 || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let packed = s.parse::<u32>().ok()?;
+    Some(((packed / 10000) as i32, packed / 100 % 100, packed % 100))
+}
+
+#[test]
+fn test_packed_date() {
+    assert_eq!(packed_date_encode(2024, 1, 15).as_deref(), Some("20240115"));
+    assert_eq!(packed_date_encode(999, 12, 31).as_deref(), Some("09991231"));
+    assert_eq!(packed_date_encode(0, 1, 1).as_deref(), Some("00000101"));
+    assert_eq!(packed_date_encode(10000, 1, 1), None);
+    assert_eq!(packed_date_encode(-1, 1, 1), None);
+
+    assert_eq!(packed_date_decode("20240115"), Some((2024, 1, 15)));
+    assert_eq!(packed_date_decode("09991231"), Some((999, 12, 31)));
+    assert_eq!(packed_date_decode("20241399"), Some((2024, 13, 99)));
+    assert_eq!(packed_date_decode("2024115"), None);
+    assert_eq!(packed_date_decode("+2024115"), None);
+    assert_eq!(packed_date_decode("202401150"), None);
+    assert_eq!(packed_date_decode("2024-1-1"), None);
+}
+
+/// Power of ten, which is exact for the scales of fixed-point decimals, which are up to 18.
+fn pow10(scale: u32) -> f64 {
+    (0..scale).fold(1.0, |pow, _| pow * 10.0)
+}
+
+/// Writes the number as a fixed-point decimal with `scale` digits after the point, like `314` for 3.14
+/// with the scale of 2, rounding half away from zero, or returns `None` if the number is not finite,
+/// or does not fit into `i64` after scaling.
+pub fn fixed_point_encode(value: f64, scale: u32) -> Option<i64> {
+    let scaled = value * pow10(scale);
+    // The bounds are powers of two, which are exact, unlike `i64::MAX as f64`
+    if !(-9_223_372_036_854_775_808.0..9_223_372_036_854_775_808.0).contains(&scaled) {
+        return None;
+    }
+    let n = scaled as i64;
+    let rest = scaled - n as f64;
+    if rest >= 0.5 {
+        n.checked_add(1)
+    } else if rest <= -0.5 {
+        n.checked_sub(1)
+    } else {
+        Some(n)
+    }
+}
+
+/// Reads the fixed-point decimal with `scale` digits after the point, like 3.14 for `314` with the scale of 2.
+/// The result is the nearest `f64` to the exact value, if `n` has no more than 15 digits.
+pub fn fixed_point_decode(n: i64, scale: u32) -> f64 {
+    n as f64 / pow10(scale)
+}
+
+#[test]
+fn test_fixed_point() {
+    assert_eq!(fixed_point_encode(12.34, 2), Some(1234));
+    assert_eq!(fixed_point_encode(-12.34, 2), Some(-1234));
+    assert_eq!(fixed_point_encode(0.1 + 0.2, 1), Some(3));
+    assert_eq!(fixed_point_encode(1.005, 3), Some(1005));
+    assert_eq!(fixed_point_encode(0.125, 2), Some(13));
+    assert_eq!(fixed_point_encode(-0.125, 2), Some(-13));
+    assert_eq!(fixed_point_encode(0.124, 2), Some(12));
+    assert_eq!(fixed_point_encode(-0.0, 2), Some(0));
+    assert_eq!(fixed_point_encode(42.0, 0), Some(42));
+    assert_eq!(fixed_point_encode(9.2e18, 0), Some(9_200_000_000_000_000_000));
+    assert_eq!(fixed_point_encode(-9_223_372_036_854_775_808.0, 0), Some(i64::MIN));
+    assert_eq!(fixed_point_encode(9_223_372_036_854_775_808.0, 0), None);
+    assert_eq!(fixed_point_encode(1e17, 2), None);
+    assert_eq!(fixed_point_encode(f64::NAN, 2), None);
+    assert_eq!(fixed_point_encode(f64::INFINITY, 2), None);
+    assert_eq!(fixed_point_encode(f64::NEG_INFINITY, 2), None);
+
+    assert_eq!(fixed_point_decode(1234, 2), 12.34);
+    assert_eq!(fixed_point_decode(-1234, 2), -12.34);
+    assert_eq!(fixed_point_decode(3, 1), 0.3);
+    assert_eq!(fixed_point_decode(0, 18), 0.0);
+    assert_eq!(fixed_point_decode(1, 18), 1e-18);
+    assert_eq!(fixed_point_decode(42, 0), 42.0);
+}
+
+/// Writes the share as a percentage with up to two decimal places, like `75%` for 0.75 or `12.35%` for 0.12345,
+/// rounding half away from zero. Shares out of the range of 0 to 1 are clamped to it, or rejected if `strict`,
+/// and so is NaN anyway.
+pub fn percentage_encode(value: f64, strict: bool) -> Option<String> {
+    if value.is_nan() || (strict && !(0.0..=1.0).contains(&value)) {
+        return None;
+    }
+    let hundredths = fixed_point_encode(value.clamp(0.0, 1.0), 4)?;
+    let (int, frac) = (hundredths / 100, hundredths % 100);
+    Some(match frac {
+        0 => alloc::format!("{}%", int),
+        _ if frac % 10 == 0 => alloc::format!("{}.{}%", int, frac / 10),
+        _ => alloc::format!("{}.{:02}%", int, frac),
+    })
+}
+
+/// Reads the percentage, like `75%` or `12.5%`, into the share, like 0.75 or 0.125. Shares out of the range
+/// of 0 to 1 are clamped to it, or rejected if `strict`.
+pub fn percentage_decode(s: &str, strict: bool) -> Option<f64> {
+    let number = s.strip_suffix('%')?;
+    let digits = number.strip_prefix('-').unwrap_or(number);
+    let (int, frac) = digits.split_once('.').unwrap_or((digits, "0"));
+    if int.is_empty() || frac.is_empty() || !(int.bytes().chain(frac.bytes()).all(|b| b.is_ascii_digit())) {
+        return None;
+    }
+    let value = number.parse::<f64>().ok()? / 100.0;
+    if strict && !(0.0..=1.0).contains(&value) {
+        return None;
+    }
+    Some(value.clamp(0.0, 1.0))
+}
+
+#[test]
+fn test_percentage() {
+    let encode = |value| percentage_encode(value, false);
+    assert_eq!(encode(0.75).as_deref(), Some("75%"));
+    assert_eq!(encode(0.0).as_deref(), Some("0%"));
+    assert_eq!(encode(1.0).as_deref(), Some("100%"));
+    assert_eq!(encode(0.125).as_deref(), Some("12.5%"));
+    assert_eq!(encode(0.12345).as_deref(), Some("12.35%"));
+    assert_eq!(encode(0.0701).as_deref(), Some("7.01%"));
+    assert_eq!(encode(0.07).as_deref(), Some("7%"));
+    assert_eq!(encode(1.5).as_deref(), Some("100%"));
+    assert_eq!(encode(-0.5).as_deref(), Some("0%"));
+    assert_eq!(encode(f64::INFINITY).as_deref(), Some("100%"));
+    assert_eq!(encode(f64::NAN), None);
+    assert_eq!(percentage_encode(1.5, true), None);
+    assert_eq!(percentage_encode(-0.5, true), None);
+    assert_eq!(percentage_encode(f64::NAN, true), None);
+    assert_eq!(percentage_encode(0.5, true).as_deref(), Some("50%"));
+
+    let decode = |s| percentage_decode(s, false);
+    assert_eq!(decode("75%"), Some(0.75));
+    assert_eq!(decode("12.5%"), Some(0.125));
+    assert_eq!(decode("0%"), Some(0.0));
+    assert_eq!(decode("100%"), Some(1.0));
+    assert_eq!(decode("150%"), Some(1.0));
+    assert_eq!(decode("-5%"), Some(0.0));
+    assert_eq!(percentage_decode("150%", true), None);
+    assert_eq!(percentage_decode("-5%", true), None);
+    assert_eq!(percentage_decode("7.01%", true), Some(0.0701));
+    for bad in [
+        "75", "%", "75%%", ".5%", "5.%", "+5%", " 5%", "1e2%", "inf%", "NaN%", "5,5%",
+    ]
+    .iter()
+    {
+        assert_eq!(decode(bad), None, "{:?}", bad);
+    }
+}
+
+/// Packs the positions of the bits, from 0 to 63, into the bitmask, or returns the first position out of this range.
+pub fn bitmask_encode<'a>(positions: impl IntoIterator<Item = &'a u8>) -> Result<i64, u8> {
+    let mut mask = 0_u64;
+    for &pos in positions {
+        mask |= 1_u64.checked_shl(u32::from(pos)).ok_or(pos)?;
+    }
+    Ok(mask as i64)
+}
+
+/// Positions of the set bits of the bitmask, from 0 to 63, where the sign bit is the last one.
+pub fn bitmask_decode(mask: i64) -> impl Iterator<Item = u8> {
+    let mask = mask as u64;
+    (0..64).filter(move |&pos| mask >> pos & 1 == 1)
+}
+
+#[test]
+fn test_bitmask() {
+    assert_eq!(bitmask_encode(&[0, 2, 3]), Ok(13));
+    assert_eq!(bitmask_encode(&[3, 3]), Ok(8));
+    assert_eq!(bitmask_encode(&[]), Ok(0));
+    assert_eq!(bitmask_encode(&[63]), Ok(i64::MIN));
+    assert_eq!(bitmask_encode(&[1, 64, 65]), Err(64));
+    assert_eq!(bitmask_encode(&[255]), Err(255));
+
+    let decode = |mask| bitmask_decode(mask).collect::<alloc::vec::Vec<_>>();
+    assert_eq!(decode(13), [0, 2, 3]);
+    assert_eq!(decode(0), []);
+    assert_eq!(decode(i64::MIN), [63]);
+    assert_eq!(decode(-1), (0..64).collect::<alloc::vec::Vec<_>>());
+}
+
+/// Formats the color as `#rrggbbaa`, in lowercase.
+pub fn rgba_encode((r, g, b, a): (u8, u8, u8, u8)) -> String {
+    alloc::format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+}
+
+/// Parses the color of `#rrggbb` or `#rrggbbaa`, in any case, where the alpha is 255 if it is missing.
+pub fn rgba_decode(s: &str) -> Option<(u8, u8, u8, u8)> {
+    let hex = s.strip_prefix('#')?;
+    // `from_str_radix` accepts the sign too, which is not a digit of the color
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    let alpha = if hex.len() == 8 { channel(6)? } else { 255 };
+    Some((channel(0)?, channel(2)?, channel(4)?, alpha))
+}
+
+#[test]
+fn test_rgba() {
+    assert_eq!(rgba_encode((255, 136, 0, 255)), "#ff8800ff");
+    assert_eq!(rgba_encode((0, 0, 0, 0)), "#00000000");
+
+    assert_eq!(rgba_decode("#ff8800"), Some((255, 136, 0, 255)));
+    assert_eq!(rgba_decode("#FF880080"), Some((255, 136, 0, 128)));
+    assert_eq!(rgba_decode("#00000000"), Some((0, 0, 0, 0)));
+    assert_eq!(rgba_decode("ff8800"), None);
+    assert_eq!(rgba_decode("#ff880"), None);
+    assert_eq!(rgba_decode("#ff88000"), None);
+    assert_eq!(rgba_decode("#+f8800"), None);
+    assert_eq!(rgba_decode("#gg8800"), None);
+    assert_eq!(rgba_decode("#ff8800ff00"), None);
+}
+
+/// Wraps the string into double quotes, if it contains spaces, escaping the quotes and backslashes in it
+/// with backslashes. So are the strings which start with a quote, to be told apart from the quoted ones.
+pub fn quote_spaces(s: &str) -> Cow<'_, str> {
+    if !s.contains(' ') && !s.starts_with('"') {
+        return Cow::Borrowed(s);
+    }
+    let mut res = String::with_capacity(s.len() + 2);
+    res.push('"');
+    for ch in s.chars() {
+        if ch == '"' || ch == '\\' {
+            res.push('\\');
+        }
+        res.push(ch);
+    }
+    res.push('"');
+    Cow::Owned(res)
+}
+
+/// Reverse of `quote_spaces`, which leaves the strings which are not quoted as is.
+pub fn unquote(s: &str) -> Cow<'_, str> {
+    let inner = match s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        Some(inner) => inner,
+        None => return Cow::Borrowed(s),
+    };
+    let mut res = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => res.extend(chars.next()),
+            ch => res.push(ch),
+        }
+    }
+    Cow::Owned(res)
+}
+
+#[test]
+fn test_quote_spaces() {
+    assert!(matches!(quote_spaces("foo"), Cow::Borrowed("foo")));
+    assert!(matches!(quote_spaces("a\"b"), Cow::Borrowed("a\"b")));
+    assert_eq!(quote_spaces("hello world"), r#""hello world""#);
+    assert_eq!(quote_spaces(r#"say "hi" \o/"#), r#""say \"hi\" \\o/""#);
+    assert_eq!(quote_spaces(r#""foo""#), r#""\"foo\"""#);
+    assert_eq!(quote_spaces(" "), r#"" ""#);
+
+    assert!(matches!(unquote("foo"), Cow::Borrowed("foo")));
+    assert!(matches!(unquote("\""), Cow::Borrowed("\"")));
+    assert_eq!(unquote(r#""""#), "");
+    assert_eq!(unquote(r#""hello world""#), "hello world");
+    for s in [
+        "",
+        "foo",
+        "hello world",
+        r#"say "hi" \o/"#,
+        r#""foo""#,
+        "\"",
+        "a\\",
+        " \\",
+    ]
+    .iter()
+    {
+        assert_eq!(unquote(&quote_spaces(s)), *s);
+    }
+}
+
+/// Writes the bytes as lowercase hex digits, two per byte.
+pub fn hex_encode(bytes: &[u8]) -> String {
+    let mut res = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = core::fmt::Write::write_fmt(&mut res, format_args!("{:02x}", byte));
+    }
+    res
+}
+
+/// Parses exactly `2 * N` hex digits, in any case, into `N` bytes.
+pub fn hex_decode<const N: usize>(s: &str) -> Option<[u8; N]> {
+    let digits = s.as_bytes();
+    if digits.len() != N * 2 {
+        return None;
+    }
+    let hex = |byte: u8| (byte as char).to_digit(16);
+    let mut res = [0; N];
+    for (byte, pair) in res.iter_mut().zip(digits.chunks_exact(2)) {
+        *byte = (hex(pair[0])? * 16 + hex(pair[1])?) as u8;
+    }
+    Some(res)
+}
+
+#[test]
+fn test_hex() {
+    assert_eq!(hex_encode(&[0xde, 0xad, 0x00, 0x0f]), "dead000f");
+    assert_eq!(hex_encode(&[]), "");
+
+    assert_eq!(hex_decode::<4>("dead000f"), Some([0xde, 0xad, 0x00, 0x0f]));
+    assert_eq!(hex_decode::<4>("DEAD000F"), Some([0xde, 0xad, 0x00, 0x0f]));
+    assert_eq!(hex_decode::<2>("dead000f"), None);
+    assert_eq!(hex_decode::<4>("dead00f"), None);
+    assert_eq!(hex_decode::<4>("dead00+f"), None);
+    assert_eq!(hex_decode::<2>("déad"), None);
+    assert_eq!(hex_decode::<2>("xyzw"), None);
+}
+
+/// Same as `str::split("__")`, or `str::splitn(limit, "__")` if the limit is given, but it is not generic,
+/// so that the generated code only calls it. With the `perf` feature the separators are located
+/// by checking eight bytes at once, which is faster than the generic search of the two-byte pattern.
+/// Legacy values with other separators, like `-`, are split by the plain search of the separator.
+#[derive(Clone)]
+pub struct Fragments<'a> {
+    rest: Option<&'a str>,
+    limit: usize,
+    separator: &'static str,
+}
+
+impl<'a> Fragments<'a> {
+    pub fn new(s: &'a str, limit: Option<usize>) -> Self {
+        Self::with_separator(s, limit, "__")
+    }
+
+    pub fn with_separator(s: &'a str, limit: Option<usize>, separator: &'static str) -> Self {
+        Fragments {
+            rest: Some(s),
+            limit: limit.unwrap_or(usize::MAX),
+            separator,
+        }
+    }
+}
+
+impl<'a> Iterator for Fragments<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let rest = self.rest?;
+        if self.limit == 0 {
+            self.rest = None;
+            return None;
+        }
+        self.limit -= 1;
+        let separator = match self.separator {
+            _ if self.limit == 0 => None,
+            "__" => find_separator(rest),
+            separator => find_legacy_separator(rest, separator),
+        };
+        match separator.and_then(|pos| rest.get(..pos).zip(rest.get(pos + self.separator.len()..))) {
+            Some((fragment, tail)) => {
+                self.rest = Some(tail);
+                Some(fragment)
+            }
+            None => {
+                self.rest = None;
+                Some(rest)
+            }
+        }
+    }
+}
+
+/// Position of the first legacy separator in the string, searched byte by byte, since the separators are short
+/// and rare, and `str::find` has panic paths the optimizer does not remove.
+fn find_legacy_separator(s: &str, separator: &str) -> Option<usize> {
+    let (bytes, separator) = (s.as_bytes(), separator.as_bytes());
+    let first = *separator.first()?;
+    let mut from = 0;
+    while let Some(pos) = bytes.get(from..)?.iter().position(|&b| b == first) {
+        let pos = from + pos;
+        if bytes.get(pos..).is_some_and(|tail| tail.starts_with(separator)) {
+            return Some(pos);
+        }
+        from = pos + 1;
+    }
+    None
+}
+
+/// Position of the first `__` in the string.
+#[cfg(not(feature = "perf"))]
+fn find_separator(s: &str) -> Option<usize> {
+    // Single byte is searched in a simple loop, which is vectorized, unlike the two-byte pattern
+    let bytes = s.as_bytes();
+    let mut from = 0;
+    while let Some(pos) = bytes.get(from..)?.iter().position(|&b| b == b'_') {
+        let pos = from + pos;
+        if bytes.get(pos + 1) == Some(&b'_') {
+            return Some(pos);
+        }
+        from = pos + 1;
+    }
+    None
+}
+
+/// Position of the first `__` in the string.
+#[cfg(feature = "perf")]
+fn find_separator(s: &str) -> Option<usize> {
+    const LOW: u64 = 0x7f7f_7f7f_7f7f_7f7f;
+    const UNDERSCORES: u64 = 0x5f5f_5f5f_5f5f_5f5f;
+
+    // Words overlap by a byte, so that a pair split between two words is found in the second one
+    let bytes = s.as_bytes();
+    let mut start = 0;
+    while let Some(word) = bytes.get(start..start + 8) {
+        let word = core::convert::TryInto::<[u8; 8]>::try_into(word).ok()?;
+        let v = u64::from_le_bytes(word) ^ UNDERSCORES;
+        // Highest bit of each byte which was an underscore
+        let found = !(((v & LOW) + LOW) | v | LOW);
+        // Pair of underscores starting at any of the first seven bytes
+        let pairs = found & (found >> 8);
+        if pairs != 0 {
+            return Some(start + pairs.trailing_zeros() as usize / 8);
+        }
+        start += 7;
+    }
+    bytes
+        .get(start..)?
+        .windows(2)
+        .position(|w| w == b"__")
+        .map(|pos| start + pos)
+}
+
+#[test]
+fn test_fragments() {
+    let inputs = [
+        "",
+        "_",
+        "__",
+        "___",
+        "____",
+        "a",
+        "a__b",
+        "a___b",
+        "a____b",
+        "__a__",
+        "a_b__c_d",
+        "%s%d__foo__42",
+        "привет__мир",
+        "a__b__c__d",
+        "abcdefg__h",
+        "abcdefgh__",
+        "abcdef_g_h__i",
+        "order_status__3P__x_y",
+    ];
+    for input in inputs.iter() {
+        assert_eq!(
+            Fragments::new(input, None).collect::<Vec<_>>(),
+            input.split("__").collect::<Vec<_>>(),
+            "{:?}",
+            input
+        );
+        for limit in 0..5 {
+            assert_eq!(
+                Fragments::new(input, Some(limit)).collect::<Vec<_>>(),
+                input.splitn(limit, "__").collect::<Vec<_>>(),
+                "{:?} {}",
+                input,
+                limit
+            );
+        }
+    }
+
+    // Every placement of underscores, crossing the word boundaries
+    for len in 0..=18 {
+        for bits in 0..1_u32 << len {
+            let input = (0..len)
+                .map(|i| if bits >> i & 1 == 1 { '_' } else { 'a' })
+                .collect::<String>();
+            assert_eq!(
+                Fragments::new(&input, None).collect::<Vec<_>>(),
+                input.split("__").collect::<Vec<_>>()
+            );
+        }
+    }
+
+    // Legacy separator
+    for input in ["", "-", "a-b", "a--b", "%s%d-foo-42", "a__b-c_d", "a-_b--_-c"].iter() {
+        assert_eq!(
+            Fragments::with_separator(input, None, "-").collect::<Vec<_>>(),
+            input.split('-').collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Fragments::with_separator(input, Some(2), "-").collect::<Vec<_>>(),
+            input.splitn(2, '-').collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Fragments::with_separator(input, None, "-_").collect::<Vec<_>>(),
+            input.split("-_").collect::<Vec<_>>()
+        );
+    }
+}
+
+/// Checks the descriptor, which is the first fragment of the value, against the one of the format string.
+/// It is only the prefix of the descriptor for open-ended format strings and the ones with optional items,
+/// which is the lax check kept for compatibility, while `match_descriptor_strict` checks the rest too.
+pub fn match_descriptor(descriptor: Option<&str>, expected: &str, starts_with: bool) -> bool {
+    match descriptor {
+        Some(descriptor) if starts_with => descriptor.starts_with(expected),
+        Some(descriptor) => descriptor == expected,
+        None => false,
+    }
+}
+
+/// Checks the descriptor against the format string item by item: it must start with the mandatory items,
+/// which are followed by some of the optional ones, in the same order and of the same types, and, if the format
+/// string is open-ended, by any other items. Returns the number of the items, which must be the number
+/// of the fragments, or `None` if the descriptor does not match.
+pub fn match_descriptor_strict(descriptor: Option<&str>, mandatory: &str, optional: &str, open: bool) -> Option<usize> {
+    let rest = descriptor?.strip_prefix(mandatory)?;
+    if rest.len() % 2 != 0 {
+        return None;
+    }
+    let mut optional = optional.as_bytes().chunks(2);
+    let mut items = mandatory.len() / 2;
+    for item in rest.as_bytes().chunks(2) {
+        if item != b"%s" && item != b"%d" {
+            return None;
+        }
+        match optional.next() {
+            Some(expected) if expected != item => return None,
+            None if !open => return None,
+            _ => items += 1,
+        }
+    }
+    Some(items)
+}
+
+/// Parses the next fragment as a plain `%s` item.
+pub fn next_str(fragments: &mut Fragments<'_>) -> Option<String> {
+    fragments.next().map(String::from)
+}
+
+/// Parses the next fragment as a plain `%d` item.
+pub fn next_int(fragments: &mut Fragments<'_>) -> Option<i64> {
+    fragments.next()?.parse().ok()
+}
+
+/// Parses the fragment of a `%d` item with the `radix = auto` option: hexadecimal with the `0x` or `0X`
+/// prefix, binary with the `0b` or `0B` prefix, and decimal otherwise, optionally negative.
+pub fn parse_int_auto(s: &str) -> Option<i64> {
+    let (negative, unsigned) = match s.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, s),
+    };
+    let (radix, digits) = match unsigned.get(..2) {
+        Some("0x" | "0X") => (16, unsigned.get(2..)?),
+        Some("0b" | "0B") => (2, unsigned.get(2..)?),
+        _ => return s.parse().ok(),
+    };
+    // Signs are accepted by `from_str_radix`, but not after the prefix
+    if digits.starts_with('+') {
+        return None;
+    }
+    let n = i128::from(u64::from_str_radix(digits, radix).ok()?);
+    i64::try_from(if negative { -n } else { n }).ok()
+}
+
+#[test]
+fn test_parse_int_auto() {
+    assert_eq!(parse_int_auto("42"), Some(42));
+    assert_eq!(parse_int_auto("-42"), Some(-42));
+    assert_eq!(parse_int_auto("+42"), Some(42));
+    assert_eq!(parse_int_auto("0x1a2b"), Some(0x1a2b));
+    assert_eq!(parse_int_auto("0X1A2B"), Some(0x1a2b));
+    assert_eq!(parse_int_auto("-0x10"), Some(-16));
+    assert_eq!(parse_int_auto("0b101"), Some(5));
+    assert_eq!(parse_int_auto("0B101"), Some(5));
+    assert_eq!(parse_int_auto("0x7fffffffffffffff"), Some(i64::MAX));
+    assert_eq!(parse_int_auto("-0x8000000000000000"), Some(i64::MIN));
+    assert_eq!(parse_int_auto("0"), Some(0));
+    assert_eq!(parse_int_auto("0o17"), None);
+
+    assert_eq!(parse_int_auto("0x"), None);
+    assert_eq!(parse_int_auto("0x1g"), None);
+    assert_eq!(parse_int_auto("0b102"), None);
+    assert_eq!(parse_int_auto("0x+1"), None);
+    assert_eq!(parse_int_auto("0x-1"), None);
+    assert_eq!(parse_int_auto("+0x1"), None);
+    assert_eq!(parse_int_auto("0x8000000000000000"), None);
+    assert_eq!(parse_int_auto(" 0x1"), None);
+}
+
+/// Splits the value of a closed format string with a single item into the descriptor and the fragment,
+/// or returns `None` if there are more fragments, or no fragments at all.
+pub fn split_single(s: &str) -> Option<(&str, &str)> {
+    let mut fragments = Fragments::new(s, Some(3));
+    let descriptor = fragments.next()?;
+    let value = fragments.next()?;
+    if fragments.next().is_some() {
+        return None;
+    }
+    Some((descriptor, value))
+}
+
+/// Splits the value of checked mode into the rest of the value and the checksum, which is the last fragment.
+pub fn split_checksum(s: &str) -> Option<(&str, &str)> {
+    let pos = s.as_bytes().windows(2).rposition(|w| w == b"__")?;
+    s.get(..pos).zip(s.get(pos + 2..))
+}
+
+/// Checks that there are no fragments left after the items of a closed format string.
+pub fn finish_closed(fragments: &mut Fragments<'_>) -> bool {
+    fragments.next().is_none()
+}
+
+#[test]
+fn test_parse_helpers() {
+    assert!(match_descriptor(Some("%s%d"), "%s%d", false));
+    assert!(!match_descriptor(Some("%s%d%s"), "%s%d", false));
+    assert!(match_descriptor(Some("%s%d%s"), "%s%d", true));
+    assert!(!match_descriptor(Some("%s"), "%s%d", true));
+    assert!(!match_descriptor(None, "%s", true));
+
+    assert_eq!(match_descriptor_strict(Some("%s%d"), "%s%d", "", false), Some(2));
+    assert_eq!(match_descriptor_strict(Some("%s%d%s"), "%s%d", "", false), None);
+    assert_eq!(match_descriptor_strict(Some("%s%d%s%d"), "%s%d", "", true), Some(4));
+    assert_eq!(match_descriptor_strict(Some("%s%dx"), "%s%d", "", true), None);
+    assert_eq!(match_descriptor_strict(Some("%s%d%"), "%s%d", "", true), None);
+    assert_eq!(match_descriptor_strict(Some("%s"), "%s", "%d%s", false), Some(1));
+    assert_eq!(match_descriptor_strict(Some("%s%d"), "%s", "%d%s", false), Some(2));
+    assert_eq!(match_descriptor_strict(Some("%s%s"), "%s", "%d%s", false), None);
+    assert_eq!(match_descriptor_strict(Some("%s%d%s%d"), "%s", "%d%s", false), None);
+    assert_eq!(match_descriptor_strict(Some("%s%d%s%d"), "%s", "%d%s", true), Some(4));
+    assert_eq!(match_descriptor_strict(None, "%s", "", true), None);
+
+    let mut fragments = Fragments::new("foo__42__x__", Some(4));
+    assert_eq!(next_str(&mut fragments), Some("foo".to_owned()));
+    assert_eq!(next_int(&mut fragments), Some(42));
+    assert_eq!(next_int(&mut fragments), None);
+    assert!(!finish_closed(&mut fragments));
+    assert!(finish_closed(&mut fragments));
+    assert_eq!(next_str(&mut fragments), None);
+
+    assert_eq!(split_single("%s__foo"), Some(("%s", "foo")));
+    assert_eq!(split_single("%s__"), Some(("%s", "")));
+    assert_eq!(split_single("%s___"), Some(("%s", "_")));
+    assert_eq!(split_single("%s__foo__bar"), None);
+    assert_eq!(split_single("%s"), None);
+
+    assert_eq!(split_checksum("%s%d__foo__42"), Some(("%s%d__foo", "42")));
+    assert_eq!(split_checksum("%d__a___42"), Some(("%d__a_", "42")));
+    assert_eq!(split_checksum("%d__42__"), Some(("%d__42", "")));
+    assert_eq!(split_checksum("%d"), None);
+}
+
+/// Classifies the failure to parse the value for the `trace` option: `not_fragmented` if it is not
+/// a fragmented string at all, `descriptor` if the descriptor does not start with any of the expected ones,
+/// and `value` if it does, so some fragment is malformed or missing, or there are extra ones.
+pub fn failure_reason(input: &str, expected: &[&str], checked: bool) -> &'static str {
+    let descriptor = match input.split_once("__") {
+        Some((descriptor, _)) => descriptor,
+        None => return "not_fragmented",
+    };
+    let descriptor = match descriptor.strip_suffix("%d") {
+        Some(descriptor) if checked => descriptor,
+        _ => descriptor,
+    };
+    if expected.iter().any(|expected| descriptor.starts_with(expected)) {
+        "value"
+    } else {
+        "descriptor"
+    }
+}
+
+/// Longest input, in bytes, which is shown by `redact_input` in full.
+const REDACTED_INPUT_LEN: usize = 64;
+
+/// Hides the values of the input for the `trace` option, keeping the descriptor and the number
+/// of the fragments, and truncates the result at `REDACTED_INPUT_LEN` bytes.
+pub fn redact_input(input: &str) -> String {
+    let mut fragments = input.split("__");
+    let mut res = fragments.next().unwrap_or_default().to_owned();
+    for _ in fragments {
+        res.push_str("__***");
+    }
+    if res.len() > REDACTED_INPUT_LEN {
+        let mut end = REDACTED_INPUT_LEN;
+        while !res.is_char_boundary(end) {
+            end -= 1;
+        }
+        res.truncate(end);
+        res.push_str("...");
+    }
+    res
+}
+
+#[test]
+fn test_trace_helpers() {
+    assert_eq!(failure_reason("%s%d", &["%s%d"], false), "not_fragmented");
+    assert_eq!(failure_reason("", &["%s"], false), "not_fragmented");
+    assert_eq!(failure_reason("%s%s__foo__bar", &["%s%d"], false), "descriptor");
+    assert_eq!(failure_reason("%s%d__foo__bar", &["%s%d"], false), "value");
+    assert_eq!(failure_reason("%s%d%s__foo__42__x", &["%s%d"], false), "value");
+    assert_eq!(
+        failure_reason("%s%s%d__v3__foo__42", &["%s%s%d", "%s%d"], false),
+        "value"
+    );
+    assert_eq!(failure_reason("%s%d%d__foo__x__1", &["%s%d"], true), "value");
+    assert_eq!(failure_reason("%d%d__1__1", &["%s%d"], true), "descriptor");
+
+    assert_eq!(redact_input("%s%d__foo__42"), "%s%d__***__***");
+    assert_eq!(redact_input("%s__"), "%s__***");
+    assert_eq!(redact_input("foo"), "foo");
+    let redacted = redact_input(&format!("x{}__x", "%sé".repeat(30)));
+    assert_eq!(redacted, format!("x{}%s...", "%sé".repeat(15)));
+    assert!(redacted.len() <= REDACTED_INPUT_LEN + 3);
+}
+
+/// Instruction of the regex program, compiled from the `%s[re=r'...']` annotation at compile time,
+/// the generated code keeps the program in a static.
+#[derive(Debug)]
+pub enum RegexInst {
+    Char(char),
+    Any,
+    /// Inclusive ranges of characters, and whether the class is negated.
+    Class(bool, &'static [(char, char)]),
+    /// Continues with both instructions.
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+/// Checks that the regex program matches the whole string. It runs the program as an NFA, keeping the set
+/// of the instructions reached after every character, so it takes linear time and no recursion on any pattern.
+pub fn regex_match<const N: usize>(program: &[RegexInst; N], s: &str) -> bool {
+    let mut current = [false; N];
+    let mut stack = [0; N];
+    regex_add(program, &mut current, &mut stack, 0);
+    for ch in s.chars() {
+        let mut next = [false; N];
+        let mut reached = false;
+        for (pc, (inst, &on)) in program.iter().zip(current.iter()).enumerate() {
+            let matches = on
+                && match *inst {
+                    RegexInst::Char(expected) => ch == expected,
+                    RegexInst::Any => true,
+                    RegexInst::Class(negated, ranges) => ranges.iter().any(|&(lo, hi)| lo <= ch && ch <= hi) != negated,
+                    RegexInst::Split(..) | RegexInst::Jump(_) | RegexInst::Match => false,
+                };
+            if matches {
+                regex_add(program, &mut next, &mut stack, pc + 1);
+                reached = true;
+            }
+        }
+        if !reached {
+            return false;
+        }
+        current = next;
+    }
+    program
+        .iter()
+        .zip(current.iter())
+        .any(|(inst, &on)| on && matches!(inst, RegexInst::Match))
+}
+
+/// Adds the instruction to the set, with all the instructions reached from it without a character.
+/// Every instruction is pushed to the stack once, when it is added to the set, so the stack is large enough.
+fn regex_add<const N: usize>(program: &[RegexInst; N], set: &mut [bool; N], stack: &mut [usize; N], pc: usize) {
+    fn push<const N: usize>(set: &mut [bool; N], stack: &mut [usize; N], len: &mut usize, pc: usize) {
+        if let Some(on @ false) = set.get_mut(pc) {
+            *on = true;
+            if let Some(top) = stack.get_mut(*len) {
+                *top = pc;
+                *len += 1;
+            }
+        }
+    }
+
+    let mut len = 0;
+    push(set, stack, &mut len, pc);
+    while let Some(new_len) = len.checked_sub(1) {
+        len = new_len;
+        match stack.get(len).and_then(|&pc| program.get(pc)) {
+            Some(&RegexInst::Split(first, second)) => {
+                push(set, stack, &mut len, first);
+                push(set, stack, &mut len, second);
+            }
+            Some(&RegexInst::Jump(target)) => push(set, stack, &mut len, target),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn test_regex_match() {
+    use RegexInst::*;
+
+    // [A-Z]{2,3}
+    static CODE: [RegexInst; 5] = [
+        Class(false, &[('A', 'Z')]),
+        Class(false, &[('A', 'Z')]),
+        Split(3, 4),
+        Class(false, &[('A', 'Z')]),
+        Match,
+    ];
+    assert!(regex_match(&CODE, "AB"));
+    assert!(regex_match(&CODE, "ABC"));
+    assert!(!regex_match(&CODE, "A"));
+    assert!(!regex_match(&CODE, "ABCD"));
+    assert!(!regex_match(&CODE, "Ab"));
+
+    // (a|ab)*b?c matches the whole string only
+    static ALT: [RegexInst; 11] = [
+        Split(1, 7),
+        Split(2, 4),
+        Char('a'),
+        Jump(6),
+        Char('a'),
+        Char('b'),
+        Jump(0),
+        Split(8, 9),
+        Char('b'),
+        Char('c'),
+        Match,
+    ];
+    assert!(regex_match(&ALT, "c"));
+    assert!(regex_match(&ALT, "ababac"));
+    assert!(regex_match(&ALT, "aabc"));
+    assert!(regex_match(&ALT, "abbc"));
+    assert!(!regex_match(&ALT, "abbbc"));
+    assert!(!regex_match(&ALT, "ac "));
+
+    // Empty repetitions, (.?)*, do not loop forever
+    static EMPTY: [RegexInst; 5] = [Split(1, 4), Split(2, 3), Any, Jump(0), Match];
+    assert!(regex_match(&EMPTY, ""));
+    assert!(regex_match(&EMPTY, "привет"));
+    static NOT_DIGITS: [RegexInst; 5] = [
+        Class(true, &[('0', '9')]),
+        Split(2, 4),
+        Class(true, &[('0', '9')]),
+        Jump(1),
+        Match,
+    ];
+    assert!(regex_match(&NOT_DIGITS, "π-x"));
+    assert!(!regex_match(&NOT_DIGITS, "x1"));
+
+    // Long fragments take no stack, and nested repetitions take no exponential time
+    static ANY_A: [RegexInst; 4] = [Split(1, 3), Char('a'), Jump(0), Match];
+    let long = "a".repeat(100_000);
+    assert!(regex_match(&ANY_A, &long));
+    assert!(!regex_match(&ANY_A, &format!("{}b", long)));
+    // (a|aa)*b
+    static NESTED: [RegexInst; 9] = [
+        Split(1, 7),
+        Split(2, 4),
+        Char('a'),
+        Jump(6),
+        Char('a'),
+        Char('a'),
+        Jump(0),
+        Char('b'),
+        Match,
+    ];
+    assert!(regex_match(&NESTED, "aaab"));
+    assert!(!regex_match(&NESTED, &long[..10_000]));
+}
+
+/// Same as `format!`, but into `SmallString`.
+#[cfg(feature = "small-strings")]
+pub fn format_small(args: core::fmt::Arguments<'_>) -> crate::small::SmallString {
+    let mut res = crate::small::SmallString::new();
+    core::fmt::Write::write_fmt(&mut res, args)
+        .expect("a formatting trait implementation returned an error when the underlying stream did not");
+    res
+}
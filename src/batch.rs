@@ -0,0 +1,83 @@
+//! Parsing and formatting of large batches of values on all the cores, e.g. for reindexing.
+//!
+//! With the `rayon` feature the records are parsed or formatted by the `rayon` thread pool,
+//! and the results are returned in the order of the records.
+//! The format string is expanded once, so the same code is shared by all the threads:
+//! ```
+//! use fragstrings::{par_format, par_parse};
+//!
+//! let records = ["%s%d%d__foo__1__2", "%s%d%d__bar__3__4", "%s%d__baz__5"];
+//! let parsed = par_parse!("%s%d%d", &records);
+//! assert_eq!(parsed[0], Some(("foo".to_owned(), 1, 2)));
+//! assert_eq!(parsed[2], None);
+//!
+//! let rows = vec![("foo".to_owned(), 1, 2), ("bar".to_owned(), 3, 4)];
+//! let formatted = par_format!("%s%d%d", &rows, |(name, a, b)| name, *a, *b);
+//! assert_eq!(formatted, ["%s%d%d__foo__1__2", "%s%d%d__bar__3__4"]);
+//! ```
+
+use alloc::vec::Vec;
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
+/// Maps the items with the function on the `rayon` thread pool, keeping the order of the items.
+/// Small batches are mapped by the calling thread, since splitting them would take longer.
+pub fn par_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync + Send) -> Vec<R> {
+    if items.len() < MIN_ITEMS_PER_THREAD * 2 {
+        return items.iter().map(f).collect();
+    }
+    // Panics of the function are passed on to the caller by `rayon`, like in the single thread
+    items.par_iter().with_min_len(MIN_ITEMS_PER_THREAD).map(f).collect()
+}
+
+/// Least number of items worth a thread of its own.
+const MIN_ITEMS_PER_THREAD: usize = 1024;
+
+/// Parses the records, anything `AsRef<str>`, with the format string by the `frag_parse!` macro on all the cores,
+/// and returns the results in the order of the records.
+///
+/// ```
+/// # use fragstrings::par_parse;
+/// let records = vec!["%s%d__foo__42".to_owned(), "%d__42".to_owned()];
+/// assert_eq!(par_parse!("%s%d", &records), [Some(("foo".to_owned(), 42)), None]);
+/// ```
+#[macro_export]
+macro_rules! par_parse {
+    ($pattern:tt, $records:expr) => {
+        $crate::batch::par_map($records, |record| {
+            $crate::frag_parse!($pattern, ::core::convert::AsRef::<str>::as_ref(record))
+        })
+    };
+}
+
+/// Formats the rows with the format string by the `frag_format!` macro on all the cores, and returns the values
+/// in the order of the rows. Every row is matched with the pattern, like the argument of a closure,
+/// which takes the row by reference, and the arguments of `frag_format!` follow it.
+///
+/// ```
+/// # use fragstrings::par_format;
+/// let rows = [("foo", 42), ("bar", -1)];
+/// assert_eq!(par_format!("%s%d", &rows, |(name, n)| name, *n), ["%s%d__foo__42", "%s%d__bar__-1"]);
+/// ```
+#[macro_export]
+macro_rules! par_format {
+    ($pattern:tt, $rows:expr, |$row:pat_param| $($arg:expr),* $(,)?) => {
+        $crate::batch::par_map($rows, |$row| $crate::frag_format!($pattern $(, $arg)*))
+    };
+}
+
+#[test]
+fn test_par_map() {
+    let items = (0..10_000).collect::<Vec<u32>>();
+    let res = par_map(&items, |n| n * 2);
+    assert_eq!(res, items.iter().map(|n| n * 2).collect::<Vec<_>>());
+
+    assert_eq!(par_map(&[] as &[u32], |n| n * 2), []);
+    assert_eq!(par_map(&[1, 2, 3], |n| n * 2), [2, 4, 6]);
+}
+
+#[test]
+fn test_par_map_panic() {
+    let items = (0..10_000).collect::<Vec<u32>>();
+    let res = std::panic::catch_unwind(|| par_map(&items, |&n| assert_ne!(n, 9_999)));
+    assert!(res.is_err());
+}
@@ -0,0 +1,213 @@
+//! Interning of parsed strings, which repeat across many values, like asset ids.
+//!
+//! With the `intern` feature and the `intern` option `frag_parse!` returns `%s` items as `Arc<str>`s,
+//! which are deduplicated by the given interner, so every distinct string is stored once,
+//! no matter how many records it is parsed from:
+//! ```
+//! use fragstrings::frag_parse;
+//! use fragstrings::intern::FragInterner;
+//! use std::sync::Arc;
+//!
+//! let interner = FragInterner::new();
+//! let (a, _) = frag_parse!(intern = &interner, "%s%d", "%s%d__WAVES__1").unwrap();
+//! let (b, _) = frag_parse!(intern = &interner, "%s%d", "%s%d__WAVES__2").unwrap();
+//! assert_eq!(&*a, "WAVES");
+//! assert!(Arc::ptr_eq(&a, &b));
+//! ```
+//!
+//! The interner is shared by reference and locks on every lookup, so it can be used by many threads at once.
+
+use alloc::sync::Arc;
+use std::collections::HashSet;
+use std::sync::{Mutex, PoisonError};
+
+/// Set of interned strings with the get-or-insert semantics.
+///
+/// By default the interner grows without limits. With [`with_max_entries`](Self::with_max_entries)
+/// the strings which are no longer used outside of the interner are evicted when it is full,
+/// and if all of them are still in use, new strings are returned without being interned.
+/// Looking for the unused strings takes a pass over all of them, so after a pass which evicts `k` of `n` strings
+/// the next one is done after `n` more new strings, the first `k` of which are interned, and the other ones
+/// are not, even if some strings are no longer used by then. [`evict_unused`](Self::evict_unused)
+/// evicts them right away.
+/// With [`with_max_len`](Self::with_max_len) longer strings, which are unlikely to repeat, are never interned.
+#[derive(Debug)]
+pub struct FragInterner {
+    strings: Mutex<Strings>,
+    max_entries: usize,
+    max_len: usize,
+}
+
+#[derive(Debug, Default)]
+struct Strings {
+    set: HashSet<Arc<str>>,
+    /// Number of the new strings which are not interned before the next pass for the unused ones.
+    skipped_evictions: usize,
+}
+
+impl Strings {
+    fn evict_unused(&mut self) -> usize {
+        let len = self.set.len();
+        self.set.retain(|interned| Arc::strong_count(interned) > 1);
+        self.skipped_evictions = 0;
+        len - self.set.len()
+    }
+}
+
+impl FragInterner {
+    /// Creates an empty interner without limits.
+    pub fn new() -> Self {
+        FragInterner {
+            strings: Mutex::new(Strings::default()),
+            max_entries: usize::MAX,
+            max_len: usize::MAX,
+        }
+    }
+
+    /// Creates an empty interner, which keeps `max_entries` strings at most.
+    pub fn with_max_entries(max_entries: usize) -> Self {
+        FragInterner {
+            max_entries,
+            ..FragInterner::new()
+        }
+    }
+
+    /// Sets the length of the longest string, in bytes, which is interned.
+    pub fn with_max_len(self, max_len: usize) -> Self {
+        FragInterner { max_len, ..self }
+    }
+
+    /// Returns the interned copy of the string, interning it first if there is none.
+    pub fn get_or_insert(&self, s: &str) -> Arc<str> {
+        if s.len() > self.max_len {
+            return Arc::from(s);
+        }
+        let mut strings = self.lock();
+        if let Some(interned) = strings.set.get(s) {
+            return Arc::clone(interned);
+        }
+        if strings.set.len() >= self.max_entries {
+            if strings.skipped_evictions > 0 {
+                strings.skipped_evictions -= 1;
+                return Arc::from(s);
+            }
+            strings.evict_unused();
+            // Along with the evicted strings, which are replaced by new ones, the pass is paid for
+            // by as many new strings as there are strings left
+            strings.skipped_evictions = strings.set.len();
+            if strings.set.len() >= self.max_entries {
+                return Arc::from(s);
+            }
+        }
+        let interned = Arc::<str>::from(s);
+        strings.set.insert(Arc::clone(&interned));
+        interned
+    }
+
+    /// Number of the interned strings.
+    pub fn len(&self) -> usize {
+        self.lock().set.len()
+    }
+
+    /// Whether there are no interned strings.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Evicts the strings which are no longer used outside of the interner, and returns their number.
+    pub fn evict_unused(&self) -> usize {
+        self.lock().evict_unused()
+    }
+
+    /// Evicts all the strings. The values returned before keep their copies.
+    pub fn clear(&self) {
+        let mut strings = self.lock();
+        strings.set.clear();
+        strings.skipped_evictions = 0;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Strings> {
+        self.strings.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for FragInterner {
+    fn default() -> Self {
+        FragInterner::new()
+    }
+}
+
+#[test]
+fn test_get_or_insert() {
+    let interner = FragInterner::new();
+    assert!(interner.is_empty());
+    let a = interner.get_or_insert("foo");
+    let b = interner.get_or_insert("foo");
+    let c = interner.get_or_insert("bar");
+    assert!(Arc::ptr_eq(&a, &b));
+    assert_eq!((&*a, &*c), ("foo", "bar"));
+    assert_eq!(interner.len(), 2);
+
+    // Strings in use are kept, the others are evicted
+    drop(c);
+    assert_eq!(interner.evict_unused(), 1);
+    assert!(Arc::ptr_eq(&a, &interner.get_or_insert("foo")));
+    interner.clear();
+    assert!(interner.is_empty());
+    assert!(!Arc::ptr_eq(&a, &interner.get_or_insert("foo")));
+}
+
+#[test]
+fn test_limits() {
+    let interner = FragInterner::with_max_entries(2).with_max_len(3);
+    let a = interner.get_or_insert("a");
+    let b = interner.get_or_insert("b");
+
+    // Full of strings in use, so new ones are not interned
+    let c = interner.get_or_insert("c");
+    assert!(!Arc::ptr_eq(&c, &interner.get_or_insert("c")));
+    assert_eq!(interner.len(), 2);
+
+    // Unused strings make room for new ones, once the new strings pay for the pass for them
+    drop(b);
+    assert!(!Arc::ptr_eq(&c, &interner.get_or_insert("c")));
+    let c = interner.get_or_insert("c");
+    assert!(Arc::ptr_eq(&c, &interner.get_or_insert("c")));
+    assert!(Arc::ptr_eq(&a, &interner.get_or_insert("a")));
+    assert_eq!(interner.len(), 2);
+
+    // Long strings are never interned
+    drop((a, c));
+    let long = interner.get_or_insert("long");
+    assert!(!Arc::ptr_eq(&long, &interner.get_or_insert("long")));
+    assert_eq!(&*long, "long");
+}
+
+#[test]
+fn test_full_of_strings_in_use() {
+    let interner = FragInterner::with_max_entries(100);
+    let mut used = (0..100)
+        .map(|i| interner.get_or_insert(&i.to_string()))
+        .collect::<Vec<_>>();
+
+    // New strings are not interned, and the pass for the unused strings is done once per 100 of them
+    let mut passes = 0;
+    for i in 100..1_100 {
+        let skipped = interner.lock().skipped_evictions;
+        let s = interner.get_or_insert(&i.to_string());
+        assert_eq!(&*s, i.to_string());
+        assert_eq!(Arc::strong_count(&s), 1);
+        passes += (interner.lock().skipped_evictions > skipped) as usize;
+    }
+    assert_eq!(passes, 10);
+    assert_eq!(interner.len(), 100);
+
+    // Unused strings are evicted by the next pass, and new strings replace them
+    used.truncate(50);
+    let new = (1_100..1_200)
+        .map(|i| interner.get_or_insert(&i.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(new.iter().filter(|s| Arc::strong_count(s) > 1).count(), 50);
+    assert_eq!(interner.len(), 100);
+    assert!(used.iter().all(|s| Arc::ptr_eq(s, &interner.get_or_insert(s))));
+}
@@ -0,0 +1,100 @@
+//! Lazily copied strings, which are returned by the `frag_parse!` macro with the `lazy` option.
+
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use core::fmt;
+use core::ops::Deref;
+
+/// String fragment borrowed from the input, which is copied only when `get()` is called,
+/// so that nothing is allocated for the values which are never used.
+/// Otherwise it is used as `&str`.
+///
+/// ```
+/// use fragstrings::lazy::LazyStr;
+/// let value = LazyStr::new("foo");
+/// assert_eq!(value.len(), 3);
+/// assert_eq!(value, "foo");
+/// let owned: String = value.get();
+/// assert_eq!(owned, "foo");
+/// ```
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LazyStr<'a>(&'a str);
+
+impl<'a> LazyStr<'a> {
+    /// Wraps the string without copying it.
+    pub fn new(value: &'a str) -> Self {
+        LazyStr(value)
+    }
+
+    /// Returns the string borrowed from the input.
+    pub fn as_str(&self) -> &'a str {
+        self.0
+    }
+
+    /// Returns an owned copy of the string.
+    pub fn get(&self) -> String {
+        self.0.to_owned()
+    }
+}
+
+impl fmt::Debug for LazyStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for LazyStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Deref for LazyStr<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0
+    }
+}
+
+impl AsRef<str> for LazyStr<'_> {
+    fn as_ref(&self) -> &str {
+        self.0
+    }
+}
+
+impl<'a> From<&'a str> for LazyStr<'a> {
+    fn from(value: &'a str) -> Self {
+        LazyStr(value)
+    }
+}
+
+impl From<LazyStr<'_>> for String {
+    fn from(value: LazyStr<'_>) -> Self {
+        value.get()
+    }
+}
+
+impl PartialEq<str> for LazyStr<'_> {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for LazyStr<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+#[test]
+fn test_lazy_str() {
+    let value = LazyStr::new("foo");
+    assert_eq!(format!("{:?}", value), r#""foo""#);
+    assert_eq!(format!("{:>5}", value), "  foo");
+    assert_eq!(value.get(), "foo");
+    assert_eq!(String::from(value), "foo");
+    assert_eq!(value.as_str(), "foo");
+    assert!(value.starts_with('f'));
+    assert_eq!(value, LazyStr::from("foo"));
+}
@@ -30,9 +30,170 @@
 //! assert_eq!(bar, "bar");
 //! // Fragment "baz" is silently ignored here and does not raise any errors.
 //! ```
+//!
+//! # Checked mode
+//!
+//! With the `checked` option the formatted string gets one extra trailing `%d` fragment
+//! containing a CRC32 of all the preceding bytes, which is verified and stripped when parsing
+//! in the same mode. Parsing in checked mode returns `Result` instead of `Option`,
+//! so that a damaged record can be told apart from a record which does not match the format:
+//! ```
+//! use fragstrings::{frag_format, frag_parse, ParseError};
+//! let value = frag_format!(checked, "%s%d", "foo", 42);
+//! assert_eq!(value, "%s%d%d__foo__42__4234519149");
+//!
+//! let (foo, bar) = frag_parse!(checked, "%s%d", value).unwrap();
+//! assert_eq!(foo, "foo");
+//! assert_eq!(bar, 42);
+//!
+//! assert_eq!(frag_parse!(checked, "%s%d", "%s%d%d__foo__43__4234519149"), Err(ParseError::BadChecksum));
+//! assert_eq!(frag_parse!(checked, "%d%d", value), Err(ParseError::Mismatch));
+//! ```
+//!
+//! Readers which do not know about the checksum can still read checked records,
+//! if their format string is open-ended, because the checksum is just an extra fragment:
+//! ```
+//! use fragstrings::frag_parse;
+//! let (foo, bar) = frag_parse!("%s%d*", "%s%d%d__foo__42__4234519149").unwrap();
+//! assert_eq!(foo, "foo");
+//! assert_eq!(bar, 42);
+//! ```
+
+#![cfg_attr(not(feature = "std"), no_std)]
+#![forbid(unsafe_code)]
+
+extern crate alloc;
 
 #[cfg(feature = "format")]
-pub use format_procmacro::frag_format;
+pub use fragstrings_procmacro::frag_format;
 
 #[cfg(feature = "parse")]
-pub use parse_procmacro::frag_parse;
+pub use fragstrings_procmacro::{
+    frag_assert_disjoint, frag_compat_assert, frag_explain, frag_parse, frag_parse_arc, frag_parse_partial,
+    frag_parse_ref, frag_parse_strict,
+};
+
+#[cfg(all(feature = "parse", feature = "std"))]
+pub use fragstrings_procmacro::{frag_parse_file_name, frag_parse_os};
+
+#[cfg(feature = "bytes-integration")]
+pub use fragstrings_procmacro::frag_parse_bytes;
+
+#[cfg(feature = "small-strings")]
+pub use fragstrings_procmacro::frag_format_small;
+
+/// Error returned by the `frag_format!` macro with the `max_bytes`, `roman`, `human_duration`, `packed_date`,
+/// `fixed_point`, `bitmask` or `path_safe` options.
+#[cfg(feature = "format")]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FormatError {
+    /// The formatted value is longer than `max_bytes`, its actual length is `len`.
+    TooLong { len: usize, max_bytes: usize },
+    /// The integer `value` can not be written as a Roman numeral, or as a duration if it is negative,
+    /// or it is the year of a date, which can not be packed, because it is not in the range of 0 to 9999,
+    /// or it is the position of a bit, which is not in the range of 0 to 63.
+    OutOfRange { value: i64 },
+    /// The number can not be written as a fixed-point decimal, because it is not finite,
+    /// or it does not fit into `i64` after scaling. The same goes for percentages, which also can not be
+    /// out of the range of 0 to 1 with `percent = strict`.
+    FixedPointOutOfRange,
+    /// The string value of the argument `arg`, as written in the macro call, has characters
+    /// which can not be in file names.
+    NotPathSafe { arg: &'static str },
+}
+
+/// Error returned by the `frag_parse!` macro in checked mode.
+#[cfg(feature = "parse")]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ParseError {
+    /// The value does not match the format string.
+    Mismatch,
+    /// The checksum fragment is missing or does not match the contents of the value.
+    BadChecksum,
+}
+
+/// Error of a single item returned by the `frag_parse_partial!` macro.
+#[cfg(feature = "parse")]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FieldError {
+    /// There is no fragment for the item.
+    Missing,
+    /// The fragment can not be parsed into the type of the item, or it fails the checks of the item.
+    Malformed,
+}
+
+/// String allocator for the `frag_parse!` macro with the `alloc` option,
+/// typically an arena, which outlives the parsed values.
+///
+/// ```
+/// use fragstrings::{frag_parse, StringAllocator};
+///
+/// struct Leaking;
+///
+/// impl StringAllocator for Leaking {
+///     fn alloc_str(&self, s: &str) -> &str {
+///         Box::leak(s.to_owned().into_boxed_str())
+///     }
+/// }
+///
+/// let arena = Leaking;
+/// let (foo, bar) = frag_parse!(alloc = arena, "%s%d", "%s%d__foo__42").unwrap();
+/// assert_eq!(foo, "foo"); // `foo` is `&str` borrowed from the `arena`
+/// assert_eq!(bar, 42);
+/// ```
+#[cfg(feature = "parse")]
+pub trait StringAllocator {
+    /// Stores a copy of the string in the allocator.
+    fn alloc_str(&self, s: &str) -> &str;
+}
+
+/// Audit log for the `frag_parse!` macro with the `audit` option, which records every parse attempt,
+/// e.g. for compliance logging of sensitive values.
+///
+/// ```
+/// use fragstrings::{frag_parse, AuditLogger};
+/// use std::sync::Mutex;
+///
+/// struct Log(Mutex<Vec<(String, bool)>>);
+///
+/// impl AuditLogger for Log {
+///     fn record(&self, fmt: &str, _input: &str, success: bool) {
+///         self.0.lock().unwrap().push((fmt.to_owned(), success));
+///     }
+/// }
+///
+/// let log = Log(Mutex::new(Vec::new()));
+/// assert!(frag_parse!(audit = &log, "%s%d", "%s%d__foo__42").is_some());
+/// assert!(frag_parse!(audit = &log, "%s%d", "%s%d__foo__bar").is_none());
+/// assert_eq!(*log.0.lock().unwrap(), [("%s%d".to_owned(), true), ("%s%d".to_owned(), false)]);
+/// ```
+#[cfg(feature = "parse")]
+pub trait AuditLogger {
+    /// Records the parse attempt with the format string as written, the input, and whether a value was returned.
+    fn record(&self, fmt: &str, input: &str, success: bool);
+}
+
+#[cfg(feature = "parse")]
+pub mod lazy;
+
+#[cfg(feature = "parse")]
+pub mod redact;
+
+#[cfg(feature = "registry")]
+pub mod registry;
+
+#[cfg(feature = "small-strings")]
+pub mod small;
+
+#[cfg(feature = "vectors")]
+pub mod vectors;
+
+#[cfg(feature = "rayon")]
+pub mod batch;
+
+#[cfg(feature = "intern")]
+pub mod intern;
+
+/// Runtime support for the code generated by the macros, not a public API.
+#[doc(hidden)]
+pub mod __private;
@@ -0,0 +1,66 @@
+//! Wrapper for sensitive values, which are returned by the `frag_parse!` macro with the `redact` option.
+
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+/// Sensitive value, like a password or a token, which is not revealed by `Debug`,
+/// so that it is not logged by accident with `{:?}`, while `Display` shows it as is.
+///
+/// ```
+/// use fragstrings::redact::Redacted;
+/// let token = Redacted("secret".to_string());
+/// assert_eq!(format!("{:?}", token), "***");
+/// assert_eq!(format!("{}", token), "secret");
+/// assert_eq!(token.len(), 6);
+/// ```
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Redacted<T>(pub T);
+
+impl<T> Redacted<T> {
+    /// Returns the wrapped value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("***")
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T> Deref for Redacted<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Redacted<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> From<T> for Redacted<T> {
+    fn from(value: T) -> Self {
+        Redacted(value)
+    }
+}
+
+#[test]
+fn test_redacted() {
+    let value = Redacted(("foo", 42));
+    assert_eq!(format!("{:?}", value), "***");
+    assert_eq!(format!("{:?}", Some(value)), "Some(***)");
+    assert_eq!(format!("{:>5}", Redacted(42)), "   42");
+    assert_eq!(value.into_inner(), ("foo", 42));
+    assert_eq!(*value, ("foo", 42));
+}
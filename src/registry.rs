@@ -0,0 +1,75 @@
+//! Registry of all the patterns used by the macros in the binary, for auditing.
+//!
+//! With the `registry` feature every `frag_format!` and `frag_parse!` call site registers its format string
+//! at startup, before `main`, so that the registries of two releases can be compared to catch
+//! accidental pattern changes:
+//! ```
+//! use fragstrings::registry::{self, Direction};
+//!
+//! fn write(name: &str, n: i64) -> String {
+//!     fragstrings::frag_format!("%s%d", name, n)
+//! }
+//!
+//! # fn main() {
+//! let uses = registry::all();
+//! assert!(uses.iter().any(|u| u.pattern == "%s%d" && u.direction == Direction::Format));
+//! # let _ = write;
+//! # }
+//! ```
+//!
+//! Registration relies on static constructors, which are supported on Linux, Android, FreeBSD, macOS,
+//! iOS and Windows. On other targets the registry is empty.
+
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+/// Whether the pattern is used for formatting or for parsing.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Direction {
+    Format,
+    Parse,
+}
+
+/// Format string used by a macro call site.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct PatternUse {
+    /// Source file of the call site, as given by `file!()`.
+    pub file: &'static str,
+    /// Line of the call site, as given by `line!()`.
+    pub line: u32,
+    /// Format string as written, like `%s%d?*`.
+    pub pattern: &'static str,
+    pub direction: Direction,
+}
+
+/// Returns all the registered patterns, ordered by the call site.
+pub fn all() -> &'static [PatternUse] {
+    static ALL: OnceLock<Vec<PatternUse>> = OnceLock::new();
+    ALL.get_or_init(|| {
+        let mut uses = USES.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        uses.sort();
+        uses
+    })
+}
+
+/// Registered patterns, which are pushed by static constructors.
+static USES: Mutex<Vec<PatternUse>> = Mutex::new(Vec::new());
+
+/// Registration of a call site, generated by the macros, not a public API.
+#[doc(hidden)]
+pub struct Registration {
+    pattern_use: PatternUse,
+}
+
+impl Registration {
+    #[doc(hidden)]
+    pub const fn new(pattern_use: PatternUse) -> Self {
+        Registration { pattern_use }
+    }
+
+    #[doc(hidden)]
+    pub fn register(&'static self) {
+        USES.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(self.pattern_use);
+    }
+}
@@ -0,0 +1,225 @@
+//! Strings stored inline when they are short, which are returned by the `frag_format_small!` macro.
+
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::ops::Deref;
+
+/// Longest string which is stored inline, without allocation.
+pub const INLINE_CAPACITY: usize = 47;
+
+/// String which is stored inline if it is not longer than `INLINE_CAPACITY` bytes,
+/// and in a `String` otherwise. It is used as `&str`, and compares and hashes like one.
+///
+/// ```
+/// use fragstrings::small::SmallString;
+/// let mut value = SmallString::from("%s__foo");
+/// assert!(value.is_inline());
+/// value.push_str(&"o".repeat(64));
+/// assert!(!value.is_inline());
+/// assert!(value.starts_with("%s__foo"));
+/// ```
+#[derive(Clone)]
+pub struct SmallString(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Inline { len: u8, bytes: [u8; INLINE_CAPACITY] },
+    Heap(String),
+}
+
+impl SmallString {
+    /// Creates an empty string, which is stored inline.
+    pub const fn new() -> Self {
+        SmallString(Repr::Inline {
+            len: 0,
+            bytes: [0; INLINE_CAPACITY],
+        })
+    }
+
+    /// Returns the contents as `&str`.
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            // Only whole strings are appended, so the bytes are always valid UTF-8
+            Repr::Inline { len, bytes } => bytes
+                .get(..*len as usize)
+                .and_then(|bytes| core::str::from_utf8(bytes).ok())
+                .unwrap_or_default(),
+            Repr::Heap(s) => s,
+        }
+    }
+
+    /// Checks whether the string is stored inline.
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, Repr::Inline { .. })
+    }
+
+    /// Appends the string, moving the contents to the heap, if they do not fit inline anymore.
+    pub fn push_str(&mut self, s: &str) {
+        if let Repr::Inline { len, bytes } = &mut self.0 {
+            let start = *len as usize;
+            if let Some(rest) = bytes.get_mut(start..start + s.len()) {
+                rest.copy_from_slice(s.as_bytes());
+                *len += s.len() as u8;
+                return;
+            }
+        }
+        match &mut self.0 {
+            Repr::Inline { .. } => {
+                let mut heap = String::with_capacity(self.len() + s.len());
+                heap.push_str(self.as_str());
+                heap.push_str(s);
+                self.0 = Repr::Heap(heap);
+            }
+            Repr::Heap(heap) => heap.push_str(s),
+        }
+    }
+
+    /// Converts into `String`, which allocates if the contents are inline.
+    pub fn into_string(self) -> String {
+        match self.0 {
+            Repr::Inline { .. } => self.as_str().to_owned(),
+            Repr::Heap(s) => s,
+        }
+    }
+}
+
+impl Default for SmallString {
+    fn default() -> Self {
+        SmallString::new()
+    }
+}
+
+impl fmt::Write for SmallString {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for SmallString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl fmt::Display for SmallString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl Deref for SmallString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for SmallString {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for SmallString {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for SmallString {
+    fn from(value: &str) -> Self {
+        let mut res = SmallString::new();
+        res.push_str(value);
+        res
+    }
+}
+
+impl From<SmallString> for String {
+    fn from(value: SmallString) -> Self {
+        value.into_string()
+    }
+}
+
+impl PartialEq for SmallString {
+    fn eq(&self, other: &SmallString) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for SmallString {}
+
+impl PartialOrd for SmallString {
+    fn partial_cmp(&self, other: &SmallString) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SmallString {
+    fn cmp(&self, other: &SmallString) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Hash for SmallString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl PartialEq<str> for SmallString {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for SmallString {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for SmallString {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<SmallString> for String {
+    fn eq(&self, other: &SmallString) -> bool {
+        self == other.as_str()
+    }
+}
+
+#[test]
+fn test_small_string() {
+    let mut value = SmallString::new();
+    assert!(value.is_empty());
+    value.push_str("%s__");
+    value.push_str(&"x".repeat(INLINE_CAPACITY - 4));
+    assert!(value.is_inline());
+    assert_eq!(value.len(), INLINE_CAPACITY);
+    value.push_str("é");
+    assert!(!value.is_inline());
+    assert_eq!(value, format!("%s__{}é", "x".repeat(INLINE_CAPACITY - 4)));
+
+    let value = SmallString::from("привет");
+    assert_eq!(format!("{:?}", value), r#""привет""#);
+    assert_eq!(format!("{:>7}", value), " привет");
+    assert_eq!(value, "привет");
+    assert_eq!(String::from(value.clone()), "привет");
+    assert_eq!(value, SmallString::from(String::from("привет").as_str()));
+    let (a, b) = (SmallString::from("a"), SmallString::from("b"));
+    assert!(a < b);
+
+    let set = [SmallString::from("foo")]
+        .iter()
+        .cloned()
+        .collect::<std::collections::HashSet<_>>();
+    assert!(set.contains("foo"));
+}
@@ -0,0 +1,336 @@
+//! Conformance vectors for other implementations of fragmented strings, like the Scala and TypeScript ones.
+//!
+//! With the `vectors` feature the results of the macros can be collected into a JSON file,
+//! which the other implementations are tested against, so that they do not drift from this one:
+//! ```
+//! use fragstrings::vectors::Vectors;
+//! use fragstrings::{format_vector, parse_vector};
+//!
+//! let mut vectors = Vectors::new();
+//! parse_vector!(vectors, "%s%d", "%s%d__foo__42");
+//! parse_vector!(vectors, "%s%d", "%d__42");
+//! format_vector!(vectors, "%s%d", "foo", 42);
+//! assert_eq!(
+//!     vectors.to_json(),
+//!     r#"{
+//!   "parse": [
+//!     {"pattern": "%s%d", "input": "%s%d__foo__42", "result": ["foo", 42]},
+//!     {"pattern": "%s%d", "input": "%d__42", "error": "descriptor"}
+//!   ],
+//!   "format": [
+//!     {"pattern": "%s%d", "args": ["foo", 42], "output": "%s%d__foo__42"}
+//!   ]
+//! }
+//! "#
+//! );
+//! ```
+//!
+//! Results are written as `frag_parse!` returns them, so the result of a single item is not an array,
+//! and absent optional items are `null`. Failures are classified the same way as by the `trace` option:
+//! `not_fragmented` if the input is not a fragmented string at all, `descriptor` if its descriptor does not
+//! start with the one of the mandatory items, and `value` otherwise.
+
+use crate::__private::failure_reason;
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Collected vectors, which are written as JSON by `to_json()`.
+#[derive(Clone, Default, Debug)]
+pub struct Vectors {
+    parse: Vec<String>,
+    format: Vec<String>,
+}
+
+impl Vectors {
+    /// Creates an empty set of vectors.
+    pub fn new() -> Self {
+        Vectors::default()
+    }
+
+    /// Adds the result of parsing the `input` with the `pattern`, which is returned by the `parse_vector!` macro.
+    pub fn parse<T: ToJson>(&mut self, pattern: &str, input: &str, result: Option<T>) {
+        let mut vector = String::from("{\"pattern\": ");
+        pattern.write_json(&mut vector);
+        vector.push_str(", \"input\": ");
+        input.write_json(&mut vector);
+        match result {
+            Some(result) => {
+                vector.push_str(", \"result\": ");
+                result.write_json(&mut vector);
+            }
+            None => {
+                let reason = failure_reason(input, &[&mandatory_descriptor(pattern)], false);
+                vector.push_str(", \"error\": ");
+                reason.write_json(&mut vector);
+            }
+        }
+        vector.push('}');
+        self.parse.push(vector);
+    }
+
+    /// Adds the `output` of formatting the `args`, usually a tuple, with the `pattern`,
+    /// which is returned by the `format_vector!` macro.
+    pub fn format<A: ToJson>(&mut self, pattern: &str, args: A, output: &str) {
+        let mut vector = String::from("{\"pattern\": ");
+        pattern.write_json(&mut vector);
+        vector.push_str(", \"args\": ");
+        args.write_json(&mut vector);
+        vector.push_str(", \"output\": ");
+        output.write_json(&mut vector);
+        vector.push('}');
+        self.format.push(vector);
+    }
+
+    /// Writes the vectors as JSON, one vector per line, in the order they were added.
+    pub fn to_json(&self) -> String {
+        let mut res = String::from("{\n");
+        for (i, (name, vectors)) in [("parse", &self.parse), ("format", &self.format)].iter().enumerate() {
+            if i > 0 {
+                res.push_str(",\n");
+            }
+            let _ = write!(res, "  \"{}\": [", name);
+            for (j, vector) in vectors.iter().enumerate() {
+                res.push_str(if j > 0 { ",\n    " } else { "\n    " });
+                res.push_str(vector);
+            }
+            res.push_str(if vectors.is_empty() { "]" } else { "\n  ]" });
+        }
+        res.push_str("\n}\n");
+        res
+    }
+
+    /// Writes the vectors into the file, replacing it.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+}
+
+/// Descriptor of the mandatory items of the format string, like `%s%d` for `%s%d:u8%s?*`.
+fn mandatory_descriptor(pattern: &str) -> String {
+    let mut res = String::new();
+    let mut chars = pattern.chars();
+    let mut depth = 0;
+    while let Some(ch) = chars.next() {
+        match ch {
+            '%' if depth == 0 => match chars.next() {
+                Some('d') => res.push_str("%d"),
+                Some(_) => res.push_str("%s"),
+                None => break,
+            },
+            // Options of items, like regexes, can have anything in quotes
+            '\'' => {
+                for ch in chars.by_ref() {
+                    if ch == '\'' {
+                        break;
+                    }
+                }
+            }
+            '[' | '{' => depth += 1,
+            ']' | '}' => depth -= 1,
+            // The optional items are the last ones, and so are the extra fragments
+            '?' if depth == 0 => {
+                res.truncate(res.len() - 2);
+                break;
+            }
+            '*' if depth == 0 => break,
+            _ => {}
+        }
+    }
+    res
+}
+
+/// Value which is written into the vectors as JSON. It is implemented for the types
+/// of the items returned by the `frag_parse!` macro, and of the arguments of the `frag_format!` macro.
+pub trait ToJson {
+    /// Appends the value as JSON to the string.
+    fn write_json(&self, out: &mut String);
+}
+
+impl<T: ToJson + ?Sized> ToJson for &T {
+    fn write_json(&self, out: &mut String) {
+        (**self).write_json(out)
+    }
+}
+
+impl ToJson for str {
+    fn write_json(&self, out: &mut String) {
+        out.push('"');
+        for ch in self.chars() {
+            match ch {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                ch if (ch as u32) < 0x20 => {
+                    let _ = write!(out, "\\u{:04x}", ch as u32);
+                }
+                ch => out.push(ch),
+            }
+        }
+        out.push('"');
+    }
+}
+
+impl ToJson for String {
+    fn write_json(&self, out: &mut String) {
+        self.as_str().write_json(out)
+    }
+}
+
+impl ToJson for Path {
+    fn write_json(&self, out: &mut String) {
+        self.to_string_lossy().write_json(out)
+    }
+}
+
+impl ToJson for PathBuf {
+    fn write_json(&self, out: &mut String) {
+        self.as_path().write_json(out)
+    }
+}
+
+macro_rules! impl_to_json_int {
+    ($($int:ty),*) => {
+        $(
+            impl ToJson for $int {
+                fn write_json(&self, out: &mut String) {
+                    let _ = write!(out, "{}", self);
+                }
+            }
+        )*
+    };
+}
+
+impl_to_json_int!(i8, i16, i32, i64, u8, u16, u32, u64);
+
+impl ToJson for bool {
+    fn write_json(&self, out: &mut String) {
+        out.push_str(if *self { "true" } else { "false" });
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn write_json(&self, out: &mut String) {
+        match self {
+            Some(value) => value.write_json(out),
+            None => out.push_str("null"),
+        }
+    }
+}
+
+/// Writes the items as a JSON array.
+fn write_array<'a, T: ToJson + ?Sized + 'a>(items: impl Iterator<Item = &'a T>, out: &mut String) {
+    out.push('[');
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        item.write_json(out);
+    }
+    out.push(']');
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn write_json(&self, out: &mut String) {
+        write_array(self.iter(), out)
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn write_json(&self, out: &mut String) {
+        write_array(self.iter(), out)
+    }
+}
+
+impl<T: ToJson> ToJson for BTreeSet<T> {
+    fn write_json(&self, out: &mut String) {
+        write_array(self.iter(), out)
+    }
+}
+
+// Tuples are written as arrays, including the empty one of the format strings without items
+macro_rules! impl_to_json_tuple {
+    ($($name:ident)*) => {
+        impl<$($name: ToJson),*> ToJson for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn write_json(&self, out: &mut String) {
+                let ($($name,)*) = self;
+                let items: &[&dyn ToJson] = &[$($name),*];
+                write_array(items.iter(), out)
+            }
+        }
+    };
+}
+
+impl_to_json_tuple!();
+impl_to_json_tuple!(A);
+impl_to_json_tuple!(A B);
+impl_to_json_tuple!(A B C);
+impl_to_json_tuple!(A B C D);
+impl_to_json_tuple!(A B C D E);
+impl_to_json_tuple!(A B C D E F);
+impl_to_json_tuple!(A B C D E F G);
+impl_to_json_tuple!(A B C D E F G H);
+
+/// Parses the input with the format string by the `frag_parse!` macro, and adds the result to the vectors.
+///
+/// ```
+/// # use fragstrings::{parse_vector, vectors::Vectors};
+/// let mut vectors = Vectors::new();
+/// parse_vector!(vectors, "%s%d?", "%s__foo");
+/// ```
+#[macro_export]
+macro_rules! parse_vector {
+    ($vectors:expr, $pattern:tt, $input:expr) => {{
+        let input: &str = $input;
+        let result = $crate::frag_parse!($pattern, input);
+        $crate::vectors::Vectors::parse(&mut $vectors, $pattern, input, result)
+    }};
+}
+
+/// Formats the arguments with the format string by the `frag_format!` macro, and adds the output
+/// to the vectors. The arguments are evaluated twice, so they are expected to be plain values.
+///
+/// ```
+/// # use fragstrings::{format_vector, vectors::Vectors};
+/// let mut vectors = Vectors::new();
+/// format_vector!(vectors, "%s%d", "foo", -1);
+/// ```
+#[macro_export]
+macro_rules! format_vector {
+    ($vectors:expr, $pattern:tt $(, $arg:expr)* $(,)?) => {{
+        let output = $crate::frag_format!($pattern $(, $arg)*);
+        $crate::vectors::Vectors::format(&mut $vectors, $pattern, ($(&$arg,)*), &output)
+    }};
+}
+
+#[test]
+fn test_mandatory_descriptor() {
+    assert_eq!(mandatory_descriptor("%s%d"), "%s%d");
+    assert_eq!(mandatory_descriptor("%s%d:u8%s?*"), "%s%d");
+    assert_eq!(mandatory_descriptor("%d%s*"), "%d%s");
+    assert_eq!(mandatory_descriptor("%s62%P%s{set}%d:SystemTime"), "%s%s%s%d");
+    assert_eq!(mandatory_descriptor("%s[..8]%s{trie:a,b}%d?"), "%s%s");
+    assert_eq!(mandatory_descriptor(r"%s[re=r'^[%d]{2}?$']%d"), "%s%d");
+}
+
+#[test]
+fn test_to_json() {
+    let json = |value: &dyn Fn(&mut String)| {
+        let mut out = String::new();
+        value(&mut out);
+        out
+    };
+    assert_eq!(json(&|out| "a\"b\\c\n\u{1}é".write_json(out)), r#""a\"b\\c\n\u0001é""#);
+    assert_eq!(
+        json(&|out| (-1_i64, Some(2_u8), None::<i64>).write_json(out)),
+        "[-1, 2, null]"
+    );
+    assert_eq!(json(&|out| ().write_json(out)), "[]");
+    assert_eq!(json(&|out| vec![vec!["a"], vec![]].write_json(out)), r#"[["a"], []]"#);
+}
@@ -0,0 +1,48 @@
+#![cfg(feature = "rayon")]
+
+use fragstrings::{par_format, par_parse};
+
+#[test]
+fn test_par_parse() {
+    // OK: large batches are split between the threads, and the order is kept
+    let records = (0..10_000)
+        .map(|i| {
+            if i % 7 == 0 {
+                format!("%d__{}", i)
+            } else {
+                format!("%s%d%d__r{}__{}__{}", i, i, -i)
+            }
+        })
+        .collect::<Vec<_>>();
+    let parsed = par_parse!("%s%d%d", &records);
+    assert_eq!(parsed.len(), records.len());
+    for (i, value) in (0_i64..).zip(&parsed) {
+        let expected = if i % 7 == 0 {
+            None
+        } else {
+            Some((format!("r{}", i), i, -i))
+        };
+        assert_eq!(*value, expected);
+    }
+
+    // OK: small batches, borrowed records
+    let records = ["%s%d__foo__42", "%s%d__bar__x"];
+    assert_eq!(par_parse!("%s%d", &records), [Some(("foo".to_owned(), 42)), None]);
+    let records: &[&str] = &[];
+    assert_eq!(par_parse!("%s%d", records), []);
+}
+
+#[test]
+fn test_par_format() {
+    // OK: rows are formatted in order, and parsed back
+    let rows = (0..10_000).map(|i| (format!("r{}", i), i, -i)).collect::<Vec<_>>();
+    let formatted = par_format!("%s%d%d", &rows, |(name, a, b)| name, *a, *b);
+    assert_eq!(formatted[1], "%s%d%d__r1__1__-1");
+    let parsed = par_parse!("%s%d%d", &formatted);
+    assert!(parsed.into_iter().eq(rows.into_iter().map(Some)));
+
+    // OK: rows which are plain values
+    let rows = [42, 7];
+    let formatted = par_format!("%d", &rows, |&n| n);
+    assert_eq!(formatted, ["%d__42", "%d__7"]);
+}
@@ -0,0 +1,67 @@
+#![cfg(feature = "chrono")]
+
+use chrono::NaiveDate;
+use fragstrings::{frag_format, frag_parse, FormatError};
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).expect("valid date")
+}
+
+#[test]
+fn test_frag_format_packed_date() {
+    // OK: plain integers are written as packed dates, the other items as usual
+    assert_eq!(
+        frag_format!(packed_date, "%d%s", date(2024, 1, 15), "report").as_deref(),
+        Ok("%d%s__20240115__report")
+    );
+    assert_eq!(
+        frag_format!(packed_date, "%d%d:u8", &date(5, 12, 31), 7_u8).as_deref(),
+        Ok("%d%d__00051231__7")
+    );
+
+    // Bad: years out of the four digits can not be written
+    assert_eq!(
+        frag_format!(packed_date, "%d", date(10000, 1, 1)),
+        Err(FormatError::OutOfRange { value: 10000 })
+    );
+    assert_eq!(
+        frag_format!(packed_date, "%d", date(-1, 1, 1)),
+        Err(FormatError::OutOfRange { value: -1 })
+    );
+
+    // frag_format!(packed_date, roman, "%d", date(2024, 1, 15)); // Compile error
+}
+
+#[test]
+fn test_frag_parse_packed_date() {
+    // OK: eight digits of a valid date, the other items are parsed as usual
+    assert_eq!(
+        frag_parse!(packed_date, "%d%s", "%d%s__20240115__report"),
+        Some((date(2024, 1, 15), "report".to_string()))
+    );
+    assert_eq!(
+        frag_parse!(packed_date, "%d%d:u8?", "%d__20240229"),
+        Some((date(2024, 2, 29), None))
+    );
+    assert_eq!(frag_parse!(packed_date, "%d", "%d__00000101"), Some(date(0, 1, 1)));
+
+    // Bad: invalid dates, and numbers of other lengths
+    assert_eq!(frag_parse!(packed_date, "%d", "%d__20230230"), None);
+    assert_eq!(frag_parse!(packed_date, "%d", "%d__20230229"), None);
+    assert_eq!(frag_parse!(packed_date, "%d", "%d__20231301"), None);
+    assert_eq!(frag_parse!(packed_date, "%d", "%d__20230100"), None);
+    assert_eq!(frag_parse!(packed_date, "%d", "%d__2023011"), None);
+    assert_eq!(frag_parse!(packed_date, "%d", "%d__-2023011"), None);
+    assert_eq!(frag_parse!(packed_date, "%d", "%d__202301151"), None);
+
+    // OK: round trip
+    for &value in [date(2024, 1, 15), date(1999, 12, 31), date(9999, 12, 31), date(0, 1, 1)].iter() {
+        let formatted = frag_format!(packed_date, "%d%s", value, "x").expect("in range");
+        assert_eq!(
+            frag_parse!(packed_date, "%d%s", formatted),
+            Some((value, "x".to_string()))
+        );
+    }
+
+    // frag_parse!(packed_date, human_duration, "%d", "%d__20240115"); // Compile error
+}
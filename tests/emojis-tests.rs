@@ -0,0 +1,45 @@
+#![cfg(feature = "emojis")]
+
+use fragstrings::{frag_format, frag_parse};
+use std::collections::BTreeSet;
+
+#[test]
+fn test_frag_format_emoji() {
+    // OK: shortcodes are replaced, unknown ones are left as is
+    assert_eq!(frag_format!(emoji, "%s%d", "hi :wave:", 42), "%s%d__hi \u{1f44b}__42");
+    assert_eq!(frag_format!(emoji, "%s", ":+1::frown:"), "%s__\u{1f44d}:frown:");
+    assert_eq!(frag_format!(emoji, "%s", "a:zz:c"), "%s__a:zz:c");
+
+    // OK: emoji go before the other encoding, into custom types and sets
+    assert_eq!(
+        frag_format!(emoji, html_encode, "%s", ":smile: & co"),
+        "%s__\u{1f604} &amp; co"
+    );
+    let set = vec![":fire:".to_string()].into_iter().collect::<BTreeSet<_>>();
+    assert_eq!(frag_format!(emoji, "%s{set}", set), "%s__\u{1f525}");
+}
+
+#[test]
+fn test_frag_parse_demoji() {
+    // OK: emoji are replaced, the ones without shortcodes and other characters are left as is
+    assert_eq!(
+        frag_parse!(demoji, "%s%d", "%s%d__hi \u{1f44b}__42"),
+        Some(("hi :wave:".to_string(), 42))
+    );
+    assert_eq!(frag_parse!(demoji, "%s", "%s__привет"), Some("привет".to_string()));
+
+    // OK: emoji go after the other decoding
+    assert_eq!(
+        frag_parse!(demoji, html_decode, "%s", "%s__&#128516; &amp; co"),
+        Some(":smile: & co".to_string())
+    );
+
+    // OK: round trip
+    let value = frag_format!(emoji, "%s%s", ":thumbsup: :smile:", "x");
+    assert_eq!(
+        frag_parse!(demoji, "%s%s?", value),
+        Some((":+1: :smile:".to_string(), Some("x".to_string())))
+    );
+
+    // fragstrings::frag_parse_ref!(demoji, "%s", "%s__foo"); // Compile error
+}
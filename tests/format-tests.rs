@@ -1,4 +1,4 @@
-use format_procmacro::frag_format;
+use fragstrings_procmacro::frag_format;
 
 #[test]
 fn test_frag_format() {
@@ -16,6 +16,7 @@ fn test_frag_format() {
     // frag_format!("%d?", 42); // Compile error
     // frag_format!("%s%d?", 42); // Compile error
     // frag_format!("%s%d?*", 42); // Compile error
+    // frag_format!("%P", "/tmp"); // Compile error
 
     assert_eq!(frag_format!("%s", "test"), "%s__test");
     assert_eq!(frag_format!("%d", 42), "%d__42");
@@ -34,7 +35,31 @@ fn test_frag_format() {
     assert_eq!(frag_format!(r#"%s"#, "test"), "%s__test");
     assert_eq!(frag_format!(r##"%s"##, "test"), "%s__test");
     assert_eq!(frag_format!(r###"%s"###, "test"), "%s__test");
-    assert_eq!(frag_format!(b"%s", "test"), "%s__test");
+    // Only str literals are format strings
+    // frag_format!(b"%s", "test"); // Compile error
+    // frag_format!(br"%s", "test"); // Compile error
+    // frag_format!(c"%s", "test"); // Compile error
+    // frag_format!('s', "test"); // Compile error
+    // frag_format!(42, "test"); // Compile error
+    assert_eq!(frag_format!("%s\x25d", "test", 42), "%s%d__test__42");
+    assert_eq!(frag_format!("%s\u{25}d", "test", 42), "%s%d__test__42");
+    // Adjacent literals are concatenated, whatever their kinds are
+    assert_eq!(
+        frag_format!("%s%s%d" "%s%s", "a", "b", 1, "c", "d"),
+        "%s%s%d%s%s__a__b__1__c__d"
+    );
+    assert_eq!(
+        frag_format!(
+            "%s%d"
+            r"%s"
+            "%d",
+            "a", 1, "b", 2
+        ),
+        "%s%d%s%d__a__1__b__2"
+    );
+    // frag_format!("%s" x "%d", "a", 1); // Compile error
+    // frag_format!("%s" b"%d", "a", 1); // Compile error
+    assert_eq!(frag_format!(r#"%s"#, "привет 😀"), "%s__привет 😀");
 
     assert_eq!(frag_format!("%d", 2 + 2), "%d__4");
     assert_eq!(frag_format!("%d", (2 + 2) * 2), "%d__8");
@@ -51,3 +76,806 @@ fn test_frag_format() {
 fn int_fn(a: i32, b: i32) -> i32 {
     a + b
 }
+
+#[test]
+fn test_frag_format_checked() {
+    // frag_format!(checked = 1, "%s", "test"); // Compile error
+    // frag_format!(unchecked, "%s", "test"); // Compile error
+
+    assert_eq!(frag_format!(checked, "%s", "test"), "%s%d__test__4078901278");
+    assert_eq!(frag_format!(checked, "%s%d", "foo", 42), "%s%d%d__foo__42__4234519149");
+
+    let checksum = fragstrings::__private::crc32(b"%s%d%d__foo__-1");
+    assert_eq!(
+        frag_format!(checked, "%s%d", "foo", -1),
+        format!("%s%d%d__foo__-1__{}", checksum)
+    );
+}
+
+#[test]
+fn test_frag_format_versioned() {
+    // frag_format!(version, "%s", "test"); // Compile error
+    // frag_format!(version = 0, "%s", "test"); // Compile error
+    // frag_format!(version = -1, "%s", "test"); // Compile error
+    // frag_format!(version = [1, 2], "%s", "test"); // Compile error
+
+    assert_eq!(frag_format!(version = 1, "%s", "test"), "%s%s__v1__test");
+    assert_eq!(
+        frag_format!(version = 2, "%s%d%s", "foo", 42, "bar"),
+        "%s%s%d%s__v2__foo__42__bar"
+    );
+    assert_eq!(frag_format!(version = 1_000, "%d", 42), "%s%d__v1000__42");
+
+    let checksum = fragstrings::__private::crc32(b"%s%d%d__v3__42");
+    assert_eq!(
+        frag_format!(version = 3, checked, "%d", 42),
+        format!("%s%d%d__v3__42__{}", checksum)
+    );
+}
+
+#[test]
+fn test_frag_format_html_encode() {
+    assert_eq!(frag_format!(html_encode, "%s", "test"), "%s__test");
+    assert_eq!(
+        frag_format!(html_encode, "%s%d%s", "<a href=\"x\">", 42, "Tom & Jerry's"),
+        "%s%d%s__&lt;a href=&quot;x&quot;&gt;__42__Tom &amp; Jerry&#39;s"
+    );
+    let data_string = "a&b".to_string();
+    assert_eq!(frag_format!(html_encode, "%s", data_string), "%s__a&amp;b");
+}
+
+#[test]
+fn test_frag_format_quote_spaces() {
+    assert_eq!(frag_format!(quote_spaces, "%s", "test"), "%s__test");
+    assert_eq!(
+        frag_format!(quote_spaces, "%s%d%s", "hello world", 42, r#"say "hi" \o/"#),
+        r#"%s%d%s__"hello world"__42__"say \"hi\" \\o/""#
+    );
+    assert_eq!(frag_format!(quote_spaces, "%s", r#""foo""#), r#"%s__"\"foo\"""#);
+    // Quotes go after the other encoding, which has no spaces to quote
+    assert_eq!(
+        frag_format!(quote_spaces, html_encode, "%s", "a b\""),
+        r#"%s__"a b&quot;""#
+    );
+}
+
+#[test]
+fn test_frag_format_custom_types() {
+    use std::fmt;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[derive(Copy, Clone)]
+    enum Color {
+        Red,
+        Green,
+    }
+
+    impl fmt::Display for Color {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str(match self {
+                Color::Red => "red",
+                Color::Green => "green",
+            })
+        }
+    }
+
+    // frag_format!("%s:Color", "red"); // Compile error
+    // frag_format!("%s:", Color::Red); // Compile error
+
+    assert_eq!(frag_format!("%s:Color%d", Color::Red, 42), "%s%d__red__42");
+    assert_eq!(frag_format!("%s%s:Color", "foo", Color::Green), "%s%s__foo__green");
+    assert_eq!(frag_format!("%d:u8", 255u8), "%d__255");
+    assert_eq!(
+        frag_format!("%s:str%s:str", "foo", String::from("bar")),
+        "%s%s__foo__bar"
+    );
+    assert_eq!(
+        frag_format!("%s:std::net::IpAddr", IpAddr::V4(Ipv4Addr::LOCALHOST)),
+        "%s__127.0.0.1"
+    );
+
+    // OK: with other options
+    assert_eq!(frag_format!(version = 1, "%s:Color", Color::Red), "%s%s__v1__red");
+
+    struct Html;
+
+    impl fmt::Display for Html {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("<b>")
+        }
+    }
+
+    assert_eq!(frag_format!(html_encode, "%s:Html", Html), "%s__&lt;b&gt;");
+}
+
+#[test]
+fn test_frag_format_base62() {
+    // frag_format!("%s62", 42); // Compile error
+    // frag_format!("%s62:Color", "test"); // Compile error
+
+    assert_eq!(frag_format!("%s62", b"Hello"), "%s__5TP3P3v");
+    assert_eq!(frag_format!("%s62", "Hello"), "%s__5TP3P3v");
+    assert_eq!(frag_format!("%s62%d", vec![0u8, 0, 1], 42), "%s%d__001__42");
+    assert_eq!(frag_format!("%s%s62", "foo", [0u8; 0]), "%s%s__foo__");
+
+    // Underscores never appear in the encoded value
+    let data = (0..=255).collect::<Vec<u8>>();
+    let value = frag_format!("%s62", data);
+    assert!(value["%s__".len()..].bytes().all(|ch| ch.is_ascii_alphanumeric()));
+}
+
+#[test]
+fn test_frag_format_hex16() {
+    // frag_format!("%s:hex16{4}", [1u8, 2, 3]); // Compile error: wrong size
+    // frag_format!("%s:hex16{0}", [0u8; 0]); // Compile error
+    // frag_format!("%d:hex16{4}", [0u8; 4]); // Compile error
+
+    assert_eq!(
+        frag_format!("%s:hex16{4}%d", [0xde, 0xad, 0xbe, 0xef], 42),
+        "%s%d__deadbeef__42"
+    );
+    let mac = [0x00_u8, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e];
+    assert_eq!(frag_format!("%s%s:hex16{6}", "eth0", mac), "%s%s__eth0__001a2b3c4d5e");
+    assert_eq!(
+        frag_format!(
+            html_encode,
+            null_sentinel = "NULL",
+            "%s:hex16{1}%s:hex16{1}?",
+            [255],
+            None::<[u8; 1]>
+        ),
+        "%s%s__ff__NULL"
+    );
+}
+
+#[test]
+fn test_frag_format_max_bytes() {
+    use fragstrings::FormatError;
+
+    // frag_format!(max_bytes, "%s", "test"); // Compile error
+    // frag_format!(max_bytes = 0, "%s", "test"); // Compile error
+    // frag_format!(max_bytes = 7, "%s", "test"); // Compile error: literals are checked at compile time
+    // frag_format!(max_bytes = 13, "%s%d", "test", -1); // Compile error
+    // frag_format!(max_bytes = 10, checked, "%s", "test"); // Compile error
+
+    // OK: exactly at the limit
+    assert_eq!(frag_format!(max_bytes = 8, "%s", "test"), Ok("%s__test".to_string()));
+    assert_eq!(
+        frag_format!(max_bytes = 14, "%s%d", "test", -1),
+        Ok("%s%d__test__-1".to_string())
+    );
+    assert_eq!(
+        frag_format!(max_bytes = 27, checked, "%s%d", "foo", 42),
+        Ok("%s%d%d__foo__42__4234519149".to_string())
+    );
+
+    // Bad: one byte over the limit
+    let value = "test";
+    assert_eq!(frag_format!(max_bytes = 8, "%s", value), Ok("%s__test".to_string()));
+    assert_eq!(
+        frag_format!(max_bytes = 7, "%s", value),
+        Err(FormatError::TooLong { len: 8, max_bytes: 7 })
+    );
+    assert_eq!(
+        frag_format!(max_bytes = 26, checked, "%s%d", "foo", 40 + 2),
+        Err(FormatError::TooLong { len: 27, max_bytes: 26 })
+    );
+
+    // OK: lengths are in bytes, not in chars
+    let value = "привет";
+    assert_eq!(frag_format!(max_bytes = 16, "%s", value), Ok("%s__привет".to_string()));
+    assert_eq!(
+        frag_format!(max_bytes = 15, "%s", value),
+        Err(FormatError::TooLong { len: 16, max_bytes: 15 })
+    );
+
+    // OK: Waves key limit
+    let long = "x".repeat(400);
+    assert!(frag_format!(max_bytes = 400, "%s", long).is_err());
+    assert!(frag_format!(max_bytes = 400, "%s", long[..396]).is_ok());
+    assert!(frag_format!(max_bytes = 400, "%s", long[..397]).is_err());
+}
+
+#[test]
+fn test_frag_format_max_items() {
+    // frag_format!(max_items, "%s", "test"); // Compile error
+    // frag_format!(max_items = 1, "%s%d", "test", 42); // Compile error
+    // frag_format!(max_items = 2, checked, "%s%d", "test", 42); // Compile error
+    // frag_format!(max_items = 2, version = 1, "%s%d", "test", 42); // Compile error
+
+    assert_eq!(frag_format!(max_items = 2, "%s%d", "test", 42), "%s%d__test__42");
+    assert_eq!(frag_format!(max_items = 20, "%s%d", "test", 42), "%s%d__test__42");
+    assert_eq!(
+        frag_format!(max_items = 3, version = 1, "%s%d", "test", 42),
+        "%s%s%d__v1__test__42"
+    );
+}
+
+#[test]
+fn test_frag_format_null_sentinel() {
+    // frag_format!("%s%s?", "foo", Some("bar")); // Compile error: optional items need a sentinel
+    // frag_format!(null_sentinel = "", "%s%s?", "foo", Some("bar")); // Compile error
+    // frag_format!(null_sentinel = "A__B", "%s%s?", "foo", Some("bar")); // Compile error
+    // frag_format!(null_sentinel = "NULL", "%s%s?*", "foo", Some("bar")); // Compile error
+    // frag_format!(null_sentinel = "NULL", "%s?%s", Some("bar"), "foo"); // Compile error
+
+    // OK: absent values are written as the sentinel, so the number of fragments is always the same
+    assert_eq!(
+        frag_format!(null_sentinel = "NULL", "%s%s?%d?", "foo", Some("bar"), Some(42)),
+        "%s%s%d__foo__bar__42"
+    );
+    assert_eq!(
+        frag_format!(null_sentinel = "NULL", "%s%s?%d?", "foo", None::<&str>, None::<i32>),
+        "%s%s%d__foo__NULL__NULL"
+    );
+
+    let value = Some("bar".to_string());
+    assert_eq!(frag_format!(null_sentinel = "null", "%d%s?", 1, value), "%d%s__1__bar");
+
+    // OK: with other options
+    assert_eq!(
+        frag_format!(
+            null_sentinel = "NULL",
+            html_encode,
+            "%d%s?%s62?",
+            1,
+            Some("a&b"),
+            None::<Vec<u8>>
+        ),
+        "%d%s%s__1__a&amp;b__NULL"
+    );
+}
+
+#[test]
+fn test_frag_format_system_time() {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    // frag_format!("%d:SystemTime", 42); // Compile error
+    // frag_format!("%s:SystemTime", UNIX_EPOCH); // Compile error
+
+    let time = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    assert_eq!(frag_format!("%d:SystemTime%s", time, "desc"), "%d%s__1600000000__desc");
+    assert_eq!(frag_format!("%d:std::time::SystemTime", UNIX_EPOCH), "%d__0");
+
+    // Fractions of a second are truncated
+    let time = UNIX_EPOCH + Duration::from_millis(1_999);
+    assert_eq!(frag_format!("%d:SystemTime", time), "%d__1");
+
+    // Before the epoch
+    let time = UNIX_EPOCH - Duration::from_secs(42);
+    assert_eq!(frag_format!("%d:SystemTime", time), "%d__-42");
+
+    let now = SystemTime::now();
+    let secs = now.duration_since(UNIX_EPOCH).unwrap().as_secs();
+    assert_eq!(frag_format!("%d:SystemTime", now), format!("%d__{}", secs));
+}
+
+#[test]
+fn test_frag_format_non_zero_u64() {
+    // frag_format!("%d:NonZeroU64", 42_u64); // Compile error
+
+    let id = std::num::NonZeroU64::new(42).unwrap();
+    assert_eq!(frag_format!("%d:NonZeroU64%s", id, "foo"), "%d%s__42__foo");
+    assert_eq!(frag_format!("%d:core::num::NonZeroU64", id), "%d__42");
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_frag_format_trailing_comma() {
+    assert_eq!(frag_format!("%s%d", "foo", 42,), "%s%d__foo__42");
+    assert_eq!(frag_format!("%s", "foo",), "%s__foo");
+    assert_eq!(frag_format!(version = 2, "%d", 42,), "%s%d__v2__42");
+
+    // frag_format!("%s%d", "foo",, 42); // Compile error
+    // frag_format!("%s%d", "foo", 42,,); // Compile error
+    // frag_format!(, "%s", "foo"); // Compile error
+}
+
+#[test]
+#[allow(clippy::just_underscores_and_digits)]
+fn test_frag_format_hygiene() {
+    // User variables named like the ones of the generated code
+    let _0 = "zero";
+    let _1 = 1;
+    let value = "value";
+    let checksum = 42;
+
+    assert_eq!(frag_format!("%d%s", _1, _0), "%d%s__1__zero");
+    assert_eq!(frag_format!("%s%s%d", value, _0, _1), "%s%s%d__value__zero__1");
+    assert_eq!(
+        frag_format!(checked, "%s%d", value, checksum),
+        frag_format!(checked, "%s%d", "value", 42)
+    );
+    assert_eq!(frag_format!(max_bytes = 100, "%s", value), Ok("%s__value".to_string()));
+
+    // Nested macro in the arguments
+    let nested = frag_format!("%s%s", frag_format!("%d", _1), _0);
+    assert_eq!(nested, "%s%s__%d__1__zero");
+    assert_eq!(
+        frag_format!(null_sentinel = "NULL", "%s%s?", _0, Some(value)),
+        "%s%s__zero__value"
+    );
+}
+
+#[test]
+fn test_frag_format_str_set() {
+    use std::collections::BTreeSet;
+
+    let roles = ["writer", "admin", "reader", "admin"]
+        .iter()
+        .copied()
+        .collect::<BTreeSet<_>>();
+    assert_eq!(frag_format!("%s{set}%d", roles, 42), "%s%d__admin,reader,writer__42");
+    assert_eq!(frag_format!("%s{set}", &roles), "%s__admin,reader,writer");
+
+    let strings = roles.iter().map(|s| s.to_string()).collect::<BTreeSet<_>>();
+    assert_eq!(frag_format!("%d%s{set}", 1, strings), "%d%s__1__admin,reader,writer");
+    assert_eq!(frag_format!("%s{set}", BTreeSet::<String>::new()), "%s__");
+    assert_eq!(
+        frag_format!(
+            html_encode,
+            "%s{set}",
+            ["Tom & Jerry", "<b>"].iter().copied().collect::<BTreeSet<_>>()
+        ),
+        "%s__&lt;b&gt;,Tom &amp; Jerry"
+    );
+
+    // frag_format!("%s{set}", vec!["a", "b"]); // Compile error
+}
+
+#[test]
+fn test_frag_format_str_map() {
+    use std::collections::HashMap;
+
+    // Entries are sorted by key
+    let attrs = [("size", "XL"), ("color", "red")]
+        .iter()
+        .copied()
+        .collect::<HashMap<_, _>>();
+    assert_eq!(frag_format!("%s{map}%d", attrs, 42), "%s%d__color=red,size=XL__42");
+    assert_eq!(frag_format!("%s{map}", &attrs), "%s__color=red,size=XL");
+
+    let strings = attrs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(frag_format!("%d%s{map}", 1, strings), "%d%s__1__color=red,size=XL");
+    assert_eq!(frag_format!("%s{map}", HashMap::<String, String>::new()), "%s__");
+    assert_eq!(
+        frag_format!(
+            html_encode,
+            "%s{map}",
+            [("<b>", "Tom & Jerry")].iter().copied().collect::<HashMap<_, _>>()
+        ),
+        "%s__&lt;b&gt;=Tom &amp; Jerry"
+    );
+
+    // frag_format!("%s{map}", vec![("a", "b")]); // Compile error
+}
+
+// Generated code must not depend on the prelude, and must not be confused by local items named like the prelude ones
+#[allow(dead_code)]
+mod no_implicit_prelude {
+    #![no_implicit_prelude]
+
+    use ::fragstrings::frag_format;
+    use ::std::assert_eq;
+
+    struct Some;
+    struct None;
+    struct Ok;
+    struct Err;
+    struct Option;
+    struct Result;
+    struct String;
+    struct Vec;
+    trait Iterator {}
+    trait ToString {}
+    trait AsRef {}
+
+    #[test]
+    fn test_frag_format_no_implicit_prelude() {
+        assert_eq!(
+            frag_format!("%s%d%s62", "foo", 42, b"Hello"),
+            "%s%d%s__foo__42__5TP3P3v"
+        );
+        assert_eq!(
+            frag_format!(checked, version = 2, "%s", "foo"),
+            "%s%s%d__v2__foo__2502291126"
+        );
+        assert_eq!(
+            frag_format!(html_encode, "%s:u8%d:SystemTime", 42_u8, ::std::time::UNIX_EPOCH),
+            "%s%d__42__0"
+        );
+
+        let value = frag_format!(
+            null_sentinel = "NULL",
+            max_bytes = 100,
+            "%s%d?",
+            "foo",
+            ::std::option::Option::None::<i64>
+        );
+        assert_eq!(value.as_deref(), ::std::result::Result::Ok("%s%d__foo__NULL"));
+
+        let mut set = ::std::collections::BTreeSet::new();
+        set.insert("b");
+        set.insert("a");
+        assert_eq!(frag_format!(max_items = 1, "%s{set}", set), "%s__a,b");
+    }
+}
+
+#[test]
+fn test_frag_format_commas_in_arguments() {
+    fn sum<A: Into<i64>, B: Into<i64>>(a: A, b: B) -> i64 {
+        a.into() + b.into()
+    }
+
+    // Commas in turbofish, qualified paths and closure parameters do not split arguments
+    assert_eq!(frag_format!("%d%s", sum::<u8, u16>(1, 2), "x"), "%d%s__3__x");
+    assert_eq!(frag_format!("%d%d", <i64 as From<u8>>::from(4), 5), "%d%d__4__5");
+    let names = ["a", "b"];
+    assert_eq!(
+        frag_format!("%s%d", names.iter().fold(String::new(), |acc, s| acc + s), 1),
+        "%s%d__ab__1"
+    );
+    assert_eq!(
+        frag_format!("%d%s:bool", if names.len() < 3 { 1 } else { 2 }, names.len() > 1),
+        "%d%s__1__true"
+    );
+
+    // Nor do the ones in the type of a cast
+    struct W<A, B>(A, std::marker::PhantomData<B>);
+    impl<A: AsRef<str>, B> AsRef<str> for W<A, B> {
+        fn as_ref(&self) -> &str {
+            self.0.as_ref()
+        }
+    }
+    let w = W("w", std::marker::PhantomData);
+    assert_eq!(frag_format!("%s%d", &w as &W<&str, i64>, 2), "%s%d__w__2");
+}
+
+#[test]
+fn test_frag_format_roman() {
+    use fragstrings::FormatError;
+
+    // OK: plain integers are written as Roman numerals, the other items as usual
+    assert_eq!(frag_format!(roman, "%d%s", 14, "foo").as_deref(), Ok("%d%s__XIV__foo"));
+    assert_eq!(
+        frag_format!(roman, "%d%d:u8", 1994_u16, 7_u8).as_deref(),
+        Ok("%d%d__MCMXCIV__7")
+    );
+    assert_eq!(
+        frag_format!(roman, null_sentinel = "NULL", "%d%d?", 3999, None::<i64>).as_deref(),
+        Ok("%d%d__MMMCMXCIX__NULL")
+    );
+    assert_eq!(
+        frag_format!(roman, checked, "%d", 4).as_deref(),
+        Ok("%d%d__IV__3349407825")
+    );
+    assert_eq!(
+        frag_format!(roman, max_bytes = 8, "%d", 18).as_deref(),
+        Err(&FormatError::TooLong { len: 9, max_bytes: 8 })
+    );
+
+    // Bad: zero, negative and too large numbers can not be written
+    assert_eq!(frag_format!(roman, "%d", 0), Err(FormatError::OutOfRange { value: 0 }));
+    assert_eq!(
+        frag_format!(roman, "%d%d", 1, -5),
+        Err(FormatError::OutOfRange { value: -5 })
+    );
+    assert_eq!(
+        frag_format!(roman, null_sentinel = "NULL", "%d%d?", 1, Some(4000)),
+        Err(FormatError::OutOfRange { value: 4000 })
+    );
+    assert_eq!(
+        frag_format!(roman, max_bytes = 8, "%d", 4000),
+        Err(FormatError::OutOfRange { value: 4000 })
+    );
+}
+
+#[test]
+fn test_frag_format_human_duration() {
+    use fragstrings::FormatError;
+
+    // OK: plain integers are written as durations, the other items as usual
+    assert_eq!(
+        frag_format!(human_duration, "%d%s", 5400, "foo").as_deref(),
+        Ok("%d%s__1h 30m__foo")
+    );
+    assert_eq!(
+        frag_format!(human_duration, "%d%d:u8", 120_u32, 7_u8).as_deref(),
+        Ok("%d%d__2m__7")
+    );
+    assert_eq!(frag_format!(human_duration, "%d", 0).as_deref(), Ok("%d__0s"));
+    assert_eq!(frag_format!(human_duration, "%d", 3661).as_deref(), Ok("%d__1h 1m 1s"));
+
+    // Bad: negative durations can not be written
+    assert_eq!(
+        frag_format!(human_duration, "%d", -5),
+        Err(FormatError::OutOfRange { value: -5 })
+    );
+
+    // frag_format!(human_duration, roman, "%d", 5); // Compile error
+    // frag_format!(human_duration, packed_date, "%d", 5); // Compile error
+    // frag_format!(packed_date, "%d", date); // Compile error without the `chrono` feature
+}
+
+#[test]
+fn test_frag_format_cstring() {
+    use std::ffi::{CStr, CString};
+
+    // OK: C strings are written as strings, the other items as usual
+    let name = CString::new("foo").expect("no nul bytes");
+    assert_eq!(frag_format!(cstring, "%s%d", name, 42), "%s%d__foo__42");
+    let name: &CStr = &name;
+    assert_eq!(frag_format!(cstring, "%s%s:u8", name, 7_u8), "%s%s__foo__7");
+    assert_eq!(
+        frag_format!(cstring, html_encode, "%s", CString::new("a&b").expect("no nul bytes")),
+        "%s__a&amp;b"
+    );
+    assert_eq!(
+        frag_format!(cstring, null_sentinel = "NULL", "%s%s?", name, None::<CString>),
+        "%s%s__foo__NULL"
+    );
+
+    // OK: the ones which are not UTF-8 are empty
+    let bad = CString::new(vec![0xff, b'a']).expect("no nul bytes");
+    assert_eq!(frag_format!(cstring, "%s%d", bad, 42), "%s%d____42");
+
+    // frag_format!(cstring, "%s", "foo"); // Compile error: not a C string
+}
+
+#[test]
+fn test_frag_format_rgba() {
+    // OK: colors are written with the alpha, the other items are written as usual
+    assert_eq!(
+        frag_format!(rgba, "%s%d", (255, 136, 0, 128), 42),
+        "%s%d__#ff880080__42"
+    );
+    assert_eq!(frag_format!(rgba, "%s", (0, 0, 0, 255)), "%s__#000000ff");
+    let color = (1_u8, 2_u8, 3_u8, 4_u8);
+    assert_eq!(frag_format!(rgba, "%s%s:u8", color, 7_u8), "%s%s__#01020304__7");
+    assert_eq!(
+        frag_format!(rgba, null_sentinel = "NULL", "%s%s?", color, None::<(u8, u8, u8, u8)>),
+        "%s%s__#01020304__NULL"
+    );
+
+    // frag_format!(rgba, "%s", "#ff8800"); // Compile error: not a color
+    // frag_format!(rgba, cstring, "%s", (0, 0, 0, 0)); // Compile error
+}
+
+#[test]
+fn test_frag_format_path_safe() {
+    use fragstrings::FormatError;
+    use std::collections::BTreeSet;
+
+    // OK: values without forbidden characters, integers are always fine
+    assert_eq!(
+        frag_format!(path_safe, "%s%d", "foo bar.txt", -42).as_deref(),
+        Ok("%s%d__foo bar.txt__-42")
+    );
+    assert_eq!(
+        frag_format!(path_safe, null_sentinel = "NULL", "%s%s?", "100%", None::<&str>).as_deref(),
+        Ok("%s%s__100%__NULL")
+    );
+
+    // Bad: characters forbidden on Unix
+    let name = "etc/passwd".to_string();
+    assert_eq!(
+        frag_format!(path_safe, "%d%s", 1, name),
+        Err(FormatError::NotPathSafe { arg: "name" })
+    );
+    assert_eq!(
+        frag_format!(path_safe, "%s", "a\0b"),
+        Err(FormatError::NotPathSafe { arg: "\"a\\0b\"" })
+    );
+
+    // Bad: characters forbidden on Windows, also in the elements of sets
+    for name in ["a\\b", "a<b", "a>b", "C:", "\"a\"", "a|b", "a?", "*", "a\nb", "\x1f"].iter() {
+        assert!(frag_format!(path_safe, "%s", name).is_err(), "{:?}", name);
+        let set = std::iter::once(*name).collect::<BTreeSet<_>>();
+        assert!(frag_format!(path_safe, "%s{set}", set).is_err(), "{:?}", name);
+    }
+
+    // OK: forbidden characters and `%` are escaped instead
+    assert_eq!(
+        frag_format!(path_safe = escape, "%s%d", "C:\\Temp\\a/b 100%", 42),
+        "%s%d__C%3A%5CTemp%5Ca%2Fb 100%25__42"
+    );
+    assert_eq!(
+        frag_format!(path_safe = escape, "%s", "<\"a\"|b?*\0>"),
+        "%s__%3C%22a%22%7Cb%3F%2A%00%3E"
+    );
+
+    // frag_format!(path_safe, null_sentinel = "N/A", "%s?", None::<&str>); // Compile error
+    // frag_format!(path_safe = quote, "%s", "a"); // Compile error
+}
+
+#[test]
+fn test_frag_format_padding() {
+    use std::fmt;
+
+    // Writes itself ignoring the width, but is padded anyway
+    struct Html;
+
+    impl fmt::Display for Html {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("<b>")
+        }
+    }
+
+    // OK: values are padded to the width in chars, longer ones are written as is
+    assert_eq!(frag_format!("%s[pad:6]%d", "foo", 42), "%s%d__foo   __42");
+    assert_eq!(
+        frag_format!("%s[pad:6<]%s[pad:6>]%s[pad:6^]", "a", "b", "c"),
+        "%s%s%s__a     __     b__  c   "
+    );
+    assert_eq!(frag_format!("%s[pad:3]", "πρ"), "%s__πρ ");
+    assert_eq!(frag_format!("%s[pad:2]", "foobar"), "%s__foobar");
+    assert_eq!(frag_format!("%s:Html[pad:5>]", Html), "%s__  <b>");
+    assert_eq!(frag_format!(html_encode, "%s:Html[pad:8]", Html), "%s__&lt;b&gt;");
+    assert_eq!(
+        frag_format!(null_sentinel = "NULL", "%s%s[pad:6]?", "foo", None::<&str>),
+        "%s%s__foo__NULL  "
+    );
+
+    // frag_format!("%d[pad:6]", 42); // Compile error
+}
+
+#[test]
+fn test_frag_format_fixed_point() {
+    use fragstrings::FormatError;
+
+    // OK: plain numbers are scaled and rounded, the other items are written as usual
+    assert_eq!(
+        frag_format!(fixed_point = 2, "%d%s", 12.34, "EUR").as_deref(),
+        Ok("%d%s__1234__EUR")
+    );
+    assert_eq!(
+        frag_format!(fixed_point = 2, "%d%d:u8", 0.1 + 0.2, 7_u8).as_deref(),
+        Ok("%d%d__30__7")
+    );
+    assert_eq!(frag_format!(fixed_point = 3, "%d", -1.0005).as_deref(), Ok("%d__-1001"));
+    assert_eq!(frag_format!(fixed_point = 1, "%d", 42).as_deref(), Ok("%d__420"));
+    assert_eq!(
+        frag_format!(fixed_point = 18, "%d", 0.5).as_deref(),
+        Ok("%d__500000000000000000")
+    );
+
+    // Bad: numbers which are not finite, or too large
+    assert_eq!(
+        frag_format!(fixed_point = 2, "%d", f64::NAN),
+        Err(FormatError::FixedPointOutOfRange)
+    );
+    assert_eq!(
+        frag_format!(fixed_point = 2, "%d", f64::INFINITY),
+        Err(FormatError::FixedPointOutOfRange)
+    );
+    assert_eq!(
+        frag_format!(fixed_point = 2, "%d", 1e17),
+        Err(FormatError::FixedPointOutOfRange)
+    );
+
+    // frag_format!(fixed_point = 0, "%d", 1.0); // Compile error
+    // frag_format!(fixed_point = 19, "%d", 1.0); // Compile error
+    // frag_format!(fixed_point = 2, roman, "%d", 1.0); // Compile error
+}
+
+#[test]
+fn test_frag_format_percent() {
+    use fragstrings::FormatError;
+
+    // OK: shares are written as percentages with two decimals at most, the other items as usual
+    assert_eq!(
+        frag_format!(percent, "%d%s", 0.75, "done").as_deref(),
+        Ok("%d%s__75%__done")
+    );
+    assert_eq!(
+        frag_format!(percent, "%d%d:u8", 0.125, 7_u8).as_deref(),
+        Ok("%d%d__12.5%__7")
+    );
+    assert_eq!(frag_format!(percent, "%d", 0.12345).as_deref(), Ok("%d__12.35%"));
+    assert_eq!(frag_format!(percent, "%d", 1).as_deref(), Ok("%d__100%"));
+
+    // OK: out of range shares are clamped
+    assert_eq!(frag_format!(percent, "%d", 1.5).as_deref(), Ok("%d__100%"));
+    assert_eq!(frag_format!(percent, "%d", -0.5).as_deref(), Ok("%d__0%"));
+
+    // Bad: shares which are not numbers, or out of range ones in the strict mode
+    assert_eq!(
+        frag_format!(percent, "%d", f64::NAN),
+        Err(FormatError::FixedPointOutOfRange)
+    );
+    assert_eq!(
+        frag_format!(percent = strict, "%d", 1.5),
+        Err(FormatError::FixedPointOutOfRange)
+    );
+    assert_eq!(frag_format!(percent = strict, "%d", 0.5).as_deref(), Ok("%d__50%"));
+
+    // frag_format!(percent = clamp, "%d", 1.0); // Compile error
+    // frag_format!(percent, fixed_point = 2, "%d", 1.0); // Compile error
+}
+
+#[test]
+fn test_frag_format_bitmask() {
+    use fragstrings::FormatError;
+    use std::collections::{BTreeSet, HashSet};
+
+    // OK: bit positions are packed into integers, the other items are written as usual
+    let flags = [0_u8, 2, 3].iter().copied().collect::<HashSet<_>>();
+    assert_eq!(
+        frag_format!(bitmask, "%d%s", flags, "user").as_deref(),
+        Ok("%d%s__13__user")
+    );
+    assert_eq!(
+        frag_format!(bitmask, "%d%d:u8", [1_u8, 1], 7_u8).as_deref(),
+        Ok("%d%d__2__7")
+    );
+    assert_eq!(frag_format!(bitmask, "%d", Vec::<u8>::new()).as_deref(), Ok("%d__0"));
+    assert_eq!(
+        frag_format!(bitmask, "%d", [63_u8, 0].iter().copied().collect::<BTreeSet<_>>()).as_deref(),
+        Ok("%d__-9223372036854775807")
+    );
+
+    // Bad: positions which do not fit
+    assert_eq!(
+        frag_format!(bitmask, "%d", [1_u8, 64]),
+        Err(FormatError::OutOfRange { value: 64 })
+    );
+
+    // frag_format!(bitmask, fixed_point = 2, "%d", [1_u8]); // Compile error
+}
+
+#[test]
+fn test_frag_format_single_item() {
+    use std::collections::BTreeSet;
+
+    // Single item is appended to the descriptor, the same way as it is formatted otherwise
+    let foo = String::from("foo");
+    assert_eq!(frag_format!("%s", foo), "%s__foo");
+    assert_eq!(frag_format!("%s", ""), "%s__");
+    assert_eq!(frag_format!("%s", &foo[1..]), "%s__oo");
+    assert_eq!(frag_format!(html_encode, "%s", "<a>"), "%s__&lt;a&gt;");
+    assert_eq!(frag_format!(version = 3, "%s", "foo"), "%s%s__v3__foo");
+    assert_eq!(
+        frag_format!(checked, "%s", "foo"),
+        frag_format!(checked, "%s:String", foo)
+    );
+    let set = ["b", "a"].iter().copied().collect::<BTreeSet<_>>();
+    assert_eq!(frag_format!("%s{set}", set), "%s__a,b");
+    assert_eq!(frag_format!(max_bytes = 7, "%s", "foo"), Ok("%s__foo".to_owned()));
+    assert!(frag_format!(max_bytes = 6, "%s", foo).is_err());
+}
+
+#[test]
+#[cfg(feature = "small-strings")]
+fn test_frag_format_small() {
+    use fragstrings_procmacro::frag_format_small;
+
+    // OK: same contents as the `String` output, short values are inline
+    let value = frag_format_small!("%s%d", "foo", 42);
+    assert_eq!(value, frag_format!("%s%d", "foo", 42));
+    assert!(value.is_inline());
+    let long = "x".repeat(64);
+    let value = frag_format_small!("%s%d", long, 42);
+    assert_eq!(value, frag_format!("%s%d", long, 42));
+    assert!(!value.is_inline());
+
+    // OK: options work the same way
+    assert_eq!(
+        frag_format_small!(checked, version = 2, html_encode, "%s", "<foo>"),
+        frag_format!(checked, version = 2, html_encode, "%s", "<foo>")
+    );
+    assert_eq!(
+        frag_format_small!(null_sentinel = "NULL", "%s%d?", "foo", None::<i64>),
+        "%s%d__foo__NULL"
+    );
+    let value = frag_format_small!(max_bytes = 16, "%s%d", "foo", 42).expect("short enough");
+    assert_eq!(value, "%s%d__foo__42");
+    assert!(frag_format_small!(max_bytes = 16, "%s%d", long, 42).is_err());
+
+    // OK: round trip
+    let value = frag_format_small!("%s%d", "foo", 42);
+    let parsed = fragstrings_procmacro::frag_parse!("%s%d", value);
+    assert_eq!(parsed, Some(("foo".to_owned(), 42)));
+}
@@ -0,0 +1,76 @@
+#![cfg(feature = "intern")]
+
+use fragstrings::intern::FragInterner;
+use fragstrings::{frag_parse, frag_parse_partial};
+use std::sync::Arc;
+use std::thread;
+
+#[test]
+fn test_frag_parse_intern() {
+    let interner = FragInterner::new();
+
+    // OK: repeated strings are shared, the other items are parsed as usual
+    let (a, n) = frag_parse!(intern = &interner, "%s%d", "%s%d__WAVES__1").unwrap();
+    let (b, m) = frag_parse!(intern = &interner, "%s%d", "%s%d__WAVES__2").unwrap();
+    assert_eq!((&*a, n, m), ("WAVES", 1, 2));
+    assert!(Arc::ptr_eq(&a, &b));
+
+    // OK: optional items, sets and decoded strings are interned too
+    let parsed = frag_parse!(
+        intern = &interner,
+        html_decode,
+        "%s{set}%s%s?",
+        "%s%s%s__b,a__x&amp;y__WAVES"
+    );
+    let (set, decoded, a) = parsed.unwrap();
+    assert_eq!(set.iter().map(|s| &**s).collect::<Vec<_>>(), ["a", "b"]);
+    assert_eq!(&*decoded, "x&y");
+    assert!(Arc::ptr_eq(a.as_ref().unwrap(), &b));
+    assert_eq!(interner.len(), 4);
+
+    // OK: partial parsing
+    let (a, n) = frag_parse_partial!(intern = &interner, "%s%d", "%s%d__WAVES__x").unwrap();
+    assert!(Arc::ptr_eq(&a.unwrap(), &b));
+    assert!(n.is_err());
+
+    // Bad: strings of malformed values are left unused, so they can be evicted
+    interner.clear();
+    assert_eq!(frag_parse!(intern = &interner, "%s%d", "%s%d__WAVES__x"), None);
+    assert_eq!(interner.evict_unused(), 1);
+    assert!(interner.is_empty());
+
+    // frag_parse!(intern = &interner, alloc = arena, "%s", "%s__foo"); // Compile error
+    // frag_parse!(intern = &interner, lazy, "%s", "%s__foo"); // Compile error
+    // frag_parse_ref!(intern = &interner, "%s", "%s__foo"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_intern_threads() {
+    let interner = FragInterner::with_max_entries(1_000);
+    let parsed = thread::scope(|scope| {
+        let threads = (0..8)
+            .map(|t| {
+                let interner = &interner;
+                scope.spawn(move || {
+                    (0..1_000)
+                        .map(|i| {
+                            let value = format!("%s%d__asset{}__{}", i % 10, t);
+                            frag_parse!(intern = interner, "%s%d", value).unwrap().0
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        threads.into_iter().map(|t| t.join().unwrap()).collect::<Vec<_>>()
+    });
+
+    // Every distinct string is stored once, and shared by all the threads
+    assert_eq!(interner.len(), 10);
+    let first = &parsed[0];
+    for values in &parsed {
+        for (i, value) in values.iter().enumerate() {
+            assert_eq!(&**value, format!("asset{}", i % 10));
+            assert!(Arc::ptr_eq(value, &first[i % 10]));
+        }
+    }
+}
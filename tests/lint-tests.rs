@@ -0,0 +1,162 @@
+// Expansions of the macros must not trigger any warnings at the call site,
+// which is checked by clippy with all the lints below denied.
+#![deny(warnings, clippy::all, clippy::pedantic)]
+
+use fragstrings::{
+    frag_format, frag_parse, frag_parse_arc, frag_parse_file_name, frag_parse_os, frag_parse_partial, frag_parse_ref,
+    frag_parse_strict, AuditLogger, ParseError, StringAllocator,
+};
+use std::collections::BTreeSet;
+use std::time::UNIX_EPOCH;
+
+struct Leaking;
+
+struct Silent;
+
+impl AuditLogger for Silent {
+    fn record(&self, _fmt: &str, _input: &str, _success: bool) {}
+}
+
+impl StringAllocator for Leaking {
+    fn alloc_str(&self, s: &str) -> &str {
+        Box::leak(s.to_owned().into_boxed_str())
+    }
+}
+
+#[test]
+fn test_frag_format_lints() {
+    let set = ["b", "a"].iter().copied().collect::<BTreeSet<_>>();
+    let value = frag_format!(
+        "%s%d%s62%s{set}%d:SystemTime%s:u8",
+        "foo",
+        42,
+        b"x",
+        set,
+        UNIX_EPOCH,
+        1_u8
+    );
+    assert_eq!(value, "%s%d%s%s%d%s__foo__42__1w__a,b__0__1");
+
+    let value = frag_format!(checked, version = 2, html_encode, max_items = 10, "%s", "foo");
+    assert_eq!(value, "%s%s%d__v2__foo__2502291126");
+
+    let value = frag_format!(max_bytes = 100, null_sentinel = "NULL", "%s%d?", "foo", None::<i64>);
+    assert_eq!(value.as_deref(), Ok("%s%d__foo__NULL"));
+
+    let value = frag_format!(fixed_point = 2, "%d%d:u8", 12.5, 1_u8);
+    assert_eq!(value.as_deref(), Ok("%d%d__1250__1"));
+
+    let value = frag_format!(percent, "%d%d:u8", 0.5, 1_u8);
+    assert_eq!(value.as_deref(), Ok("%d%d__50%__1"));
+
+    let value = frag_format!(path_safe, "%s%s:u8", "a b", 1_u8);
+    assert_eq!(value.as_deref(), Ok("%s%s__a b__1"));
+
+    let value = frag_format!(path_safe = escape, "%s%s{set}", "a/b", set);
+    assert_eq!(value, "%s%s__a%2Fb__a,b");
+
+    let value = frag_format!(quote_spaces, "%s%s:u8", "a b", 1_u8);
+    assert_eq!(value, "%s%s__\"a b\"__1");
+
+    let value = frag_format!(rgba, "%s%d", (255, 136, 0, 255), 1);
+    assert_eq!(value, "%s%d__#ff8800ff__1");
+
+    let value = frag_format!("%s[pad:4]%s:u8[pad:2>]", "foo", 1_u8);
+    assert_eq!(value, "%s%s__foo __ 1");
+
+    let value = frag_format!(bitmask, "%d%s", [3_u8], "foo");
+    assert_eq!(value.as_deref(), Ok("%d%s__8__foo"));
+}
+
+#[test]
+fn test_frag_parse_lints() {
+    let input = "%s%d%s%d__foo__42__/tmp__7";
+
+    let parsed = frag_parse!("%s%d%P%d:u8?*", input);
+    assert!(parsed.is_some());
+    let parsed = frag_parse!("%s:str[..3]%d", "%s%d__quux__42");
+    assert!(parsed.is_none());
+    let parsed = frag_parse!(r"%s[re=r'^[A-Z]{2,3}$']%d", input);
+    assert!(parsed.is_none());
+    let parsed = frag_parse!("%s{trie:foo,bar}%d%s{trie:/tmp}?*", input);
+    assert!(parsed.is_some());
+    let parsed = frag_parse!("%s:hex16{2}%d*", "%s%d__0a0b__1");
+    assert!(parsed.is_some());
+    let parsed = frag_parse!("%s[slice:1..]%d%s[slice:..2]?*", input);
+    assert!(parsed.is_some());
+    let parsed = frag_parse!("%s[pad:4]%d%s[pad:4^]?*", input);
+    assert!(parsed.is_some());
+    let parsed = frag_parse!(trie_threshold = 1, "%s{trie:foo,bar,baz}%d*", input);
+    assert!(parsed.is_some());
+    let parsed = frag_parse!("%s62%s{set}%d:SystemTime", "%s%s%d__1w__a,b__0");
+    assert!(parsed.is_some());
+    let parsed = frag_parse!(
+        html_decode,
+        hostname,
+        ident,
+        prime,
+        max_items = 5,
+        "%s%d",
+        "%s%d__foo__7"
+    );
+    assert!(parsed.is_some());
+    let parsed = frag_parse!(
+        null_sentinel = "NULL",
+        version = [0, 2],
+        "%s%d?",
+        "%s%s%d__v2__foo__NULL"
+    );
+    assert_eq!(parsed, Some((2, "foo".to_owned(), None)));
+    let parsed = frag_parse!(checked, version = 2, "%s", "%s%s%d__v2__foo__2502291126");
+    assert_eq!(parsed, Ok("foo".to_owned()));
+    let parsed = frag_parse!(union_sep, null_sentinel = "NULL", "%s%d?", "%s%d-foo-NULL");
+    assert_eq!(parsed, Some(("-", ("foo".to_owned(), None))));
+    let parsed = frag_parse!(checked, "%s", "%s%d__foo__1");
+    assert_eq!(parsed, Err(ParseError::BadChecksum));
+    let calls = std::sync::atomic::AtomicU64::new(0);
+    let parsed = frag_parse!(count = &calls, count_failures = &calls, "%s%d*", input);
+    assert!(parsed.is_some());
+    let parsed = frag_parse!(verbose, checked, "%s", input);
+    assert!(parsed.is_err());
+    let parsed = frag_parse!(audit = &Silent, "%s%d*", input);
+    assert!(parsed.is_some());
+    let parsed = frag_parse!(trace, checked, version = [0, 2], "%s", input);
+    assert!(parsed.is_err());
+    let parsed = frag_parse!(on_fail = |fmt, input| assert_ne!(fmt, input), "%s%d*", input);
+    assert!(parsed.is_some());
+    let parsed = frag_parse!(lazy, redact, "%s%d%P%d:u8?*", input);
+    assert!(parsed.is_some());
+    let parsed = frag_parse!(radix = auto, prime, "%s%d%d:u8?", "%s%d%d__foo__0x1f__7");
+    assert!(parsed.is_some());
+    let parsed = frag_parse!(cstring, html_decode, "%s%d%s?*", input);
+    assert!(parsed.is_some());
+    let parsed = frag_parse!(fixed_point = 2, prime, "%d%s*", "%d%s__1231__foo");
+    assert!(parsed.is_some());
+    let parsed = frag_parse!(percent = strict, prime, "%d%s*", "%d%s__12.5%__foo");
+    assert!(parsed.is_some());
+    let parsed = frag_parse!(path_safe, "%s%d%s?*", input);
+    assert!(parsed.is_none());
+    let parsed = frag_parse!(path_safe = escape, "%s%d%s{set}?*", input);
+    assert!(parsed.is_some());
+    let parsed = frag_parse!(unquote, html_decode, "%s%d?*", input);
+    assert!(parsed.is_some());
+    let parsed = frag_parse!(rgba, html_decode, "%s%d?*", "%s%d__#FF8800__1");
+    assert!(parsed.is_some());
+    let parsed = frag_parse!(bitmask, radix = auto, "%d%s*", "%d%s__0x1f__foo");
+    assert!(parsed.is_some());
+    let parsed = frag_parse!(alloc = Leaking, "%s%d*", input);
+    assert!(parsed.is_some());
+
+    let parsed = frag_parse_ref!("%s%d%P*", input);
+    assert!(parsed.is_some());
+    let parsed = frag_parse_arc!("%s%d%P*", input);
+    assert!(parsed.is_some());
+    let parsed = frag_parse_strict!("%s%d%P%d:u8?*", input);
+    assert!(parsed.is_some());
+    let parsed = frag_parse_partial!(prime, "%s%d%P%d:u8?*", input);
+    assert!(parsed.is_some());
+    let parsed = frag_parse_os!(checked, "%s%d%P", std::path::Path::new(input));
+    assert!(parsed.is_err());
+    let parsed = frag_parse_file_name!("%s%d%P%d:u8?*", std::env::temp_dir().join(input));
+    assert!(parsed.is_none());
+}
@@ -1,4 +1,4 @@
-use parse_procmacro::frag_parse;
+use fragstrings_procmacro::frag_parse;
 
 #[test]
 fn test_frag_parse() {
@@ -71,7 +71,9 @@ fn test_frag_parse() {
     assert!(frag_parse!("%d", "%d__").is_none());
     assert!(frag_parse!("%d", "%d__1").is_some());
     assert!(frag_parse!("%d", "%d__foo").is_none());
-    assert!(frag_parse!("%d", "%s__foo").is_none());
+    let mismatched = "%s__foo";
+    assert!(frag_parse!("%d", mismatched).is_none());
+    // frag_parse!("%d", "%s__foo"); // Compile error: literal value never matches
     assert!(frag_parse!("%s", "%s__foo").is_some());
     assert!(frag_parse!("%d%s", "%d%s__42").is_none());
     assert!(frag_parse!("%d%s", "%d%s__42__foo").is_some());
@@ -81,7 +83,15 @@ fn test_frag_parse() {
     assert!(frag_parse!(r#"%s"#, "%s__test").is_some());
     assert!(frag_parse!(r##"%s"##, "%s__test").is_some());
     assert!(frag_parse!(r###"%s"###, "%s__test").is_some());
-    assert!(frag_parse!(b"%s", "%s__test").is_some());
+    // Only str literals are format strings
+    // frag_parse!(b"%s", "%s__test"); // Compile error
+    // frag_parse!(c"%s", "%s__test"); // Compile error
+    // frag_parse!('s', "%s__test"); // Compile error
+    assert!(frag_parse!("%s\x25d", "%s%d__test__42").is_some());
+    // Adjacent literals are concatenated, whatever their kinds are
+    assert!(frag_parse!("%s%d" r"%s[re=r'\d+']" "%d", "%s%d%s%d__a__1__42__2").is_some());
+    assert!(frag_parse!("%s%d" r"%s[re=r'\d+']" "%d", "%s%d%s%d__a__1__b__2").is_none());
+    // frag_parse!("%s" x "%d", "%s%d__a__1"); // Compile error
 }
 
 #[test]
@@ -92,7 +102,8 @@ fn test_frag_parse_non_strict() {
     assert_eq!(frag2, 42);
 
     // Bad: extra parameters beyond described, no asterisk
-    assert!(frag_parse!("%s%d", "%s%d%s__test__42__foo").is_none());
+    let mismatched = "%s%d%s__test__42__foo";
+    assert!(frag_parse!("%s%d", mismatched).is_none());
 
     // OK: asterisk + no extra parameters
     let (frag1, frag2) = frag_parse!("%s%d*", "%s%d__test__42").expect("failed to parse");
@@ -155,7 +166,2247 @@ fn test_frag_parse_optional() {
     assert_eq!(frag2, Some(42));
 
     //TODO FIXME This is a known bug - parameter type mismatch must not be accepted
-    assert!(frag_parse!("%s%d", "%s%s__test__42").is_none());
+    let mismatched = "%s%s__test__42";
+    assert!(frag_parse!("%s%d", mismatched).is_none());
     //assert!(frag_parse!("%s%d?", "%s%s__test__42").is_none());
     //assert!(frag_parse!("%s%d?*", "%s%s__test__42").is_none());
 }
+
+#[test]
+fn test_frag_parse_checked() {
+    use fragstrings::{frag_format, ParseError};
+
+    // OK: round trip
+    let value = frag_format!(checked, "%s%d", "test", 42);
+    let (frag1, frag2) = frag_parse!(checked, "%s%d", value).expect("failed to parse");
+    assert_eq!(frag1, "test");
+    assert_eq!(frag2, 42);
+
+    // OK: open ending and optionals work as usual
+    let value = frag_format!(checked, "%s%d%s", "test", 42, "foo");
+    let (frag1, frag2) = frag_parse!(checked, "%s%d*", value).expect("failed to parse");
+    assert_eq!(frag1, "test");
+    assert_eq!(frag2, 42);
+    let (frag1, frag2, frag3) = frag_parse!(checked, "%s%d%s?", value).expect("failed to parse");
+    assert_eq!(frag1, "test");
+    assert_eq!(frag2, 42);
+    assert_eq!(frag3, Some("foo".into()));
+
+    // Bad: damaged value
+    assert_eq!(
+        frag_parse!(checked, "%s%d", "%s%d%d__test__43__4134012590"),
+        Err(ParseError::BadChecksum)
+    );
+    assert_eq!(
+        frag_parse!(checked, "%s%d", "%s%d%d__test__42__1"),
+        Err(ParseError::BadChecksum)
+    );
+    assert_eq!(
+        frag_parse!(checked, "%s%d", "%s%d%d__test__42__foo"),
+        Err(ParseError::BadChecksum)
+    );
+
+    // Bad: no checksum at all
+    let mismatched = "%s%d__test__42";
+    assert_eq!(frag_parse!(checked, "%s%d", mismatched), Err(ParseError::BadChecksum));
+    let mismatched = "%s";
+    assert_eq!(frag_parse!(checked, "%s", mismatched), Err(ParseError::BadChecksum));
+
+    // Bad: checksum is fine, but the value does not match the format
+    let value = frag_format!(checked, "%s%d", "test", 42);
+    assert_eq!(frag_parse!(checked, "%s%s", value), Err(ParseError::Mismatch));
+    assert_eq!(frag_parse!(checked, "%s", value), Err(ParseError::Mismatch));
+
+    // OK: plain open-ended reader sees the checksum as an extra fragment
+    let (frag1, frag2) = frag_parse!("%s%d*", value).expect("failed to parse");
+    assert_eq!(frag1, "test");
+    assert_eq!(frag2, 42);
+
+    // Bad: plain closed reader does not expect the checksum
+    assert!(frag_parse!("%s%d", value).is_none());
+}
+
+#[test]
+fn test_frag_parse_alloc() {
+    use fragstrings::StringAllocator;
+    use std::cell::Cell;
+
+    // Allocator which leaks all the strings and counts them
+    #[derive(Default)]
+    struct Arena {
+        count: Cell<usize>,
+    }
+
+    impl StringAllocator for Arena {
+        fn alloc_str(&self, s: &str) -> &str {
+            self.count.set(self.count.get() + 1);
+            Box::leak(s.to_owned().into_boxed_str())
+        }
+    }
+
+    let arena = Arena::default();
+
+    // OK: strings are allocated in the arena
+    let (frag1, frag2): (&str, i64) = frag_parse!(alloc = arena, "%s%d", "%s%d__test__42").expect("failed to parse");
+    assert_eq!(frag1, "test");
+    assert_eq!(frag2, 42);
+    assert_eq!(arena.count.get(), 1);
+
+    // OK: allocator by reference, optional items
+    let arena_ref = &arena;
+    let (frag1, frag2): (&str, Option<&str>) =
+        frag_parse!(alloc = arena_ref, "%s%s?", "%s%s__foo__bar").expect("failed to parse");
+    assert_eq!(frag1, "foo");
+    assert_eq!(frag2, Some("bar"));
+    assert_eq!(arena.count.get(), 3);
+
+    // OK: values outlive the input
+    let value = {
+        let input = "%s__test".to_string();
+        frag_parse!(alloc = &arena, "%s", input).expect("failed to parse")
+    };
+    assert_eq!(value, "test");
+
+    // Bad: mismatch
+    assert!(frag_parse!(alloc = arena, "%s%d", "%s%d__test__foo").is_none());
+
+    // OK: together with checked mode
+    let value = fragstrings::frag_format!(checked, "%s", "test");
+    assert_eq!(frag_parse!(alloc = arena, checked, "%s", value), Ok("test"));
+}
+
+#[test]
+fn test_frag_parse_versioned() {
+    // frag_parse!(version = 0, "%s", "%s__test"); // Compile error
+    // frag_parse!(version = [], "%s", "%s__test"); // Compile error
+    // frag_parse!(version = [1, 1], "%s", "%s__test"); // Compile error
+
+    // OK: required version
+    let (frag1, frag2) = frag_parse!(version = 2, "%s%d", "%s%s%d__v2__test__42").expect("failed to parse");
+    assert_eq!(frag1, "test");
+    assert_eq!(frag2, 42);
+
+    // Bad: other version, malformed version, legacy record
+    assert!(frag_parse!(version = 2, "%s%d", "%s%s%d__v1__test__42").is_none());
+    assert!(frag_parse!(version = 2, "%s%d", "%s%s%d__v02__test__42").is_none());
+    assert!(frag_parse!(version = 2, "%s%d", "%s%s%d__2__test__42").is_none());
+    let mismatched = "%d%s%d__2__test__42";
+    assert!(frag_parse!(version = 2, "%s%d", mismatched).is_none());
+    let mismatched = "%s%d__test__42";
+    assert!(frag_parse!(version = 2, "%s%d", mismatched).is_none());
+
+    // OK: set of versions, the matched one is returned first
+    let (version, frag1, frag2) =
+        frag_parse!(version = [1, 2], "%s%d", "%s%s%d__v1__test__42").expect("failed to parse");
+    assert_eq!(version, 1);
+    assert_eq!(frag1, "test");
+    assert_eq!(frag2, 42);
+    let (version, frag1) = frag_parse!(version = [1, 2], "%s*", "%s%s%d__v2__test__42").expect("failed to parse");
+    assert_eq!(version, 2);
+    assert_eq!(frag1, "test");
+    assert!(frag_parse!(version = [1, 2], "%s%d", "%s%s%d__v3__test__42").is_none());
+    let mismatched = "%s%d__test__42";
+    assert!(frag_parse!(version = [1, 2], "%s%d", mismatched).is_none());
+
+    // OK: legacy records are accepted as version 0
+    let (version, frag1, frag2) = frag_parse!(version = [0, 1], "%s%d", "%s%d__test__42").expect("failed to parse");
+    assert_eq!(version, 0);
+    assert_eq!(frag1, "test");
+    assert_eq!(frag2, 42);
+    let (version, frag1, frag2) =
+        frag_parse!(version = [0, 1], "%s%d", "%s%s%d__v1__test__42").expect("failed to parse");
+    assert_eq!(version, 1);
+    assert_eq!(frag1, "test");
+    assert_eq!(frag2, 42);
+
+    // OK: legacy record, which looks like versioned one
+    let (version, frag1, frag2) = frag_parse!(version = [0, 1], "%s%d", "%s%d__v1__42").expect("failed to parse");
+    assert_eq!(version, 0);
+    assert_eq!(frag1, "v1");
+    assert_eq!(frag2, 42);
+
+    // OK: round trip, together with checked mode
+    let value = fragstrings::frag_format!(version = 3, checked, "%s%d", "test", 42);
+    let (version, frag1, frag2) = frag_parse!(version = [0, 3], checked, "%s%d", value).expect("failed to parse");
+    assert_eq!(version, 3);
+    assert_eq!(frag1, "test");
+    assert_eq!(frag2, 42);
+}
+
+#[test]
+fn test_frag_parse_union_sep() {
+    // frag_parse!(union_sep, checked, "%s", "%s__test"); // Compile error
+
+    // OK: current and legacy separators
+    let parsed = frag_parse!(union_sep, "%s%d", "%s%d__test__42");
+    assert_eq!(parsed, Some(("__", ("test".to_string(), 42))));
+    let parsed = frag_parse!(union_sep, "%s%d", "%s%d-test-42");
+    assert_eq!(parsed, Some(("-", ("test".to_string(), 42))));
+    let parsed = frag_parse!(union_sep, "%s", "%s-test");
+    assert_eq!(parsed, Some(("-", "test".to_string())));
+    let parsed = frag_parse!(union_sep, "%s%d?*", "%s%d%s-a_b-1-c");
+    assert_eq!(parsed, Some(("-", ("a_b".to_string(), Some(1)))));
+    let parsed = frag_parse!(union_sep, version = [0, 2], "%s", "%s%s-v2-test");
+    assert_eq!(parsed, Some(("-", (2, "test".to_string()))));
+
+    // OK: the current separator goes first, so dashes are in the values
+    let parsed = frag_parse!(union_sep, "%s", "%s__a-b");
+    assert_eq!(parsed, Some(("__", "a-b".to_string())));
+
+    // Bad: mixed separators, extra fragments, negative numbers of legacy values
+    assert!(frag_parse!(union_sep, "%s%d", "%s%d__test-42").is_none());
+    assert!(frag_parse!(union_sep, "%s", "%s-a-b").is_none());
+    assert!(frag_parse!(union_sep, "%s%d", "%s%d-test--42").is_none());
+}
+
+#[test]
+fn test_frag_parse_html_decode() {
+    // OK: entities are decoded in string items only
+    let (frag1, frag2, frag3) =
+        frag_parse!(html_decode, "%s%d%s?", "%s%d%s__Tom &amp; Jerry&#39;s__42__&lt;b&gt;").expect("failed to parse");
+    assert_eq!(frag1, "Tom & Jerry's");
+    assert_eq!(frag2, 42);
+    assert_eq!(frag3, Some("<b>".into()));
+
+    // OK: without entities
+    let value = frag_parse!(html_decode, "%s", "%s__test").expect("failed to parse");
+    assert_eq!(value, "test");
+
+    // Bad: integers are not decoded
+    assert!(frag_parse!(html_decode, "%d", "%d__&#52;&#50;").is_none());
+
+    // OK: round trip
+    let value = fragstrings::frag_format!(html_encode, "%s%d", "<a href=\"x\">", 42);
+    let (frag1, frag2) = frag_parse!(html_decode, "%s%d", value).expect("failed to parse");
+    assert_eq!(frag1, "<a href=\"x\">");
+    assert_eq!(frag2, 42);
+}
+
+#[test]
+fn test_frag_parse_unquote() {
+    // OK: quotes are stripped from string items only, and the unquoted values are left as is
+    let (frag1, frag2, frag3) =
+        frag_parse!(unquote, "%s%d%s?", r#"%s%d%s__"hello world"__42__foo"#).expect("failed to parse");
+    assert_eq!(frag1, "hello world");
+    assert_eq!(frag2, 42);
+    assert_eq!(frag3, Some("foo".into()));
+
+    // OK: escaped quotes and backslashes, and a lone quote
+    let value = frag_parse!(unquote, "%s", r#"%s__"say \"hi\" \\o/""#).expect("failed to parse");
+    assert_eq!(value, r#"say "hi" \o/"#);
+    let value = frag_parse!(unquote, "%s", r#"%s__""#).expect("failed to parse");
+    assert_eq!(value, "\"");
+
+    // OK: together with HTML entities, quotes are stripped first
+    let value = frag_parse!(unquote, html_decode, "%s", r#"%s__"a b&quot;""#).expect("failed to parse");
+    assert_eq!(value, "a b\"");
+
+    // OK: round trip
+    let value = fragstrings::frag_format!(quote_spaces, "%s%s", "hello world", r#""foo""#);
+    let (frag1, frag2) = frag_parse!(unquote, "%s%s", value).expect("failed to parse");
+    assert_eq!(frag1, "hello world");
+    assert_eq!(frag2, r#""foo""#);
+
+    // frag_parse_ref!(unquote, "%s", "%s__foo"); // Compile error
+    // frag_parse!(unquote, "%s:str", "%s__foo"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_decode_path() {
+    use std::collections::BTreeSet;
+    use std::path::PathBuf;
+
+    // OK: percent-encoded bytes are decoded in string and path items
+    let (frag1, frag2, frag3) =
+        frag_parse!(decode_path, "%s%d%P", "%s%d%s__foo%2Fbar%20baz__42__%2Ftmp%2Ffoo").expect("failed to parse");
+    assert_eq!(frag1, "foo/bar baz");
+    assert_eq!(frag2, 42);
+    assert_eq!(frag3, PathBuf::from("/tmp/foo"));
+
+    // OK: set elements are decoded after splitting, malformed escapes are left as is
+    let frag1 = frag_parse!(decode_path, "%s{set}", "%s__a%2Cb,100%").expect("failed to parse");
+    assert_eq!(
+        frag1,
+        ["a,b", "100%"].iter().map(|s| s.to_string()).collect::<BTreeSet<_>>()
+    );
+
+    // OK: together with HTML entities, percent-encoded bytes are decoded first
+    let frag1 = frag_parse!(decode_path, html_decode, "%s", "%s__%26amp%3B").expect("failed to parse");
+    assert_eq!(frag1, "&");
+
+    // Bad: validation applies to the decoded value, and integers are not decoded
+    assert!(frag_parse!(decode_path, ident, "%s", "%s__foo%2Fbar").is_none());
+    assert!(frag_parse!(decode_path, "%d", "%d__%34%32").is_none());
+
+    // frag_parse!(decode_path, "%s:str", "%s__foo"); // Compile error
+    // fragstrings::frag_parse_ref!(decode_path, "%s", "%s__foo"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_path_safe() {
+    use fragstrings::frag_format;
+
+    let input = |s: &str| s.to_string(); // Not a literal, so that it is parsed at runtime
+
+    // OK: escaped values are decoded
+    let unsafe_name = "C:\\Temp\\a/b <c> \"d\" e|f? g* 100%\0\t";
+    let value = frag_format!(path_safe = escape, "%s%d", unsafe_name, 42);
+    assert_eq!(
+        frag_parse!(path_safe = escape, "%s%d", value),
+        Some((unsafe_name.to_string(), 42))
+    );
+    let value = frag_format!(path_safe, "%s%d", "foo bar.txt", 42).expect("path-safe value");
+    assert_eq!(
+        frag_parse!(path_safe, "%s%d", value),
+        Some(("foo bar.txt".to_string(), 42))
+    );
+
+    // Bad: characters forbidden on Unix and on Windows
+    for name in [
+        "a/b", "a\0b", "a\\b", "a<b", "a>b", "C:", "\"a\"", "a|b", "a?", "*", "a\nb",
+    ]
+    .iter()
+    {
+        assert!(
+            frag_parse!(path_safe, "%s", input(&format!("%s__{}", name))).is_none(),
+            "{:?}",
+            name
+        );
+    }
+
+    // fragstrings::frag_parse_ref!(path_safe = escape, "%s", "%s__foo"); // Compile error
+    // frag_parse!(path_safe = quote, "%s", "%s__foo"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_path() {
+    use std::path::{Path, PathBuf};
+
+    // OK: paths are strings in the value
+    let (frag1, frag2) = frag_parse!("%P%d", "%s%d__/tmp/foo__42").expect("failed to parse");
+    assert_eq!(frag1, PathBuf::from("/tmp/foo"));
+    assert_eq!(frag2, 42);
+
+    let (frag1, frag2) = frag_parse!("%s%P?", "%s__test").expect("failed to parse");
+    assert_eq!(frag1, "test");
+    assert_eq!(frag2, None);
+
+    // Bad: descriptor must be a string
+    let mismatched = "%P__/tmp/foo";
+    assert!(frag_parse!("%P", mismatched).is_none());
+    let mismatched = "%d__42";
+    assert!(frag_parse!("%P", mismatched).is_none());
+
+    // OK: with other options
+    let value = fragstrings::frag_format!(html_encode, "%s", "/tmp/a&b");
+    let path = frag_parse!(html_decode, "%P", value).expect("failed to parse");
+    assert_eq!(path, Path::new("/tmp/a&b"));
+}
+
+#[test]
+fn test_frag_parse_ref() {
+    use fragstrings::frag_parse_ref;
+    use std::path::Path;
+
+    // frag_parse_ref!(html_decode, "%s", "%s__test"); // Compile error
+    // frag_parse_ref!(alloc = arena, "%s", "%s__test"); // Compile error
+
+    // OK: values are borrowed from the input
+    let input = "%s%s%d%s__foo__/tmp/bar__42__baz".to_string();
+    let (frag1, frag2, frag3, frag4): (&str, &Path, i64, Option<&str>) =
+        frag_parse_ref!("%s%P%d%s?", input).expect("failed to parse");
+    assert_eq!(frag1, "foo");
+    assert_eq!(frag2, Path::new("/tmp/bar"));
+    assert_eq!(frag3, 42);
+    assert_eq!(frag4, Some("baz"));
+    assert!(std::ptr::eq(frag1.as_ptr(), input[10..].as_ptr()));
+
+    // OK: values outlive the macro call, but not the input
+    fn first_fragment(input: &str) -> Option<&str> {
+        frag_parse_ref!("%s*", input)
+    }
+    assert_eq!(first_fragment("%s%d__foo__42"), Some("foo"));
+
+    // Bad: same rules as usual
+    assert!(frag_parse_ref!("%s%d", "%s%d__foo__bar").is_none());
+    let mismatched = "%s%s__foo__bar";
+    assert!(frag_parse_ref!("%s", mismatched).is_none());
+
+    // OK: together with checked and versioned modes
+    let value = fragstrings::frag_format!(version = 2, checked, "%s", "foo");
+    assert_eq!(frag_parse_ref!(version = [1, 2], checked, "%s", value), Ok((2, "foo")));
+}
+
+#[test]
+fn test_frag_parse_custom_types() {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::str::FromStr;
+
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    enum Color {
+        Red,
+        Green,
+    }
+
+    impl FromStr for Color {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "red" => Ok(Color::Red),
+                "green" => Ok(Color::Green),
+                _ => Err(()),
+            }
+        }
+    }
+
+    // frag_parse!("%s:", "%s__red"); // Compile error
+    // frag_parse!("%s:Color?%d", "%s%d__red__42"); // Compile error
+    // frag_parse!("%P:Color", "%s__red"); // Compile error
+
+    // OK: custom types are parsed with `FromStr`
+    let (frag1, frag2) = frag_parse!("%s:Color%d", "%s%d__red__42").expect("failed to parse");
+    assert_eq!(frag1, Color::Red);
+    assert_eq!(frag2, 42);
+
+    let (frag1, frag2) = frag_parse!("%d:u8%s:std::net::IpAddr", "%d%s__255__127.0.0.1").expect("failed to parse");
+    assert_eq!(frag1, 255u8);
+    assert_eq!(frag2, IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+    // OK: integer types other than `i64`, including values out of its range
+    let (frag1, frag2) = frag_parse!("%d:u64%d:i32", "%d%d__18446744073709551615__-7").expect("failed to parse");
+    assert_eq!(frag1, u64::MAX);
+    assert_eq!(frag2, -7_i32);
+    assert!(frag_parse!("%d", "%d__18446744073709551615").is_none());
+
+    // Bad: value is not parseable
+    assert!(frag_parse!("%s:Color", "%s__blue").is_none());
+    assert!(frag_parse!("%d:u8", "%d__256").is_none());
+    assert!(frag_parse!("%d:u8", "%d__-1").is_none());
+
+    // Bad: descriptors are checked as usual
+    let mismatched = "%d__42";
+    assert!(frag_parse!("%s:Color", mismatched).is_none());
+    let mismatched = "%s__42";
+    assert!(frag_parse!("%d:u8", mismatched).is_none());
+
+    // OK: optional items
+    let (frag1, frag2) = frag_parse!("%s%s:Color?", "%s__foo").expect("failed to parse");
+    assert_eq!(frag1, "foo");
+    assert_eq!(frag2, None);
+
+    let (frag1, frag2) = frag_parse!("%s%s:Color?", "%s%s__foo__green").expect("failed to parse");
+    assert_eq!(frag1, "foo");
+    assert_eq!(frag2, Some(Color::Green));
+
+    assert!(frag_parse!("%s%s:Color?", "%s%s__foo__blue").is_none());
+
+    // OK: with other options
+    let value = fragstrings::frag_format!(html_encode, "%s", "a&b");
+    let frag1 = frag_parse!(html_decode, "%s:String", value).expect("failed to parse");
+    assert_eq!(frag1, "a&b");
+
+    let frag1 = fragstrings::frag_parse_ref!("%s:Color", "%s__red").expect("failed to parse");
+    assert_eq!(frag1, Color::Red);
+}
+
+#[test]
+fn test_frag_parse_base62() {
+    // OK: bytes are decoded
+    let (frag1, frag2) = frag_parse!("%s62%d", "%s%d__5TP3P3v__42").expect("failed to parse");
+    assert_eq!(frag1, b"Hello".to_vec());
+    assert_eq!(frag2, 42);
+
+    let frag1 = frag_parse!("%s62", "%s__001").expect("failed to parse");
+    assert_eq!(frag1, vec![0, 0, 1]);
+
+    let frag1 = frag_parse!("%s62", "%s__").expect("failed to parse");
+    assert!(frag1.is_empty());
+
+    // Bad: not a base62 value
+    assert!(frag_parse!("%s62", "%s__a-b").is_none());
+    assert!(frag_parse!("%s62", "%s__ab==").is_none());
+
+    // Bad: descriptor must be a string
+    let mismatched = "%d__42";
+    assert!(frag_parse!("%s62", mismatched).is_none());
+
+    // OK: optional items
+    let (frag1, frag2) = frag_parse!("%s%s62?", "%s__foo").expect("failed to parse");
+    assert_eq!(frag1, "foo");
+    assert_eq!(frag2, None);
+
+    // OK: round trip, together with other options
+    let data = (0..=255).rev().collect::<Vec<u8>>();
+    let value = fragstrings::frag_format!(checked, "%s62%s", data, "foo");
+    let (frag1, frag2) = frag_parse!(checked, "%s62%s", value).expect("failed to parse");
+    assert_eq!(frag1, data);
+    assert_eq!(frag2, "foo");
+
+    let (frag1, frag2) = fragstrings::frag_parse_ref!("%s62%s", "%s%s__5TP3P3v__foo").expect("failed to parse");
+    assert_eq!(frag1, b"Hello".to_vec());
+    assert_eq!(frag2, "foo");
+}
+
+#[test]
+fn test_frag_parse_hex16() {
+    // frag_parse!("%s:hex16{65}", "%s__00"); // Compile error
+    // frag_parse!("%s:hex16{x}", "%s__00"); // Compile error
+
+    // OK: bytes are decoded, in any case
+    let (frag1, frag2) = frag_parse!("%s:hex16{4}%d", "%s%d__deadBEEF__42").expect("failed to parse");
+    assert_eq!(frag1, [0xde, 0xad, 0xbe, 0xef]);
+    assert_eq!(frag2, 42);
+
+    // Bad: exactly two digits per byte
+    assert!(frag_parse!("%s:hex16{4}", "%s__deadbee").is_none());
+    assert!(frag_parse!("%s:hex16{4}", "%s__deadbeef00").is_none());
+    assert!(frag_parse!("%s:hex16{4}", "%s__deadbeeg").is_none());
+    assert!(frag_parse!("%s:hex16{1}", "%s__+f").is_none());
+
+    // OK: optional items and borrowed values
+    let (frag1, frag2) = frag_parse!("%s%s:hex16{2}?", "%s__foo").expect("failed to parse");
+    assert_eq!(frag1, "foo");
+    assert_eq!(frag2, None);
+    let (frag1, frag2) = fragstrings::frag_parse_ref!("%s:hex16{2}%s", "%s%s__0a0b__foo").expect("failed to parse");
+    assert_eq!(frag1, [10, 11]);
+    assert_eq!(frag2, "foo");
+
+    // OK: round trip
+    let digest = [0x5a_u8; 32];
+    let value = fragstrings::frag_format!("%s:hex16{32}%d", digest, 7);
+    assert_eq!(frag_parse!("%s:hex16{32}%d", value), Some((digest, 7)));
+}
+
+#[test]
+fn test_frag_parse_hostname() {
+    // OK: all string values are hostnames
+    let (frag1, frag2) = frag_parse!(hostname, "%s%d", "%s%d__node-1.example.com__6869").expect("failed to parse");
+    assert_eq!(frag1, "node-1.example.com");
+    assert_eq!(frag2, 6869);
+
+    // Bad: not a hostname
+    assert!(frag_parse!(hostname, "%s", "%s__").is_none());
+    assert!(frag_parse!(hostname, "%s", "%s__example.com.").is_none());
+    assert!(frag_parse!(hostname, "%s", "%s__-example.com").is_none());
+    assert!(frag_parse!(hostname, "%s", "%s__exa_mple.com").is_none());
+    assert!(frag_parse!(hostname, "%s%s", "%s%s__example.com__foo bar").is_none());
+
+    // OK: optional items are validated only if present
+    let (frag1, frag2) = frag_parse!(hostname, "%s%s?", "%s__localhost").expect("failed to parse");
+    assert_eq!(frag1, "localhost");
+    assert_eq!(frag2, None);
+    assert!(frag_parse!(hostname, "%s%s?", "%s%s__localhost__local host").is_none());
+
+    // OK: together with other options, values are validated after decoding
+    let (frag1, frag2) =
+        fragstrings::frag_parse_ref!(hostname, "%s%s:String", "%s%s__a.b__c.d").expect("failed to parse");
+    assert_eq!(frag1, "a.b");
+    assert_eq!(frag2, "c.d");
+    assert!(frag_parse!(hostname, html_decode, "%s", "%s__a&#46;b").is_some());
+    assert!(frag_parse!(hostname, html_decode, "%s", "%s__a&amp;b").is_none());
+}
+
+#[test]
+fn test_frag_parse_max_items() {
+    // frag_parse!(max_items, "%s", "%s__test"); // Compile error
+    // frag_parse!(max_items = 1, "%s%d", "%s%d__test__42"); // Compile error
+    // frag_parse!(max_items = 1, "%s%d?", "%s__test"); // Compile error: optional items count too
+    // frag_parse!(max_items = 20, "%s%d*", "%s%d__test__42"); // Compile error: open-ended is unbounded
+    // frag_parse!(max_items = 2, checked, "%s%d", "%s%d__test__42"); // Compile error
+    // frag_parse!(max_items = 2, version = [0, 1], "%s%d", "%s%d__test__42"); // Compile error
+
+    let (frag1, frag2) = frag_parse!(max_items = 2, "%s%d", "%s%d__test__42").expect("failed to parse");
+    assert_eq!(frag1, "test");
+    assert_eq!(frag2, 42);
+
+    let (frag1, frag2) = frag_parse!(max_items = 2, "%s%d?", "%s__test").expect("failed to parse");
+    assert_eq!(frag1, "test");
+    assert_eq!(frag2, None);
+
+    // OK: legacy version has no version fragment
+    let (version, frag1, frag2) =
+        frag_parse!(max_items = 2, version = [0], "%s%d", "%s%d__test__42").expect("failed to parse");
+    assert_eq!(version, 0);
+    assert_eq!(frag1, "test");
+    assert_eq!(frag2, 42);
+}
+
+#[test]
+fn test_frag_parse_max_fields() {
+    // frag_parse!("%d%d%d%d%d%d%d%d%d%d%d%d%d%d%d%d%d", input); // Compile error: 17 items
+    // frag_parse!(max_fields = 1, "%s%d?", "%s__test"); // Compile error: optional items count too
+    // frag_parse!(max_fields, "%s", "%s__test"); // Compile error
+
+    // OK: 16 items by default
+    let input = "%d%d%d%d%d%d%d%d%d%d%d%d%d%d%d%d__1__2__3__4__5__6__7__8__9__10__11__12__13__14__15__16";
+    let parsed = frag_parse!("%d%d%d%d%d%d%d%d%d%d%d%d%d%d%d%d", input).expect("failed to parse");
+    assert_eq!(parsed.15, 16);
+
+    // OK: the limit is raised
+    let input = "%d%d%d%d%d%d%d%d%d%d%d%d%d%d%d%d%d__1__2__3__4__5__6__7__8__9__10__11__12__13__14__15__16__17";
+    let parsed = frag_parse!(max_fields = 17, "%d%d%d%d%d%d%d%d%d%d%d%d%d%d%d%d%d", input).expect("failed to parse");
+    assert_eq!(parsed.16, 17);
+
+    // OK: the limit is lowered, and extra fragments are not items
+    let parsed = frag_parse!(max_fields = 1, "%s*", "%s%d__test__42").expect("failed to parse");
+    assert_eq!(parsed, "test");
+}
+
+#[test]
+fn test_frag_parse_null_sentinel() {
+    // frag_parse!(null_sentinel = "", "%s%s?", "%s%s__foo__NULL"); // Compile error
+    // frag_parse!(null_sentinel = NULL, "%s%s?", "%s%s__foo__NULL"); // Compile error
+
+    // OK: the sentinel is the absent value
+    let (frag1, frag2, frag3) =
+        frag_parse!(null_sentinel = "NULL", "%s%s?%d?", "%s%s%d__foo__NULL__NULL").expect("failed to parse");
+    assert_eq!(frag1, "foo");
+    assert_eq!(frag2, None);
+    assert_eq!(frag3, None);
+
+    let (frag1, frag2) = frag_parse!(null_sentinel = "NULL", "%s%s?", "%s%s__foo__bar").expect("failed to parse");
+    assert_eq!(frag1, "foo");
+    assert_eq!(frag2, Some("bar".to_string()));
+
+    let (frag1, frag2) = frag_parse!(null_sentinel = "NULL", "%s%s?", "%s__foo").expect("failed to parse");
+    assert_eq!(frag1, "foo");
+    assert_eq!(frag2, None);
+
+    // Bad: the sentinel is not a value of a mandatory item
+    assert!(frag_parse!(null_sentinel = "NULL", "%s%s?", "%s%s__NULL__bar").is_none());
+
+    // OK: without the option the sentinel is an ordinary value
+    let (frag1, frag2) = frag_parse!("%s%s?", "%s%s__NULL__NULL").expect("failed to parse");
+    assert_eq!(frag1, "NULL");
+    assert_eq!(frag2, Some("NULL".to_string()));
+
+    // OK: sentinel is configurable and compared exactly
+    let (frag1, frag2) = frag_parse!(null_sentinel = "null", "%d%s?", "%d%s__1__NULL").expect("failed to parse");
+    assert_eq!(frag1, 1);
+    assert_eq!(frag2, Some("NULL".to_string()));
+
+    // OK: round trip
+    let value = fragstrings::frag_format!(null_sentinel = "NULL", "%d%s?", 42, None::<&str>);
+    let (frag1, frag2) = frag_parse!(null_sentinel = "NULL", "%d%s?", value).expect("failed to parse");
+    assert_eq!(frag1, 42);
+    assert_eq!(frag2, None);
+}
+
+#[test]
+fn test_frag_parse_borrowed_str() {
+    // frag_parse!(html_decode, "%s:str", "%s__test"); // Compile error
+
+    // OK: `%s:str` items are borrowed from the input, others are owned
+    let input = "%s%s%d__foo__bar__42".to_string();
+    let (frag1, frag2, frag3): (&str, String, i64) = frag_parse!("%s:str%s%d", input).expect("failed to parse");
+    assert_eq!(frag1, "foo");
+    assert_eq!(frag2, "bar");
+    assert_eq!(frag3, 42);
+    assert!(std::ptr::eq(frag1.as_ptr(), input[8..].as_ptr()));
+
+    // OK: optional items
+    let (frag1, frag2): (i64, Option<&str>) = frag_parse!("%d%s:str?", "%d__42").expect("failed to parse");
+    assert_eq!(frag1, 42);
+    assert_eq!(frag2, None);
+
+    // Bad: same rules as usual
+    let mismatched = "%d__42";
+    assert!(frag_parse!("%s:str", mismatched).is_none());
+    assert!(frag_parse!(hostname, "%s:str", "%s__foo bar").is_none());
+
+    // OK: with other options
+    let value = fragstrings::frag_format!(checked, "%s:str%d", "foo", 42);
+    assert_eq!(frag_parse!(checked, "%s:str%d", value), Ok(("foo", 42)));
+}
+
+#[test]
+fn test_frag_parse_single_item() {
+    // Single item of a closed format is parsed by its own code path, and the legacy version
+    // makes the general one parse the same values, so both must agree on every input
+    let checked = fragstrings::frag_format!(checked, "%s", "foo");
+    let inputs = [
+        "",
+        "%s",
+        "%s__",
+        "%s__foo",
+        "%s__foo__",
+        "%s__foo__bar",
+        "%s%d__foo__42",
+        "%d__42",
+        "%d__-7",
+        "%d__4_2",
+        "%d__42__",
+        "%d__300",
+        "__%s__foo",
+        "%s__привет",
+        "%s%s__foo",
+        &checked,
+    ];
+    for input in inputs.iter() {
+        assert_eq!(
+            frag_parse!("%s", input),
+            frag_parse!(version = [0], "%s", input).map(|(_, value)| value)
+        );
+        assert_eq!(
+            frag_parse!("%d", input),
+            frag_parse!(version = [0], "%d", input).map(|(_, value)| value)
+        );
+        assert_eq!(
+            frag_parse!("%s:str", input),
+            frag_parse!(version = [0], "%s:str", input).map(|(_, value)| value)
+        );
+        assert_eq!(
+            frag_parse!("%d:u8", input),
+            frag_parse!(version = [0], "%d:u8", input).map(|(_, value)| value)
+        );
+        assert_eq!(
+            frag_parse!("%s[..3]", input),
+            frag_parse!(version = [0], "%s[..3]", input).map(|(_, value)| value)
+        );
+        assert_eq!(
+            frag_parse!(html_decode, "%s", input),
+            frag_parse!(html_decode, version = [0], "%s", input).map(|(_, value)| value)
+        );
+        assert_eq!(
+            frag_parse!(checked, "%s", input),
+            frag_parse!(checked, version = [0], "%s", input).map(|(_, value)| value)
+        );
+    }
+    assert_eq!(frag_parse!(checked, "%s", checked), Ok("foo".to_owned()));
+}
+
+#[test]
+fn test_frag_parse_words() {
+    use fragstrings::frag_parse_ref;
+
+    // OK: words are borrowed from the input
+    let input = "%s%d__buy  WAVES\tUSDN __42".to_string();
+    let (frag1, frag2): (Vec<&str>, i64) = frag_parse_ref!("%s:words%d", input).expect("failed to parse");
+    assert_eq!(frag1, ["buy", "WAVES", "USDN"]);
+    assert_eq!(frag2, 42);
+    assert!(std::ptr::eq(frag1[0].as_ptr(), input[6..].as_ptr()));
+
+    // OK: empty fragment has no words, and the other macros return them borrowed too
+    let (frag1, frag2) = frag_parse!("%s:words%s", "%s%s__ __foo").expect("failed to parse");
+    assert!(frag1.is_empty());
+    assert_eq!(frag2, "foo");
+    assert_eq!(frag_parse!("%d%s:words?", "%d__42"), Some((42, None)));
+
+    // Bad: same rules as usual
+    let mismatched = "%d__42";
+    assert!(frag_parse_ref!("%s:words", mismatched).is_none());
+    assert!(frag_parse!(hostname, "%s:words", "%s__foo bar").is_none());
+
+    // frag_parse!(html_decode, "%s:words", "%s__foo"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_arc() {
+    use fragstrings::frag_parse_arc;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    // frag_parse_arc!(alloc = arena, "%s", "%s__test"); // Compile error
+
+    // OK: strings and paths are shared
+    let (frag1, frag2, frag3, frag4): (Arc<String>, Arc<PathBuf>, i64, Option<Arc<String>>) =
+        frag_parse_arc!("%s%P%d%s?", "%s%s%d__foo__/tmp/bar__42").expect("failed to parse");
+    assert_eq!(*frag1, "foo");
+    assert_eq!(*frag2, PathBuf::from("/tmp/bar"));
+    assert_eq!(frag3, 42);
+    assert_eq!(frag4, None);
+
+    let cached = Arc::clone(&frag1);
+    assert!(Arc::ptr_eq(&cached, &frag1));
+
+    // Bad: same rules as usual
+    assert!(frag_parse_arc!("%s%d", "%s%d__foo__bar").is_none());
+
+    // OK: with other options, custom types are not shared
+    let (frag1, frag2) = frag_parse_arc!(html_decode, "%s%d:u8", "%s%d__a&amp;b__7").expect("failed to parse");
+    assert_eq!(*frag1, "a&b");
+    assert_eq!(frag2, 7u8);
+}
+
+#[test]
+fn test_frag_parse_system_time() {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    // OK: Unix timestamps in seconds
+    let (frag1, frag2): (SystemTime, String) =
+        frag_parse!("%d:SystemTime%s", "%d%s__1600000000__desc").expect("failed to parse");
+    assert_eq!(frag1, UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+    assert_eq!(frag2, "desc");
+
+    let frag1 = frag_parse!("%d:std::time::SystemTime", "%d__0").expect("failed to parse");
+    assert_eq!(frag1, UNIX_EPOCH);
+
+    // Bad: before the epoch or not a timestamp
+    assert!(frag_parse!("%d:SystemTime", "%d__-1").is_none());
+    assert!(frag_parse!("%d:SystemTime", "%d__1.5").is_none());
+    assert!(frag_parse!("%d:SystemTime", "%d__foo").is_none());
+    let mismatched = "%s__42";
+    assert!(frag_parse!("%d:SystemTime", mismatched).is_none());
+
+    // OK: optional items
+    let (frag1, frag2) = frag_parse!("%s%d:SystemTime?", "%s__foo").expect("failed to parse");
+    assert_eq!(frag1, "foo");
+    assert_eq!(frag2, None);
+
+    // OK: round trip
+    let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let value = fragstrings::frag_format!("%d:SystemTime", time);
+    assert_eq!(frag_parse!("%d:SystemTime", value), Some(time));
+}
+
+#[test]
+fn test_frag_parse_non_zero_u64() {
+    use std::num::NonZeroU64;
+    let input = |s: &str| s.to_string(); // Not a literal, so that it is parsed at runtime
+
+    // OK: positive integers, which need no import
+    let (id, name) = frag_parse!("%d:NonZeroU64%s", input("%d%s__42__foo")).expect("failed to parse");
+    assert_eq!(id, NonZeroU64::new(42).unwrap());
+    assert_eq!(name, "foo");
+    assert_eq!(
+        frag_parse!("%d:std::num::NonZeroU64", input("%d__18446744073709551615")),
+        NonZeroU64::new(u64::MAX)
+    );
+    assert_eq!(frag_parse!("%d%d:NonZeroU64?", input("%d__1")), Some((1, None)));
+
+    // Bad: zero, negative and not integers
+    assert_eq!(frag_parse!("%d:NonZeroU64", input("%d__0")), None);
+    assert_eq!(frag_parse!("%d:NonZeroU64", input("%d__-1")), None);
+    assert_eq!(frag_parse!("%d:NonZeroU64", input("%d__1.5")), None);
+    assert_eq!(frag_parse!("%d:NonZeroU64", input("%d__18446744073709551616")), None);
+
+    // OK: other checks still apply
+    assert_eq!(frag_parse!(prime, "%d:NonZeroU64", input("%d__7")), NonZeroU64::new(7));
+    assert_eq!(frag_parse!(prime, "%d:NonZeroU64", input("%d__8")), None);
+
+    // OK: round trip
+    let value = fragstrings::frag_format!("%d:NonZeroU64%s", NonZeroU64::new(42).unwrap(), "foo");
+    assert_eq!(value, "%d%s__42__foo");
+    assert_eq!(
+        frag_parse!("%d:NonZeroU64%s", value),
+        Some((NonZeroU64::new(42).unwrap(), "foo".to_string()))
+    );
+}
+
+#[test]
+fn test_frag_parse_ident() {
+    // OK: all string values are identifiers
+    let (frag1, frag2) = frag_parse!(ident, "%s%d", "%s%d__foo_bar__42").expect("failed to parse");
+    assert_eq!(frag1, "foo_bar");
+    assert_eq!(frag2, 42);
+
+    // Bad: not an identifier, or a keyword
+    assert!(frag_parse!(ident, "%s", "%s__").is_none());
+    assert!(frag_parse!(ident, "%s", "%s__1foo").is_none());
+    assert!(frag_parse!(ident, "%s", "%s__foo-bar").is_none());
+    assert!(frag_parse!(ident, "%s", "%s__type").is_none());
+    assert!(frag_parse!(ident, "%s%s", "%s%s__foo__self").is_none());
+
+    // OK: optional items are validated only if present
+    let (frag1, frag2) = frag_parse!(ident, "%s%s?", "%s__Foo").expect("failed to parse");
+    assert_eq!(frag1, "Foo");
+    assert_eq!(frag2, None);
+    assert!(frag_parse!(ident, "%s%s?", "%s%s__Foo__fn").is_none());
+
+    // OK: together with hostname
+    assert!(frag_parse!(ident, hostname, "%s", "%s__localhost").is_some());
+    assert!(frag_parse!(ident, hostname, "%s", "%s__local_host").is_none());
+    assert!(frag_parse!(ident, hostname, "%s", "%s__local-host").is_none());
+}
+
+#[test]
+fn test_frag_parse_length_limits() {
+    // OK: byte count within the limit, not the char count
+    assert!(frag_parse!("%s[bytes:..6]%d", "%s%d__привет__42").is_none()); // 12 bytes
+    let (frag1, frag2) = frag_parse!("%s[bytes:..12]%d", "%s%d__привет__42").expect("failed to parse");
+    assert_eq!(frag1, "привет");
+    assert_eq!(frag2, 42);
+
+    // OK: char count within the limit
+    let frag1 = frag_parse!("%s[..6]", "%s__привет").expect("failed to parse");
+    assert_eq!(frag1, "привет");
+    assert!(frag_parse!("%s[..5]", "%s__привет").is_none());
+
+    // OK: lower bounds
+    assert!(frag_parse!("%s[1..]", "%s__").is_none());
+    assert!(frag_parse!("%s[7..]", "%s__привет").is_none());
+    assert!(frag_parse!("%s[bytes:7..]", "%s__привет").is_some());
+    assert!(frag_parse!("%s[bytes:2..2]", "%s__ab").is_some());
+    assert!(frag_parse!("%s[bytes:2..2]", "%s__abc").is_none());
+
+    // OK: with custom types and optional items
+    assert_eq!(frag_parse!("%s:str[..3]", "%s__foo"), Some("foo"));
+    let (frag1, frag2) = frag_parse!("%d%s[..3]?", "%d__42").expect("failed to parse");
+    assert_eq!(frag1, 42);
+    assert_eq!(frag2, None);
+    assert!(frag_parse!("%d%s[..3]?", "%d%s__42__quux").is_none());
+
+    // OK: sentinel is not a value, so it is not limited
+    let (frag1, frag2) = frag_parse!(null_sentinel = "NULL", "%d%s[..3]?", "%d%s__42__NULL").expect("failed to parse");
+    assert_eq!(frag1, 42);
+    assert_eq!(frag2, None);
+
+    // frag_parse!("%d[..3]", "%d__42"); // Compile error
+    // frag_parse!("%s[..]", "%s__foo"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_regex() {
+    // OK: the whole fragment matches
+    let (frag1, frag2) = frag_parse!("%s[re=r'^[A-Z]{2,3}$']%d", "%s%d__USD__42").expect("failed to parse");
+    assert_eq!(frag1, "USD");
+    assert_eq!(frag2, 42);
+    assert!(frag_parse!("%s[re=r'^[A-Z]{2,3}$']", "%s__US").is_some());
+
+    // Bad: only a part of the fragment matches
+    assert!(frag_parse!("%s[re=r'^[A-Z]{2,3}$']", "%s__USDT").is_none());
+    assert!(frag_parse!("%s[re=r'[A-Z]{2,3}']", "%s__usd").is_none());
+    assert!(frag_parse!(r"%s[re=r'\d+']", "%s__42x").is_none());
+
+    // OK: anchors are implied, alternation and groups
+    assert!(frag_parse!(r"%s[re=r'\d+']", "%s__42").is_some());
+    assert!(frag_parse!(r"%s[re=r'(buy|sell)_(\w+)']", "%s__sell_WAVES").is_some());
+    assert!(frag_parse!(r"%s[re=r'(buy|sell)_(\w+)']", "%s__hold_WAVES").is_none());
+
+    // OK: with length limits, custom types, optional items and borrowed values
+    assert_eq!(frag_parse!("%s:str[..3][re=r'[a-z]+']", "%s__foo"), Some("foo"));
+    assert!(frag_parse!("%s:str[..3][re=r'[a-z]+']", "%s__quux").is_none());
+    assert_eq!(frag_parse!("%d%s[re=r'x+']?", "%d__42"), Some((42, None)));
+    assert!(frag_parse!("%d%s[re=r'x+']?", "%d%s__42__y").is_none());
+    assert_eq!(fragstrings::frag_parse_ref!("%s[re=r'.']", "%s__π"), Some("π"));
+
+    // OK: long fragments and nested repetitions take linear time
+    let long = format!("%s__{}", "a".repeat(100_000));
+    assert!(frag_parse!("%s[re=r'a*']", &long).is_some());
+    assert!(frag_parse!("%s[re=r'(a|aa)*b']", &long).is_none());
+
+    // frag_parse!("%d[re=r'1']", "%d__1"); // Compile error
+    // frag_parse!("%s[re=r'(a']", "%s__a"); // Compile error
+    // frag_parse!("%s[re=r'[0-9]{1,2000}']", "%s__1"); // Compile error
+    // frag_format!("%s[re=r'a']", "a"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_trie() {
+    let input = |s: &str| s.to_string(); // Not a literal, so that it is parsed at runtime
+
+    // OK: few values are compared one by one
+    let (side, amount) = frag_parse!("%s{trie:buy,sell}%d", input("%s%d__sell__42")).expect("failed to parse");
+    assert_eq!(side, "sell");
+    assert_eq!(amount, 42);
+    assert!(frag_parse!("%s{trie:buy,sell}%d", input("%s%d__hold__42")).is_none());
+
+    // OK: many values, and the ones which are prefixes of the others, are matched by the trie
+    let values = [
+        "", "a", "ab", "abc", "abd", "b", "ba", "bab", "π", "πρ", "x_y", "buy", "sell", "sel", "selll", "z",
+    ];
+    for &value in values.iter() {
+        let expected = Some(value.to_string()).filter(|value| {
+            ["a", "abc", "abd", "ba", "πρ", "x_y", "buy", "sell", "selll", "z"].contains(&value.as_str())
+        });
+        let value = format!("%s__{}", value);
+        let parsed = frag_parse!("%s{trie:a,abc,abd,ba,πρ,x_y,buy,sell,selll,z}", value);
+        assert_eq!(parsed, expected, "{}", value);
+        let parsed = frag_parse!(
+            trie_threshold = 1,
+            "%s{trie:a,abc,abd,ba,πρ,x_y,buy,sell,selll,z}",
+            value
+        );
+        assert_eq!(parsed, expected, "{}", value);
+        let parsed = frag_parse!(trie_threshold = 1, "%s{trie:πρ}", value);
+        assert_eq!(parsed, expected.filter(|value| value == "πρ"), "{}", value);
+    }
+
+    // OK: literal values are matched at compile time
+    assert_eq!(frag_parse!("%s{trie:buy,sell}", "%s__buy"), Some("buy".to_string()));
+    assert_eq!(frag_parse!("%s{trie:buy,sell}", "%s__hold"), None);
+
+    // OK: with custom types, length limits, optional items and borrowed values
+    assert_eq!(
+        frag_parse!("%s{trie:1,2,300}:u8%d", input("%s%d__2__42")),
+        Some((2, 42))
+    );
+    assert!(frag_parse!("%s{trie:1,2,300}:u8%d", input("%s%d__300__42")).is_none());
+    assert!(frag_parse!("%s{trie:buy,sell}[..3]", input("%s__sell")).is_none());
+    assert_eq!(frag_parse!("%d%s{trie:x}?", input("%d__42")), Some((42, None)));
+    assert!(frag_parse!("%d%s{trie:x}?", input("%d%s__42__y")).is_none());
+    let value = input("%s__sell");
+    assert_eq!(
+        fragstrings::frag_parse_ref!(trie_threshold = 1, "%s{trie:buy,sell}", value),
+        Some("sell")
+    );
+
+    // frag_parse!("%s{trie:a,,b}", "%s__a"); // Compile error
+    // frag_parse!("%d{trie:1}", "%d__1"); // Compile error
+    // frag_format!("%s{trie:a}", "a"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_assert() {
+    let input = |s: &str| s.to_string(); // Not a literal, so that it is parsed at runtime
+
+    // OK: the value is returned only if it is the asserted one
+    let (version, n) = frag_parse!("%s[assert=v1]%d", input("%s%d__v1__42")).expect("failed to parse");
+    assert_eq!(version, "v1");
+    assert_eq!(n, 42);
+    assert_eq!(frag_parse!("%s[assert=v1]%d", input("%s%d__v2__42")), None);
+    assert_eq!(frag_parse!("%s[assert=v1]%d", input("%s%d__v10__42")), None);
+    assert_eq!(
+        frag_parse!("%s[assert=v1]%d", input("%s%d__v2__42")),
+        frag_parse!("%s%d", input("%s%d__v2__42")).filter(|(s, _)| s == "v1")
+    );
+
+    // OK: literal values are matched at compile time
+    assert_eq!(frag_parse!("%s[assert=v1]", "%s__v1"), Some("v1".to_string()));
+    assert_eq!(frag_parse!("%s[assert=v1]", "%s__v2"), None);
+
+    // OK: with custom types, optional items, options and borrowed values
+    assert_eq!(frag_parse!("%s:u8[assert=7]%d", input("%s%d__7__42")), Some((7, 42)));
+    assert_eq!(frag_parse!("%s:u8[assert=7]%d", input("%s%d__07__42")), None);
+    assert_eq!(frag_parse!("%d%s[assert=x]?", input("%d__42")), Some((42, None)));
+    assert_eq!(frag_parse!("%d%s[assert=x]?", input("%d%s__42__y")), None);
+    assert_eq!(
+        frag_parse!(prime, "%s[assert=v1]%d", input("%s%d__v1__7")),
+        Some(("v1".to_string(), 7))
+    );
+    let value = input("%s%d__v1__42");
+    assert_eq!(fragstrings::frag_parse_ref!("%s[assert=v1]%d", value), Some(("v1", 42)));
+
+    // frag_parse!("%s[assert=]", "%s__a"); // Compile error
+    // frag_parse!("%d[assert=1]", "%d__1"); // Compile error
+    // frag_format!("%s[assert=v1]", "v1"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_slice() {
+    let input = |s: &str| s.to_string(); // Not a literal, so that it is parsed at runtime
+
+    // OK: only the byte range of the fragment is taken
+    let (code, amount) = frag_parse!("%s[slice:2..8]%d", input("%s%d__IDUSDEUR42__7")).expect("failed to parse");
+    assert_eq!(code, "USDEUR");
+    assert_eq!(amount, 7);
+    assert_eq!(
+        frag_parse!("%s[slice:2..]", input("%s__IDUSD")),
+        Some("USD".to_string())
+    );
+    assert_eq!(frag_parse!("%s[slice:..2]", input("%s__IDUSD")), Some("ID".to_string()));
+    assert_eq!(frag_parse!("%s[slice:2..5]", "%s__IDUSD"), Some("USD".to_string()));
+
+    // Fail: the range is out of bounds, or not on char boundaries
+    assert!(frag_parse!("%s[slice:2..8]%d", input("%s%d__IDUSD__7")).is_none());
+    assert!(frag_parse!("%s[slice:6..]", input("%s__IDUSD")).is_none());
+    assert!(frag_parse!("%s[slice:1..]", input("%s__πρ")).is_none());
+    assert_eq!(frag_parse!("%s[slice:2..]", input("%s__πρ")), Some("ρ".to_string()));
+
+    // OK: with custom types, regexes, tries, optional items and borrowed values
+    assert_eq!(frag_parse!("%s:u8[slice:3..]", input("%s__id_255")), Some(255));
+    assert!(frag_parse!("%s:u8[slice:3..]", input("%s__id_256")).is_none());
+    assert_eq!(
+        frag_parse!("%s{trie:buy,sell}[slice:..4]", input("%s__sell_all")),
+        Some("sell".to_string())
+    );
+    assert!(frag_parse!("%s[slice:..3][re=r'^[A-Z]+$']", input("%s__usd")).is_none());
+    assert_eq!(frag_parse!("%d%s[slice:1..]?", input("%d__42")), Some((42, None)));
+    assert!(frag_parse!("%d%s[slice:4..]?", input("%d%s__42__abc")).is_none());
+    assert_eq!(
+        frag_parse!(null_sentinel = "NULL", "%d%s[slice:1..3]?", input("%d%s__42__NULL")),
+        Some((42, None))
+    );
+    let value = input("%s__IDUSD");
+    assert_eq!(fragstrings::frag_parse_ref!("%s[slice:2..]", value), Some("USD"));
+
+    // frag_parse!("%s[slice:8..2]", "%s__a"); // Compile error
+    // frag_parse!("%d[slice:1..]", "%d__1"); // Compile error
+    // frag_format!("%s[slice:1..]", "a"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_padding() {
+    use fragstrings::frag_format;
+
+    let input = |s: &str| s.to_string(); // Not a literal, so that it is parsed at runtime
+
+    // OK: the padding is trimmed on the side of the alignment
+    let value = frag_format!("%s[pad:6]%s[pad:6>]%s[pad:6^]%d", "a", "b", "c", 42);
+    assert_eq!(
+        frag_parse!("%s[pad:6]%s[pad:6>]%s[pad:6^]%d", value),
+        Some(("a".to_string(), "b".to_string(), "c".to_string(), 42))
+    );
+    assert_eq!(frag_parse!("%s[pad:6]", input("%s__ foo  ")), Some(" foo".to_string()));
+    assert_eq!(
+        frag_parse!("%s[pad:6>]", input("%s__ foo  ")),
+        Some("foo  ".to_string())
+    );
+    assert_eq!(
+        frag_parse!("%s[pad:2]", input("%s__foobar")),
+        Some("foobar".to_string())
+    );
+    assert_eq!(frag_parse!("%s:u8[pad:4>]", input("%s__  42")), Some(42));
+
+    // OK: padded sentinel, optional items and borrowed values
+    let value = frag_format!(null_sentinel = "NULL", "%d%s[pad:6]?", 1, None::<&str>);
+    assert_eq!(
+        frag_parse!(null_sentinel = "NULL", "%d%s[pad:6]?", value),
+        Some((1, None))
+    );
+    assert_eq!(frag_parse!("%d%s[pad:6]?", input("%d__1")), Some((1, None)));
+    let value = input("%s__USD   ");
+    assert_eq!(fragstrings::frag_parse_ref!("%s[pad:6]", value), Some("USD"));
+
+    // Fail: the trimmed value does not match
+    assert!(frag_parse!("%s:u8[pad:4]", input("%s__  42")).is_none());
+    assert!(frag_parse!("%s[pad:4][re=r'^[a-z]+$']", input("%s__ ab ")).is_none());
+
+    // frag_parse!("%d[pad:4]", "%d__1"); // Compile error
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_frag_parse_trailing_comma() {
+    let (frag1, frag2) = frag_parse!("%s%d", "%s%d__foo__42",).expect("failed to parse");
+    assert_eq!(frag1, "foo");
+    assert_eq!(frag2, 42);
+    assert_eq!(frag_parse!(version = 2, "%d", "%s%d__v2__42",), Some(42));
+
+    // frag_parse!("%s%d",, "%s%d__foo__42"); // Compile error
+    // frag_parse!("%s%d", "%s%d__foo__42",,); // Compile error
+    // frag_parse!(checked,, "%s%d", "%s%d__foo__42"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_prime() {
+    // OK: all integer values are prime
+    let (frag1, frag2) = frag_parse!(prime, "%d%s", "%d%s__7919__foo").expect("failed to parse");
+    assert_eq!(frag1, 7919);
+    assert_eq!(frag2, "foo");
+    assert_eq!(frag_parse!(prime, "%d", "%d__2"), Some(2));
+    assert_eq!(frag_parse!(prime, "%d", "%d__2147483647"), Some(2_147_483_647));
+
+    // Bad: not a prime
+    assert!(frag_parse!(prime, "%d", "%d__0").is_none());
+    assert!(frag_parse!(prime, "%d", "%d__1").is_none());
+    assert!(frag_parse!(prime, "%d", "%d__-7").is_none());
+    assert!(frag_parse!(prime, "%d", "%d__7917").is_none());
+    assert!(frag_parse!(prime, "%d%d", "%d%d__7__9").is_none());
+
+    // OK: custom types and optional items
+    assert_eq!(frag_parse!(prime, "%d:u8", "%d__251"), Some(251));
+    assert!(frag_parse!(prime, "%d:u8", "%d__255").is_none());
+    let (frag1, frag2) = frag_parse!(prime, "%d%d?", "%d__3").expect("failed to parse");
+    assert_eq!(frag1, 3);
+    assert_eq!(frag2, None);
+    assert!(frag_parse!(prime, "%d%d?", "%d%d__3__4").is_none());
+}
+
+#[test]
+fn test_frag_parse_roman() {
+    // OK: plain integers are parsed from Roman numerals, the other items as usual
+    assert_eq!(
+        frag_parse!(roman, "%d%s", "%d%s__XIV__foo"),
+        Some((14, "foo".to_string()))
+    );
+    assert_eq!(
+        frag_parse!(roman, "%d%d:u8?", "%d%d__MCMXCIV__7"),
+        Some((1994, Some(7)))
+    );
+    assert_eq!(frag_parse!(roman, prime, "%d", "%d__XIII"), Some(13));
+
+    // Bad: decimal, non-canonical and non-prime numbers
+    assert_eq!(frag_parse!(roman, "%d", "%d__14"), None);
+    assert_eq!(frag_parse!(roman, "%d", "%d__XIIII"), None);
+    assert_eq!(frag_parse!(roman, prime, "%d", "%d__XIV"), None);
+
+    // OK: round trip
+    let value = fragstrings::frag_format!(roman, "%d%s", 2024, "foo").expect("in range");
+    assert_eq!(frag_parse!(roman, "%d%s", value), Some((2024, "foo".to_string())));
+}
+
+#[test]
+fn test_frag_parse_human_duration() {
+    // OK: plain integers are parsed from durations, the other items as usual
+    assert_eq!(
+        frag_parse!(human_duration, "%d%s", "%d%s__1h30m__foo"),
+        Some((5400, "foo".to_string()))
+    );
+    assert_eq!(frag_parse!(human_duration, "%d", "%d__2m"), Some(120));
+    assert_eq!(frag_parse!(human_duration, "%d", "%d__1h 0m 5s"), Some(3605));
+    assert_eq!(
+        frag_parse!(human_duration, "%d%d:u8?", "%d%d__90s__7"),
+        Some((90, Some(7)))
+    );
+
+    // Bad: plain numbers, unknown units and units out of order
+    assert_eq!(frag_parse!(human_duration, "%d", "%d__5400"), None);
+    assert_eq!(frag_parse!(human_duration, "%d", "%d__1d"), None);
+    assert_eq!(frag_parse!(human_duration, "%d", "%d__30m1h"), None);
+
+    // OK: round trip
+    let value = fragstrings::frag_format!(human_duration, "%d%s", 5400, "foo").expect("in range");
+    assert_eq!(
+        frag_parse!(human_duration, "%d%s", value),
+        Some((5400, "foo".to_string()))
+    );
+
+    // frag_parse!(roman, human_duration, "%d", "%d__V"); // Compile error
+    // frag_parse!(roman, packed_date, "%d", "%d__V"); // Compile error
+    // frag_parse!(packed_date, "%d", "%d__20240115"); // Compile error without the `chrono` feature
+}
+
+#[test]
+fn test_frag_parse_cstring() {
+    use std::ffi::CString;
+
+    let c = |s: &str| CString::new(s).expect("no nul bytes");
+
+    // OK: strings are returned as C strings, the other items as usual
+    assert_eq!(frag_parse!(cstring, "%s%d", "%s%d__foo__42"), Some((c("foo"), 42)));
+    assert_eq!(
+        frag_parse!(cstring, "%s%s:u8%s?", "%s%s__foo__7"),
+        Some((c("foo"), 7, None))
+    );
+    assert_eq!(
+        frag_parse!(cstring, html_decode, "%s%s?", "%s%s__a&amp;b__"),
+        Some((c("a&b"), Some(c(""))))
+    );
+
+    // Bad: nul bytes can not be in C strings
+    assert_eq!(frag_parse!(cstring, "%s%d", "%s%d__a\0b__42"), None);
+    assert_eq!(frag_parse!(cstring, html_decode, "%s%d", "%s%d__a&#0;b__42"), None);
+
+    // OK: round trip
+    let value = fragstrings::frag_format!(cstring, "%s%d", c("foo"), 42);
+    assert_eq!(frag_parse!(cstring, "%s%d", value), Some((c("foo"), 42)));
+
+    // frag_parse_ref!(cstring, "%s", "%s__foo"); // Compile error
+    // frag_parse!(cstring, secret, "%s", "%s__foo"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_rgba() {
+    // OK: colors with and without the alpha, the other items are parsed as usual
+    assert_eq!(
+        frag_parse!(rgba, "%s%d", "%s%d__#ff8800__42"),
+        Some(((255, 136, 0, 255), 42))
+    );
+    assert_eq!(
+        frag_parse!(rgba, "%s%s:u8%s?", "%s%s__#FF880080__7"),
+        Some(((255, 136, 0, 128), 7, None))
+    );
+    assert_eq!(
+        frag_parse!(rgba, null_sentinel = "NULL", "%s%s?", "%s%s__#000000__NULL"),
+        Some(((0, 0, 0, 255), None))
+    );
+
+    // Bad: not colors
+    assert_eq!(frag_parse!(rgba, "%s", "%s__ff8800"), None);
+    assert_eq!(frag_parse!(rgba, "%s", "%s__#ff88"), None);
+    assert_eq!(frag_parse!(rgba, "%s", "%s__#ff880"), None);
+    assert_eq!(frag_parse!(rgba, "%s", "%s__#orange"), None);
+
+    // OK: round trip
+    let value = fragstrings::frag_format!(rgba, "%s%d", (1, 2, 3, 4), 42);
+    assert_eq!(frag_parse!(rgba, "%s%d", value), Some(((1, 2, 3, 4), 42)));
+
+    // frag_parse_ref!(rgba, "%s", "%s__#ff8800"); // Compile error
+    // frag_parse!(rgba, cstring, "%s", "%s__#ff8800"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_fixed_point() {
+    // OK: plain integers are divided by the power of ten, the other items are parsed as usual
+    assert_eq!(
+        frag_parse!(fixed_point = 2, "%d%s", "%d%s__1234__EUR"),
+        Some((12.34, "EUR".to_string()))
+    );
+    assert_eq!(frag_parse!(fixed_point = 1, "%d", "%d__3"), Some(0.3));
+    assert_eq!(frag_parse!(fixed_point = 3, "%d", "%d__-1001"), Some(-1.001));
+    assert_eq!(
+        frag_parse!(fixed_point = 2, "%d%d:u8?", "%d%d__5__7"),
+        Some((0.05, Some(7)))
+    );
+
+    // Bad: the fragments are still integers
+    assert_eq!(frag_parse!(fixed_point = 2, "%d", "%d__12.34"), None);
+    assert_eq!(frag_parse!(fixed_point = 2, "%d", "%d__1e3"), None);
+
+    // OK: round trip
+    let value = fragstrings::frag_format!(fixed_point = 2, "%d%s", 12.34, "EUR").expect("in range");
+    assert_eq!(
+        frag_parse!(fixed_point = 2, "%d%s", value),
+        Some((12.34, "EUR".to_string()))
+    );
+
+    // frag_parse!(fixed_point = 2, roman, "%d", "%d__V"); // Compile error
+    // frag_parse!(fixed_point = 2, radix = auto, "%d", "%d__0x1"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_percent() {
+    // OK: percentages are read into shares, the other items are parsed as usual
+    assert_eq!(
+        frag_parse!(percent, "%d%s", "%d%s__75%__done"),
+        Some((0.75, "done".to_string()))
+    );
+    assert_eq!(frag_parse!(percent, "%d", "%d__12.5%"), Some(0.125));
+    assert_eq!(frag_parse!(percent, "%d%d:u8?", "%d%d__0%__7"), Some((0.0, Some(7))));
+
+    // OK: out of range shares are clamped, unless in the strict mode
+    assert_eq!(frag_parse!(percent, "%d", "%d__150%"), Some(1.0));
+    assert_eq!(frag_parse!(percent = strict, "%d", "%d__150%"), None);
+    assert_eq!(frag_parse!(percent = strict, "%d", "%d__-5%"), None);
+
+    // Bad: the sign is required, and the numbers are plain decimals
+    assert_eq!(frag_parse!(percent, "%d", "%d__75"), None);
+    assert_eq!(frag_parse!(percent, "%d", "%d__1e2%"), None);
+    assert_eq!(frag_parse!(percent, "%d", "%d__inf%"), None);
+
+    // OK: round trip
+    let value = fragstrings::frag_format!(percent, "%d%s", 0.375, "done").expect("in range");
+    assert_eq!(frag_parse!(percent, "%d%s", value), Some((0.375, "done".to_string())));
+
+    // frag_parse!(percent, fixed_point = 2, "%d", "%d__1%"); // Compile error
+    // frag_parse!(percent, radix = auto, "%d", "%d__1%"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_bitmask() {
+    let set = |positions: &[u8]| positions.iter().copied().collect::<std::collections::HashSet<_>>();
+
+    // OK: plain integers are unpacked into bit positions, the other items are parsed as usual
+    assert_eq!(
+        frag_parse!(bitmask, "%d%s", "%d%s__13__user"),
+        Some((set(&[0, 2, 3]), "user".to_string()))
+    );
+    assert_eq!(frag_parse!(bitmask, "%d", "%d__0"), Some(set(&[])));
+    assert_eq!(
+        frag_parse!(bitmask, "%d", "%d__-9223372036854775807"),
+        Some(set(&[0, 63]))
+    );
+    assert_eq!(
+        frag_parse!(bitmask, "%d%d:u8?", "%d%d__1__7"),
+        Some((set(&[0]), Some(7)))
+    );
+    assert_eq!(
+        frag_parse!(bitmask, radix = auto, "%d", "%d__0b1010"),
+        Some(set(&[1, 3]))
+    );
+
+    // Bad: the fragments are still integers
+    assert_eq!(frag_parse!(bitmask, "%d", "%d__0b1010"), None);
+    assert_eq!(frag_parse!(bitmask, "%d", "%d__9223372036854775808"), None);
+
+    // OK: round trip
+    let value = fragstrings::frag_format!(bitmask, "%d%s", set(&[1, 5, 63]), "user").expect("in range");
+    assert_eq!(
+        frag_parse!(bitmask, "%d%s", value),
+        Some((set(&[1, 5, 63]), "user".to_string()))
+    );
+
+    // frag_parse!(bitmask, roman, "%d", "%d__V"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_radix_auto() {
+    // OK: plain integers are parsed as hexadecimal or binary with prefixes, and as decimal otherwise
+    assert_eq!(
+        frag_parse!(radix = auto, "%d%s", "%d%s__0x1a2b__foo"),
+        Some((0x1a2b, "foo".to_string()))
+    );
+    assert_eq!(frag_parse!(radix = auto, "%d%d", "%d%d__0B101__-0x10"), Some((5, -16)));
+    assert_eq!(frag_parse!(radix = auto, "%d", "%d__42"), Some(42));
+    assert_eq!(
+        frag_parse!(radix = auto, "%d%d?*", "%d%d%d__1__0xff__1"),
+        Some((1, Some(255)))
+    );
+    assert_eq!(frag_parse!(radix = auto, prime, "%d", "%d__0x1f"), Some(31));
+
+    // Bad: malformed digits, overflow, non-prime numbers, and custom types, which are always decimal
+    assert_eq!(frag_parse!(radix = auto, "%d", "%d__0x1g"), None);
+    assert_eq!(frag_parse!(radix = auto, "%d", "%d__0b2"), None);
+    assert_eq!(frag_parse!(radix = auto, "%d", "%d__0x"), None);
+    assert_eq!(frag_parse!(radix = auto, "%d", "%d__0x8000000000000000"), None);
+    assert_eq!(frag_parse!(radix = auto, prime, "%d", "%d__0x20"), None);
+    assert_eq!(frag_parse!(radix = auto, "%d:u8", "%d__0x1f"), None);
+
+    // Bad: prefixes are not accepted without the option
+    assert_eq!(frag_parse!("%d", "%d__0x1a2b"), None);
+
+    // frag_parse!(radix = 16, "%d", "%d__ff"); // Compile error
+    // frag_parse!(radix = auto, roman, "%d", "%d__V"); // Compile error
+}
+
+#[test]
+#[allow(clippy::just_underscores_and_digits)]
+fn test_frag_parse_hygiene() {
+    // User variables named like the ones of the generated code
+    let input = "%s%d__input__1";
+    let fragments = "%s%d__fragments__2";
+    let ok = "%s%d__ok__3";
+    let pattern = "%s%d";
+    let value = "%s%d__value__4";
+    let all_good = false;
+    let _0 = "%s%d__zero__5";
+
+    assert_eq!(frag_parse!("%s%d", input), Some(("input".to_string(), 1)));
+    assert_eq!(frag_parse!("%s%d", fragments), Some(("fragments".to_string(), 2)));
+    assert_eq!(frag_parse!("%s%d", ok), Some(("ok".to_string(), 3)));
+    assert_eq!(frag_parse!("%s%d", value), Some(("value".to_string(), 4)));
+    assert_eq!(frag_parse!("%s%d", _0), Some(("zero".to_string(), 5)));
+    assert_eq!(
+        frag_parse!(checked, "%s%d", input),
+        Err(fragstrings::ParseError::BadChecksum)
+    );
+
+    // Value expressions see the user variables only
+    let parsed = frag_parse!("%s%d", if all_good { ok } else { input });
+    assert_eq!(parsed, Some(("input".to_string(), 1)));
+    let parsed = frag_parse!("%s%d", format!("{}__{}__{}", pattern, "pattern", fragments.len()));
+    assert_eq!(parsed, Some(("pattern".to_string(), 18)));
+
+    // Nested macro in the value expression
+    let parsed = frag_parse!("%s%d", format!("%s%d__{}__6", frag_parse!("%s", "%s__nested").unwrap()));
+    assert_eq!(parsed, Some(("nested".to_string(), 6)));
+    let parsed = frag_parse!("%d", frag_parse!("%s:str", "%s__seven").map_or(input, |_| "%d__8"));
+    assert_eq!(parsed, Some(8));
+
+    // Allocator is not visible to the value expression either
+    struct Leaking;
+
+    impl fragstrings::StringAllocator for Leaking {
+        fn alloc_str(&self, s: &str) -> &str {
+            Box::leak(s.to_owned().into_boxed_str())
+        }
+    }
+
+    let allocator = "%s__allocator";
+    let parsed = frag_parse!(alloc = Leaking, "%s", allocator);
+    assert_eq!(parsed, Some("allocator"));
+}
+
+#[test]
+fn test_frag_parse_str_set() {
+    use std::collections::BTreeSet;
+
+    let set = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<BTreeSet<_>>();
+
+    // OK: elements are deduplicated and sorted
+    let (frag1, frag2) = frag_parse!("%s{set}%d", "%s%d__writer,admin,reader,admin__42").expect("failed to parse");
+    assert_eq!(frag1, set(&["admin", "reader", "writer"]));
+    assert_eq!(frag2, 42);
+    assert_eq!(frag_parse!("%s{set}", "%s__foo"), Some(set(&["foo"])));
+    assert_eq!(frag_parse!("%s{set}", "%s__"), Some(set(&[])));
+    assert_eq!(frag_parse!("%s{set}", "%s__a,,b"), Some(set(&["", "a", "b"])));
+
+    // OK: optional items
+    let (frag1, frag2) = frag_parse!("%d%s{set}?", "%d__42").expect("failed to parse");
+    assert_eq!(frag1, 42);
+    assert_eq!(frag2, None);
+
+    // OK: elements are validated and decoded separately
+    assert_eq!(frag_parse!(ident, "%s{set}", "%s__foo,bar"), Some(set(&["bar", "foo"])));
+    assert!(frag_parse!(ident, "%s{set}", "%s__foo,type").is_none());
+    assert_eq!(
+        frag_parse!(html_decode, "%s{set}", "%s__a&#44;b,c"),
+        Some(set(&["a,b", "c"]))
+    );
+
+    // OK: borrowed elements
+    let input = "%s__b,a".to_string();
+    let frag1: BTreeSet<&str> = fragstrings::frag_parse_ref!("%s{set}", input).expect("failed to parse");
+    assert_eq!(frag1.into_iter().collect::<Vec<_>>(), ["a", "b"]);
+
+    // OK: round trip
+    let value = fragstrings::frag_format!("%s{set}%d", set(&["b", "a"]), 42);
+    assert_eq!(value, "%s%d__a,b__42");
+    assert_eq!(frag_parse!("%s{set}%d", value), Some((set(&["a", "b"]), 42)));
+
+    // frag_parse!("%s{set}:Color", "%s__red"); // Compile error
+    // frag_parse!("%s{list}", "%s__a,b"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_str_map() {
+    use std::collections::HashMap;
+
+    let map = |items: &[(&str, &str)]| {
+        items
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>()
+    };
+
+    // OK: entries are split on the first `=`
+    let (frag1, frag2) = frag_parse!("%s{map}%d", "%s%d__color=red,size=XL__42").expect("failed to parse");
+    assert_eq!(frag1, map(&[("color", "red"), ("size", "XL")]));
+    assert_eq!(frag2, 42);
+    assert_eq!(frag_parse!("%s{map}", "%s__a=b=c"), Some(map(&[("a", "b=c")])));
+    assert_eq!(frag_parse!("%s{map}", "%s__a=,=b"), Some(map(&[("a", ""), ("", "b")])));
+    assert_eq!(frag_parse!("%s{map}", "%s__"), Some(map(&[])));
+    assert_eq!(frag_parse!("%s{map}", "%s__a=1,a=2"), Some(map(&[("a", "2")])));
+
+    // Fail: malformed entries
+    assert!(frag_parse!("%s{map}%d", "%s%d__color__42").is_none());
+    assert!(frag_parse!("%s{map}", "%s__a=1,,b=2").is_none());
+
+    // OK: optional items
+    let (frag1, frag2) = frag_parse!("%d%s{map}?", "%d__42").expect("failed to parse");
+    assert_eq!(frag1, 42);
+    assert_eq!(frag2, None);
+
+    // OK: keys and values are validated and decoded separately
+    assert_eq!(
+        frag_parse!(ident, "%s{map}", "%s__foo=bar"),
+        Some(map(&[("foo", "bar")]))
+    );
+    assert!(frag_parse!(ident, "%s{map}", "%s__foo=type").is_none());
+    assert!(frag_parse!(ident, "%s{map}", "%s__type=foo").is_none());
+    assert_eq!(
+        frag_parse!(html_decode, "%s{map}", "%s__a&#61;b=c&#44;d"),
+        Some(map(&[("a=b", "c,d")]))
+    );
+
+    // OK: borrowed entries
+    let input = "%s__a=1".to_string();
+    let frag1: HashMap<&str, &str> = fragstrings::frag_parse_ref!("%s{map}", input).expect("failed to parse");
+    assert_eq!(frag1.get("a"), Some(&"1"));
+
+    // OK: round trip
+    let value = fragstrings::frag_format!("%s{map}%d", map(&[("b", "2"), ("a", "1")]), 42);
+    assert_eq!(value, "%s%d__a=1,b=2__42");
+    assert_eq!(
+        frag_parse!("%s{map}%d", value),
+        Some((map(&[("a", "1"), ("b", "2")]), 42))
+    );
+
+    // frag_parse!("%s{map}:Color", "%s__a=red"); // Compile error
+}
+
+// Generated code must not depend on the prelude, and must not be confused by local items named like the prelude ones
+#[allow(dead_code)]
+mod no_implicit_prelude {
+    #![no_implicit_prelude]
+
+    use ::fragstrings::{frag_parse, frag_parse_arc, frag_parse_ref};
+    use ::std::assert_eq;
+
+    struct Some;
+    struct None;
+    struct Ok;
+    struct Err;
+    struct Option;
+    struct Result;
+    struct String;
+    struct Vec;
+    struct Box;
+    trait Iterator {}
+    trait ToString {}
+    trait From {}
+
+    #[test]
+    fn test_frag_parse_no_implicit_prelude() {
+        let (frag1, frag2, frag3) = frag_parse!("%s%d%P*", "%s%d%s%s__foo__42__/tmp__bar").expect("failed to parse");
+        assert_eq!(frag1, "foo");
+        assert_eq!(frag2, 42);
+        assert_eq!(frag3, ::std::path::Path::new("/tmp"));
+
+        let value = ::fragstrings::frag_format!(checked, "%s", "foo");
+        let (frag1, frag2) = frag_parse!(checked, "%s:str%d?", value).expect("failed to parse");
+        assert_eq!(frag1, "foo");
+        assert_eq!(frag2, ::std::option::Option::None);
+
+        let (version, frag1) = frag_parse!(version = [0, 2], "%d:u8", "%s%d__v2__42").expect("failed to parse");
+        assert_eq!((version, frag1), (2, 42));
+
+        let (frag1, frag2) = frag_parse!(
+            null_sentinel = "NULL",
+            hostname,
+            prime,
+            "%s[..16]%d?",
+            "%s%d__localhost__NULL"
+        )
+        .expect("failed to parse");
+        assert_eq!(frag1, "localhost");
+        assert_eq!(frag2, ::std::option::Option::None);
+
+        let (frag1, frag2) = frag_parse!(html_decode, ident, "%s%s{set}", "%s%s__foo__a,b").expect("failed to parse");
+        assert_eq!(frag1, "foo");
+        assert_eq!(frag2.len(), 2);
+
+        let frag1 = frag_parse!("%s62", "%s__5TP3P3v").expect("failed to parse");
+        assert_eq!(frag1, b"Hello");
+
+        let frag1 = frag_parse!("%d:SystemTime", "%d__0").expect("failed to parse");
+        assert_eq!(frag1, ::std::time::UNIX_EPOCH);
+
+        struct Leaking;
+
+        impl ::fragstrings::StringAllocator for Leaking {
+            fn alloc_str(&self, s: &str) -> &str {
+                ::std::boxed::Box::leak(::std::string::ToString::to_string(s).into_boxed_str())
+            }
+        }
+
+        let (frag1, frag2) = frag_parse!(alloc = Leaking, "%s%P", "%s%s__foo__bar").expect("failed to parse");
+        assert_eq!((frag1, frag2), ("foo", ::std::path::Path::new("bar")));
+
+        let (frag1, frag2) = frag_parse_ref!("%s%P", "%s%s__foo__bar").expect("failed to parse");
+        assert_eq!((frag1, frag2), ("foo", ::std::path::Path::new("bar")));
+
+        let (frag1, frag2) = frag_parse_arc!("%s%P", "%s%s__foo__bar").expect("failed to parse");
+        assert_eq!(*frag1, "foo");
+        assert_eq!(*frag2, ::std::path::Path::new("bar"));
+    }
+}
+
+#[test]
+fn test_frag_parse_valid_json() {
+    // OK: all string values are JSON, returned as is
+    let (frag1, frag2) =
+        frag_parse!(valid_json, "%s%d", r#"%s%d__{"a": [1, 2.5, null]}__42"#).expect("failed to parse");
+    assert_eq!(frag1, r#"{"a": [1, 2.5, null]}"#);
+    assert_eq!(frag2, 42);
+    assert_eq!(frag_parse!(valid_json, "%s", "%s__true"), Some("true".to_string()));
+    assert_eq!(
+        frag_parse!(valid_json, "%s", r#"%s__"foo""#),
+        Some(r#""foo""#.to_string())
+    );
+
+    // Bad: not JSON
+    assert!(frag_parse!(valid_json, "%s", "%s__").is_none());
+    assert!(frag_parse!(valid_json, "%s", "%s__foo").is_none());
+    assert!(frag_parse!(valid_json, "%s", "%s__{'a': 1}").is_none());
+    assert!(frag_parse!(valid_json, "%s%s", "%s%s__[1]__[1,]").is_none());
+
+    // OK: together with decoding, JSON is validated after it
+    let frag1 = frag_parse!(valid_json, html_decode, "%s", "%s__{&quot;a&quot;: 1}").expect("failed to parse");
+    assert_eq!(frag1, r#"{"a": 1}"#);
+
+    // OK: borrowed items
+    assert_eq!(frag_parse!(valid_json, "%s:str", "%s__[]"), Some("[]"));
+}
+
+#[test]
+fn test_frag_parse_redact() {
+    use fragstrings::redact::Redacted;
+
+    // OK: string values are redacted, the other ones are not
+    let parsed = frag_parse!(redact, "%s%d%P", "%s%d%s__secret__42__/tmp").expect("failed to parse");
+    assert_eq!(format!("{:?}", parsed), r#"(***, 42, "/tmp")"#);
+    let (frag1, frag2, _) = parsed;
+    assert_eq!(frag1, Redacted("secret".to_string()));
+    assert_eq!(frag1.to_string(), "secret");
+    assert_eq!(*frag1, "secret");
+    assert_eq!(frag2, 42);
+
+    // OK: custom types, borrowed strings and optional items
+    let (frag1, frag2, frag3) = frag_parse!(redact, "%s:str%s:u8%s?", "%s%s__foo__7").expect("failed to parse");
+    assert_eq!(frag1.into_inner(), "foo");
+    assert_eq!(frag2, Redacted(7));
+    assert_eq!(frag3, None);
+    let frag1 = fragstrings::frag_parse_ref!(redact, "%s", "%s__foo").expect("failed to parse");
+    assert_eq!(*frag1, "foo");
+}
+
+#[test]
+fn test_frag_parse_lazy() {
+    use fragstrings::lazy::LazyStr;
+
+    // OK: string values are borrowed until they are copied, the other ones are parsed as usual
+    let input = "%s%d%s%s%s__foo__42__bar__7__/tmp".to_string();
+    let (frag1, frag2, frag3, frag4, frag5) = frag_parse!(lazy, "%s%d%s%s:u8%P", input).expect("failed to parse");
+    assert_eq!(frag1, "foo");
+    assert_eq!(frag1.len(), 3);
+    assert_eq!(frag2, 42);
+    let frag3: String = frag3.get();
+    assert_eq!(frag3, "bar");
+    assert_eq!(frag4, 7);
+    assert_eq!(frag5, std::path::PathBuf::from("/tmp"));
+
+    // OK: validation, optional items and redaction apply as usual
+    assert_eq!(
+        frag_parse!(lazy, ident, "%s%s?", "%s__foo"),
+        Some((LazyStr::new("foo"), None))
+    );
+    assert_eq!(frag_parse!(lazy, ident, "%s", "%s__1foo"), None);
+    let frag1 = frag_parse!(lazy, redact, "%s", "%s__secret").expect("failed to parse");
+    assert_eq!(format!("{:?}", frag1), "***");
+    assert_eq!(frag1.get(), "secret");
+
+    // frag_parse!(lazy, html_decode, "%s", "%s__foo"); // Compile error
+    // frag_parse!(lazy, alloc = arena, "%s", "%s__foo"); // Compile error
+    // fragstrings::frag_parse_ref!(lazy, "%s", "%s__foo"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_literal() {
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    // OK: literal values are parsed at compile time
+    assert_eq!(frag_parse!("%s%d", "%s%d__WAVES__42"), Some(("WAVES".to_string(), 42)));
+    assert_eq!(
+        frag_parse!("%s:str%P", "%s%s__foo__/tmp"),
+        Some(("foo", PathBuf::from("/tmp")))
+    );
+    assert_eq!(
+        frag_parse!("%s%d?%d?", "%s%d__foo__-1"),
+        Some(("foo".to_string(), Some(-1), None))
+    );
+    assert_eq!(frag_parse!("%s*", "%s%d__foo__42"), Some("foo".to_string()));
+    assert_eq!(
+        fragstrings::frag_parse_ref!("%s%P", "%s%s__foo__/tmp"),
+        Some(("foo", Path::new("/tmp")))
+    );
+    assert_eq!(
+        fragstrings::frag_parse_arc!("%s", "%s__foo"),
+        Some(Arc::new("foo".to_string()))
+    );
+
+    // Bad: descriptor matches, but the fragments do not
+    assert_eq!(frag_parse!("%s%d", "%s%d__foo__bar"), None);
+    assert_eq!(frag_parse!("%s%d", "%s%d__foo"), None);
+    assert_eq!(frag_parse!("%s%d?", "%s%d__foo__1__2"), None);
+    assert_eq!(frag_parse!("%s[..2]", "%s__foo"), None);
+
+    // frag_parse!("%s%d", "%d%s__42__foo"); // Compile error
+    // frag_parse!(checked, "%s", "%s__foo"); // Compile error
+}
+
+// Format strings of values stored under the same keys, told apart by parsing
+fragstrings::frag_assert_disjoint!("%s%d", "%d%s", "%s%d%s%d?", "%d%d*");
+
+#[test]
+fn test_frag_assert_disjoint() {
+    fragstrings::frag_assert_disjoint!("%s", "%d");
+    fragstrings::frag_assert_disjoint!("%s%d", "%s%s%d*",);
+
+    // Values of one format never match the other ones
+    let mismatched = "%s%d%s%d__foo__1__bar__2";
+    assert_eq!(
+        frag_parse!("%s%d%s%d?", mismatched),
+        Some(("foo".to_string(), 1, "bar".to_string(), Some(2)))
+    );
+    assert_eq!(frag_parse!("%s%d", mismatched), None);
+
+    // fragstrings::frag_assert_disjoint!("%s%d", "%s%d%s?"); // Compile error
+    // fragstrings::frag_assert_disjoint!("%s*", "%s%d"); // Compile error
+    // fragstrings::frag_assert_disjoint!("%s", "%s"); // Compile error
+    // fragstrings::frag_assert_disjoint!(); // Compile error
+}
+
+// Format string of the stored values, which was extended with an optional item
+fragstrings::frag_compat_assert!(old = "%s%d", new = "%s%d%s?");
+
+#[test]
+fn test_frag_compat_assert() {
+    use fragstrings::frag_compat_assert;
+
+    frag_compat_assert!(old = "%s%d", new = "%s%d");
+    frag_compat_assert!(old = "%s:Color%d?", new = "%s:Color%d?%s?%d?");
+    frag_compat_assert!(old = "%s%d*", new = "%s%d%s%d*");
+    frag_compat_assert!(old = "%s%d", new = "%s%d*");
+    frag_compat_assert!(new = "%s[..64]%d" "%s?", old = "%s%d",);
+
+    // Old values are parsed by the new format string, and new ones by the old readers of open-ended values
+    assert_eq!(
+        frag_parse!("%s%d%s?", "%s%d__foo__42"),
+        Some(("foo".to_string(), 42, None))
+    );
+    assert_eq!(
+        frag_parse!("%s%d*", "%s%d%s%d__foo__42__bar__7"),
+        Some(("foo".to_string(), 42))
+    );
+
+    // frag_compat_assert!(old = "%s%d", new = "%s%s"); // Compile error: type of item 2 is changed
+    // frag_compat_assert!(old = "%s%d:u8", new = "%s%d"); // Compile error: type of item 2 is changed
+    // frag_compat_assert!(old = "%s%d", new = "%s"); // Compile error: mandatory item 2 is removed
+    // frag_compat_assert!(old = "%s%d?", new = "%s%d"); // Compile error: optional item 2 is made mandatory
+    // frag_compat_assert!(old = "%s%d", new = "%s%d?"); // Compile error: mandatory item 2 is made optional
+    // frag_compat_assert!(old = "%s%d", new = "%s%d%s"); // Compile error: mandatory item 3 is added
+    // frag_compat_assert!(old = "%s*", new = "%s%d"); // Compile error: the `*` ending is removed
+    // frag_compat_assert!(old = "%s%d"); // Compile error
+    // frag_compat_assert!(old = "%s", new = "%s", "%s"); // Compile error
+}
+
+#[test]
+fn test_frag_explain() {
+    use fragstrings::frag_explain;
+
+    const HELP: &str = frag_explain!("%s%s%d?*");
+    assert_eq!(
+        HELP,
+        "string, string, optional integer, followed by any extra fragments"
+    );
+    assert_eq!(
+        frag_explain!("%s:Color%d:u8%s[..64]?"),
+        "string as Color, integer as u8, optional string of up to 64 chars"
+    );
+    assert_eq!(
+        frag_explain!(r"%s{trie:buy,sell}%s[re=r'^[A-Z]{3}$']" "%P"),
+        "string which is one of buy, sell, string matching ^[A-Z]{3}$, path"
+    );
+    assert_eq!(
+        concat!("Keys are ", fragstrings::frag_explain!("%s%d"), "."),
+        "Keys are string, integer."
+    );
+
+    // frag_explain!("%s%x"); // Compile error
+    // frag_explain!("%s", input); // Compile error
+    // frag_explain!(); // Compile error
+}
+
+#[test]
+fn test_frag_parse_bytes() {
+    use fragstrings_procmacro::frag_parse_bytes;
+    use std::ops::{Deref, Range};
+    use std::sync::Arc;
+
+    // Same API as `bytes::Bytes`, which is not a dependency here
+    #[derive(Clone, Debug)]
+    struct Bytes(Arc<Vec<u8>>, Range<usize>);
+
+    impl Bytes {
+        fn slice(&self, range: Range<usize>) -> Bytes {
+            Bytes(
+                Arc::clone(&self.0),
+                self.1.start + range.start..self.1.start + range.end,
+            )
+        }
+    }
+
+    impl Deref for Bytes {
+        type Target = [u8];
+
+        fn deref(&self) -> &[u8] {
+            &self.0[self.1.clone()]
+        }
+    }
+
+    let bytes = |s: &[u8]| Bytes(Arc::new(s.to_vec()), 0..s.len());
+
+    // OK: string items are slices of the input, sharing its buffer
+    let input = bytes(b"%s%d%s__foo__42__/tmp");
+    let (frag1, frag2, frag3, frag4) = frag_parse_bytes!("%s%d%P%s?", input).expect("failed to parse");
+    assert_eq!(&*frag1, b"foo");
+    assert!(Arc::ptr_eq(&frag1.0, &input.0));
+    assert_eq!(frag1.1, 8..11);
+    assert_eq!(frag2, 42);
+    assert_eq!(frag3, std::path::PathBuf::from("/tmp"));
+    assert!(frag4.is_none());
+
+    // OK: checked and versioned values
+    let input = bytes(fragstrings::frag_format!(checked, version = 2, "%s", "foo").as_bytes());
+    let frag1 = frag_parse_bytes!(checked, version = 2, "%s", input).expect("failed to parse");
+    assert_eq!(&*frag1, b"foo");
+
+    // Bad: mismatch and invalid UTF-8
+    assert!(frag_parse_bytes!("%s%d", bytes(b"%s%d__foo__bar")).is_none());
+    assert!(frag_parse_bytes!("%s", bytes(b"%s__\xff")).is_none());
+
+    // Bad: not UTF-8 does not match right away, and the callbacks are not called with an empty input in place of it
+    let failures = std::cell::RefCell::new(Vec::new());
+    let record = |fmt: &str, input: &str| failures.borrow_mut().push((fmt.to_string(), input.to_string()));
+    assert!(frag_parse_bytes!(on_fail = record, "%s%d?", bytes(b"")).is_none());
+    assert_eq!(failures.borrow().len(), 1);
+    assert!(frag_parse_bytes!(on_fail = record, "%s%d?", bytes(b"\xff")).is_none());
+    assert!(frag_parse_bytes!(on_fail = record, "%s%d", bytes(b"%s%d__f\xff__1")).is_none());
+    assert_eq!(failures.borrow().len(), 1);
+    assert!(matches!(
+        frag_parse_bytes!(checked, "%s%d", bytes(b"%s%d__f\xff__1")),
+        Err(fragstrings::ParseError::Mismatch)
+    ));
+    assert!(frag_parse_bytes!(union_sep, "%s%d", bytes(b"%s%d-f\xff-1")).is_none());
+
+    // frag_parse_bytes!(html_decode, "%s", input); // Compile error
+    // frag_parse_bytes!(lazy, "%s", input); // Compile error
+}
+
+#[test]
+fn test_frag_parse_os() {
+    use fragstrings_procmacro::{frag_parse_file_name, frag_parse_os};
+    use std::ffi::{OsStr, OsString};
+    use std::path::{Path, PathBuf};
+
+    // OK: OsStr, OsString, Path and PathBuf, also temporary ones
+    let path = Path::new("/var/cache/%s%d__foo__42.bin");
+    assert_eq!(
+        frag_parse_os!("%s%d", path.file_stem().unwrap()),
+        Some(("foo".to_string(), 42))
+    );
+    assert_eq!(
+        frag_parse_os!("%s%d", OsString::from("%s%d__foo__42")),
+        Some(("foo".to_string(), 42))
+    );
+    assert_eq!(
+        frag_parse_os!("%s%P", Path::new("%s%s__foo__bar")),
+        Some(("foo".to_string(), PathBuf::from("bar")))
+    );
+    let checked = fragstrings::frag_format!(checked, "%s", "foo");
+    assert_eq!(
+        frag_parse_os!(checked, "%s", OsStr::new(&checked)),
+        Ok("foo".to_string())
+    );
+
+    // OK: file names
+    assert_eq!(
+        frag_parse_file_name!("%s%d", path.with_extension("")),
+        Some(("foo".to_string(), 42))
+    );
+    assert_eq!(
+        frag_parse_file_name!("%s%d", "%s%d__foo__42"),
+        Some(("foo".to_string(), 42))
+    );
+
+    // Bad: mismatch, and paths without file names
+    assert!(frag_parse_os!("%s%d", OsStr::new("%s%d__foo__bar")).is_none());
+    assert!(frag_parse_os!("%s%d", path).is_none());
+    assert!(frag_parse_file_name!("%s%d", path).is_none());
+    assert!(frag_parse_file_name!("%s", Path::new("/")).is_none());
+    assert!(frag_parse_file_name!("%s", Path::new("%s__foo/..")).is_none());
+
+    // frag_parse_os!("%s%d"); // Compile error
+}
+
+#[test]
+#[cfg(unix)]
+fn test_frag_parse_os_not_utf8() {
+    use fragstrings_procmacro::{frag_parse_file_name, frag_parse_os};
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    // Bad: not UTF-8, which would be parsed with the replacement character by `to_string_lossy()`
+    let name = OsStr::from_bytes(b"%s%d__fo\xff__42");
+    assert!(frag_parse!("%s%d", name.to_string_lossy()).is_some());
+    assert!(frag_parse_os!("%s%d", name).is_none());
+    assert!(frag_parse_file_name!("%s%d", Path::new("/tmp").join(name)).is_none());
+
+    // Bad: not UTF-8 does not match right away, and the callbacks are not called with an empty input in place of it
+    let failures = std::cell::RefCell::new(Vec::new());
+    let record = |fmt: &str, input: &str| failures.borrow_mut().push((fmt.to_string(), input.to_string()));
+    assert!(frag_parse_os!(on_fail = record, "%s%d?", OsStr::new("")).is_none());
+    assert_eq!(failures.borrow().len(), 1);
+    assert!(frag_parse_os!(on_fail = record, "%s%d?", OsStr::from_bytes(b"\xff")).is_none());
+    assert!(frag_parse_os!(on_fail = record, "%s%d", name).is_none());
+    assert_eq!(failures.borrow().len(), 1);
+    assert_eq!(
+        frag_parse_os!(checked, "%s", name),
+        Err(fragstrings::ParseError::Mismatch)
+    );
+    assert_eq!(frag_parse_os!(union_sep, "%s%d", name), None);
+}
+
+#[test]
+#[cfg(windows)]
+fn test_frag_parse_os_not_utf8() {
+    use fragstrings_procmacro::{frag_parse_file_name, frag_parse_os};
+    use std::ffi::OsString;
+    use std::os::windows::ffi::OsStringExt;
+    use std::path::Path;
+
+    // Bad: unpaired surrogate, which would be parsed with the replacement character by `to_string_lossy()`
+    let mut wide = "%s%d__fo".encode_utf16().collect::<Vec<_>>();
+    wide.push(0xD800);
+    wide.extend("__42".encode_utf16());
+    let name = OsString::from_wide(&wide);
+    assert!(frag_parse!("%s%d", name.to_string_lossy()).is_some());
+    assert!(frag_parse_os!("%s%d", name).is_none());
+    assert!(frag_parse_file_name!("%s%d", Path::new("C:\\Temp").join(&name)).is_none());
+}
+
+#[test]
+fn test_frag_parse_count() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    static CALLS: AtomicU64 = AtomicU64::new(0);
+    static FAILURES: AtomicU64 = AtomicU64::new(0);
+
+    // OK: every call is counted, and failures are counted separately
+    let parse = |input: &str| frag_parse!(count = &CALLS, count_failures = &FAILURES, "%s%d", input);
+    assert_eq!(parse("%s%d__foo__42"), Some(("foo".to_string(), 42)));
+    assert_eq!(parse("%s%d__foo__bar"), None);
+    assert_eq!(parse("%d__42"), None);
+    assert_eq!(CALLS.load(Ordering::Relaxed), 3);
+    assert_eq!(FAILURES.load(Ordering::Relaxed), 2);
+
+    // OK: checked mode counts errors, literal values are counted too, and so are shared counters
+    let failures = Arc::new(AtomicU64::new(0));
+    assert!(frag_parse!(checked, count_failures = &failures, "%s", "%s%d__foo__1").is_err());
+    assert_eq!(frag_parse!(count = &CALLS, "%s", "%s__foo"), Some("foo".to_string()));
+    assert_eq!(failures.load(Ordering::Relaxed), 1);
+    assert_eq!(CALLS.load(Ordering::Relaxed), 4);
+}
+
+#[test]
+fn test_frag_parse_partial() {
+    use fragstrings::{frag_parse_partial, FieldError};
+
+    let s = |s: &str| s.to_string();
+
+    // OK: the fields around the garbage one are recovered
+    let (name, amount, height) = frag_parse_partial!("%s%d%d", s("%s%d%d__foo__4x2__42")).expect("descriptor matches");
+    assert_eq!(name, Ok(s("foo")));
+    assert_eq!(amount, Err(FieldError::Malformed));
+    assert_eq!(height, Ok(42));
+
+    // OK: missing fragments are told apart from malformed ones, and extra fragments are ignored
+    assert_eq!(
+        frag_parse_partial!("%s%d%d", s("%s%d%d__foo__42")),
+        Some((Ok(s("foo")), Ok(42), Err(FieldError::Missing)))
+    );
+    assert_eq!(
+        frag_parse_partial!("%s%d:u8%s?", s("%s%d__foo__256")),
+        Some((Ok(s("foo")), Err(FieldError::Malformed), Ok(None)))
+    );
+    assert_eq!(
+        frag_parse_partial!("%s%d:u8%s?", s("%s%d%s__foo__7__bar__baz")),
+        Some((Ok(s("foo")), Ok(7), Ok(Some(s("bar")))))
+    );
+    assert_eq!(frag_parse_partial!("%d", s("%d__")), Some(Err(FieldError::Malformed)));
+
+    // OK: the checks of the items fail only their own fields
+    assert_eq!(
+        frag_parse_partial!(html_decode, "%s[..3]%s%s[slice:1..]", s("%s%s%s__quux__a&amp;b__x")),
+        Some((Err(FieldError::Malformed), Ok(s("a&b")), Ok(s(""))))
+    );
+    assert_eq!(
+        frag_parse_partial!(null_sentinel = "NULL", "%s%d?", s("%s%d__NULL__NULL")),
+        Some((Err(FieldError::Malformed), Ok(None)))
+    );
+
+    // Bad: the descriptor still gates the whole value
+    assert_eq!(frag_parse_partial!("%s%d%d", s("%d%d%s__42__42__foo")), None);
+    assert_eq!(frag_parse_partial!("%s%d%d", s("foo")), None);
+    assert_eq!(
+        frag_parse_partial!("%s%d", "%s%d__foo__bar"),
+        Some((Ok(s("foo")), Err(FieldError::Malformed)))
+    );
+
+    // frag_parse_partial!(checked, "%s", "%s__foo"); // Compile error
+    // frag_parse_partial!(version = 2, "%s", "%s__foo"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_strict() {
+    use fragstrings::{frag_format, frag_parse_strict};
+
+    let s = |s: &str| s.to_string();
+
+    // OK: values described in full are parsed the same way in both modes
+    let input = s("%s%d__foo__42");
+    assert_eq!(frag_parse_strict!("%s%d?", input), Some((s("foo"), Some(42))));
+    assert_eq!(frag_parse!("%s%d?", input), Some((s("foo"), Some(42))));
+    let input = s("%s__foo");
+    assert_eq!(frag_parse_strict!("%s%d?", input), Some((s("foo"), None)));
+    assert_eq!(frag_parse!("%s%d?", input), Some((s("foo"), None)));
+    let input = s("%s%d%s__foo__42__bar");
+    assert_eq!(frag_parse_strict!("%s%d*", input), Some((s("foo"), 42)));
+    assert_eq!(frag_parse!("%s%d*", input), Some((s("foo"), 42)));
+    let input = frag_format!(checked, version = 2, "%s%d", "foo", 42);
+    assert_eq!(
+        frag_parse_strict!(checked, version = 2, "%s%d?", input),
+        Ok((s("foo"), Some(42)))
+    );
+
+    // Bad: the descriptor has more items than the value, or less, or other types at optional positions,
+    // or it is not made of items at all after the prefix of open-ended format strings
+    let lax = !cfg!(feature = "strict");
+    let input = s("%s%d__foo");
+    assert_eq!(frag_parse_strict!("%s%d?", input), None);
+    assert_eq!(frag_parse!("%s%d?", input), Some((s("foo"), None)).filter(|_| lax));
+    let input = s("%s__foo__42");
+    assert_eq!(frag_parse_strict!("%s%d?", input), None);
+    assert_eq!(frag_parse!("%s%d?", input), Some((s("foo"), Some(42))).filter(|_| lax));
+    let input = s("%s%s__foo__42");
+    assert_eq!(frag_parse_strict!("%s%d?", input), None);
+    assert_eq!(frag_parse!("%s%d?", input), Some((s("foo"), Some(42))).filter(|_| lax));
+    let input = s("%s%dx__foo__42");
+    assert_eq!(frag_parse_strict!("%s%d*", input), None);
+    assert_eq!(frag_parse!("%s%d*", input), Some((s("foo"), 42)).filter(|_| lax));
+    let input = s("%s%d__foo");
+    assert_eq!(frag_parse_strict!("%s*", input), None);
+    assert_eq!(frag_parse!("%s*", input), Some(s("foo")).filter(|_| lax));
+    let input = s("%s__foo__bar");
+    assert_eq!(frag_parse_strict!("%s*", input), None);
+    assert_eq!(fragstrings::frag_parse_ref!("%s*", input), Some("foo").filter(|_| lax));
+}
+
+#[test]
+fn test_frag_parse_verbose() {
+    // OK: failures are only logged, the results are the same
+    assert_eq!(
+        frag_parse!(verbose, "%s%d", "%s%d__foo__42"),
+        Some(("foo".to_string(), 42))
+    );
+    assert_eq!(frag_parse!(verbose, "%s%d", "%s%d__foo__bar"), None);
+    let input = "%d__42".to_string();
+    assert_eq!(frag_parse!(verbose, "%s%d*", input), None);
+    assert_eq!(
+        frag_parse!(verbose, checked, "%s", "%s%d__foo__1"),
+        Err(fragstrings::ParseError::BadChecksum)
+    );
+
+    // OK: borrowed values
+    let input = "%s%d__foo__42".to_string();
+    assert_eq!(fragstrings::frag_parse_ref!(verbose, "%s%d", input), Some(("foo", 42)));
+}
+
+#[test]
+fn test_frag_parse_on_fail() {
+    use std::cell::RefCell;
+
+    let failures = RefCell::new(Vec::new());
+    let record = |fmt: &str, input: &str| failures.borrow_mut().push((fmt.to_string(), input.to_string()));
+
+    // OK: the callback is called on failures only, with the format string as written
+    assert_eq!(
+        frag_parse!(on_fail = record, "%s%d", "%s%d__foo__42"),
+        Some(("foo".to_string(), 42))
+    );
+    assert_eq!(frag_parse!(on_fail = record, "%s%d:u8", "%s%d__foo__bar"), None);
+    assert_eq!(
+        frag_parse!(on_fail = record, checked, "%s", "%s%d__foo__1"),
+        Err(fragstrings::ParseError::BadChecksum)
+    );
+    let input = "%d__42".to_string();
+    assert_eq!(
+        frag_parse!(
+            on_fail = |fmt: &str, input: &str| failures.borrow_mut().push((fmt.to_string(), input.to_string())),
+            "%s*",
+            input
+        ),
+        None
+    );
+
+    // OK: borrowed values
+    let input = "%d__1".to_string();
+    assert_eq!(fragstrings::frag_parse_ref!(on_fail = record, "%s", input), None);
+
+    assert_eq!(
+        failures.into_inner(),
+        [
+            ("%s%d:u8".to_string(), "%s%d__foo__bar".to_string()),
+            ("%s".to_string(), "%s%d__foo__1".to_string()),
+            ("%s*".to_string(), "%d__42".to_string()),
+            ("%s".to_string(), "%d__1".to_string()),
+        ]
+    );
+
+    // Bad: the callback can panic
+    let panicked = std::panic::catch_unwind(|| {
+        frag_parse!(
+            on_fail = |fmt, input| panic!("{} does not match {}", input, fmt),
+            "%d",
+            "%d__x"
+        )
+    });
+    assert!(panicked.is_err());
+
+    // fragstrings::frag_parse_partial!(on_fail = record, "%s", "%s__foo"); // Compile error
+}
+
+#[test]
+fn test_frag_parse_audit() {
+    use fragstrings::AuditLogger;
+    use std::cell::RefCell;
+
+    #[derive(Default)]
+    struct Log(RefCell<Vec<(String, String, bool)>>);
+
+    impl AuditLogger for Log {
+        fn record(&self, fmt: &str, input: &str, success: bool) {
+            self.0.borrow_mut().push((fmt.to_string(), input.to_string(), success));
+        }
+    }
+
+    let log = Log::default();
+    let entry = |fmt: &str, input: &str, success| (fmt.to_string(), input.to_string(), success);
+
+    // OK: every attempt is recorded, with the format string as written
+    assert_eq!(
+        frag_parse!(audit = &log, "%s%d", "%s%d__foo__42"),
+        Some(("foo".to_string(), 42))
+    );
+    assert_eq!(frag_parse!(audit = &log, "%s%d:u8", "%s%d__foo__bar"), None);
+    let input = "%d__42".to_string();
+    assert_eq!(frag_parse!(audit = &log, verbose, "%d*", input), Some(42));
+    assert_eq!(
+        frag_parse!(audit = &log, checked, "%s", "%s%d__foo__1"),
+        Err(fragstrings::ParseError::BadChecksum)
+    );
+
+    // OK: borrowed values
+    assert_eq!(fragstrings::frag_parse_ref!(audit = log, "%s", "%s__bar"), Some("bar"));
+
+    assert_eq!(
+        log.0.into_inner(),
+        [
+            entry("%s%d", "%s%d__foo__42", true),
+            entry("%s%d:u8", "%s%d__foo__bar", false),
+            entry("%d*", "%d__42", true),
+            entry("%s", "%s%d__foo__1", false),
+            entry("%s", "%s__bar", true),
+        ]
+    );
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn test_frag_parse_trace() {
+    // OK: failures are only traced, with the `tracing` feature, the results are the same
+    assert_eq!(
+        frag_parse!(trace, "%s%d", "%s%d__foo__42"),
+        Some(("foo".to_string(), 42))
+    );
+    assert_eq!(frag_parse!(trace, "%s%d", "%s%d__foo__bar"), None);
+    let input = "%d__42".to_string();
+    assert_eq!(frag_parse!(trace, verbose, "%s%d*", input), None);
+    assert_eq!(
+        frag_parse!(trace, checked, version = [0, 2], "%s", "%s%d__foo__1"),
+        Err(fragstrings::ParseError::BadChecksum)
+    );
+    assert_eq!(fragstrings::frag_parse_ref!(trace, "%s", "%s__bar"), Some("bar"));
+}
+
+#[test]
+fn test_frag_parse_nested_format() {
+    use fragstrings::frag_format;
+
+    // OK: descriptors of the nested frag_format! match
+    assert_eq!(
+        frag_parse!("%s%d", frag_format!("%s%d", "foo", 42)),
+        Some(("foo".to_string(), 42))
+    );
+    assert_eq!(
+        frag_parse!("%s%d*", frag_format!("%s%d%d", "foo", 1, 2)),
+        Some(("foo".to_string(), 1))
+    );
+    assert_eq!(
+        frag_parse!("%s%d?", frag_format!("%s", "foo")),
+        Some(("foo".to_string(), None))
+    );
+    assert_eq!(
+        frag_parse!(
+            checked,
+            version = 1,
+            "%s",
+            frag_format!(checked, version = 1, "%s", "foo")
+        ),
+        Ok("foo".to_string())
+    );
+    assert_eq!(
+        frag_parse!(version = [0, 1], "%s", frag_format!("%s", "foo")),
+        Some((0, "foo".to_string()))
+    );
+
+    // frag_parse!("%s%d", frag_format!("%d%s", 42, "foo")); // Compile error
+    // frag_parse!(checked, "%s", frag_format!("%s", "foo")); // Compile error: no checksum
+    // frag_parse!(version = 1, "%s", frag_format!("%s", "foo")); // Compile error: no version
+}
@@ -0,0 +1,37 @@
+#![cfg(feature = "registry")]
+
+use fragstrings::registry::{self, Direction};
+use fragstrings::{frag_format, frag_parse};
+
+fn write(name: &str, n: i64) -> String {
+    frag_format!(checked, "%s%d", name, n)
+}
+
+fn read(value: &str) -> Option<(String, Option<i64>)> {
+    frag_parse!("%s%d?*", value)
+}
+
+#[test]
+fn test_registry() {
+    // Call sites are registered before they are executed, with format strings as written
+    let uses = registry::all();
+    let find = |pattern: &str, direction: Direction| {
+        uses.iter()
+            .find(|u| u.file == file!() && u.pattern == pattern && u.direction == direction)
+            .map(|u| u.line)
+    };
+    let write_line = find("%s%d", Direction::Format).expect("frag_format! is registered");
+    let read_line = find("%s%d?*", Direction::Parse).expect("frag_parse! is registered");
+    assert!(write_line < read_line);
+    assert!(read_line < line!());
+
+    // Literal values are registered too
+    assert_eq!(frag_parse!("%s", "%s__foo"), Some("foo".to_string()));
+    assert_eq!(find("%s", Direction::Parse), Some(line!() - 1));
+
+    // The registry is sorted and collected once
+    assert!(uses.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert!(std::ptr::eq(uses, registry::all()));
+
+    assert_eq!(read(&write("foo", 42)), Some(("foo".to_string(), Some(42))));
+}
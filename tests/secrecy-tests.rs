@@ -0,0 +1,36 @@
+#![cfg(feature = "secrecy")]
+
+use fragstrings::{frag_format, frag_parse};
+use secrecy::{ExposeSecret, SecretString};
+
+#[test]
+fn test_frag_parse_secret() {
+    // OK: plain strings are secret, the other items are parsed as usual
+    let (password, n): (SecretString, i64) = frag_parse!(secret, "%s%d", "%s%d__hunter2__42").expect("failed to parse");
+    assert_eq!(password.expose_secret(), "hunter2");
+    assert_eq!(n, 42);
+    assert_eq!(format!("{:?}", password), "SecretBox<str>([REDACTED])");
+
+    // OK: optional items, decoded values, and strings with custom types, which are not secret
+    let (user, password, port) =
+        frag_parse!(secret, html_decode, "%s%s?%d:u16?", "%s%s__a&amp;b__x&lt;y").expect("failed to parse");
+    assert_eq!(user.expose_secret(), "a&b");
+    assert_eq!(
+        password.map(|password| password.expose_secret().to_string()),
+        Some("x<y".to_string())
+    );
+    assert_eq!(port, None);
+    let (token, n): (SecretString, u8) = frag_parse!(secret, "%s%s:u8", "%s%s__t__7").expect("failed to parse");
+    assert_eq!((token.expose_secret(), n), ("t", 7));
+
+    // OK: round trip
+    let value = frag_format!("%s%d", "hunter2", 42);
+    let (password, _) = frag_parse!(secret, "%s%d", value).expect("failed to parse");
+    assert_eq!(password.expose_secret(), "hunter2");
+
+    // Bad: the value does not match
+    assert!(frag_parse!(secret, "%s%d", "%s%d__hunter2__x").is_none());
+
+    // frag_parse!(secret, redact, "%s", "%s__foo"); // Compile error
+    // fragstrings::frag_parse_ref!(secret, "%s", "%s__foo"); // Compile error
+}
@@ -0,0 +1,63 @@
+#![cfg(feature = "vectors")]
+
+use fragstrings::vectors::Vectors;
+use fragstrings::{format_vector, parse_vector};
+use std::collections::BTreeSet;
+
+/// Vectors for the other implementations, which are kept in `tests/vectors.json`.
+fn conformance_vectors() -> Vectors {
+    let mut vectors = Vectors::new();
+
+    parse_vector!(vectors, "%s%d", "%s%d__foo__42");
+    parse_vector!(vectors, "%s%d", "%s%d__foo__-42");
+    parse_vector!(vectors, "%s%d", "%s%d____0");
+    parse_vector!(vectors, "%s%d", "%s%d__привет__+7");
+    parse_vector!(vectors, "%s%d", "%s%d__foo_bar___1");
+    parse_vector!(vectors, "%s%d", "%d%s__42__foo");
+    parse_vector!(vectors, "%s%d", "%s%d__foo");
+    parse_vector!(vectors, "%s%d", "%s%d__foo__42__bar");
+    parse_vector!(vectors, "%s%d", "%s%d__foo__4x2");
+    parse_vector!(vectors, "%s%d", "%s%d__foo__9223372036854775808");
+    parse_vector!(vectors, "%s%d", "foo");
+    parse_vector!(vectors, "%s%d", "");
+    parse_vector!(vectors, "%s", "%s__foo");
+    parse_vector!(vectors, "%d", "%d__-9223372036854775808");
+    parse_vector!(vectors, "%s*", "%s%d__foo__42");
+    parse_vector!(vectors, "%s*", "%s");
+    parse_vector!(vectors, "%s%d?", "%s__foo");
+    parse_vector!(vectors, "%s%d?", "%s%d__foo__42");
+    parse_vector!(vectors, "%s%d?", "%s%s__foo__bar");
+    parse_vector!(vectors, "%s%d?*", "%s%d%s__foo__42__bar");
+    parse_vector!(vectors, "%d:u8", "%d__255");
+    parse_vector!(vectors, "%d:u8", "%d__256");
+    parse_vector!(vectors, "%s[..3]", "%s__foo");
+    parse_vector!(vectors, "%s[..3]", "%s__quux");
+    parse_vector!(vectors, "%s{trie:buy,sell}", "%s__sell");
+    parse_vector!(vectors, "%s{trie:buy,sell}", "%s__hold");
+    parse_vector!(vectors, "%s[re=r'^[A-Z]{2,3}$']", "%s__USD");
+    parse_vector!(vectors, "%s[re=r'^[A-Z]{2,3}$']", "%s__usd");
+    parse_vector!(vectors, "%s{set}", "%s__b,a,b");
+    parse_vector!(vectors, "%s{set}", "%s__");
+    parse_vector!(vectors, "%s62", "%s__1w");
+
+    format_vector!(vectors, "%s%d", "foo", 42);
+    format_vector!(vectors, "%s%d", "", -42);
+    format_vector!(vectors, "%s%s", "привет", "foo_bar_");
+    format_vector!(vectors, "%d%d", i64::MIN, i64::MAX);
+    format_vector!(vectors, "%s{set}", ["b", "a"].iter().copied().collect::<BTreeSet<_>>());
+    format_vector!(vectors, "%s{set}", BTreeSet::<&str>::new());
+
+    vectors
+}
+
+#[test]
+fn test_conformance_vectors() {
+    // The file is regenerated with `UPDATE_VECTORS=1 cargo test --features vectors`
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/vectors.json");
+    let vectors = conformance_vectors();
+    if std::env::var_os("UPDATE_VECTORS").is_some() {
+        vectors.write(path).unwrap();
+    }
+    let expected = std::fs::read_to_string(path).unwrap();
+    assert_eq!(vectors.to_json(), expected);
+}